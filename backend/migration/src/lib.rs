@@ -13,6 +13,20 @@ mod m20250830_000001_add_password_to_chat_rooms;
 mod m20250830_000002_create_message_reactions_table;
 mod m20250830_000003_add_room_code_to_chat_rooms;
 mod m20250830_000004_create_room_memberships_table;
+mod m20250901_000001_create_revoked_tokens_table;
+mod m20250901_000002_create_refresh_tokens_table;
+mod m20250902_000001_add_chat_messages_keyset_index;
+mod m20250902_000002_add_edit_and_delete_to_chat_messages;
+mod m20250902_000003_create_chat_message_edits_table;
+mod m20250903_000001_add_reply_to_to_chat_messages;
+mod m20250903_000002_add_read_state_to_room_memberships;
+mod m20250904_000001_add_search_vector_to_chat_messages;
+mod m20250904_000002_add_role_and_mute_to_room_memberships;
+mod m20250904_000003_create_room_bans_table;
+mod m20250905_000001_add_last_seen_to_users;
+mod m20250906_000001_create_chat_attachments_table;
+mod m20250907_000001_add_previews_to_chat_attachments;
+mod m20250908_000001_create_chat_backplane_payloads_table;
 
 pub struct Migrator;
 
@@ -33,6 +47,20 @@ impl MigratorTrait for Migrator {
             Box::new(m20250830_000002_create_message_reactions_table::Migration),
             Box::new(m20250830_000003_add_room_code_to_chat_rooms::Migration),
             Box::new(m20250830_000004_create_room_memberships_table::Migration),
+            Box::new(m20250901_000001_create_revoked_tokens_table::Migration),
+            Box::new(m20250901_000002_create_refresh_tokens_table::Migration),
+            Box::new(m20250902_000001_add_chat_messages_keyset_index::Migration),
+            Box::new(m20250902_000002_add_edit_and_delete_to_chat_messages::Migration),
+            Box::new(m20250902_000003_create_chat_message_edits_table::Migration),
+            Box::new(m20250903_000001_add_reply_to_to_chat_messages::Migration),
+            Box::new(m20250903_000002_add_read_state_to_room_memberships::Migration),
+            Box::new(m20250904_000001_add_search_vector_to_chat_messages::Migration),
+            Box::new(m20250904_000002_add_role_and_mute_to_room_memberships::Migration),
+            Box::new(m20250904_000003_create_room_bans_table::Migration),
+            Box::new(m20250905_000001_add_last_seen_to_users::Migration),
+            Box::new(m20250906_000001_create_chat_attachments_table::Migration),
+            Box::new(m20250907_000001_add_previews_to_chat_attachments::Migration),
+            Box::new(m20250908_000001_create_chat_backplane_payloads_table::Migration),
         ]
     }
 }
\ No newline at end of file
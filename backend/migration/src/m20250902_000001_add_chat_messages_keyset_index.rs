@@ -0,0 +1,66 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Composite index backing keyset pagination of a room's history on (created_at, id)
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_chat_messages_room_created_at_id")
+                    .table(ChatMessages::Table)
+                    .col(ChatMessages::RoomId)
+                    .col(ChatMessages::CreatedAt)
+                    .col(ChatMessages::Id)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        // The composite index covers every lookup the room_id index served
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_chat_messages_room_id")
+                    .table(ChatMessages::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_chat_messages_room_id")
+                    .table(ChatMessages::Table)
+                    .col(ChatMessages::RoomId)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_chat_messages_room_created_at_id")
+                    .table(ChatMessages::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Reference to the "chat_messages" table
+#[derive(Iden)]
+enum ChatMessages {
+    Table,
+    Id,
+    RoomId,
+    CreatedAt,
+}
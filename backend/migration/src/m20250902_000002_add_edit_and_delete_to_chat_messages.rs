@@ -0,0 +1,55 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // edited_at is set on every edit; deleted_at/deleted_by turn a message into a tombstone
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ChatMessages::Table)
+                    .add_column(
+                        ColumnDef::new(ChatMessages::EditedAt)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .add_column(
+                        ColumnDef::new(ChatMessages::DeletedAt)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .add_column(
+                        ColumnDef::new(ChatMessages::DeletedBy)
+                            .uuid()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ChatMessages::Table)
+                    .drop_column(ChatMessages::EditedAt)
+                    .drop_column(ChatMessages::DeletedAt)
+                    .drop_column(ChatMessages::DeletedBy)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Reference to the "chat_messages" table
+#[derive(Iden)]
+enum ChatMessages {
+    Table,
+    EditedAt,
+    DeletedAt,
+    DeletedBy,
+}
@@ -0,0 +1,104 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ChatMessageEdits::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ChatMessageEdits::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatMessageEdits::MessageId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatMessageEdits::EditorId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatMessageEdits::PreviousContent)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatMessageEdits::EditedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_chat_message_edits_message_id")
+                            .from(ChatMessageEdits::Table, ChatMessageEdits::MessageId)
+                            .to(ChatMessages::Table, ChatMessages::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_chat_message_edits_editor_id")
+                            .from(ChatMessageEdits::Table, ChatMessageEdits::EditorId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Create an index on (message_id, edited_at) to list a message's revisions in order
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_chat_message_edits_message_id_edited_at")
+                    .table(ChatMessageEdits::Table)
+                    .col(ChatMessageEdits::MessageId)
+                    .col(ChatMessageEdits::EditedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ChatMessageEdits::Table).to_owned())
+            .await
+    }
+}
+
+/// Reference to the "chat_message_edits" table
+#[derive(Iden)]
+enum ChatMessageEdits {
+    Table,
+    Id,
+    MessageId,
+    EditorId,
+    PreviousContent,
+    EditedAt,
+}
+
+/// Reference to the "chat_messages" table for foreign key
+#[derive(Iden)]
+enum ChatMessages {
+    Table,
+    Id,
+}
+
+/// Reference to the "users" table for foreign key
+#[derive(Iden)]
+enum Users {
+    Table,
+    Id,
+}
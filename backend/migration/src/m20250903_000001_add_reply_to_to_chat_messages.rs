@@ -0,0 +1,75 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Replies point at the root message of their thread
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ChatMessages::Table)
+                    .add_column(
+                        ColumnDef::new(ChatMessages::ReplyToId)
+                            .uuid()
+                            .null(),
+                    )
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_chat_messages_reply_to_id")
+                            .from_tbl(ChatMessages::Table)
+                            .from_col(ChatMessages::ReplyToId)
+                            .to_tbl(ChatMessages::Table)
+                            .to_col(ChatMessages::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Create an index on (reply_to_id, created_at) to list a thread and count its replies
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_chat_messages_reply_to_id_created_at")
+                    .table(ChatMessages::Table)
+                    .col(ChatMessages::ReplyToId)
+                    .col(ChatMessages::CreatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_chat_messages_reply_to_id_created_at")
+                    .table(ChatMessages::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ChatMessages::Table)
+                    .drop_foreign_key(Alias::new("fk_chat_messages_reply_to_id"))
+                    .drop_column(ChatMessages::ReplyToId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Reference to the "chat_messages" table
+#[derive(Iden)]
+enum ChatMessages {
+    Table,
+    Id,
+    ReplyToId,
+    CreatedAt,
+}
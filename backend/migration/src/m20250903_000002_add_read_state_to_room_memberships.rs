@@ -0,0 +1,66 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The newest message a member has read, and when they read it
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RoomMemberships::Table)
+                    .add_column(
+                        ColumnDef::new(RoomMemberships::LastReadMessageId)
+                            .uuid()
+                            .null(),
+                    )
+                    .add_column(
+                        ColumnDef::new(RoomMemberships::LastReadAt)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_room_memberships_last_read_message_id")
+                            .from_tbl(RoomMemberships::Table)
+                            .from_col(RoomMemberships::LastReadMessageId)
+                            .to_tbl(ChatMessages::Table)
+                            .to_col(ChatMessages::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RoomMemberships::Table)
+                    .drop_foreign_key(Alias::new("fk_room_memberships_last_read_message_id"))
+                    .drop_column(RoomMemberships::LastReadMessageId)
+                    .drop_column(RoomMemberships::LastReadAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Reference to the "room_memberships" table
+#[derive(Iden)]
+enum RoomMemberships {
+    Table,
+    LastReadMessageId,
+    LastReadAt,
+}
+
+/// Reference to the "chat_messages" table
+#[derive(Iden)]
+enum ChatMessages {
+    Table,
+    Id,
+}
@@ -0,0 +1,49 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Generated columns are not supported by the schema builder, so this requires raw SQL.
+        // Attachment links ([image](url), [video](url), [audio](url)) are left out of the index,
+        // and the 'simple' configuration keeps search language-neutral.
+        let add_column_sql = r#"
+            ALTER TABLE chat_messages
+            ADD COLUMN search_vector tsvector
+            GENERATED ALWAYS AS (
+                to_tsvector(
+                    'simple'::regconfig,
+                    regexp_replace(content, '\[(image|video|audio)\]\([^)]*\)', ' ', 'g')
+                )
+            ) STORED
+        "#;
+
+        manager.get_connection().execute_unprepared(add_column_sql).await?;
+
+        // Create a GIN index on search_vector for full-text queries
+        let index_sql = r#"
+            CREATE INDEX idx_chat_messages_search_vector
+            ON chat_messages USING GIN (search_vector)
+        "#;
+
+        manager.get_connection().execute_unprepared(index_sql).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared("DROP INDEX IF EXISTS idx_chat_messages_search_vector")
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared("ALTER TABLE chat_messages DROP COLUMN IF EXISTS search_vector")
+            .await?;
+
+        Ok(())
+    }
+}
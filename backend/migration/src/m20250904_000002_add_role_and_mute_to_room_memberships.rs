@@ -0,0 +1,63 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // role is one of 'owner', 'moderator' or 'member'; muted_until silences a member for a while
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RoomMemberships::Table)
+                    .add_column(
+                        ColumnDef::new(RoomMemberships::Role)
+                            .string()
+                            .not_null()
+                            .default("member"),
+                    )
+                    .add_column(
+                        ColumnDef::new(RoomMemberships::MutedUntil)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Room creators become the owners of their rooms
+        // This requires raw SQL since the update joins chat_rooms
+        let update_sql = r#"
+            UPDATE room_memberships
+            SET role = 'owner'
+            FROM chat_rooms
+            WHERE room_memberships.room_id = chat_rooms.id
+              AND room_memberships.user_id = chat_rooms.created_by
+        "#;
+
+        manager.get_connection().execute_unprepared(update_sql).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RoomMemberships::Table)
+                    .drop_column(RoomMemberships::Role)
+                    .drop_column(RoomMemberships::MutedUntil)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Reference to the "room_memberships" table
+#[derive(Iden)]
+enum RoomMemberships {
+    Table,
+    Role,
+    MutedUntil,
+}
@@ -0,0 +1,119 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RoomBans::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RoomBans::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(RoomBans::RoomId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(RoomBans::UserId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(RoomBans::BannedBy)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(RoomBans::Reason)
+                            .text()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(RoomBans::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_room_bans_room_id")
+                            .from(RoomBans::Table, RoomBans::RoomId)
+                            .to(ChatRooms::Table, ChatRooms::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_room_bans_user_id")
+                            .from(RoomBans::Table, RoomBans::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_room_bans_banned_by")
+                            .from(RoomBans::Table, RoomBans::BannedBy)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // A user is banned from a room at most once
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_room_bans_room_id_user_id")
+                    .table(RoomBans::Table)
+                    .col(RoomBans::RoomId)
+                    .col(RoomBans::UserId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RoomBans::Table).to_owned())
+            .await
+    }
+}
+
+/// Reference to the "room_bans" table
+#[derive(Iden)]
+enum RoomBans {
+    Table,
+    Id,
+    RoomId,
+    UserId,
+    BannedBy,
+    Reason,
+    CreatedAt,
+}
+
+/// Reference to the "chat_rooms" table for foreign key
+#[derive(Iden)]
+enum ChatRooms {
+    Table,
+    Id,
+}
+
+/// Reference to the "users" table for foreign key
+#[derive(Iden)]
+enum Users {
+    Table,
+    Id,
+}
@@ -0,0 +1,226 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ChatAttachments::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ChatAttachments::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatAttachments::OwnerId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatAttachments::RoomId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    // Set once the attachment is sent in a message
+                    .col(
+                        ColumnDef::new(ChatAttachments::MessageId)
+                            .uuid()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatAttachments::Kind)
+                            .string_len(16)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatAttachments::StorageKey)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatAttachments::MimeType)
+                            .string()
+                            .not_null(),
+                    )
+                    // Size and checksum are unknown for files uploaded before attachments were recorded
+                    .col(
+                        ColumnDef::new(ChatAttachments::SizeBytes)
+                            .big_integer()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatAttachments::Checksum)
+                            .string_len(64)
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatAttachments::Width)
+                            .integer()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatAttachments::Height)
+                            .integer()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatAttachments::DurationMs)
+                            .integer()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatAttachments::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_chat_attachments_owner_id")
+                            .from(ChatAttachments::Table, ChatAttachments::OwnerId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_chat_attachments_room_id")
+                            .from(ChatAttachments::Table, ChatAttachments::RoomId)
+                            .to(ChatRooms::Table, ChatRooms::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_chat_attachments_message_id")
+                            .from(ChatAttachments::Table, ChatAttachments::MessageId)
+                            .to(ChatMessages::Table, ChatMessages::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Messages load their attachments by message id
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_chat_attachments_message_id")
+                    .table(ChatAttachments::Table)
+                    .col(ChatAttachments::MessageId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Record the files linked from existing messages so they stay downloadable by room members.
+        // Their links were embedded in the content as [image](url), [video](url) and [audio](url).
+        let backfill_sql = r#"
+            INSERT INTO chat_attachments (id, owner_id, room_id, message_id, kind, storage_key, mime_type, created_at)
+            SELECT DISTINCT ON (links.storage_key)
+                gen_random_uuid(),
+                links.user_id,
+                links.room_id,
+                links.message_id,
+                links.kind,
+                links.storage_key,
+                CASE links.kind || ':' || lower(substring(links.storage_key from '\.([^.]+)$'))
+                    WHEN 'image:jpg' THEN 'image/jpeg'
+                    WHEN 'image:jpeg' THEN 'image/jpeg'
+                    WHEN 'image:png' THEN 'image/png'
+                    WHEN 'image:gif' THEN 'image/gif'
+                    WHEN 'video:mp4' THEN 'video/mp4'
+                    WHEN 'video:webm' THEN 'video/webm'
+                    WHEN 'video:ogv' THEN 'video/ogg'
+                    WHEN 'video:ogg' THEN 'video/ogg'
+                    WHEN 'audio:mp3' THEN 'audio/mpeg'
+                    WHEN 'audio:wav' THEN 'audio/wav'
+                    WHEN 'audio:ogg' THEN 'audio/ogg'
+                    WHEN 'audio:m4a' THEN 'audio/mp4'
+                    WHEN 'audio:aac' THEN 'audio/aac'
+                    WHEN 'audio:flac' THEN 'audio/flac'
+                    WHEN 'audio:webm' THEN 'audio/webm'
+                    ELSE 'application/octet-stream'
+                END,
+                links.created_at
+            FROM (
+                SELECT
+                    m.id AS message_id,
+                    m.user_id,
+                    m.room_id,
+                    m.created_at,
+                    link[1] AS kind,
+                    CASE link[1]
+                        WHEN 'image' THEN 'chat_images/'
+                        WHEN 'video' THEN 'chat_videos/'
+                        ELSE 'voice_messages/'
+                    END || link[2] AS storage_key
+                FROM chat_messages m
+                CROSS JOIN LATERAL regexp_matches(
+                    m.content,
+                    '\[(image|video|audio)\]\([^)]*/api/chat/(?:image|video|voice)/([^)/?#]+)\)',
+                    'g'
+                ) AS link
+            ) links
+            ORDER BY links.storage_key, links.created_at
+            ON CONFLICT (storage_key) DO NOTHING
+        "#;
+
+        manager.get_connection().execute_unprepared(backfill_sql).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ChatAttachments::Table).to_owned())
+            .await
+    }
+}
+
+/// Reference to the "chat_attachments" table
+#[derive(Iden)]
+enum ChatAttachments {
+    Table,
+    Id,
+    OwnerId,
+    RoomId,
+    MessageId,
+    Kind,
+    StorageKey,
+    MimeType,
+    SizeBytes,
+    Checksum,
+    Width,
+    Height,
+    DurationMs,
+    CreatedAt,
+}
+
+/// Reference to the "chat_messages" table for foreign key
+#[derive(Iden)]
+enum ChatMessages {
+    Table,
+    Id,
+}
+
+/// Reference to the "chat_rooms" table for foreign key
+#[derive(Iden)]
+enum ChatRooms {
+    Table,
+    Id,
+}
+
+/// Reference to the "users" table for foreign key
+#[derive(Iden)]
+enum Users {
+    Table,
+    Id,
+}
@@ -0,0 +1,49 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Placeholder and smaller copies generated for uploaded images; null for other files
+        // and for images uploaded before previews were generated
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ChatAttachments::Table)
+                    .add_column(
+                        ColumnDef::new(ChatAttachments::Blurhash)
+                            .string_len(64)
+                            .null(),
+                    )
+                    .add_column(
+                        ColumnDef::new(ChatAttachments::Previews)
+                            .json_binary()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ChatAttachments::Table)
+                    .drop_column(ChatAttachments::Blurhash)
+                    .drop_column(ChatAttachments::Previews)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Reference to the "chat_attachments" table
+#[derive(Iden)]
+enum ChatAttachments {
+    Table,
+    Blurhash,
+    Previews,
+}
@@ -0,0 +1,61 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ChatBackplanePayloads::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ChatBackplanePayloads::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatBackplanePayloads::Payload)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatBackplanePayloads::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Create an index on created_at so old payloads can be pruned cheaply
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_chat_backplane_payloads_created_at")
+                    .table(ChatBackplanePayloads::Table)
+                    .col(ChatBackplanePayloads::CreatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ChatBackplanePayloads::Table).to_owned())
+            .await
+    }
+}
+
+/// Reference to the "chat_backplane_payloads" table
+#[derive(Iden)]
+enum ChatBackplanePayloads {
+    Table,
+    Id,
+    Payload,
+    CreatedAt,
+}
@@ -1,4 +1,4 @@
-use actix_web::{post, web, HttpResponse, Responder, HttpRequest};
+use actix_web::{post, web, HttpResponse, HttpRequest};
 use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, ColumnTrait};
 use serde::{Deserialize, Serialize};
 use log::{info, error, debug, warn};
@@ -14,8 +14,12 @@ use argon2::{
 
 use crate::models::{User, UserResponseDto, entities::user::Column};
 use crate::models::entities::{TwoFactorAuth, two_factor_auth::Column as TwoFactorColumn};
-use crate::auth::Claims;
-use crate::api::auth::create_session;
+use crate::auth::{Claims, TokenPair, issue_token_pair};
+use crate::auth::tokens::build_auth_cookies;
+use crate::api::auth::create_session_for_request;
+use crate::api::ApiError;
+use crate::config::AppConfig;
+use crate::metrics;
 use totp_rs::{TOTP, Secret, Algorithm};
 
 #[derive(Debug, Deserialize)]
@@ -34,6 +38,8 @@ pub struct VerifyTwoFactorRequest {
 pub struct LoginResponse {
     pub user: UserResponseDto,
     pub token: String,
+    pub refresh_token: String,
+    pub expires_in: i64,
 }
 
 #[derive(Debug, Serialize)]
@@ -43,25 +49,17 @@ pub struct LoginTwoFactorResponse {
     pub temp_token: String,
 }
 
-#[post("/api/auth/login")]
+#[post("/login")]
 pub async fn login(
     db: web::Data<DatabaseConnection>,
-    jwt_secret: web::Data<String>,
+    config: web::Data<AppConfig>,
     login_data: web::Json<LoginRequest>,
     req: HttpRequest,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
     let login_data = login_data.into_inner();
     
     debug!("Login attempt for email: {}", login_data.email);
     
-    // Extract client information from request headers
-    let ip_address = req.connection_info().realip_remote_addr()
-        .unwrap_or("unknown").to_string();
-    
-    let user_agent = req.headers().get("User-Agent")
-        .and_then(|h| h.to_str().ok())
-        .unwrap_or("unknown").to_string();
-    
     // Find user by email
     let user = match User::find()
         .filter(Column::Email.eq(&login_data.email))
@@ -70,15 +68,12 @@ pub async fn login(
             Ok(Some(user)) => user,
             Ok(None) => {
                 warn!("Login attempt for non-existent user: {}", login_data.email);
-                return HttpResponse::Unauthorized().json(
-                    serde_json::json!({"error": "Invalid email or password"})
-                );
+                metrics::record_login_failure("unknown_user");
+                return Err(ApiError::InvalidCredentials);
             }
             Err(e) => {
                 error!("Database error when finding user by email: {:?}", e);
-                return HttpResponse::InternalServerError().json(
-                    serde_json::json!({"error": "Database error when finding user"})
-                );
+                return Err(ApiError::Database(e));
             }
         };
     
@@ -87,9 +82,8 @@ pub async fn login(
         Some(hash) => hash,
         None => {
             warn!("Login attempt for user without password (OAuth-only): {}", login_data.email);
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({"error": "This account doesn't support password login"})
-            );
+            metrics::record_login_failure("no_password");
+            return Err(ApiError::Unauthorized("This account doesn't support password login".to_string()));
         }
     };
     
@@ -98,17 +92,14 @@ pub async fn login(
         Ok(hash) => hash,
         Err(e) => {
             error!("Failed to parse stored password hash: {:?}", e);
-            return HttpResponse::InternalServerError().json(
-                serde_json::json!({"error": "Internal server error"})
-            );
+            return Err(ApiError::Internal("Internal server error".to_string()));
         }
     };
     
     if Argon2::default().verify_password(login_data.password.as_bytes(), &parsed_hash).is_err() {
         warn!("Invalid password for user: {}", login_data.email);
-        return HttpResponse::Unauthorized().json(
-            serde_json::json!({"error": "Invalid email or password"})
-        );
+        metrics::record_login_failure("invalid_password");
+        return Err(ApiError::InvalidCredentials);
     }
     
     // Check if 2FA is enabled for the user
@@ -121,35 +112,11 @@ pub async fn login(
                 // No 2FA record, proceed with normal login
                 debug!("No 2FA record found for user: {}", user.id);
                 
-                // Parse user agent to extract browser, device, and OS information
-                let (browser, device_type, os) = parse_user_agent(&user_agent);
-                
-                // Create a session record
-                match create_session(
-                    db.get_ref(),
-                    user.id,
-                    ip_address.clone(),
-                    user_agent.clone(),
-                    device_type,
-                    browser,
-                    os,
-                ).await {
-                    Ok(_) => {
-                        info!("Session created for user: {}", user.id);
-                    }
-                    Err(e) => {
-                        error!("Failed to create session: {}", e);
-                        // Continue with login even if session creation fails
-                    }
-                }
-                
-                return generate_normal_login_response(user, jwt_secret);
+                return generate_normal_login_response(db.get_ref(), user, &req, &config, "password").await;
             }
             Err(e) => {
                 error!("Database error when finding 2FA record: {:?}", e);
-                return HttpResponse::InternalServerError().json(
-                    serde_json::json!({"error": "Database error when checking 2FA status"})
-                );
+                return Err(ApiError::Database(e));
             }
         };
 
@@ -178,50 +145,27 @@ pub async fn login(
         let temp_token = match encode(
             &Header::default(),
             &claims,
-            &EncodingKey::from_secret(jwt_secret.as_bytes()),
+            &EncodingKey::from_secret(config.jwt_secret.as_bytes()),
         ) {
             Ok(token) => token,
             Err(e) => {
                 error!("Failed to generate temporary JWT token: {:?}", e);
-                return HttpResponse::InternalServerError().json(
-                    serde_json::json!({"error": "Failed to generate temporary authentication token"})
-                );
+                return Err(ApiError::Internal("Failed to generate temporary authentication token".to_string()));
             }
         };
         
         info!("User requires 2FA verification: {}", user.id);
-        HttpResponse::Ok().json(LoginTwoFactorResponse {
+        metrics::record_two_factor_challenge("issued");
+        Ok(HttpResponse::Ok().json(LoginTwoFactorResponse {
             user: user.into(),
             requires_2fa: true,
             temp_token,
-        })
+        }))
     } else {
         // 2FA is not enabled, proceed with normal login
         debug!("2FA is not enabled for user: {}", user.id);
         
-        // Parse user agent to extract browser, device, and OS information
-        let (browser, device_type, os) = parse_user_agent(&user_agent);
-        
-        // Create a session record
-        match create_session(
-            db.get_ref(),
-            user.id,
-            ip_address,
-            user_agent,
-            device_type,
-            browser,
-            os,
-        ).await {
-            Ok(_) => {
-                info!("Session created for user: {}", user.id);
-            }
-            Err(e) => {
-                error!("Failed to create session: {}", e);
-                // Continue with login even if session creation fails
-            }
-        }
-
-        generate_normal_login_response(user, jwt_secret)
+        generate_normal_login_response(db.get_ref(), user, &req, &config, "password").await
     }
 }
 
@@ -264,13 +208,13 @@ fn create_totp(secret: &str) -> Option<TOTP> {
     }
 }
 
-#[post("/api/auth/verify-2fa")]
+#[post("/verify-2fa")]
 pub async fn verify_two_factor(
     db: web::Data<DatabaseConnection>,
-    jwt_secret: web::Data<String>,
+    config: web::Data<AppConfig>,
     verify_req: web::Json<VerifyTwoFactorRequest>,
     req: HttpRequest,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
     let verify_req = verify_req.into_inner();
     
     debug!("Verifying 2FA code");
@@ -283,7 +227,7 @@ pub async fn verify_two_factor(
     // Decode and validate the token
     let token_data = match decode::<Claims>(
         &verify_req.temp_token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
+        &DecodingKey::from_secret(config.jwt_secret.as_bytes()),
         &validation,
     ) {
         Ok(data) => data,
@@ -291,30 +235,15 @@ pub async fn verify_two_factor(
             match e.kind() {
                 ErrorKind::ExpiredSignature => {
                     warn!("Temporary token expired");
-                    return HttpResponse::Unauthorized().json(
-                        serde_json::json!({
-                            "error": "Unauthorized",
-                            "message": "Temporary token expired"
-                        })
-                    );
+                    return Err(ApiError::TokenExpired);
                 }
                 ErrorKind::InvalidSignature => {
                     warn!("Invalid temporary token signature");
-                    return HttpResponse::Unauthorized().json(
-                        serde_json::json!({
-                            "error": "Unauthorized",
-                            "message": "Invalid temporary token signature"
-                        })
-                    );
+                    return Err(ApiError::InvalidToken("Invalid temporary token signature".to_string()));
                 }
                 _ => {
                     warn!("Invalid temporary token: {:?}", e);
-                    return HttpResponse::Unauthorized().json(
-                        serde_json::json!({
-                            "error": "Unauthorized",
-                            "message": "Invalid temporary token"
-                        })
-                    );
+                    return Err(ApiError::InvalidToken("Invalid temporary token".to_string()));
                 }
             }
         }
@@ -327,12 +256,7 @@ pub async fn verify_two_factor(
         Some(id) => id,
         None => {
             warn!("Missing backend_user_id in temporary token");
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({
-                    "error": "Unauthorized",
-                    "message": "Missing user ID in temporary token"
-                })
-            );
+            return Err(ApiError::InvalidToken("Missing user ID in temporary token".to_string()));
         }
     };
     
@@ -340,12 +264,7 @@ pub async fn verify_two_factor(
         Ok(id) => id,
         Err(e) => {
             warn!("Invalid backend_user_id format in temporary token: {:?}", e);
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid user ID format in temporary token"
-                })
-            );
+            return Err(ApiError::InvalidToken("Invalid user ID format in temporary token".to_string()));
         }
     };
     
@@ -356,21 +275,11 @@ pub async fn verify_two_factor(
         Ok(Some(user)) => user,
         Ok(None) => {
             warn!("User not found for ID: {}", user_id);
-            return HttpResponse::NotFound().json(
-                serde_json::json!({
-                    "error": "Not Found",
-                    "message": "User not found"
-                })
-            );
+            return Err(ApiError::NotFound("User not found".to_string()));
         }
         Err(e) => {
             error!("Database error when finding user: {:?}", e);
-            return HttpResponse::InternalServerError().json(
-                serde_json::json!({
-                    "error": "Internal Server Error",
-                    "message": "Database error when finding user"
-                })
-            );
+            return Err(ApiError::Database(e));
         }
     };
     
@@ -382,179 +291,79 @@ pub async fn verify_two_factor(
             Ok(Some(two_factor)) => two_factor,
             Ok(None) => {
                 warn!("2FA record not found for user: {}", user_id);
-                return HttpResponse::NotFound().json(
-                    serde_json::json!({
-                        "error": "Not Found",
-                        "message": "Two-factor authentication not set up"
-                    })
-                );
+                return Err(ApiError::NotFound("Two-factor authentication not set up".to_string()));
             }
             Err(e) => {
                 error!("Database error when finding 2FA record: {:?}", e);
-                return HttpResponse::InternalServerError().json(
-                    serde_json::json!({
-                        "error": "Internal Server Error",
-                        "message": "Database error when finding 2FA record"
-                    })
-                );
+                return Err(ApiError::Database(e));
             }
         };
     
     // Verify the code
     if !verify_totp(&two_factor.secret, &verify_req.code) {
         warn!("Invalid 2FA code for user: {}", user_id);
-        return HttpResponse::BadRequest().json(
-            serde_json::json!({
-                "error": "Bad Request",
-                "message": "Invalid verification code"
-            })
-        );
+        metrics::record_two_factor_challenge("failed");
+        metrics::record_login_failure("invalid_2fa_code");
+        return Err(ApiError::InvalidTwoFactorCode);
     }
     
     // Code is valid, generate a standard JWT token
     info!("2FA verification successful for user: {}", user_id);
+    metrics::record_two_factor_challenge("passed");
     
-    // Extract client information from request headers
-    let ip_address = req.connection_info().realip_remote_addr()
-        .unwrap_or("unknown").to_string();
-    
-    let user_agent = req.headers().get("User-Agent")
-        .and_then(|h| h.to_str().ok())
-        .unwrap_or("unknown").to_string();
-    
-    // Parse user agent to extract browser, device, and OS information
-    let (browser, device_type, os) = parse_user_agent(&user_agent);
-    
-    // Create a session record
-    match create_session(
-        db.get_ref(),
-        user_id,
-        ip_address,
-        user_agent,
-        device_type,
-        browser,
-        os,
-    ).await {
-        Ok(_) => {
-            info!("Session created for user: {}", user_id);
-        }
-        Err(e) => {
-            error!("Failed to create session: {}", e);
-            // Continue with login even if session creation fails
-        }
-    }
-    
-    generate_normal_login_response(user, jwt_secret)
+    generate_normal_login_response(db.get_ref(), user, &req, &config, "two_factor").await
 }
 
-// Helper function to parse User-Agent string
-fn parse_user_agent(user_agent: &str) -> (String, String, String) {
-    // Default values
-    let mut browser = "Unknown".to_string();
-    let mut device_type = "Unknown".to_string();
-    let mut os = "Unknown".to_string();
-    
-    // Very basic parsing - in a real app, you'd use a proper user-agent parsing library
-    let ua_lower = user_agent.to_lowercase();
-    
-    // Detect browser
-    if ua_lower.contains("firefox") {
-        browser = "Firefox".to_string();
-    } else if ua_lower.contains("chrome") && !ua_lower.contains("edg") {
-        browser = "Chrome".to_string();
-    } else if ua_lower.contains("safari") && !ua_lower.contains("chrome") {
-        browser = "Safari".to_string();
-    } else if ua_lower.contains("edg") {
-        browser = "Edge".to_string();
-    } else if ua_lower.contains("opera") || ua_lower.contains("opr") {
-        browser = "Opera".to_string();
-    }
-    
-    // Detect OS
-    if ua_lower.contains("windows") {
-        os = "Windows".to_string();
-    } else if ua_lower.contains("mac os") {
-        os = "macOS".to_string();
-    } else if ua_lower.contains("android") {
-        os = "Android".to_string();
-    } else if ua_lower.contains("ios") || ua_lower.contains("iphone") || ua_lower.contains("ipad") {
-        os = "iOS".to_string();
-    } else if ua_lower.contains("linux") {
-        os = "Linux".to_string();
-    }
-    
-    // Detect device type
-    if ua_lower.contains("mobile") || ua_lower.contains("android") || ua_lower.contains("iphone") {
-        device_type = "Mobile".to_string();
-    } else if ua_lower.contains("tablet") || ua_lower.contains("ipad") {
-        device_type = "Tablet".to_string();
-    } else {
-        device_type = "Desktop".to_string();
-    }
-    
-    (browser, device_type, os)
-}
-
-// Helper function to generate a normal login response with a standard JWT token
-fn generate_normal_login_response(
+// Helper function to start a session and respond with its access/refresh token pair
+// `method` labels the login in the metrics ("password" or "two_factor")
+pub(crate) async fn generate_normal_login_response(
+    db: &DatabaseConnection,
     user: crate::models::entities::user::Model,
-    jwt_secret: web::Data<String>,
-) -> HttpResponse {
-    // Generate JWT token
-    let now = Utc::now();
-    let exp = (now + Duration::hours(24)).timestamp() as usize; // 24 hour expiration
-    let iat = now.timestamp() as usize;
-    
-    let claims = Claims {
-        id: user.id.to_string(),
-        sub: user.email.clone(),
-        email: user.email.clone(),
-        name: user.name.clone(),
-        backend_user_id: Some(user.id.to_string()),
-        user_role: Some(user.role.clone()),
-        exp,
-        iat,
-        jti: Uuid::new_v4().to_string(),
-        ..Default::default()
+    req: &HttpRequest,
+    config: &AppConfig,
+    method: &str,
+) -> Result<HttpResponse, ApiError> {
+    // Every login gets its own session; the refresh token is bound to it
+    let session = match create_session_for_request(db, user.id, req).await {
+        Ok(session) => session,
+        Err(e) => {
+            error!("Failed to create session: {:?}", e);
+            return Err(ApiError::Database(e));
+        }
     };
     
-    let token = match encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(jwt_secret.as_bytes()),
-    ) {
-        Ok(token) => token,
+    info!("Session created for user: {}", user.id);
+    
+    let pair = match issue_token_pair(db, &user, session.id, &config.jwt_secret).await {
+        Ok(pair) => pair,
         Err(e) => {
-            error!("Failed to generate JWT token: {:?}", e);
-            return HttpResponse::InternalServerError().json(
-                serde_json::json!({"error": "Failed to generate authentication token"})
-            );
+            error!("Failed to issue token pair: {:?}", e);
+            return Err(e.into());
         }
     };
     
     info!("User logged in successfully: {}", user.id);
+    metrics::record_login(method);
     
-    // Set secure cookie with the JWT token
-    // For localhost development, we use SameSite=Lax and Secure=false
-    // In production, these should be SameSite=Strict and Secure=true
-    let is_production = std::env::var("ENVIRONMENT").unwrap_or_else(|_| "development".to_string()) == "production";
-    
-    // Convert chrono Duration to seconds for cookie max_age
-    let max_age_seconds = Duration::hours(24).num_seconds() as i64;
-    
-    let cookie = actix_web::cookie::Cookie::build("auth_token", token.clone())
-        .path("/")
-        .http_only(true)
-        .same_site(actix_web::cookie::SameSite::Lax)
-        .secure(is_production)
-        .max_age(actix_web::cookie::time::Duration::seconds(max_age_seconds))
-        .finish();
+    Ok(token_pair_response(user, pair, config))
+}
+
+// Helper function to return a token pair both as cookies and as JSON
+pub(crate) fn token_pair_response(
+    user: crate::models::entities::user::Model,
+    pair: TokenPair,
+    config: &AppConfig,
+) -> HttpResponse {
+    let (access_cookie, refresh_cookie) = build_auth_cookies(&pair, config.is_production());
     
-    // Return both cookie and JSON response for backward compatibility
+    // Return both cookies and JSON response for backward compatibility
     HttpResponse::Ok()
-        .cookie(cookie)
+        .cookie(access_cookie)
+        .cookie(refresh_cookie)
         .json(LoginResponse {
             user: user.into(),
-            token,
+            token: pair.access_token,
+            refresh_token: pair.refresh_token,
+            expires_in: pair.expires_in,
         })
 }
\ No newline at end of file
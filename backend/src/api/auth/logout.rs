@@ -1,21 +1,12 @@
 use actix_web::{post, get, web, HttpResponse, Responder, HttpRequest, cookie::Cookie};
 use serde::{Deserialize, Serialize};
 use log::{info, debug, error, warn};
-use std::sync::Mutex;
-use std::collections::HashSet;
-use lazy_static::lazy_static;
-use chrono::{DateTime, Utc};
-use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, ColumnTrait, Set, ActiveModelTrait};
+use sea_orm::DatabaseConnection;
 use uuid::Uuid;
 
-use crate::auth::{AuthUser, Claims};
-use crate::models::entities::{UserSession, UserSessionActiveModel};
-use jsonwebtoken::{decode, DecodingKey, Validation};
-
-// In a production environment, this should be replaced with a Redis cache or similar
-lazy_static! {
-    static ref TOKEN_BLACKLIST: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
-}
+use crate::auth::{AuthUser, Claims, JwtAuth, revoke_token, revoke_session_family};
+use crate::auth::tokens::{clear_refresh_cookie, find_refresh_token_session, REFRESH_TOKEN_COOKIE};
+use crate::config::AppConfig;
 
 #[derive(Debug, Deserialize)]
 pub struct LogoutRequest {
@@ -27,10 +18,11 @@ pub struct LogoutResponse {
     pub message: String,
 }
 
-#[post("/api/auth/logout")]
+#[post("/logout")]
 pub async fn logout(
     db: web::Data<DatabaseConnection>,
-    auth_user: web::ReqData<AuthUser>,
+    config: web::Data<AppConfig>,
+    auth_user: AuthUser,
     logout_data: web::Json<LogoutRequest>,
 ) -> impl Responder {
     let logout_data = logout_data.into_inner();
@@ -38,40 +30,19 @@ pub async fn logout(
     
     debug!("Logging out user: {}", user_id);
     
-    // Add token to blacklist
-    let mut blacklist = TOKEN_BLACKLIST.lock().unwrap();
-    blacklist.insert(logout_data.token);
-    
-    // Find the most recent active session for the user
-    let sessions = UserSession::find()
-        .filter(crate::models::entities::user_session::Column::UserId.eq(user_id))
-        .filter(crate::models::entities::user_session::Column::IsActive.eq(true))
-        .all(db.get_ref())
-        .await.unwrap_or_else(|e| {
-        error!("Database error when finding sessions: {:?}", e);
-        // Continue with logout even if session termination fails
-        vec![]
-    });
-    
-    // Sort sessions by last_active_at (most recent first)
-    let mut sorted_sessions = sessions;
-    sorted_sessions.sort_by(|a, b| b.last_active_at.cmp(&a.last_active_at));
-    
-    // Terminate the most recent session (current session)
-    if !sorted_sessions.is_empty() {
-        let current_session = &sorted_sessions[0];
-        let mut session_active: UserSessionActiveModel = current_session.clone().into();
-        session_active.is_active = Set(false);
-        session_active.updated_at = Set(Utc::now());
-        
-        match session_active.update(db.get_ref()).await {
-            Ok(_) => {
-                info!("Session terminated for user: {}", user_id);
-            }
-            Err(e) => {
-                error!("Database error when terminating session: {:?}", e);
-                // Continue with logout even if session termination fails
-            }
+    // Revoke the token until it expires
+    if let Some(claims) = decode_claims(&logout_data.token, &config.jwt_secret) {
+        if let Err(e) = revoke_token(db.get_ref(), &claims).await {
+            error!("Database error when revoking token: {:?}", e);
+        }
+    }
+    
+    // End the session this request belongs to
+    match revoke_session_family(db.get_ref(), auth_user.session_id).await {
+        Ok(_) => info!("Session terminated for user: {}", user_id),
+        Err(e) => {
+            error!("Database error when terminating session: {:?}", e);
+            // Continue with logout even if session termination fails
         }
     }
     
@@ -93,16 +64,17 @@ pub async fn logout(
     HttpResponse::Ok()
         .cookie(remove_auth_token)
         .cookie(remove_auth_user)
+        .cookie(clear_refresh_cookie())
         .json(LogoutResponse {
             message: "Logged out successfully".to_string(),
         })
 }
 
-#[get("/api/auth/logout")]
+#[get("/logout")]
 pub async fn logout_get(
     db: web::Data<DatabaseConnection>,
     req: HttpRequest,
-    jwt_secret: web::Data<String>,
+    config: web::Data<AppConfig>,
 ) -> impl Responder {
     debug!("GET request to logout endpoint");
     
@@ -116,14 +88,23 @@ pub async fn logout_get(
         }
     };
     
-    // Add token to blacklist
-    {
-        let mut blacklist = TOKEN_BLACKLIST.lock().unwrap();
-        blacklist.insert(token.clone());
+    // Decode the token (an invalid or expired token has nothing left to revoke)
+    let claims = match decode_claims(&token, &config.jwt_secret) {
+        Some(claims) => claims,
+        None => {
+            warn!("Could not decode token for logout");
+            // Even if we can't decode the token, we should still clear cookies
+            return create_logout_response();
+        }
+    };
+    
+    // Revoke the token until it expires
+    if let Err(e) = revoke_token(db.get_ref(), &claims).await {
+        error!("Database error when revoking token: {:?}", e);
     }
     
     // Try to extract user ID from token
-    let user_id = match extract_user_id_from_token(&token, jwt_secret.as_ref()) {
+    let user_id = match claims.backend_user_id.as_deref().and_then(|id| Uuid::parse_str(id).ok()) {
         Some(id) => id,
         None => {
             warn!("Could not extract user ID from token");
@@ -134,38 +115,9 @@ pub async fn logout_get(
     
     debug!("Logging out user: {}", user_id);
     
-    // Find the most recent active session for the user
-    let sessions = UserSession::find()
-        .filter(crate::models::entities::user_session::Column::UserId.eq(user_id))
-        .filter(crate::models::entities::user_session::Column::IsActive.eq(true))
-        .all(db.get_ref())
-        .await.unwrap_or_else(|e| {
-        error!("Database error when finding sessions: {:?}", e);
-        // Continue with logout even if session termination fails
-        vec![]
-    });
-    
-    // Sort sessions by last_active_at (most recent first)
-    let mut sorted_sessions = sessions;
-    sorted_sessions.sort_by(|a, b| b.last_active_at.cmp(&a.last_active_at));
-    
-    // Terminate the most recent session (current session)
-    if !sorted_sessions.is_empty() {
-        let current_session = &sorted_sessions[0];
-        let mut session_active: UserSessionActiveModel = current_session.clone().into();
-        session_active.is_active = Set(false);
-        session_active.updated_at = Set(Utc::now());
-        
-        match session_active.update(db.get_ref()).await {
-            Ok(_) => {
-                info!("Session terminated for user: {}", user_id);
-            }
-            Err(e) => {
-                error!("Database error when terminating session: {:?}", e);
-                // Continue with logout even if session termination fails
-            }
-        }
-    }
+    // End the session this request belongs to
+    let session_id = claims.sid.as_deref().and_then(|sid| Uuid::parse_str(sid).ok());
+    terminate_current_session(db.get_ref(), &req, user_id, session_id).await;
     
     info!("User logged out successfully: {}", user_id);
     
@@ -190,62 +142,57 @@ fn create_logout_response() -> HttpResponse {
     HttpResponse::Ok()
         .cookie(remove_auth_token)
         .cookie(remove_auth_user)
+        .cookie(clear_refresh_cookie())
         .json(LogoutResponse {
             message: "Logged out successfully".to_string(),
         })
 }
 
-// Helper function to extract user ID from token
-fn extract_user_id_from_token(token: &str, jwt_secret: &str) -> Option<Uuid> {
-    // Configure validation
-    let mut validation = Validation::default();
-    validation.validate_exp = true;
-    validation.leeway = 0; // No leeway for expiration time
-    
-    // Decode and validate the token
-    let token_data = match decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
-        &validation,
-    ) {
-        Ok(data) => data,
+// Helper function to decode and validate a token
+fn decode_claims(token: &str, jwt_secret: &str) -> Option<Claims> {
+    match JwtAuth::validate_token(token, jwt_secret) {
+        Ok(claims) => Some(claims),
         Err(e) => {
             warn!("Invalid token: {:?}", e);
-            return None;
+            None
         }
+    }
+}
+
+// Helper function to terminate the session the request belongs to
+async fn terminate_current_session(
+    db: &DatabaseConnection,
+    req: &HttpRequest,
+    user_id: Uuid,
+    session_id: Option<Uuid>,
+) {
+    // Fall back to the refresh token when the access token names no session
+    let session_id = match session_id {
+        Some(id) => Some(id),
+        None => match req.cookie(REFRESH_TOKEN_COOKIE) {
+            Some(cookie) => find_refresh_token_session(db, cookie.value(), user_id)
+                .await
+                .unwrap_or_else(|e| {
+                    error!("Database error when finding refresh token: {:?}", e);
+                    None
+                }),
+            None => None,
+        },
     };
     
-    let claims = token_data.claims;
-    
-    // Ensure backend_user_id is present
-    let backend_user_id = match claims.backend_user_id {
+    let session_id = match session_id {
         Some(id) => id,
         None => {
-            warn!("Missing backend_user_id in token");
-            return None;
+            warn!("Could not determine the session to terminate for user: {}", user_id);
+            return;
         }
     };
     
-    // Parse the user ID
-    match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => Some(id),
+    match revoke_session_family(db, session_id).await {
+        Ok(_) => info!("Session terminated for user: {}", user_id),
         Err(e) => {
-            warn!("Invalid backend_user_id format in token: {:?}", e);
-            None
+            error!("Database error when terminating session: {:?}", e);
+            // Continue with logout even if session termination fails
         }
     }
-}
-
-// Helper function to check if a token is blacklisted
-pub fn is_token_blacklisted(token: &str) -> bool {
-    let blacklist = TOKEN_BLACKLIST.lock().unwrap();
-    blacklist.contains(token)
-}
-
-// In a real implementation, you would also want to periodically clean up the blacklist
-// to remove expired tokens. This could be done with a background task.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct BlacklistedToken {
-    pub token: String,
-    pub expiry: DateTime<Utc>,
 }
\ No newline at end of file
@@ -1,11 +1,10 @@
-use actix_web::{get, web, HttpResponse, Responder, HttpRequest};
+use actix_web::{get, web, HttpResponse, HttpRequest};
 use oauth2::{
     basic::BasicClient, AuthUrl, ClientId, ClientSecret, CsrfToken, RedirectUrl, Scope,
     TokenUrl, AuthorizationCode, TokenResponse,
 };
 use oauth2::reqwest::async_http_client;
 use serde::{Deserialize, Serialize};
-use std::env;
 use log::{debug, error, info};
 use sea_orm::{DatabaseConnection, EntityTrait, Set, ActiveModelTrait, QueryFilter, ColumnTrait};
 use uuid::Uuid;
@@ -14,7 +13,12 @@ use chrono::{Utc, Duration};
 use std::collections::HashMap;
 
 use crate::models::{User, UserActiveModel, entities::user::Column};
-use crate::auth::Claims;
+use crate::auth::{Claims, issue_token_pair};
+use crate::auth::tokens::build_auth_cookies;
+use crate::api::auth::create_session_for_request;
+use crate::api::ApiError;
+use crate::config::AppConfig;
+use crate::metrics;
 
 // OAuth provider enum
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
@@ -76,33 +80,9 @@ fn get_and_remove_oauth_state(csrf_token: &str) -> Option<OAuthProvider> {
 }
 
 // Create OAuth clients
-fn create_google_oauth_client() -> Result<BasicClient, String> {
-    let google_client_id = match env::var("GOOGLE_CLIENT_ID") {
-        Ok(id) => {
-            if id == "your-google-client-id" || id.is_empty() {
-                return Err("GOOGLE_CLIENT_ID is set to a placeholder value. Please replace it with your actual Google Client ID in the .env file.".to_string());
-            }
-            id
-        },
-        Err(_) => {
-            return Err("Missing GOOGLE_CLIENT_ID environment variable. Please set it in your .env file.".to_string());
-        }
-    };
-
-    let google_client_secret = match env::var("GOOGLE_CLIENT_SECRET") {
-        Ok(secret) => {
-            if secret == "your-google-client-secret" || secret.is_empty() {
-                return Err("GOOGLE_CLIENT_SECRET is set to a placeholder value. Please replace it with your actual Google Client Secret in the .env file.".to_string());
-            }
-            secret
-        },
-        Err(_) => {
-            return Err("Missing GOOGLE_CLIENT_SECRET environment variable. Please set it in your .env file.".to_string());
-        }
-    };
-
-    let redirect_url = env::var("OAUTH_REDIRECT_URL")
-        .unwrap_or_else(|_| "http://localhost:8080/api/auth/oauth/callback".to_string());
+fn create_google_oauth_client(config: &AppConfig) -> Result<BasicClient, String> {
+    let google = config.oauth.google.as_ref()
+        .ok_or_else(|| "Google OAuth is not configured. Please set GOOGLE_CLIENT_ID and GOOGLE_CLIENT_SECRET.".to_string())?;
 
     let auth_url = AuthUrl::new("https://accounts.google.com/o/oauth2/v2/auth".to_string())
         .map_err(|_| "Invalid Google authorization endpoint URL".to_string())?;
@@ -110,45 +90,21 @@ fn create_google_oauth_client() -> Result<BasicClient, String> {
     let token_url = TokenUrl::new("https://oauth2.googleapis.com/token".to_string())
         .map_err(|_| "Invalid Google token endpoint URL".to_string())?;
 
-    let redirect_uri = RedirectUrl::new(redirect_url)
+    let redirect_uri = RedirectUrl::new(config.oauth.redirect_url.clone())
         .map_err(|_| "Invalid redirect URL".to_string())?;
 
     Ok(BasicClient::new(
-        ClientId::new(google_client_id),
-        Some(ClientSecret::new(google_client_secret)),
+        ClientId::new(google.client_id.clone()),
+        Some(ClientSecret::new(google.client_secret.clone())),
         auth_url,
         Some(token_url)
     )
         .set_redirect_uri(redirect_uri))
 }
 
-fn create_github_oauth_client() -> Result<BasicClient, String> {
-    let github_client_id = match env::var("GITHUB_CLIENT_ID") {
-        Ok(id) => {
-            if id == "your-github-client-id" || id.is_empty() {
-                return Err("GITHUB_CLIENT_ID is set to a placeholder value. Please replace it with your actual GitHub Client ID in the .env file.".to_string());
-            }
-            id
-        },
-        Err(_) => {
-            return Err("Missing GITHUB_CLIENT_ID environment variable. Please set it in your .env file.".to_string());
-        }
-    };
-
-    let github_client_secret = match env::var("GITHUB_CLIENT_SECRET") {
-        Ok(secret) => {
-            if secret == "your-github-client-secret" || secret.is_empty() {
-                return Err("GITHUB_CLIENT_SECRET is set to a placeholder value. Please replace it with your actual GitHub Client Secret in the .env file.".to_string());
-            }
-            secret
-        },
-        Err(_) => {
-            return Err("Missing GITHUB_CLIENT_SECRET environment variable. Please set it in your .env file.".to_string());
-        }
-    };
-
-    let redirect_url = env::var("OAUTH_REDIRECT_URL")
-        .unwrap_or_else(|_| "http://localhost:8080/api/auth/oauth/callback".to_string());
+fn create_github_oauth_client(config: &AppConfig) -> Result<BasicClient, String> {
+    let github = config.oauth.github.as_ref()
+        .ok_or_else(|| "GitHub OAuth is not configured. Please set GITHUB_CLIENT_ID and GITHUB_CLIENT_SECRET.".to_string())?;
 
     let auth_url = AuthUrl::new("https://github.com/login/oauth/authorize".to_string())
         .map_err(|_| "Invalid GitHub authorization endpoint URL".to_string())?;
@@ -156,12 +112,12 @@ fn create_github_oauth_client() -> Result<BasicClient, String> {
     let token_url = TokenUrl::new("https://github.com/login/oauth/access_token".to_string())
         .map_err(|_| "Invalid GitHub token endpoint URL".to_string())?;
 
-    let redirect_uri = RedirectUrl::new(redirect_url)
+    let redirect_uri = RedirectUrl::new(config.oauth.redirect_url.clone())
         .map_err(|_| "Invalid redirect URL".to_string())?;
 
     Ok(BasicClient::new(
-        ClientId::new(github_client_id),
-        Some(ClientSecret::new(github_client_secret)),
+        ClientId::new(github.client_id.clone()),
+        Some(ClientSecret::new(github.client_secret.clone())),
         auth_url,
         Some(token_url)
     )
@@ -169,21 +125,15 @@ fn create_github_oauth_client() -> Result<BasicClient, String> {
 }
 
 // OAuth login endpoint for Google
-#[get("/api/auth/oauth/google")]
-pub async fn oauth_google_login(_req: HttpRequest) -> impl Responder {
+#[get("/oauth/google")]
+pub async fn oauth_google_login(config: web::Data<AppConfig>) -> Result<HttpResponse, ApiError> {
     debug!("Starting Google OAuth login flow");
 
-    let client = match create_google_oauth_client() {
+    let client = match create_google_oauth_client(&config) {
         Ok(client) => client,
         Err(error) => {
             error!("Failed to create Google OAuth client: {}", error);
-            return HttpResponse::InternalServerError().json(
-                serde_json::json!({
-                    "error": "OAuth Configuration Error",
-                    "message": error,
-                    "details": "Please check your .env file and ensure GOOGLE_CLIENT_ID and GOOGLE_CLIENT_SECRET are properly configured."
-                })
-            );
+            return Err(ApiError::Internal(format!("OAuth configuration error: {}", error)));
         }
     };
 
@@ -197,27 +147,21 @@ pub async fn oauth_google_login(_req: HttpRequest) -> impl Responder {
     store_oauth_state(csrf_token.secret(), OAuthProvider::Google);
 
     debug!("Redirecting to Google OAuth authorization URL with state: {}", csrf_token.secret());
-    HttpResponse::Found()
+    Ok(HttpResponse::Found()
         .append_header(("Location", auth_url.to_string()))
-        .finish()
+        .finish())
 }
 
 // OAuth login endpoint for GitHub
-#[get("/api/auth/oauth/github")]
-pub async fn oauth_github_login(_req: HttpRequest) -> impl Responder {
+#[get("/oauth/github")]
+pub async fn oauth_github_login(config: web::Data<AppConfig>) -> Result<HttpResponse, ApiError> {
     debug!("Starting GitHub OAuth login flow");
 
-    let client = match create_github_oauth_client() {
+    let client = match create_github_oauth_client(&config) {
         Ok(client) => client,
         Err(error) => {
             error!("Failed to create GitHub OAuth client: {}", error);
-            return HttpResponse::InternalServerError().json(
-                serde_json::json!({
-                    "error": "OAuth Configuration Error",
-                    "message": error,
-                    "details": "Please check your .env file and ensure GITHUB_CLIENT_ID and GITHUB_CLIENT_SECRET are properly configured."
-                })
-            );
+            return Err(ApiError::Internal(format!("OAuth configuration error: {}", error)));
         }
     };
 
@@ -230,77 +174,56 @@ pub async fn oauth_github_login(_req: HttpRequest) -> impl Responder {
     store_oauth_state(csrf_token.secret(), OAuthProvider::GitHub);
 
     debug!("Redirecting to GitHub OAuth authorization URL with state: {}", csrf_token.secret());
-    HttpResponse::Found()
+    Ok(HttpResponse::Found()
         .append_header(("Location", auth_url.to_string()))
-        .finish()
+        .finish())
 }
 
 // Helper function to generate OAuth success response
-fn generate_oauth_success_response(
+async fn generate_oauth_success_response(
+    db: &DatabaseConnection,
     user: crate::models::entities::user::Model,
-    jwt_secret: web::Data<String>,
-) -> HttpResponse {
-    let now = Utc::now();
-    let exp = (now + Duration::hours(24)).timestamp() as usize;
-    let iat = now.timestamp() as usize;
-
-    let claims = Claims {
-        id: user.id.to_string(),
-        sub: user.email.clone(),
-        email: user.email.clone(),
-        name: user.name.clone(),
-        backend_user_id: Some(user.id.to_string()),
-        user_role: Some(user.role.clone()),
-        exp,
-        iat,
-        jti: Uuid::new_v4().to_string(),
-        ..Default::default()
+    req: &HttpRequest,
+    config: &AppConfig,
+) -> Result<HttpResponse, ApiError> {
+    // OAuth logins get a session and token pair just like password logins
+    let session = match create_session_for_request(db, user.id, req).await {
+        Ok(session) => session,
+        Err(e) => {
+            error!("Failed to create session: {:?}", e);
+            return Err(ApiError::Database(e));
+        }
     };
 
-    let token = match encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(jwt_secret.as_bytes()),
-    ) {
-        Ok(token) => token,
+    let pair = match issue_token_pair(db, &user, session.id, &config.jwt_secret).await {
+        Ok(pair) => pair,
         Err(e) => {
-            error!("Failed to generate JWT token: {:?}", e);
-            return HttpResponse::InternalServerError().json(
-                serde_json::json!({"error": "Failed to generate authentication token"})
-            );
+            error!("Failed to issue token pair: {:?}", e);
+            return Err(e.into());
         }
     };
 
-    let is_production = env::var("ENVIRONMENT").unwrap_or_else(|_| "development".to_string()) == "production";
-    let max_age_seconds = Duration::hours(24).num_seconds();
+    let (access_cookie, refresh_cookie) = build_auth_cookies(&pair, config.is_production());
 
-    let cookie = actix_web::cookie::Cookie::build("auth_token", token)
-        .path("/")
-        .http_only(true)
-        .same_site(actix_web::cookie::SameSite::Lax)
-        .secure(is_production)
-        .max_age(actix_web::cookie::time::Duration::seconds(max_age_seconds))
-        .finish();
-
-    let frontend_url = env::var("FRONTEND_URL")
-        .unwrap_or_else(|_| "http://localhost:3000".to_string());
-
-    let redirect_url = format!("{}/dashboard", frontend_url);
+    let redirect_url = format!("{}/dashboard", config.frontend_url);
 
     debug!("OAuth authentication successful, redirecting to frontend with secure cookie");
-    HttpResponse::Found()
-        .cookie(cookie)
+    metrics::record_login("oauth");
+    Ok(HttpResponse::Found()
+        .cookie(access_cookie)
+        .cookie(refresh_cookie)
         .append_header(("Location", redirect_url))
-        .finish()
+        .finish())
 }
 
 // OAuth callback endpoint
-#[get("/api/auth/oauth/callback")]
+#[get("/oauth/callback")]
 pub async fn oauth_callback(
     query: web::Query<OAuthCallback>,
     db: web::Data<DatabaseConnection>,
-    jwt_secret: web::Data<String>,
-) -> impl Responder {
+    config: web::Data<AppConfig>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
     debug!("Received OAuth callback with state: {}", query.state);
 
     // Retrieve and validate the state
@@ -308,9 +231,8 @@ pub async fn oauth_callback(
         Some(provider) => provider,
         None => {
             error!("Invalid or expired OAuth state: {}", query.state);
-            return HttpResponse::BadRequest().json(
-                serde_json::json!({"error": "Invalid or expired authentication state. Please try again."})
-            );
+            metrics::record_login_failure("oauth_invalid_state");
+            return Err(ApiError::BadRequest("Invalid or expired authentication state. Please try again.".to_string()));
         }
     };
 
@@ -318,10 +240,10 @@ pub async fn oauth_callback(
 
     let user_info = match provider {
         OAuthProvider::Google => {
-            get_google_user_info(&query.code).await
+            get_google_user_info(&config, &query.code).await
         },
         OAuthProvider::GitHub => {
-            get_github_user_info(&query.code).await
+            get_github_user_info(&config, &query.code).await
         }
     };
 
@@ -329,9 +251,8 @@ pub async fn oauth_callback(
         Ok(info) => info,
         Err(e) => {
             error!("Failed to get user info: {:?}", e);
-            return HttpResponse::InternalServerError().json(
-                serde_json::json!({"error": "Failed to get user information from OAuth provider"})
-            );
+            metrics::record_login_failure("oauth_provider_error");
+            return Err(ApiError::Internal("Failed to get user information from OAuth provider".to_string()));
         }
     };
 
@@ -339,9 +260,7 @@ pub async fn oauth_callback(
         Ok(user) => user,
         Err(e) => {
             error!("Failed to find or create user: {:?}", e);
-            return HttpResponse::InternalServerError().json(
-                serde_json::json!({"error": "Failed to process user information"})
-            );
+            return Err(ApiError::Internal("Failed to process user information".to_string()));
         }
     };
 
@@ -355,13 +274,11 @@ pub async fn oauth_callback(
         Ok(Some(two_factor)) => two_factor,
         Ok(None) => {
             debug!("No 2FA record found for OAuth user: {}", user.id);
-            return generate_oauth_success_response(user, jwt_secret);
+            return generate_oauth_success_response(db.get_ref(), user, &req, &config).await;
         }
         Err(e) => {
             error!("Database error when finding 2FA record: {:?}", e);
-            return HttpResponse::InternalServerError().json(
-                serde_json::json!({"error": "Database error when checking 2FA status"})
-            );
+            return Err(ApiError::Database(e));
         }
     };
 
@@ -388,36 +305,32 @@ pub async fn oauth_callback(
         let temp_token = match encode(
             &Header::default(),
             &claims,
-            &EncodingKey::from_secret(jwt_secret.as_bytes()),
+            &EncodingKey::from_secret(config.jwt_secret.as_bytes()),
         ) {
             Ok(token) => token,
             Err(e) => {
                 error!("Failed to generate temporary JWT token: {:?}", e);
-                return HttpResponse::InternalServerError().json(
-                    serde_json::json!({"error": "Failed to generate temporary authentication token"})
-                );
+                return Err(ApiError::Internal("Failed to generate temporary authentication token".to_string()));
             }
         };
 
-        let frontend_url = env::var("FRONTEND_URL")
-            .unwrap_or_else(|_| "http://localhost:3000".to_string());
-
-        let redirect_url = format!("{}/oauth/callback?token={}&requires2fa=true", frontend_url, temp_token);
+        let redirect_url = format!("{}/oauth/callback?token={}&requires2fa=true", config.frontend_url, temp_token);
 
         debug!("OAuth user requires 2FA verification: {}, redirecting to 2FA page", user.id);
-        HttpResponse::Found()
+        metrics::record_two_factor_challenge("issued");
+        Ok(HttpResponse::Found()
             .append_header(("Location", redirect_url))
-            .finish()
+            .finish())
     } else {
         debug!("2FA is not enabled for OAuth user: {}", user.id);
-        generate_oauth_success_response(user, jwt_secret)
+        generate_oauth_success_response(db.get_ref(), user, &req, &config).await
     }
 }
 
-async fn get_google_user_info(code: &str) -> Result<OAuthUserInfo, String> {
+async fn get_google_user_info(config: &AppConfig, code: &str) -> Result<OAuthUserInfo, String> {
     debug!("Getting user info from Google");
 
-    let client = create_google_oauth_client()
+    let client = create_google_oauth_client(config)
         .map_err(|error| format!("OAuth Configuration Error: {}", error))?;
 
     let token_result = client
@@ -461,10 +374,10 @@ async fn get_google_user_info(code: &str) -> Result<OAuthUserInfo, String> {
     })
 }
 
-async fn get_github_user_info(code: &str) -> Result<OAuthUserInfo, String> {
+async fn get_github_user_info(config: &AppConfig, code: &str) -> Result<OAuthUserInfo, String> {
     debug!("Getting user info from GitHub");
 
-    let client = create_github_oauth_client()
+    let client = create_github_oauth_client(config)
         .map_err(|error| format!("OAuth Configuration Error: {}", error))?;
 
     let token_result = client
@@ -598,6 +511,7 @@ async fn find_or_create_user(
                 created_at: Set(Utc::now()),
                 is_active: Set(true),
                 updated_at: Set(Utc::now()),
+                last_seen_at: Set(None),
             };
 
             let created_user = new_user.insert(db)
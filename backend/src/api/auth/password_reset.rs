@@ -1,5 +1,5 @@
-use actix_web::{post, web, HttpResponse, Responder};
-use sea_orm::{DatabaseConnection, EntityTrait, ActiveValue, ActiveModelTrait, QueryFilter, ColumnTrait, Set};
+use actix_web::{post, web, HttpResponse};
+use sea_orm::{DatabaseConnection, EntityTrait, ActiveModelTrait, QueryFilter, ColumnTrait, Set};
 use serde::{Deserialize, Serialize};
 use log::{info, error, debug, warn};
 use chrono::{Utc, Duration};
@@ -22,6 +22,8 @@ use rand::distr::Alphanumeric;
 use std::fs;
 use std::path::Path;
 use crate::models::{User, UserActiveModel, entities::user::Column};
+use crate::api::ApiError;
+use crate::config::AppConfig;
 
 // Request to initiate password reset
 #[derive(Debug, Deserialize)]
@@ -58,53 +60,27 @@ fn generate_reset_token() -> String {
 }
 
 // Send password reset email
-async fn send_password_reset_email(email: &str, token: &str) -> Result<(), String> {
-    // Get SMTP settings from environment variables with better error handling
-    let smtp_host = std::env::var("SMTP_HOST").unwrap_or_else(|_| {
-        warn!("SMTP_HOST not set, using default: smtp.gmail.com");
-        "smtp.gmail.com".to_string()
-    });
+async fn send_password_reset_email(config: &AppConfig, email: &str, token: &str) -> Result<(), String> {
+    let smtp = &config.smtp;
     
-    let smtp_port = match std::env::var("SMTP_PORT") {
-        Ok(port) => {
-            // Validate that the port is a valid number
-            match port.parse::<u16>() {
-                Ok(_) => port,
-                Err(_) => {
-                    warn!("SMTP_PORT is not a valid port number, using default: 587");
-                    "587".to_string()
-                }
-            }
-        },
-        Err(_) => {
-            warn!("SMTP_PORT not set, using default: 587");
-            "587".to_string()
-        }
-    };
-    
-    let smtp_username = match std::env::var("SMTP_USERNAME") {
-        Ok(username) => username,
-        Err(_) => {
-            error!("SMTP_USERNAME environment variable is not set");
+    let smtp_username = match &smtp.username {
+        Some(username) => username.clone(),
+        None => {
+            error!("SMTP_USERNAME is not configured");
             return Err("Email configuration error: SMTP_USERNAME is not set. Please configure your email settings.".to_string());
         }
     };
     
-    let smtp_password = match std::env::var("SMTP_PASSWORD") {
-        Ok(password) => password,
-        Err(_) => {
-            error!("SMTP_PASSWORD environment variable is not set");
+    let smtp_password = match &smtp.password {
+        Some(password) => password.clone(),
+        None => {
+            error!("SMTP_PASSWORD is not configured");
             return Err("Email configuration error: SMTP_PASSWORD is not set. Please configure your email settings.".to_string());
         }
     };
     
-    let frontend_url = std::env::var("FRONTEND_URL").unwrap_or_else(|_| {
-        warn!("FRONTEND_URL not set, using default: http://localhost:3000");
-        "http://localhost:3000".to_string()
-    });
-    
     // Create the reset URL
-    let reset_url = format!("{}/reset-password?token={}", frontend_url, token);
+    let reset_url = format!("{}/reset-password?token={}", config.frontend_url, token);
     
     // Create the email
     let email_message = Message::builder()
@@ -143,20 +119,11 @@ async fn send_password_reset_email(email: &str, token: &str) -> Result<(), Strin
     // Create SMTP transport with better error handling
     let creds = Credentials::new(smtp_username.clone(), smtp_password);
     
-    // Parse SMTP port with better error handling
-    let smtp_port_num = match smtp_port.parse::<u16>() {
-        Ok(port) => port,
-        Err(e) => {
-            error!("Invalid SMTP port '{}': {}", smtp_port, e);
-            return Err("Email configuration error: Invalid SMTP port. Please check your email settings.".to_string());
-        }
-    };
-    
     // Create SMTP relay with better error handling
-    let transport_builder = match SmtpTransport::relay(&smtp_host) {
+    let transport_builder = match SmtpTransport::relay(&smtp.host) {
         Ok(builder) => builder,
         Err(e) => {
-            error!("Failed to create SMTP relay for host '{}': {}", smtp_host, e);
+            error!("Failed to create SMTP relay for host '{}': {}", smtp.host, e);
             return Err("Email configuration error: Failed to create SMTP transport. Please check your email settings.".to_string());
         }
     };
@@ -164,7 +131,7 @@ async fn send_password_reset_email(email: &str, token: &str) -> Result<(), Strin
     // Build the mailer
     let mailer = transport_builder
         .credentials(creds)
-        .port(smtp_port_num)
+        .port(smtp.port)
         .timeout(Some(std::time::Duration::from_secs(15))) // Add timeout for better reliability
         .build();
     
@@ -177,7 +144,7 @@ async fn send_password_reset_email(email: &str, token: &str) -> Result<(), Strin
         Err(e) => {
             // Log detailed error information for troubleshooting
             error!("Failed to send password reset email to {}: {}", email, e);
-            error!("SMTP configuration: host={}, port={}, username={}", smtp_host, smtp_port, smtp_username);
+            error!("SMTP configuration: host={}, port={}, username={}", smtp.host, smtp.port, smtp_username);
             
             // Return a user-friendly error message
             Err("Failed to send password reset email. Please try again later or contact support if the problem persists.".to_string())
@@ -186,11 +153,12 @@ async fn send_password_reset_email(email: &str, token: &str) -> Result<(), Strin
 }
 
 // Endpoint to request password reset
-#[post("/api/auth/forgot-password")]
+#[post("/forgot-password")]
 pub async fn forgot_password(
     db: web::Data<DatabaseConnection>,
+    config: web::Data<AppConfig>,
     req: web::Json<ForgotPasswordRequest>,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
     let email = &req.email;
     debug!("Password reset requested for email: {}", email);
     
@@ -203,15 +171,13 @@ pub async fn forgot_password(
             Ok(None) => {
                 // Don't reveal that the email doesn't exist for security reasons
                 debug!("No user found with email: {}", email);
-                return HttpResponse::Ok().json(ForgotPasswordResponse {
+                return Ok(HttpResponse::Ok().json(ForgotPasswordResponse {
                     message: "If your email is registered, you will receive a password reset link.".to_string(),
-                });
+                }));
             }
             Err(e) => {
                 error!("Database error when finding user by email: {:?}", e);
-                return HttpResponse::InternalServerError().json(
-                    serde_json::json!({"error": "Failed to process request"})
-                );
+                return Err(ApiError::Database(e));
             }
         };
     
@@ -229,35 +195,31 @@ pub async fn forgot_password(
     match user_active.update(db.get_ref()).await {
         Ok(_) => {
             // Send password reset email
-            match send_password_reset_email(email, &reset_token).await {
+            match send_password_reset_email(&config, email, &reset_token).await {
                 Ok(_) => {
-                    HttpResponse::Ok().json(ForgotPasswordResponse {
+                    Ok(HttpResponse::Ok().json(ForgotPasswordResponse {
                         message: "If your email is registered, you will receive a password reset link.".to_string(),
-                    })
+                    }))
                 }
                 Err(e) => {
                     error!("Failed to send password reset email: {}", e);
-                    HttpResponse::InternalServerError().json(
-                        serde_json::json!({"error": "Failed to send password reset email"})
-                    )
+                    Err(ApiError::Internal("Failed to send password reset email".to_string()))
                 }
             }
         }
         Err(e) => {
             error!("Database error when updating user with reset token: {:?}", e);
-            HttpResponse::InternalServerError().json(
-                serde_json::json!({"error": "Failed to process request"})
-            )
+            Err(ApiError::Database(e))
         }
     }
 }
 
 // Endpoint to reset password with token
-#[post("/api/auth/reset-password")]
+#[post("/reset-password")]
 pub async fn reset_password(
     db: web::Data<DatabaseConnection>,
     req: web::Json<ResetPasswordRequest>,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
     let token = &req.token;
     let new_password = &req.password;
     
@@ -271,15 +233,11 @@ pub async fn reset_password(
             Ok(Some(user)) => user,
             Ok(None) => {
                 warn!("Invalid or expired password reset token");
-                return HttpResponse::BadRequest().json(
-                    serde_json::json!({"error": "Invalid or expired password reset token"})
-                );
+                return Err(ApiError::BadRequest("Invalid or expired password reset token".to_string()));
             }
             Err(e) => {
                 error!("Database error when finding user by reset token: {:?}", e);
-                return HttpResponse::InternalServerError().json(
-                    serde_json::json!({"error": "Failed to process request"})
-                );
+                return Err(ApiError::Database(e));
             }
         };
     
@@ -287,21 +245,11 @@ pub async fn reset_password(
     if let Some(expires) = user.password_reset_expires {
         if expires < Utc::now() {
             warn!("Password reset token has expired");
-            return HttpResponse::BadRequest().json(
-                serde_json::json!({
-                    "error": "Password reset token has expired",
-                    "message": "The password reset link has expired. Please request a new one."
-                })
-            );
+            return Err(ApiError::BadRequest("The password reset link has expired. Please request a new one.".to_string()));
         }
     } else {
         warn!("Password reset token has no expiration");
-        return HttpResponse::BadRequest().json(
-            serde_json::json!({
-                "error": "Invalid password reset token",
-                "message": "The password reset link is invalid. Please request a new one."
-            })
-        );
+        return Err(ApiError::BadRequest("The password reset link is invalid. Please request a new one.".to_string()));
     }
     
     // Hash the new password
@@ -311,9 +259,7 @@ pub async fn reset_password(
         Ok(hash) => hash.to_string(),
         Err(e) => {
             error!("Failed to hash password: {:?}", e);
-            return HttpResponse::InternalServerError().json(
-                serde_json::json!({"error": "Failed to process request"})
-            );
+            return Err(ApiError::Internal("Failed to process request".to_string()));
         }
     };
     
@@ -327,15 +273,13 @@ pub async fn reset_password(
     match user_active.update(db.get_ref()).await {
         Ok(_) => {
             info!("Password reset successful");
-            HttpResponse::Ok().json(ResetPasswordResponse {
+            Ok(HttpResponse::Ok().json(ResetPasswordResponse {
                 message: "Password has been reset successfully".to_string(),
-            })
+            }))
         }
         Err(e) => {
             error!("Database error when updating user password: {:?}", e);
-            HttpResponse::InternalServerError().json(
-                serde_json::json!({"error": "Failed to reset password"})
-            )
+            Err(ApiError::Database(e))
         }
     }
 }
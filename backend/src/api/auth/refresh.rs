@@ -0,0 +1,56 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, ResponseError};
+use log::{debug, error, warn};
+use sea_orm::DatabaseConnection;
+use serde::Deserialize;
+
+use crate::api::auth::login::token_pair_response;
+use crate::auth::{rotate_refresh_token, RefreshError};
+use crate::auth::tokens::{clear_refresh_cookie, REFRESH_TOKEN_COOKIE};
+use crate::api::ApiError;
+use crate::config::AppConfig;
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: Option<String>,
+}
+
+#[post("/refresh")]
+pub async fn refresh_token(
+    db: web::Data<DatabaseConnection>,
+    config: web::Data<AppConfig>,
+    refresh_data: Option<web::Json<RefreshRequest>>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    // Non-browser clients send the token in the body, browsers send the cookie
+    let presented = refresh_data
+        .and_then(|data| data.into_inner().refresh_token)
+        .or_else(|| req.cookie(REFRESH_TOKEN_COOKIE).map(|cookie| cookie.value().to_string()));
+
+    let presented = match presented {
+        Some(token) if !token.is_empty() => token,
+        _ => {
+            warn!("Refresh attempted without a refresh token");
+            return Err(ApiError::RefreshTokenRejected("Refresh token required".to_string()));
+        }
+    };
+
+    match rotate_refresh_token(db.get_ref(), &presented, &config.jwt_secret).await {
+        Ok((user, pair)) => {
+            debug!("Issued new token pair for user: {}", user.id);
+            Ok(token_pair_response(user, pair, &config))
+        }
+        Err(e @ (RefreshError::Database(_) | RefreshError::Token(_))) => {
+            error!("Failed to refresh token: {:?}", e);
+            Err(e.into())
+        }
+        Err(e) => {
+            warn!("Refresh token rejected: {}", e);
+            // The browser should stop presenting a token that will never work again
+            let mut response = ApiError::from(e).error_response();
+            if let Err(e) = response.add_cookie(&clear_refresh_cookie()) {
+                error!("Failed to clear refresh cookie: {:?}", e);
+            }
+            Ok(response)
+        }
+    }
+}
@@ -1,5 +1,5 @@
-use actix_web::{post, web, HttpResponse, Responder};
-use sea_orm::{DatabaseConnection, EntityTrait, ActiveValue, ActiveModelTrait, QueryFilter, ColumnTrait};
+use actix_web::{post, web, HttpResponse};
+use sea_orm::{DatabaseConnection, EntityTrait, ActiveValue, QueryFilter, ColumnTrait};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use log::{info, error, debug};
@@ -12,6 +12,7 @@ use argon2::{
 };
 
 use crate::models::{User, UserActiveModel, UserResponseDto, entities::user::Column};
+use crate::api::ApiError;
 
 #[derive(Debug, Deserialize)]
 pub struct RegisterRequest {
@@ -26,11 +27,11 @@ pub struct RegisterResponse {
     pub user: UserResponseDto,
 }
 
-#[post("/api/auth/register")]
+#[post("/register")]
 pub async fn register(
     db: web::Data<DatabaseConnection>,
     user_data: web::Json<RegisterRequest>,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
     let user_data = user_data.into_inner();
     
     debug!("Registering new user with email: {}", user_data.email);
@@ -43,17 +44,13 @@ pub async fn register(
             Ok(user) => user,
             Err(e) => {
                 error!("Database error when checking for existing user: {:?}", e);
-                return HttpResponse::InternalServerError().json(
-                    serde_json::json!({"error": "Database error when checking for existing user"})
-                );
+                return Err(ApiError::Database(e));
             }
         };
     
     if existing_user.is_some() {
         debug!("User with email {} already exists", user_data.email);
-        return HttpResponse::BadRequest().json(
-            serde_json::json!({"error": "User with this email already exists"})
-        );
+        return Err(ApiError::Conflict("User with this email already exists".to_string()));
     }
     
     // Hash the password
@@ -63,9 +60,7 @@ pub async fn register(
         Ok(hash) => hash.to_string(),
         Err(e) => {
             error!("Failed to hash password: {:?}", e);
-            return HttpResponse::InternalServerError().json(
-                serde_json::json!({"error": "Failed to hash password"})
-            );
+            return Err(ApiError::Internal("Failed to hash password".to_string()));
         }
     };
     
@@ -84,6 +79,7 @@ pub async fn register(
         is_active: ActiveValue::Set(true),
         created_at: ActiveValue::Set(chrono::Utc::now()),
         updated_at: ActiveValue::Set(chrono::Utc::now()),
+        last_seen_at: ActiveValue::Set(None),
     };
     
     let insert_result = match User::insert(new_user)
@@ -92,9 +88,7 @@ pub async fn register(
             Ok(res) => res,
             Err(e) => {
                 error!("Failed to create user: {:?}", e);
-                return HttpResponse::InternalServerError().json(
-                    serde_json::json!({"error": "Failed to create user"})
-                );
+                return Err(ApiError::Database(e));
             }
         };
     
@@ -104,20 +98,16 @@ pub async fn register(
             Ok(Some(user)) => user,
             Ok(None) => {
                 error!("User created but not found when retrieving: {}", user_id);
-                return HttpResponse::InternalServerError().json(
-                    serde_json::json!({"error": "Failed to retrieve created user"})
-                );
+                return Err(ApiError::Internal("Failed to retrieve created user".to_string()));
             }
             Err(e) => {
                 error!("Database error when retrieving created user: {:?}", e);
-                return HttpResponse::InternalServerError().json(
-                    serde_json::json!({"error": "Database error when retrieving created user"})
-                );
+                return Err(ApiError::Database(e));
             }
         };
     
     info!("New user registered successfully: {}", user.id);
-    HttpResponse::Created().json(RegisterResponse {
+    Ok(HttpResponse::Created().json(RegisterResponse {
         user: user.into(),
-    })
+    }))
 }
\ No newline at end of file
@@ -1,38 +1,24 @@
-use actix_web::{get, delete, web, HttpRequest, HttpResponse, Responder};
-use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, ColumnTrait, Set, ActiveValue, ActiveModelTrait};
+use actix_web::{get, delete, web, HttpRequest, HttpResponse};
+use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, ColumnTrait, Set, ActiveModelTrait, DbErr};
 use log::{info, error, debug};
 use uuid::Uuid;
 use chrono::Utc;
 
 use crate::models::entities::{UserSession, UserSessionModel, UserSessionActiveModel, SessionResponseDto};
-use crate::auth::{extract_user_id_from_token, extract_token_from_cookie_or_header};
+use crate::auth::{AuthUser, revoke_session_family};
+use crate::api::ApiError;
 
-#[get("/api/auth/sessions")]
+#[get("/sessions")]
 pub async fn get_sessions(
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
-) -> impl Responder {
-    // Extract user ID from a token
-    let user_id = match extract_user_id_from_token(&req) {
-        Some(id) => id,
-        None => {
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({"error": "Unauthorized"})
-            );
-        }
-    };
+    auth_user: AuthUser,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = auth_user.id;
 
     debug!("Fetching sessions for user: {}", user_id);
 
-    // Get the current session ID from the token
-    let token = match extract_token_from_cookie_or_header(&req) {
-        Some(token) => token,
-        None => {
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({"error": "Unauthorized"})
-            );
-        }
-    };
+    // The session the request's token belongs to
+    let current_session_id = auth_user.session_id;
 
     // Find all active sessions for the user
     let sessions = match UserSession::find()
@@ -43,53 +29,36 @@ pub async fn get_sessions(
             Ok(sessions) => sessions,
             Err(e) => {
                 error!("Database error when finding sessions: {:?}", e);
-                return HttpResponse::InternalServerError().json(
-                    serde_json::json!({"error": "Database error when finding sessions"})
-                );
+                return Err(ApiError::Database(e));
             }
         };
 
-    // Convert to DTOs and mark the current session
+    // Convert to DTOs and mark the session the token belongs to as current
     let mut session_dtos: Vec<SessionResponseDto> = sessions
         .into_iter()
         .map(|session| {
+            let is_current = session.id == current_session_id;
             let mut dto: SessionResponseDto = session.into();
-            // Check if this is the current session by comparing the token
-            // In a real implementation, you would store a session token or ID in the session record
-            // For now, we'll just mark the most recent session as current
-            dto.is_current = false; // We'll set this below
+            dto.is_current = is_current;
             dto
         })
         .collect();
 
     // Sort sessions by last_active_at (most recent first)
-    session_dtos.sort_by(|a, b| b.last_active_at.cmp(&a.last_active_at));
+    session_dtos.sort_by_key(|session| std::cmp::Reverse(session.last_active_at));
 
-    // Mark the first session as current (most recent)
-    if !session_dtos.is_empty() {
-        session_dtos[0].is_current = true;
-    }
-
-    HttpResponse::Ok().json(session_dtos)
+    Ok(HttpResponse::Ok().json(session_dtos))
 }
 
-#[delete("/api/auth/sessions/{session_id}")]
+#[delete("/sessions/{session_id}")]
 pub async fn terminate_session(
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
+    auth_user: AuthUser,
     path: web::Path<String>,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
     let session_id = path.into_inner();
     
-    // Extract user ID from token
-    let user_id = match extract_user_id_from_token(&req) {
-        Some(id) => id,
-        None => {
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({"error": "Unauthorized"})
-            );
-        }
-    };
+    let user_id = auth_user.id;
 
     debug!("Terminating session {} for user: {}", session_id, user_id);
 
@@ -97,9 +66,7 @@ pub async fn terminate_session(
     let session_uuid = match Uuid::parse_str(&session_id) {
         Ok(id) => id,
         Err(_) => {
-            return HttpResponse::BadRequest().json(
-                serde_json::json!({"error": "Invalid session ID format"})
-            );
+            return Err(ApiError::Validation("Invalid session ID format".to_string()));
         }
     };
 
@@ -109,72 +76,45 @@ pub async fn terminate_session(
         .await {
             Ok(Some(session)) => session,
             Ok(None) => {
-                return HttpResponse::NotFound().json(
-                    serde_json::json!({"error": "Session not found"})
-                );
+                return Err(ApiError::NotFound("Session not found".to_string()));
             }
             Err(e) => {
                 error!("Database error when finding session: {:?}", e);
-                return HttpResponse::InternalServerError().json(
-                    serde_json::json!({"error": "Database error when finding session"})
-                );
+                return Err(ApiError::Database(e));
             }
         };
 
     // Verify the session belongs to the user
     if session.user_id != user_id {
-        return HttpResponse::Forbidden().json(
-            serde_json::json!({"error": "You don't have permission to terminate this session"})
-        );
+        return Err(ApiError::Forbidden("You don't have permission to terminate this session".to_string()));
     }
 
-    // Update the session to mark it as inactive
-    let mut session_active: UserSessionActiveModel = session.into();
-    session_active.is_active = Set(false);
-    session_active.updated_at = Set(Utc::now());
-
-    match session_active.update(db.get_ref()).await {
+    // Mark the session inactive, revoke its refresh tokens and close its websockets
+    match revoke_session_family(db.get_ref(), session.id).await {
         Ok(_) => {
             info!("Session terminated: {}", session_id);
-            HttpResponse::Ok().json(
+            Ok(HttpResponse::Ok().json(
                 serde_json::json!({"message": "Session terminated successfully"})
-            )
+            ))
         }
         Err(e) => {
             error!("Database error when updating session: {:?}", e);
-            HttpResponse::InternalServerError().json(
-                serde_json::json!({"error": "Database error when updating session"})
-            )
+            Err(ApiError::Database(e))
         }
     }
 }
 
-#[delete("/api/auth/sessions")]
+#[delete("/sessions")]
 pub async fn terminate_all_sessions(
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
-) -> impl Responder {
-    // Extract user ID from token
-    let user_id = match extract_user_id_from_token(&req) {
-        Some(id) => id,
-        None => {
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({"error": "Unauthorized"})
-            );
-        }
-    };
+    auth_user: AuthUser,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = auth_user.id;
 
     debug!("Terminating all sessions for user: {}", user_id);
 
-    // Get the current session ID from the token
-    let token = match extract_token_from_cookie_or_header(&req) {
-        Some(token) => token,
-        None => {
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({"error": "Unauthorized"})
-            );
-        }
-    };
+    // The session the request's token belongs to stays active
+    let current_session_id = auth_user.session_id;
 
     // Find all active sessions for the user
     let sessions = match UserSession::find()
@@ -185,43 +125,35 @@ pub async fn terminate_all_sessions(
             Ok(sessions) => sessions,
             Err(e) => {
                 error!("Database error when finding sessions: {:?}", e);
-                return HttpResponse::InternalServerError().json(
-                    serde_json::json!({"error": "Database error when finding sessions"})
-                );
+                return Err(ApiError::Database(e));
             }
         };
 
-    // Sort sessions by last_active_at (most recent first)
-    let mut sorted_sessions = sessions;
-    sorted_sessions.sort_by(|a, b| b.last_active_at.cmp(&a.last_active_at));
+    // Keep the current session active and terminate all others
+    let sessions_to_terminate: Vec<_> = sessions
+        .into_iter()
+        .filter(|session| session.id != current_session_id)
+        .collect();
 
-    // Keep the most recent session (current session) active
-    if sorted_sessions.is_empty() {
-        return HttpResponse::Ok().json(
+    if sessions_to_terminate.is_empty() {
+        return Ok(HttpResponse::Ok().json(
             serde_json::json!({"message": "No active sessions to terminate"})
-        );
+        ));
     }
-
-    // Skip the first session (most recent/current) and terminate all others
-    let sessions_to_terminate = &sorted_sessions[1..];
     
-    for session in sessions_to_terminate {
-        let mut session_active: UserSessionActiveModel = session.clone().into();
-        session_active.is_active = Set(false);
-        session_active.updated_at = Set(Utc::now());
-        
-        if let Err(e) = session_active.update(db.get_ref()).await {
+    for session in &sessions_to_terminate {
+        if let Err(e) = revoke_session_family(db.get_ref(), session.id).await {
             error!("Database error when updating session {}: {:?}", session.id, e);
             // Continue with other sessions even if one fails
         }
     }
 
     info!("Terminated {} sessions for user: {}", sessions_to_terminate.len(), user_id);
-    HttpResponse::Ok().json(
+    Ok(HttpResponse::Ok().json(
         serde_json::json!({
             "message": format!("Terminated {} sessions successfully", sessions_to_terminate.len())
         })
-    )
+    ))
 }
 
 // Helper function to create a new session
@@ -233,7 +165,7 @@ pub async fn create_session(
     device_type: String,
     browser: String,
     os: String,
-) -> Result<UserSessionModel, String> {
+) -> Result<UserSessionModel, DbErr> {
     let session_id = Uuid::new_v4();
     let now = Utc::now();
     
@@ -258,7 +190,74 @@ pub async fn create_session(
         }
         Err(e) => {
             error!("Database error when creating session: {:?}", e);
-            Err("Database error when creating session".to_string())
+            Err(e)
         }
     }
+}
+
+// Helper function to create a session for the client making the request
+pub async fn create_session_for_request(
+    db: &DatabaseConnection,
+    user_id: Uuid,
+    req: &HttpRequest,
+) -> Result<UserSessionModel, DbErr> {
+    // Extract client information from request headers
+    let ip_address = req.connection_info().realip_remote_addr()
+        .unwrap_or("unknown").to_string();
+
+    let user_agent = req.headers().get("User-Agent")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("unknown").to_string();
+
+    // Parse user agent to extract browser, device, and OS information
+    let (browser, device_type, os) = parse_user_agent(&user_agent);
+
+    create_session(db, user_id, ip_address, user_agent, device_type, browser, os).await
+}
+
+// Helper function to parse User-Agent string
+fn parse_user_agent(user_agent: &str) -> (String, String, String) {
+    // Default values
+    let mut browser = "Unknown".to_string();
+    let mut os = "Unknown".to_string();
+    
+    // Very basic parsing - in a real app, you'd use a proper user-agent parsing library
+    let ua_lower = user_agent.to_lowercase();
+    
+    // Detect browser
+    if ua_lower.contains("firefox") {
+        browser = "Firefox".to_string();
+    } else if ua_lower.contains("chrome") && !ua_lower.contains("edg") {
+        browser = "Chrome".to_string();
+    } else if ua_lower.contains("safari") && !ua_lower.contains("chrome") {
+        browser = "Safari".to_string();
+    } else if ua_lower.contains("edg") {
+        browser = "Edge".to_string();
+    } else if ua_lower.contains("opera") || ua_lower.contains("opr") {
+        browser = "Opera".to_string();
+    }
+    
+    // Detect OS
+    if ua_lower.contains("windows") {
+        os = "Windows".to_string();
+    } else if ua_lower.contains("mac os") {
+        os = "macOS".to_string();
+    } else if ua_lower.contains("android") {
+        os = "Android".to_string();
+    } else if ua_lower.contains("ios") || ua_lower.contains("iphone") || ua_lower.contains("ipad") {
+        os = "iOS".to_string();
+    } else if ua_lower.contains("linux") {
+        os = "Linux".to_string();
+    }
+    
+    // Detect device type
+    let device_type = if ua_lower.contains("mobile") || ua_lower.contains("android") || ua_lower.contains("iphone") {
+        "Mobile".to_string()
+    } else if ua_lower.contains("tablet") || ua_lower.contains("ipad") {
+        "Tablet".to_string()
+    } else {
+        "Desktop".to_string()
+    };
+    
+    (browser, device_type, os)
 }
\ No newline at end of file
@@ -1,12 +1,13 @@
 use sea_orm::QueryFilter;
 use sea_orm::ColumnTrait;
-use actix_web::{post, web, HttpResponse, Responder};
-use sea_orm::{DatabaseConnection, EntityTrait, Set, ActiveValue};
+use actix_web::{post, web, HttpResponse};
+use sea_orm::{DatabaseConnection, EntityTrait, Set};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use log::{info, error, debug};
 
 use crate::models::{User, UserActiveModel};
+use crate::api::ApiError;
 
 #[derive(Debug, Deserialize)]
 pub struct SyncUserRequest {
@@ -22,11 +23,11 @@ pub struct SyncUserResponse {
     pub role: String,
 }
 
-#[post("/api/auth/sync")]
+#[post("/sync")]
 pub async fn sync_user(
     db: web::Data<DatabaseConnection>,
     user_data: web::Json<SyncUserRequest>,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
     let user_data = user_data.into_inner();
     
     debug!("Syncing user with email: {}, provider: {}", user_data.email, user_data.provider);
@@ -39,9 +40,7 @@ pub async fn sync_user(
             Ok(user) => user,
             Err(e) => {
                 error!("Database error when finding user by email: {:?}", e);
-                return HttpResponse::InternalServerError().json(
-                    serde_json::json!({"error": "Database error when finding user"})
-                );
+                return Err(ApiError::Database(e));
             }
         };
     
@@ -74,24 +73,22 @@ pub async fn sync_user(
                         Ok(user) => user,
                         Err(e) => {
                             error!("Failed to update user: {:?}", e);
-                            return HttpResponse::InternalServerError().json(
-                                serde_json::json!({"error": "Failed to update user"})
-                            );
+                            return Err(ApiError::Database(e));
                         }
                     };
                 
                 info!("User updated successfully: {}", updated_user.id);
-                HttpResponse::Ok().json(SyncUserResponse {
+                Ok(HttpResponse::Ok().json(SyncUserResponse {
                     user_id: updated_user.id.to_string(),
                     role: updated_user.role,
-                })
+                }))
             } else {
                 // No changes needed
                 debug!("No changes needed for user: {}", existing_user.id);
-                HttpResponse::Ok().json(SyncUserResponse {
+                Ok(HttpResponse::Ok().json(SyncUserResponse {
                     user_id: existing_user.id.to_string(),
                     role: existing_user.role,
-                })
+                }))
             }
         }
         None => {
@@ -111,6 +108,7 @@ pub async fn sync_user(
                 is_active: Set(true),
                 created_at: Set(chrono::Utc::now()),
                 updated_at: Set(chrono::Utc::now()),
+                last_seen_at: Set(None),
             };
             
             let insert_result = match User::insert(new_user)
@@ -119,9 +117,7 @@ pub async fn sync_user(
                     Ok(res) => res,
                     Err(e) => {
                         error!("Failed to create user: {:?}", e);
-                        return HttpResponse::InternalServerError().json(
-                            serde_json::json!({"error": "Failed to create user"})
-                        );
+                        return Err(ApiError::Database(e));
                     }
                 };
             
@@ -131,23 +127,19 @@ pub async fn sync_user(
                     Ok(Some(user)) => user,
                     Ok(None) => {
                         error!("User created but not found when retrieving: {}", user_id);
-                        return HttpResponse::InternalServerError().json(
-                            serde_json::json!({"error": "Failed to retrieve created user"})
-                        );
+                        return Err(ApiError::Internal("Failed to retrieve created user".to_string()));
                     }
                     Err(e) => {
                         error!("Database error when retrieving created user: {:?}", e);
-                        return HttpResponse::InternalServerError().json(
-                            serde_json::json!({"error": "Database error when retrieving created user"})
-                        );
+                        return Err(ApiError::Database(e));
                     }
                 };
             
             info!("New user created successfully: {}", user.id);
-            HttpResponse::Created().json(SyncUserResponse {
+            Ok(HttpResponse::Created().json(SyncUserResponse {
                 user_id: user.id.to_string(),
                 role: user.role,
-            })
+            }))
         }
     }
 }
\ No newline at end of file
@@ -1,5 +1,5 @@
-use actix_web::{web, HttpResponse, Responder, post, get, http::header, HttpRequest};
-use sea_orm::{DatabaseConnection, EntityTrait, Set, ActiveModelTrait, QueryFilter, ColumnTrait, ModelTrait};
+use actix_web::{web, HttpResponse, post, get};
+use sea_orm::{DatabaseConnection, EntityTrait, Set, ActiveModelTrait, QueryFilter, ColumnTrait};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use totp_rs::{TOTP, Secret, Algorithm};
@@ -8,12 +8,11 @@ use qrcode::render::svg;
 use base64::{encode};
 use rand::Rng;
 use log::{debug, error, warn};
-use jsonwebtoken::{decode, DecodingKey, Validation, errors::ErrorKind};
 use serde_json::json; // Added for explicit JSON serialization
-use crate::auth::Claims;
-use crate::api::auth::is_token_blacklisted;
+use crate::auth::AuthUser;
 use crate::models::entities::{TwoFactorAuth, TwoFactorAuthActiveModel};
 use crate::models::entities::User;
+use crate::api::ApiError;
 
 // Request and response types
 #[derive(Debug, Serialize, Deserialize)]
@@ -258,161 +257,19 @@ fn generate_backup_codes() -> Vec<String> {
 }
 
 // API endpoints
-#[get("/api/auth/2fa/setup")]
+#[get("/2fa/setup")]
 pub async fn two_factor_setup(
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
-    jwt_secret: web::Data<String>,
-) -> impl Responder {
-    // Try to extract the token from the cookie first
-    let mut token_str = None;
-    
-    // Check for auth_token cookie
-    if let Some(cookie) = req.cookie("auth_token") {
-        debug!("Found auth_token cookie for /api/auth/2fa/setup endpoint");
-        token_str = Some(cookie.value().to_string());
-    }
-    
-    // If no cookie found, try the Authorization header as fallback
-    if token_str.is_none() {
-        debug!("No auth_token cookie found, checking Authorization header for /api/auth/2fa/setup endpoint");
-        
-        // Extract the token from the Authorization header
-        let auth_header = match req.headers().get(header::AUTHORIZATION) {
-            Some(header) => header,
-            None => {
-                warn!("No authentication found (neither cookie nor Authorization header) for /api/auth/2fa/setup endpoint");
-                return HttpResponse::Unauthorized().json(
-                    json!({
-                        "error": "Unauthorized",
-                        "message": "Authentication required"
-                    })
-                );
-            }
-        };
-
-        let auth_str = match auth_header.to_str() {
-            Ok(str) => str,
-            Err(e) => {
-                warn!("Invalid Authorization header format: {:?}", e);
-                return HttpResponse::Unauthorized().json(
-                    json!({
-                        "error": "Unauthorized",
-                        "message": "Invalid Authorization header format"
-                    })
-                );
-            }
-        };
-
-        if !auth_str.starts_with("Bearer ") {
-            warn!("Authorization header does not start with 'Bearer '");
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid Authorization header format"
-                })
-            );
-        }
-
-        token_str = Some(auth_str.trim_start_matches("Bearer ").trim().to_string());
-    }
-    
-    // Unwrap the token (we know it's Some at this point)
-    let token = token_str.unwrap();
-
-    // Check if the token is blacklisted
-    if is_token_blacklisted(&token) {
-        warn!("Token is blacklisted");
-        return HttpResponse::Unauthorized().json(
-            json!({
-                "error": "Unauthorized",
-                "message": "Token has been invalidated"
-            })
-        );
-    }
-
-    // Configure validation to explicitly check expiration
-    let mut validation = Validation::default();
-    validation.validate_exp = true;
-    validation.leeway = 0; // No leeway for expiration time
-
-    // Validate the token
-    let token_data = match decode::<Claims>(
-        &token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
-        &validation,
-    ) {
-        Ok(data) => data,
-        Err(e) => {
-            match e.kind() {
-                ErrorKind::ExpiredSignature => {
-                    warn!("Token expired");
-                    return HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Token expired"
-                        })
-                    );
-                }
-                ErrorKind::InvalidSignature => {
-                    warn!("Invalid token signature");
-                    return HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Invalid token signature"
-                        })
-                    );
-                }
-                _ => {
-                    warn!("Invalid token: {:?}", e);
-                    return HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Invalid token"
-                        })
-                    );
-                }
-            }
-        }
-    };
-
-    let claims = token_data.claims;
-
-    // Ensure backend_user_id is present
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            warn!("Missing backend_user_id in token");
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Missing user ID in token"
-                })
-            );
-        }
-    };
-
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
-        Err(e) => {
-            warn!("Invalid backend_user_id format in token: {:?}", e);
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid user ID format in token"
-                })
-            );
-        }
-    };
+    auth_user: AuthUser,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = auth_user.id;
 
     debug!("Setting up 2FA for user ID: {}", user_id);
 
     // Get user email for QR code
     let user = match User::find_by_id(user_id).one(db.as_ref()).await {
         Ok(Some(user)) => user,
-        _ => return HttpResponse::NotFound().json(json!({
-            "error": "User not found"
-        })),
+        _ => return Err(ApiError::NotFound("User not found".to_string())),
     };
 
     // Check if 2FA is already set up
@@ -423,19 +280,17 @@ pub async fn two_factor_setup(
 
     match existing_2fa {
         Ok(Some(two_factor)) if two_factor.enabled => {
-            return HttpResponse::BadRequest().json(json!({
-                "error": "Two-factor authentication is already enabled"
-            }));
+            return Err(ApiError::BadRequest("Two-factor authentication is already enabled".to_string()));
         },
         Ok(Some(two_factor)) => {
             // If 2FA exists but is not enabled, return the existing secret
             let totp = create_totp(&two_factor.secret);
             let qr_code = generate_qr_code(&totp, &user.email);
 
-            return HttpResponse::Ok().json(TwoFactorSetupResponse {
+            return Ok(HttpResponse::Ok().json(TwoFactorSetupResponse {
                 secret: two_factor.secret,
                 qr_code_url: qr_code,
-            });
+            }));
         },
         Ok(None) => {
             // No existing 2FA record, create a new one
@@ -456,178 +311,34 @@ pub async fn two_factor_setup(
             };
 
             match two_factor.insert(db.as_ref()).await {
-                Ok(_) => HttpResponse::Ok().json(TwoFactorSetupResponse {
+                Ok(_) => Ok(HttpResponse::Ok().json(TwoFactorSetupResponse {
                     secret,
                     qr_code_url: qr_code,
-                }),
+                })),
                 Err(e) => {
                     error!("Failed to set up two-factor authentication: {:?}", e);
                     if let sea_orm::DbErr::Query(ref query_err) = e {
                         error!("Database query error details: {:?}", query_err);
                     }
                     error!("backup_codes value: {:?}", Some(json!(Vec::<String>::new())));
-                    HttpResponse::InternalServerError().json(json!({
-                        "error": "Failed to set up two-factor authentication"
-                    }))
+                    Err(ApiError::Database(e))
                 },
             }
         },
         Err(e) => {
             error!("Error checking for existing 2FA: {:?}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to check for existing two-factor authentication"
-            }))
+            Err(ApiError::Database(e))
         }
     }
 }
 
-#[post("/api/auth/2fa/verify")]
+#[post("/2fa/verify")]
 pub async fn two_factor_verify(
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
-    jwt_secret: web::Data<String>,
+    auth_user: AuthUser,
     verify_req: web::Json<TwoFactorVerifyRequest>,
-) -> impl Responder {
-    // Try to extract the token from the cookie first
-    let mut token_str = None;
-    
-    // Check for auth_token cookie
-    if let Some(cookie) = req.cookie("auth_token") {
-        debug!("Found auth_token cookie for /api/auth/2fa/verify endpoint");
-        token_str = Some(cookie.value().to_string());
-    }
-    
-    // If no cookie found, try the Authorization header as fallback
-    if token_str.is_none() {
-        debug!("No auth_token cookie found, checking Authorization header for /api/auth/2fa/verify endpoint");
-        
-        // Extract the token from the Authorization header
-        let auth_header = match req.headers().get(header::AUTHORIZATION) {
-            Some(header) => header,
-            None => {
-                warn!("No authentication found (neither cookie nor Authorization header) for /api/auth/2fa/verify endpoint");
-                return HttpResponse::Unauthorized().json(
-                    json!({
-                        "error": "Unauthorized",
-                        "message": "Authentication required"
-                    })
-                );
-            }
-        };
-
-        let auth_str = match auth_header.to_str() {
-            Ok(str) => str,
-            Err(e) => {
-                warn!("Invalid Authorization header format: {:?}", e);
-                return HttpResponse::Unauthorized().json(
-                    json!({
-                        "error": "Unauthorized",
-                        "message": "Invalid Authorization header format"
-                    })
-                );
-            }
-        };
-
-        if !auth_str.starts_with("Bearer ") {
-            warn!("Authorization header does not start with 'Bearer '");
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid Authorization header format"
-                })
-            );
-        }
-
-        token_str = Some(auth_str.trim_start_matches("Bearer ").trim().to_string());
-    }
-    
-    // Unwrap the token (we know it's Some at this point)
-    let token = token_str.unwrap();
-
-    // Check if the token is blacklisted
-    if is_token_blacklisted(&token) {
-        warn!("Token is blacklisted");
-        return HttpResponse::Unauthorized().json(
-            json!({
-                "error": "Unauthorized",
-                "message": "Token has been invalidated"
-            })
-        );
-    }
-
-    // Configure validation to explicitly check expiration
-    let mut validation = Validation::default();
-    validation.validate_exp = true;
-    validation.leeway = 0; // No leeway for expiration time
-
-    // Validate the token
-    let token_data = match decode::<Claims>(
-        &token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
-        &validation,
-    ) {
-        Ok(data) => data,
-        Err(e) => {
-            return match e.kind() {
-                ErrorKind::ExpiredSignature => {
-                    warn!("Token expired");
-                    HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Token expired"
-                        })
-                    )
-                }
-                ErrorKind::InvalidSignature => {
-                    warn!("Invalid token signature");
-                    HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Invalid token signature"
-                        })
-                    )
-                }
-                _ => {
-                    warn!("Invalid token: {:?}", e);
-                    HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Invalid token"
-                        })
-                    )
-                }
-            }
-        }
-    };
-
-    let claims = token_data.claims;
-
-    // Ensure backend_user_id is present
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            warn!("Missing backend_user_id in token");
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Missing user ID in token"
-                })
-            );
-        }
-    };
-
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
-        Err(e) => {
-            warn!("Invalid backend_user_id format in token: {:?}", e);
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid user ID format in token"
-                })
-            );
-        }
-    };
+) -> Result<HttpResponse, ApiError> {
+    let user_id = auth_user.id;
 
     debug!("Verifying 2FA code for user ID: {}", user_id);
 
@@ -638,17 +349,12 @@ pub async fn two_factor_verify(
         .await
     {
         Ok(Some(two_factor)) => two_factor,
-        _ => return HttpResponse::NotFound().json(json!({
-            "error": "Two-factor authentication not set up"
-        })),
+        _ => return Err(ApiError::NotFound("Two-factor authentication not set up".to_string())),
     };
 
     // Verify TOTP code
     if !verify_totp(&two_factor.secret, &verify_req.code) {
-        return HttpResponse::BadRequest().json(json!({
-            "success": false,
-            "message": "Invalid verification code"
-        }));
+        return Err(ApiError::InvalidTwoFactorCode);
     }
 
     // If 2FA is not enabled yet, enable it and generate backup codes
@@ -662,177 +368,34 @@ pub async fn two_factor_verify(
         two_factor_active.updated_at = Set(chrono::Utc::now());
 
         match two_factor_active.update(db.as_ref()).await {
-            Ok(_) => HttpResponse::Ok().json(TwoFactorVerifyResponse {
+            Ok(_) => Ok(HttpResponse::Ok().json(TwoFactorVerifyResponse {
                 success: true,
                 message: "Two-factor authentication enabled successfully".to_string(),
-            }),
+            })),
             Err(e) => {
                 error!("Failed to enable two-factor authentication: {:?}", e);
                 if let sea_orm::DbErr::Query(ref query_err) = e {
                     error!("Database query error details: {:?}", query_err);
                 }
                 error!("backup_codes value: {:?}", Some(json!(backup_codes.clone())));
-                HttpResponse::InternalServerError().json(json!({
-                    "success": false,
-                    "message": "Failed to enable two-factor authentication"
-                }))
+                Err(ApiError::Database(e))
             },
         }
     } else {
         // If 2FA is already enabled, just return success
-        HttpResponse::Ok().json(TwoFactorVerifyResponse {
+        Ok(HttpResponse::Ok().json(TwoFactorVerifyResponse {
             success: true,
             message: "Verification successful".to_string(),
-        })
+        }))
     }
 }
 
-#[get("/api/auth/2fa/status")]
+#[get("/2fa/status")]
 pub async fn two_factor_status(
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
-    jwt_secret: web::Data<String>,
-) -> impl Responder {
-    // Try to extract the token from the cookie first
-    let mut token_str = None;
-    
-    // Check for auth_token cookie
-    if let Some(cookie) = req.cookie("auth_token") {
-        debug!("Found auth_token cookie for /api/auth/2fa/status endpoint");
-        token_str = Some(cookie.value().to_string());
-    }
-    
-    // If no cookie found, try the Authorization header as fallback
-    if token_str.is_none() {
-        debug!("No auth_token cookie found, checking Authorization header for /api/auth/2fa/status endpoint");
-        
-        // Extract the token from the Authorization header
-        let auth_header = match req.headers().get(header::AUTHORIZATION) {
-            Some(header) => header,
-            None => {
-                warn!("No authentication found (neither cookie nor Authorization header) for /api/auth/2fa/status endpoint");
-                return HttpResponse::Unauthorized().json(
-                    json!({
-                        "error": "Unauthorized",
-                        "message": "Authentication required"
-                    })
-                );
-            }
-        };
-
-        let auth_str = match auth_header.to_str() {
-            Ok(str) => str,
-            Err(e) => {
-                warn!("Invalid Authorization header format: {:?}", e);
-                return HttpResponse::Unauthorized().json(
-                    json!({
-                        "error": "Unauthorized",
-                        "message": "Invalid Authorization header format"
-                    })
-                );
-            }
-        };
-
-        if !auth_str.starts_with("Bearer ") {
-            warn!("Authorization header does not start with 'Bearer '");
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid Authorization header format"
-                })
-            );
-        }
-
-        token_str = Some(auth_str.trim_start_matches("Bearer ").trim().to_string());
-    }
-    
-    // Unwrap the token (we know it's Some at this point)
-    let token = token_str.unwrap();
-
-    // Check if the token is blacklisted
-    if is_token_blacklisted(&token) {
-        warn!("Token is blacklisted");
-        return HttpResponse::Unauthorized().json(
-            json!({
-                "error": "Unauthorized",
-                "message": "Token has been invalidated"
-            })
-        );
-    }
-
-    // Configure validation to explicitly check expiration
-    let mut validation = Validation::default();
-    validation.validate_exp = true;
-    validation.leeway = 0; // No leeway for expiration time
-
-    // Validate the token
-    let token_data = match decode::<Claims>(
-        &token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
-        &validation,
-    ) {
-        Ok(data) => data,
-        Err(e) => {
-            match e.kind() {
-                ErrorKind::ExpiredSignature => {
-                    warn!("Token expired");
-                    return HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Token expired"
-                        })
-                    );
-                }
-                ErrorKind::InvalidSignature => {
-                    warn!("Invalid token signature");
-                    return HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Invalid token signature"
-                        })
-                    );
-                }
-                _ => {
-                    warn!("Invalid token: {:?}", e);
-                    return HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Invalid token"
-                        })
-                    );
-                }
-            }
-        }
-    };
-
-    let claims = token_data.claims;
-
-    // Ensure backend_user_id is present
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            warn!("Missing backend_user_id in token");
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Missing user ID in token"
-                })
-            );
-        }
-    };
-
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
-        Err(e) => {
-            warn!("Invalid backend_user_id format in token: {:?}", e);
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid user ID format in token"
-                })
-            );
-        }
-    };
+    auth_user: AuthUser,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = auth_user.id;
 
     debug!("Checking 2FA status for user ID: {}", user_id);
 
@@ -843,169 +406,29 @@ pub async fn two_factor_status(
         .await
     {
         Ok(Some(two_factor)) => two_factor,
-        _ => return HttpResponse::Ok().json(TwoFactorStatusResponse {
+        _ => return Ok(HttpResponse::Ok().json(TwoFactorStatusResponse {
             enabled: false,
             backup_codes_remaining: None,
-        }),
+        })),
     };
 
-    HttpResponse::Ok().json(TwoFactorStatusResponse {
+    Ok(HttpResponse::Ok().json(TwoFactorStatusResponse {
         enabled: two_factor.enabled,
         // CORRECTED: Safely get array length from serde_json::Value
         backup_codes_remaining: two_factor.backup_codes
             .as_ref()
             .and_then(|v| v.as_array())
             .map(|a| a.len()),
-    })
+    }))
 }
 
-#[post("/api/auth/2fa/disable")]
+#[post("/2fa/disable")]
 pub async fn two_factor_disable(
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
-    jwt_secret: web::Data<String>,
+    auth_user: AuthUser,
     disable_req: web::Json<TwoFactorDisableRequest>,
-) -> impl Responder {
-    // Try to extract the token from the cookie first
-    let mut token_str = None;
-    
-    // Check for auth_token cookie
-    if let Some(cookie) = req.cookie("auth_token") {
-        debug!("Found auth_token cookie for /api/auth/2fa/disable endpoint");
-        token_str = Some(cookie.value().to_string());
-    }
-    
-    // If no cookie found, try the Authorization header as fallback
-    if token_str.is_none() {
-        debug!("No auth_token cookie found, checking Authorization header for /api/auth/2fa/disable endpoint");
-        
-        // Extract the token from the Authorization header
-        let auth_header = match req.headers().get(header::AUTHORIZATION) {
-            Some(header) => header,
-            None => {
-                warn!("No authentication found (neither cookie nor Authorization header) for /api/auth/2fa/disable endpoint");
-                return HttpResponse::Unauthorized().json(
-                    json!({
-                        "error": "Unauthorized",
-                        "message": "Authentication required"
-                    })
-                );
-            }
-        };
-
-        let auth_str = match auth_header.to_str() {
-            Ok(str) => str,
-            Err(e) => {
-                warn!("Invalid Authorization header format: {:?}", e);
-                return HttpResponse::Unauthorized().json(
-                    json!({
-                        "error": "Unauthorized",
-                        "message": "Invalid Authorization header format"
-                    })
-                );
-            }
-        };
-
-        if !auth_str.starts_with("Bearer ") {
-            warn!("Authorization header does not start with 'Bearer '");
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid Authorization header format"
-                })
-            );
-        }
-
-        token_str = Some(auth_str.trim_start_matches("Bearer ").trim().to_string());
-    }
-    
-    // Unwrap the token (we know it's Some at this point)
-    let token = token_str.unwrap();
-
-    // Check if the token is blacklisted
-    if is_token_blacklisted(&token) {
-        warn!("Token is blacklisted");
-        return HttpResponse::Unauthorized().json(
-            json!({
-                "error": "Unauthorized",
-                "message": "Token has been invalidated"
-            })
-        );
-    }
-
-    // Configure validation to explicitly check expiration
-    let mut validation = Validation::default();
-    validation.validate_exp = true;
-    validation.leeway = 0; // No leeway for expiration time
-
-    // Validate the token
-    let token_data = match decode::<Claims>(
-        &token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
-        &validation,
-    ) {
-        Ok(data) => data,
-        Err(e) => {
-            match e.kind() {
-                ErrorKind::ExpiredSignature => {
-                    warn!("Token expired");
-                    return HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Token expired"
-                        })
-                    );
-                }
-                ErrorKind::InvalidSignature => {
-                    warn!("Invalid token signature");
-                    return HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Invalid token signature"
-                        })
-                    );
-                }
-                _ => {
-                    warn!("Invalid token: {:?}", e);
-                    return HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Invalid token"
-                        })
-                    );
-                }
-            }
-        }
-    };
-
-    let claims = token_data.claims;
-
-    // Ensure backend_user_id is present
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            warn!("Missing backend_user_id in token");
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Missing user ID in token"
-                })
-            );
-        }
-    };
-
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
-        Err(e) => {
-            warn!("Invalid backend_user_id format in token: {:?}", e);
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid user ID format in token"
-                })
-            );
-        }
-    };
+) -> Result<HttpResponse, ApiError> {
+    let user_id = auth_user.id;
 
     debug!("Disabling 2FA for user ID: {}", user_id);
 
@@ -1016,17 +439,12 @@ pub async fn two_factor_disable(
         .await
     {
         Ok(Some(two_factor)) => two_factor,
-        _ => return HttpResponse::NotFound().json(json!({
-            "error": "Two-factor authentication not set up"
-        })),
+        _ => return Err(ApiError::NotFound("Two-factor authentication not set up".to_string())),
     };
 
     // Verify TOTP code
     if !verify_totp(&two_factor.secret, &disable_req.code) {
-        return HttpResponse::BadRequest().json(json!({
-            "success": false,
-            "message": "Invalid verification code"
-        }));
+        return Err(ApiError::InvalidTwoFactorCode);
     }
 
     // Disable 2FA
@@ -1037,170 +455,27 @@ pub async fn two_factor_disable(
     two_factor_active.updated_at = Set(chrono::Utc::now());
 
     match two_factor_active.update(db.as_ref()).await {
-        Ok(_) => HttpResponse::Ok().json(TwoFactorDisableResponse {
+        Ok(_) => Ok(HttpResponse::Ok().json(TwoFactorDisableResponse {
             success: true,
             message: "Two-factor authentication disabled successfully".to_string(),
-        }),
+        })),
         Err(e) => {
             error!("Failed to disable two-factor authentication: {:?}", e);
             if let sea_orm::DbErr::Query(ref query_err) = e {
                 error!("Database query error details: {:?}", query_err);
             }
             error!("backup_codes value: {:?}", Some(json!(Vec::<String>::new())));
-            HttpResponse::InternalServerError().json(json!({
-                "success": false,
-                "message": "Failed to disable two-factor authentication"
-            }))
+            Err(ApiError::Database(e))
         },
     }
 }
 
-#[get("/api/auth/2fa/backup-codes")]
+#[get("/2fa/backup-codes")]
 pub async fn two_factor_backup_codes(
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
-    jwt_secret: web::Data<String>,
-) -> impl Responder {
-    // Try to extract the token from the cookie first
-    let mut token_str = None;
-    
-    // Check for auth_token cookie
-    if let Some(cookie) = req.cookie("auth_token") {
-        debug!("Found auth_token cookie for /api/auth/2fa/backup-codes endpoint");
-        token_str = Some(cookie.value().to_string());
-    }
-    
-    // If no cookie found, try the Authorization header as fallback
-    if token_str.is_none() {
-        debug!("No auth_token cookie found, checking Authorization header for /api/auth/2fa/backup-codes endpoint");
-        
-        // Extract the token from the Authorization header
-        let auth_header = match req.headers().get(header::AUTHORIZATION) {
-            Some(header) => header,
-            None => {
-                warn!("No authentication found (neither cookie nor Authorization header) for /api/auth/2fa/backup-codes endpoint");
-                return HttpResponse::Unauthorized().json(
-                    json!({
-                        "error": "Unauthorized",
-                        "message": "Authentication required"
-                    })
-                );
-            }
-        };
-
-        let auth_str = match auth_header.to_str() {
-            Ok(str) => str,
-            Err(e) => {
-                warn!("Invalid Authorization header format: {:?}", e);
-                return HttpResponse::Unauthorized().json(
-                    json!({
-                        "error": "Unauthorized",
-                        "message": "Invalid Authorization header format"
-                    })
-                );
-            }
-        };
-
-        if !auth_str.starts_with("Bearer ") {
-            warn!("Authorization header does not start with 'Bearer '");
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid Authorization header format"
-                })
-            );
-        }
-
-        token_str = Some(auth_str.trim_start_matches("Bearer ").trim().to_string());
-    }
-    
-    // Unwrap the token (we know it's Some at this point)
-    let token = token_str.unwrap();
-
-    // Check if the token is blacklisted
-    if is_token_blacklisted(&token) {
-        warn!("Token is blacklisted");
-        return HttpResponse::Unauthorized().json(
-            json!({
-                "error": "Unauthorized",
-                "message": "Token has been invalidated"
-            })
-        );
-    }
-
-    // Configure validation to explicitly check expiration
-    let mut validation = Validation::default();
-    validation.validate_exp = true;
-    validation.leeway = 0; // No leeway for expiration time
-
-    // Validate the token
-    let token_data = match decode::<Claims>(
-        &token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
-        &validation,
-    ) {
-        Ok(data) => data,
-        Err(e) => {
-            match e.kind() {
-                ErrorKind::ExpiredSignature => {
-                    warn!("Token expired");
-                    return HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Token expired"
-                        })
-                    );
-                }
-                ErrorKind::InvalidSignature => {
-                    warn!("Invalid token signature");
-                    return HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Invalid token signature"
-                        })
-                    );
-                }
-                _ => {
-                    warn!("Invalid token: {:?}", e);
-                    return HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Invalid token"
-                        })
-                    );
-                }
-            }
-        }
-    };
-
-    let claims = token_data.claims;
-
-    // Ensure backend_user_id is present
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            warn!("Missing backend_user_id in token");
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Missing user ID in token"
-                })
-            );
-        }
-    };
-
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
-        Err(e) => {
-            warn!("Invalid backend_user_id format in token: {:?}", e);
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid user ID format in token"
-                })
-            );
-        }
-    };
+    auth_user: AuthUser,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = auth_user.id;
 
     debug!("Getting backup codes for user ID: {}", user_id);
 
@@ -1211,186 +486,38 @@ pub async fn two_factor_backup_codes(
         .await
     {
         Ok(Some(two_factor)) => two_factor,
-        _ => return HttpResponse::NotFound().json(json!({
-            "error": "Two-factor authentication not set up"
-        })),
+        _ => return Err(ApiError::NotFound("Two-factor authentication not set up".to_string())),
     };
 
     // Check if 2FA is enabled
     if !two_factor.enabled {
-        return HttpResponse::BadRequest().json(json!({
-            "error": "Two-factor authentication is not enabled"
-        }));
+        return Err(ApiError::BadRequest("Two-factor authentication is not enabled".to_string()));
     }
 
     // CORRECTED: Deserialize serde_json::Value back to Vec<String>
     match two_factor.backup_codes {
         Some(codes_value) => {
             match serde_json::from_value::<Vec<String>>(codes_value) {
-                Ok(codes) => HttpResponse::Ok().json(TwoFactorBackupCodesResponse {
+                Ok(codes) => Ok(HttpResponse::Ok().json(TwoFactorBackupCodesResponse {
                     backup_codes: codes,
-                }),
+                })),
                 Err(e) => {
                     error!("Failed to deserialize backup codes from DB: {:?}", e);
-                    HttpResponse::InternalServerError().json(json!({
-                        "error": "Could not read backup codes"
-                    }))
+                    Err(ApiError::Internal("Could not read backup codes".to_string()))
                 }
             }
         },
-        None => HttpResponse::NotFound().json(json!({
-            "error": "No backup codes found"
-        })),
+        None => Err(ApiError::NotFound("No backup codes found".to_string())),
     }
 }
 
-#[post("/api/auth/2fa/regenerate-backup-codes")]
+#[post("/2fa/regenerate-backup-codes")]
 pub async fn two_factor_regenerate_backup_codes(
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
-    jwt_secret: web::Data<String>,
+    auth_user: AuthUser,
     verify_req: web::Json<TwoFactorVerifyRequest>,
-) -> impl Responder {
-    // Try to extract the token from the cookie first
-    let mut token_str = None;
-    
-    // Check for auth_token cookie
-    if let Some(cookie) = req.cookie("auth_token") {
-        debug!("Found auth_token cookie for /api/auth/2fa/regenerate-backup-codes endpoint");
-        token_str = Some(cookie.value().to_string());
-    }
-    
-    // If no cookie found, try the Authorization header as fallback
-    if token_str.is_none() {
-        debug!("No auth_token cookie found, checking Authorization header for /api/auth/2fa/regenerate-backup-codes endpoint");
-        
-        // Extract the token from the Authorization header
-        let auth_header = match req.headers().get(header::AUTHORIZATION) {
-            Some(header) => header,
-            None => {
-                warn!("No authentication found (neither cookie nor Authorization header) for /api/auth/2fa/regenerate-backup-codes endpoint");
-                return HttpResponse::Unauthorized().json(
-                    json!({
-                        "error": "Unauthorized",
-                        "message": "Authentication required"
-                    })
-                );
-            }
-        };
-
-        let auth_str = match auth_header.to_str() {
-            Ok(str) => str,
-            Err(e) => {
-                warn!("Invalid Authorization header format: {:?}", e);
-                return HttpResponse::Unauthorized().json(
-                    json!({
-                        "error": "Unauthorized",
-                        "message": "Invalid Authorization header format"
-                    })
-                );
-            }
-        };
-
-        if !auth_str.starts_with("Bearer ") {
-            warn!("Authorization header does not start with 'Bearer '");
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid Authorization header format"
-                })
-            );
-        }
-
-        token_str = Some(auth_str.trim_start_matches("Bearer ").trim().to_string());
-    }
-    
-    // Unwrap the token (we know it's Some at this point)
-    let token = token_str.unwrap();
-
-    // Check if the token is blacklisted
-    if is_token_blacklisted(&token) {
-        warn!("Token is blacklisted");
-        return HttpResponse::Unauthorized().json(
-            json!({
-                "error": "Unauthorized",
-                "message": "Token has been invalidated"
-            })
-        );
-    }
-
-    // Configure validation to explicitly check expiration
-    let mut validation = Validation::default();
-    validation.validate_exp = true;
-    validation.leeway = 0; // No leeway for expiration time
-
-    // Validate the token
-    let token_data = match decode::<Claims>(
-        &token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
-        &validation,
-    ) {
-        Ok(data) => data,
-        Err(e) => {
-            match e.kind() {
-                ErrorKind::ExpiredSignature => {
-                    warn!("Token expired");
-                    return HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Token expired"
-                        })
-                    );
-                }
-                ErrorKind::InvalidSignature => {
-                    warn!("Invalid token signature");
-                    return HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Invalid token signature"
-                        })
-                    );
-                }
-                _ => {
-                    warn!("Invalid token: {:?}", e);
-                    return HttpResponse::Unauthorized().json(
-                        json!({
-                            "error": "Unauthorized",
-                            "message": "Invalid token"
-                        })
-                    );
-                }
-            }
-        }
-    };
-
-    let claims = token_data.claims;
-
-    // Ensure backend_user_id is present
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            warn!("Missing backend_user_id in token");
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Missing user ID in token"
-                })
-            );
-        }
-    };
-
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
-        Err(e) => {
-            warn!("Invalid backend_user_id format in token: {:?}", e);
-            return HttpResponse::Unauthorized().json(
-                json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid user ID format in token"
-                })
-            );
-        }
-    };
+) -> Result<HttpResponse, ApiError> {
+    let user_id = auth_user.id;
 
     debug!("Regenerating backup codes for user ID: {}", user_id);
 
@@ -1401,24 +528,17 @@ pub async fn two_factor_regenerate_backup_codes(
         .await
     {
         Ok(Some(two_factor)) => two_factor,
-        _ => return HttpResponse::NotFound().json(json!({
-            "error": "Two-factor authentication not set up"
-        })),
+        _ => return Err(ApiError::NotFound("Two-factor authentication not set up".to_string())),
     };
 
     // Check if 2FA is enabled
     if !two_factor.enabled {
-        return HttpResponse::BadRequest().json(json!({
-            "error": "Two-factor authentication is not enabled"
-        }));
+        return Err(ApiError::BadRequest("Two-factor authentication is not enabled".to_string()));
     }
 
     // Verify TOTP code
     if !verify_totp(&two_factor.secret, &verify_req.code) {
-        return HttpResponse::BadRequest().json(json!({
-            "success": false,
-            "message": "Invalid verification code"
-        }));
+        return Err(ApiError::InvalidTwoFactorCode);
     }
 
     // Generate new backup codes
@@ -1431,18 +551,16 @@ pub async fn two_factor_regenerate_backup_codes(
     two_factor_active.updated_at = Set(chrono::Utc::now());
 
     match two_factor_active.update(db.as_ref()).await {
-        Ok(_) => HttpResponse::Ok().json(TwoFactorBackupCodesResponse {
+        Ok(_) => Ok(HttpResponse::Ok().json(TwoFactorBackupCodesResponse {
             backup_codes,
-        }),
+        })),
         Err(e) => {
             error!("Failed to regenerate backup codes: {:?}", e);
             if let sea_orm::DbErr::Query(ref query_err) = e {
                 error!("Database query error details: {:?}", query_err);
             }
             error!("backup_codes value: {:?}", Some(json!(backup_codes.clone())));
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to regenerate backup codes"
-            }))
+            Err(ApiError::Database(e))
         },
     }
 }
\ No newline at end of file
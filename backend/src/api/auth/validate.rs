@@ -1,11 +1,13 @@
 use actix_web::{get, web, HttpResponse, Responder, http::header, HttpRequest};
 use log::{debug, error, warn};
 use serde::Serialize;
+use sea_orm::DatabaseConnection;
 use jsonwebtoken::{decode, DecodingKey, Validation, errors::ErrorKind};
 use uuid::Uuid;
 
 use crate::auth::Claims;
-use crate::api::auth::is_token_blacklisted;
+use crate::auth::is_token_blacklisted;
+use crate::config::AppConfig;
 
 #[derive(Debug, Serialize)]
 pub struct ValidateSessionResponse {
@@ -17,10 +19,11 @@ pub struct ValidateSessionResponse {
 
 /// Endpoint to validate a session token
 /// This endpoint validates the token directly without relying on middleware
-#[get("/api/auth/validate")]
+#[get("/validate")]
 pub async fn validate_session(
+    db: web::Data<DatabaseConnection>,
     req: HttpRequest,
-    jwt_secret: web::Data<String>,
+    config: web::Data<AppConfig>,
 ) -> impl Responder {
     // Try to extract the token from the cookie first
     let mut token_str = None;
@@ -63,12 +66,6 @@ pub async fn validate_session(
     // Unwrap the token (we know it's Some at this point)
     let token = token_str.unwrap();
 
-    // Check if the token is blacklisted
-    if is_token_blacklisted(&token) {
-        warn!("Token is blacklisted");
-        return session_invalid();
-    }
-
     // Configure validation to explicitly check expiration
     let mut validation = Validation::default();
     validation.validate_exp = true;
@@ -77,7 +74,7 @@ pub async fn validate_session(
     // Validate the token
     let token_data = match decode::<Claims>(
         &token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
+        &DecodingKey::from_secret(config.jwt_secret.as_bytes()),
         &validation,
     ) {
         Ok(data) => data,
@@ -101,6 +98,12 @@ pub async fn validate_session(
 
     let claims = token_data.claims;
 
+    // Check if the token has been revoked
+    if is_token_blacklisted(db.get_ref(), &claims.jti).await {
+        warn!("Token is blacklisted");
+        return session_invalid();
+    }
+
     // Ensure backend_user_id is present
     let backend_user_id = match claims.backend_user_id {
         Some(id) => id,
@@ -1,6 +1,18 @@
-use actix_web::{get, HttpResponse, Responder};
+use actix_web::{get, web, HttpResponse, Responder};
+use log::{error, warn};
+use migration::{Migrator, MigratorTrait};
+use sea_orm::DatabaseConnection;
 use serde::Serialize;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::api::chat::ws::chat_stats;
+use crate::storage::Storage;
+
+// How long a single readiness check may take before it counts as failed
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+// How long to wait for the chat server actor to answer before reporting it as stuck
+const CHAT_SERVER_WAIT: Duration = Duration::from_millis(250);
 
 #[derive(Serialize)]
 struct HealthResponse {
@@ -10,17 +22,45 @@ struct HealthResponse {
 }
 
 #[derive(Serialize)]
-struct RootResponse {
-    message: String,
+struct CheckResult {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+impl CheckResult {
+    fn ok(detail: Option<String>) -> Self {
+        CheckResult { status: "ok", detail }
+    }
+
+    fn failed(detail: String) -> Self {
+        CheckResult { status: "error", detail: Some(detail) }
+    }
+
+    fn is_ok(&self) -> bool {
+        self.status == "ok"
+    }
+}
+
+#[derive(Serialize)]
+struct ReadinessChecks {
+    database: CheckResult,
+    migrations: CheckResult,
+    uploads: CheckResult,
+    chat_server: CheckResult,
+}
+
+#[derive(Serialize)]
+struct ReadinessResponse {
+    status: &'static str,
     version: String,
+    checks: ReadinessChecks,
 }
 
 #[derive(Serialize)]
-struct MetricsResponse {
-    uptime: u64,
+struct RootResponse {
+    message: String,
     version: String,
-    memory_usage: String,
-    active_connections: u32,
 }
 
 /// Root endpoint that provides basic information about the API
@@ -50,30 +90,101 @@ pub async fn health_check() -> impl Responder {
     })
 }
 
+/// Liveness probe: the process is up and serving requests
+#[get("/health/live")]
+pub async fn health_live() -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "ok"
+    }))
+}
+
+/// Readiness probe: the instance can serve traffic
+///
+/// Returns 503 with the result of every check when any of them fails, so load
+/// balancers can route around a broken instance.
+#[get("/health/ready")]
+pub async fn health_ready(db: web::Data<DatabaseConnection>, storage: web::Data<dyn Storage>) -> impl Responder {
+    let checks = ReadinessChecks {
+        database: check_database(db.get_ref()).await,
+        migrations: check_migrations(db.get_ref()).await,
+        uploads: check_storage(storage.get_ref()).await,
+        chat_server: check_chat_server().await,
+    };
+
+    let ready = checks.database.is_ok()
+        && checks.migrations.is_ok()
+        && checks.uploads.is_ok()
+        && checks.chat_server.is_ok();
+
+    let response = ReadinessResponse {
+        status: if ready { "ready" } else { "not_ready" },
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        checks,
+    };
+
+    if ready {
+        HttpResponse::Ok().json(response)
+    } else {
+        warn!("Readiness check failed: {}", serde_json::to_string(&response.checks).unwrap_or_default());
+        HttpResponse::ServiceUnavailable().json(response)
+    }
+}
+
+async fn check_database(db: &DatabaseConnection) -> CheckResult {
+    match tokio::time::timeout(CHECK_TIMEOUT, db.ping()).await {
+        Ok(Ok(())) => CheckResult::ok(None),
+        Ok(Err(e)) => CheckResult::failed(format!("Database ping failed: {}", e)),
+        Err(_) => CheckResult::failed("Database ping timed out".to_string()),
+    }
+}
+
+async fn check_migrations(db: &DatabaseConnection) -> CheckResult {
+    match tokio::time::timeout(CHECK_TIMEOUT, Migrator::get_pending_migrations(db)).await {
+        Ok(Ok(pending)) if pending.is_empty() => CheckResult::ok(None),
+        Ok(Ok(pending)) => CheckResult::failed(format!("{} pending migration(s)", pending.len())),
+        Ok(Err(e)) => CheckResult::failed(format!("Failed to read migration status: {}", e)),
+        Err(_) => CheckResult::failed("Migration status check timed out".to_string()),
+    }
+}
+
+// Uploads must be writable in the configured storage backend
+async fn check_storage(storage: &dyn Storage) -> CheckResult {
+    match tokio::time::timeout(CHECK_TIMEOUT, storage.check()).await {
+        Ok(Ok(())) => CheckResult::ok(None),
+        Ok(Err(e)) => CheckResult::failed(format!("Upload storage is not writable: {}", e)),
+        Err(_) => CheckResult::failed("Upload storage check timed out".to_string()),
+    }
+}
+
+// The chat server actor must be running and answer its mailbox promptly
+async fn check_chat_server() -> CheckResult {
+    match tokio::time::timeout(CHAT_SERVER_WAIT, chat_stats()).await {
+        Ok(Some(stats)) => CheckResult::ok(Some(format!(
+            "{} connection(s) in {} active room(s)",
+            stats.connections,
+            stats.active_rooms
+        ))),
+        Ok(None) => CheckResult::failed("Chat server is not running".to_string()),
+        Err(_) => CheckResult::failed("Chat server did not answer in time".to_string()),
+    }
+}
+
 /// Metrics endpoint for Prometheus monitoring
-#[get("/metrics")]
-pub async fn metrics() -> impl Responder {
-    let version = env!("CARGO_PKG_VERSION");
-    let uptime = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-    
-    // Basic metrics in Prometheus format
-    let metrics = format!(
-        "# HELP tforce_uptime_seconds Total uptime in seconds\n\
-         # TYPE tforce_uptime_seconds counter\n\
-         tforce_uptime_seconds {}\n\
-         # HELP tforce_version_info Version information\n\
-         # TYPE tforce_version_info gauge\n\
-         tforce_version_info{{version=\"{}\"}} 1\n\
-         # HELP tforce_health_status Health status\n\
-         # TYPE tforce_health_status gauge\n\
-         tforce_health_status 1\n",
-        uptime, version
-    );
-    
-    HttpResponse::Ok()
-        .content_type("text/plain; version=0.0.4; charset=utf-8")
-        .body(metrics)
-}
\ No newline at end of file
+#[get("/api/metrics")]
+pub async fn metrics(db: web::Data<DatabaseConnection>) -> impl Responder {
+    // Sample the chat server's live state for this scrape
+    match chat_stats().await {
+        Some(stats) => crate::metrics::set_chat_stats(stats.connections, stats.active_rooms),
+        None => error!("Failed to sample chat server state for metrics"),
+    }
+
+    match crate::metrics::render(db.get_ref()) {
+        Ok(body) => HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4; charset=utf-8")
+            .body(body),
+        Err(e) => {
+            error!("Failed to encode metrics: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use actix_web::{web, get, HttpRequest, HttpResponse};
+use actix_web::web::Bytes;
+use sea_orm::{ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder};
+use sea_orm::sea_query::Expr;
+use sha2::{Digest, Sha256};
+use chrono::Utc;
+use uuid::Uuid;
+use serde::Deserialize;
+use log::error;
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use crate::auth::AuthUser;
+use crate::models::entities::{ChatAttachment, ChatAttachmentActiveModel, ChatMessage};
+use crate::models::entities::chat_attachment::{self, Model as AttachmentModel};
+use crate::api::ApiError;
+use crate::api::chat::message::ensure_member;
+use crate::api::chat::preview::{preview_key, stored_previews, Preview, StoredPreview};
+use crate::api::media::{Format, MediaKind};
+use crate::storage::{self, Caching, Storage};
+
+// Kinds of attachment, as stored in `chat_attachments.kind`
+pub(crate) const KIND_IMAGE: &str = "image";
+pub(crate) const KIND_VIDEO: &str = "video";
+pub(crate) const KIND_AUDIO: &str = "audio";
+
+// Most attachments a single message may carry
+pub(crate) const MAX_ATTACHMENTS_PER_MESSAGE: usize = 10;
+
+#[derive(Deserialize)]
+pub struct AttachmentPath {
+    attachment_id: Uuid,
+}
+
+// An uploaded file that has been written to storage and is about to be recorded
+pub(crate) struct NewAttachment<'a> {
+    pub owner_id: Uuid,
+    pub room_id: Uuid,
+    pub message_id: Option<Uuid>,
+    pub kind: &'static str,
+    pub storage_key: String,
+    pub mime_type: String,
+    pub data: &'a Bytes,
+    // Reported by the client; the server does not parse audio or video
+    pub duration_ms: Option<i32>,
+    // Generated for images only
+    pub blurhash: Option<String>,
+    pub previews: &'a [Preview],
+}
+
+// Where clients download an attachment from
+pub(crate) fn attachment_url(id: Uuid) -> String {
+    format!("/api/chat/attachments/{}", id)
+}
+
+// Where clients download a preview of an image attachment from
+fn preview_url(id: Uuid, preview: &StoredPreview) -> String {
+    if preview.mime_type == Format::WebP.mime_type(MediaKind::Image) {
+        format!("/api/chat/upload/{}?size={}&format=webp", id, preview.size)
+    } else {
+        format!("/api/chat/upload/{}?size={}", id, preview.size)
+    }
+}
+
+// Render an attachment for clients
+pub(crate) fn attachment_view(attachment: &AttachmentModel) -> serde_json::Value {
+    let previews: Vec<serde_json::Value> = stored_previews(attachment)
+        .iter()
+        .map(|preview| {
+            serde_json::json!({
+                "size": preview.size,
+                "url": preview_url(attachment.id, preview),
+                "mime_type": preview.mime_type,
+                "width": preview.width,
+                "height": preview.height,
+            })
+        })
+        .collect();
+
+    serde_json::json!({
+        "id": attachment.id,
+        "kind": attachment.kind,
+        "url": attachment_url(attachment.id),
+        "mime_type": attachment.mime_type,
+        "size": attachment.size_bytes,
+        "width": attachment.width,
+        "height": attachment.height,
+        "duration_ms": attachment.duration_ms,
+        "blurhash": attachment.blurhash,
+        "previews": previews,
+    })
+}
+
+/// Record an uploaded file, with its size, checksum and, for images, dimensions and previews
+pub(crate) async fn record_attachment<C: ConnectionTrait>(db: &C, new: NewAttachment<'_>) -> Result<AttachmentModel, ApiError> {
+    let previews = if new.previews.is_empty() {
+        None
+    } else {
+        let stored: Vec<StoredPreview> = new
+            .previews
+            .iter()
+            .map(|preview| StoredPreview {
+                size: preview.size,
+                mime_type: preview.format.mime_type(MediaKind::Image).to_string(),
+                width: preview.width,
+                height: preview.height,
+                storage_key: preview_key(&new.storage_key, preview),
+            })
+            .collect();
+        Some(serde_json::to_value(stored).map_err(|e| ApiError::Internal(format!("Failed to record previews: {}", e)))?)
+    };
+
+    let (width, height) = if new.kind == KIND_IMAGE {
+        match image::io::Reader::new(Cursor::new(new.data.as_ref()))
+            .with_guessed_format()
+            .ok()
+            .and_then(|reader| reader.into_dimensions().ok())
+        {
+            Some((width, height)) => (i32::try_from(width).ok(), i32::try_from(height).ok()),
+            None => (None, None),
+        }
+    } else {
+        (None, None)
+    };
+
+    let attachment = ChatAttachmentActiveModel {
+        id: ActiveValue::Set(Uuid::new_v4()),
+        owner_id: ActiveValue::Set(new.owner_id),
+        room_id: ActiveValue::Set(new.room_id),
+        message_id: ActiveValue::Set(new.message_id),
+        kind: ActiveValue::Set(new.kind.to_string()),
+        storage_key: ActiveValue::Set(new.storage_key),
+        mime_type: ActiveValue::Set(new.mime_type),
+        size_bytes: ActiveValue::Set(Some(new.data.len() as i64)),
+        checksum: ActiveValue::Set(Some(hex::encode(Sha256::digest(new.data)))),
+        width: ActiveValue::Set(width),
+        height: ActiveValue::Set(height),
+        duration_ms: ActiveValue::Set(new.duration_ms),
+        blurhash: ActiveValue::Set(new.blurhash),
+        previews: ActiveValue::Set(previews),
+        created_at: ActiveValue::Set(Utc::now()),
+    };
+
+    match ChatAttachment::insert(attachment).exec_with_returning(db).await {
+        Ok(attachment) => Ok(attachment),
+        Err(e) => {
+            error!("Failed to record attachment: {}", e);
+            Err(ApiError::Database(e))
+        }
+    }
+}
+
+/// Attach uploaded files to a new message
+///
+/// Every attachment must have been uploaded by the sender to the message's room and not be sent yet.
+pub(crate) async fn link_attachments<C: ConnectionTrait>(
+    db: &C,
+    attachment_ids: &[Uuid],
+    owner_id: Uuid,
+    room_id: Uuid,
+    message_id: Uuid,
+) -> Result<Vec<AttachmentModel>, ApiError> {
+    if attachment_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    if attachment_ids.len() > MAX_ATTACHMENTS_PER_MESSAGE {
+        return Err(ApiError::Validation(format!(
+            "A message can carry at most {} attachments",
+            MAX_ATTACHMENTS_PER_MESSAGE
+        )));
+    }
+
+    let result = ChatAttachment::update_many()
+        .col_expr(chat_attachment::Column::MessageId, Expr::value(message_id))
+        .filter(chat_attachment::Column::Id.is_in(attachment_ids.to_vec()))
+        .filter(chat_attachment::Column::OwnerId.eq(owner_id))
+        .filter(chat_attachment::Column::RoomId.eq(room_id))
+        .filter(chat_attachment::Column::MessageId.is_null())
+        .exec(db)
+        .await
+        .map_err(ApiError::Database)?;
+
+    // Callers run this in the message's transaction, so a partial match rolls back
+    if result.rows_affected as usize != attachment_ids.len() {
+        return Err(ApiError::Validation(
+            "Attachments must be your own unsent uploads to this room".to_string(),
+        ));
+    }
+
+    ChatAttachment::find()
+        .filter(chat_attachment::Column::MessageId.eq(message_id))
+        .order_by_asc(chat_attachment::Column::CreatedAt)
+        .all(db)
+        .await
+        .map_err(ApiError::Database)
+}
+
+// Rendered attachments of each message, for message views
+pub(crate) async fn attachments_for_messages(
+    db: &DatabaseConnection,
+    message_ids: Vec<Uuid>,
+) -> Result<HashMap<Uuid, Vec<serde_json::Value>>, DbErr> {
+    let mut by_message: HashMap<Uuid, Vec<serde_json::Value>> = HashMap::new();
+    if message_ids.is_empty() {
+        return Ok(by_message);
+    }
+
+    let attachments = ChatAttachment::find()
+        .filter(chat_attachment::Column::MessageId.is_in(message_ids))
+        .order_by_asc(chat_attachment::Column::CreatedAt)
+        .all(db)
+        .await?;
+
+    for attachment in &attachments {
+        if let Some(message_id) = attachment.message_id {
+            by_message.entry(message_id).or_default().push(attachment_view(attachment));
+        }
+    }
+
+    Ok(by_message)
+}
+
+/// Find an attachment the user may download
+///
+/// The user must be a member of the attachment's room, and attachments of deleted messages are gone.
+pub(crate) async fn authorize_download(
+    db: &DatabaseConnection,
+    attachment: Option<AttachmentModel>,
+    user_id: Uuid,
+) -> Result<AttachmentModel, ApiError> {
+    let attachment = attachment.ok_or_else(|| ApiError::NotFound("Attachment not found".to_string()))?;
+
+    ensure_member(db, attachment.room_id, user_id).await?;
+
+    if let Some(message_id) = attachment.message_id {
+        match ChatMessage::find_by_id(message_id).one(db).await {
+            Ok(Some(message)) if !message.is_deleted() => {}
+            Ok(_) => return Err(ApiError::NotFound("Attachment not found".to_string())),
+            Err(e) => return Err(ApiError::Database(e)),
+        }
+    }
+
+    Ok(attachment)
+}
+
+// The attachment stored under a key, for the download routes that predate attachment ids
+pub(crate) async fn find_by_storage_key(db: &DatabaseConnection, storage_key: &str) -> Result<Option<AttachmentModel>, ApiError> {
+    ChatAttachment::find()
+        .filter(chat_attachment::Column::StorageKey.eq(storage_key))
+        .one(db)
+        .await
+        .map_err(ApiError::Database)
+}
+
+// Download an attachment; only members of its room may
+#[get("/attachments/{attachment_id}")]
+pub async fn get_attachment(
+    req: HttpRequest,
+    auth_user: AuthUser,
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<dyn Storage>,
+    path: web::Path<AttachmentPath>,
+) -> Result<HttpResponse, ApiError> {
+    let attachment = ChatAttachment::find_by_id(path.attachment_id)
+        .one(db.get_ref())
+        .await
+        .map_err(ApiError::Database)?;
+    let attachment = authorize_download(db.get_ref(), attachment, auth_user.id).await?;
+
+    Ok(storage::serve(&req, storage.get_ref(), &attachment.storage_key, &attachment.mime_type, Caching::Private).await?)
+}
@@ -0,0 +1,306 @@
+use actix::Addr;
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use sea_orm::sqlx::postgres::PgListener;
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, DbErr, Statement};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::api::chat::presence::PresenceStatus;
+use crate::api::chat::ws::{ChatServer, RemoteEvent, Resync, WsResponse};
+
+// PostgreSQL channel every instance listens on
+const NOTIFY_CHANNEL: &str = "tforce_chat";
+
+// PostgreSQL rejects NOTIFY payloads of 8000 bytes or more
+const MAX_NOTIFY_PAYLOAD: usize = 7999;
+
+// Pause before listening again after the listener connection failed, doubled
+// on every failure in a row up to LISTEN_RETRY_MAX_DELAY
+const LISTEN_RETRY_DELAY: Duration = Duration::from_secs(1);
+const LISTEN_RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+// How long envelopes too large for NOTIFY are kept for other instances to load
+const STORED_PAYLOAD_RETENTION: &str = "10 minutes";
+
+/// A change to the chat server's state that every instance has to apply
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BackplaneMessage {
+    // Deliver an event to the room's websockets, optionally keeping it in the room history
+    Broadcast {
+        room_id: String,
+        event: WsResponse,
+        #[serde(default)]
+        store_in_history: bool,
+    },
+    // Overwrite fields of a message kept in a room's recent history
+    PatchHistory {
+        room_id: String,
+        message_id: String,
+        fields: serde_json::Value,
+    },
+    // Drop a room's cached history, which is reloaded from the database on the next join
+    InvalidateHistory { room_id: String },
+    AddMembership { room_id: String, user_id: Uuid },
+    // The user left the room; their websockets leave it too
+    RemoveMembership { room_id: String, user_id: Uuid },
+    // The user was kicked or banned: also lifts their mute
+    EvictMember { room_id: String, user_id: Uuid },
+    RemoveRoom { room_id: String },
+    SetMute {
+        room_id: String,
+        user_id: Uuid,
+        until: Option<DateTime<Utc>>,
+    },
+    CloseSessionConnections { session_id: Uuid },
+    // A user's presence on one instance, with the rooms to tell about it
+    Presence {
+        instance: Uuid,
+        user_id: Uuid,
+        user_name: String,
+        status: PresenceStatus,
+        rooms: Vec<String>,
+    },
+    // The instance is alive; presence it published is dropped when these stop
+    InstanceHeartbeat { instance: Uuid },
+}
+
+/// Carries chat server changes between backend instances
+///
+/// The chat server applies every change locally first and then publishes it;
+/// implementations deliver what other instances published as `RemoteEvent`s.
+pub trait Backplane: Send + Sync {
+    /// Hand a change to the other instances without waiting for it to be sent
+    fn publish(&self, message: BackplaneMessage);
+
+    /// Start delivering changes published by other instances to the chat server
+    fn subscribe(&self, server: Addr<ChatServer>);
+}
+
+/// Backplane for a single instance: there is nobody to tell
+pub struct InProcessBackplane;
+
+impl Backplane for InProcessBackplane {
+    fn publish(&self, _message: BackplaneMessage) {}
+
+    fn subscribe(&self, _server: Addr<ChatServer>) {}
+}
+
+// What goes over the wire, tagged with the instance that published it
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    origin: Uuid,
+    message: BackplaneMessage,
+}
+
+// Sent instead of an envelope too large for NOTIFY; the envelope itself is in
+// chat_backplane_payloads under `stored_payload`
+#[derive(Serialize, Deserialize)]
+struct StoredEnvelope {
+    origin: Uuid,
+    stored_payload: Uuid,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Notification {
+    Inline(Envelope),
+    Stored(StoredEnvelope),
+}
+
+/// Backplane over PostgreSQL `LISTEN`/`NOTIFY`, using the application database
+///
+/// Notifications are published one at a time in order. Every instance hears its
+/// own notifications too and drops them by origin id. Envelopes over the NOTIFY
+/// payload limit are written to `chat_backplane_payloads` and only their id is
+/// sent; listeners load them from there.
+pub struct PostgresBackplane {
+    origin: Uuid,
+    db: DatabaseConnection,
+    outbox: mpsc::UnboundedSender<String>,
+    // Taken by the publisher task when it starts
+    pending_outbox: Mutex<Option<mpsc::UnboundedReceiver<String>>>,
+}
+
+impl PostgresBackplane {
+    pub fn new(db: DatabaseConnection) -> Self {
+        let (outbox, rx) = mpsc::unbounded_channel();
+        Self {
+            origin: Uuid::new_v4(),
+            db,
+            outbox,
+            pending_outbox: Mutex::new(Some(rx)),
+        }
+    }
+}
+
+impl Backplane for PostgresBackplane {
+    fn publish(&self, message: BackplaneMessage) {
+        let payload = match serde_json::to_string(&Envelope { origin: self.origin, message }) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("Failed to serialize backplane message: {}", e);
+                return;
+            }
+        };
+
+        if self.outbox.send(payload).is_err() {
+            error!("Backplane publisher is not running");
+        }
+    }
+
+    fn subscribe(&self, server: Addr<ChatServer>) {
+        let Some(mut outbox) = self.pending_outbox.lock().ok().and_then(|mut rx| rx.take()) else {
+            error!("Backplane is already subscribed");
+            return;
+        };
+
+        let db = self.db.clone();
+        let origin = self.origin;
+        actix::spawn(async move {
+            while let Some(payload) = outbox.recv().await {
+                if let Err(e) = notify(&db, origin, payload).await {
+                    error!("Failed to publish backplane message: {}", e);
+                }
+            }
+        });
+
+        let db = self.db.clone();
+        let origin = self.origin;
+        actix::spawn(async move {
+            let mut listener = listen(&db).await;
+            info!("Listening for chat events from other instances on {}", NOTIFY_CHANNEL);
+
+            loop {
+                // Notifications sent while the connection is down are lost, so after listening
+                // again the server reloads whatever they could have changed
+                let notification = match listener.try_recv().await {
+                    Ok(Some(notification)) => notification,
+                    Ok(None) => {
+                        warn!("Backplane listener connection lost, listening again");
+                        listener = listen(&db).await;
+                        server.do_send(Resync);
+                        continue;
+                    }
+                    Err(e) => {
+                        error!("Backplane listener failed: {}", e);
+                        listener = listen(&db).await;
+                        server.do_send(Resync);
+                        continue;
+                    }
+                };
+
+                let envelope = match serde_json::from_str::<Notification>(notification.payload()) {
+                    Ok(Notification::Inline(envelope)) => envelope,
+                    Ok(Notification::Stored(stored)) if stored.origin == origin => continue,
+                    Ok(Notification::Stored(stored)) => match load_stored(&db, stored.stored_payload).await {
+                        Some(envelope) => envelope,
+                        None => continue,
+                    },
+                    Err(e) => {
+                        error!("Ignoring malformed backplane message: {}", e);
+                        continue;
+                    }
+                };
+                if envelope.origin != origin {
+                    server.do_send(RemoteEvent(envelope.message));
+                }
+            }
+        });
+    }
+}
+
+// Send one serialized envelope, going through chat_backplane_payloads when it is
+// too large for NOTIFY
+async fn notify(db: &DatabaseConnection, origin: Uuid, payload: String) -> Result<(), DbErr> {
+    if payload.len() <= MAX_NOTIFY_PAYLOAD {
+        let statement = Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "SELECT pg_notify($1, $2)",
+            [NOTIFY_CHANNEL.into(), payload.into()],
+        );
+        return db.execute(statement).await.map(|_| ());
+    }
+
+    let id = Uuid::new_v4();
+    let reference = serde_json::to_string(&StoredEnvelope { origin, stored_payload: id })
+        .map_err(|e| DbErr::Custom(e.to_string()))?;
+    // NOTIFY is delivered on commit, so listeners always find the row
+    let statement = Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "WITH stored AS (INSERT INTO chat_backplane_payloads (id, payload) VALUES ($1, $2)) \
+         SELECT pg_notify($3, $4)",
+        [id.into(), payload.into(), NOTIFY_CHANNEL.into(), reference.into()],
+    );
+    db.execute(statement).await?;
+
+    let prune = Statement::from_string(
+        DbBackend::Postgres,
+        format!(
+            "DELETE FROM chat_backplane_payloads WHERE created_at < now() - interval '{}'",
+            STORED_PAYLOAD_RETENTION
+        ),
+    );
+    if let Err(e) = db.execute(prune).await {
+        warn!("Failed to prune stored backplane payloads: {}", e);
+    }
+    Ok(())
+}
+
+// Load an envelope another instance stored instead of sending it inline
+async fn load_stored(db: &DatabaseConnection, id: Uuid) -> Option<Envelope> {
+    let statement = Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "SELECT payload FROM chat_backplane_payloads WHERE id = $1",
+        [id.into()],
+    );
+    let row = match db.query_one(statement).await {
+        Ok(Some(row)) => row,
+        Ok(None) => {
+            warn!("Stored backplane message {} is gone", id);
+            return None;
+        }
+        Err(e) => {
+            error!("Failed to load stored backplane message {}: {}", id, e);
+            return None;
+        }
+    };
+
+    let payload: String = match row.try_get("", "payload") {
+        Ok(payload) => payload,
+        Err(e) => {
+            error!("Failed to read stored backplane message {}: {}", id, e);
+            return None;
+        }
+    };
+    match serde_json::from_str(&payload) {
+        Ok(envelope) => Some(envelope),
+        Err(e) => {
+            error!("Ignoring malformed stored backplane message {}: {}", id, e);
+            None
+        }
+    }
+}
+
+// Open the listener connection and LISTEN on the channel, retrying with backoff
+// until both succeed
+async fn listen(db: &DatabaseConnection) -> PgListener {
+    let mut delay = LISTEN_RETRY_DELAY;
+    loop {
+        match PgListener::connect_with(db.get_postgres_connection_pool()).await {
+            Ok(mut listener) => match listener.listen(NOTIFY_CHANNEL).await {
+                Ok(()) => return listener,
+                Err(e) => error!("Failed to listen on backplane channel {}: {}", NOTIFY_CHANNEL, e),
+            },
+            Err(e) => error!("Failed to open backplane listener connection: {}", e),
+        }
+
+        warn!("Retrying backplane listener in {}s", delay.as_secs());
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(LISTEN_RETRY_MAX_DELAY);
+    }
+}
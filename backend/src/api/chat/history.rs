@@ -0,0 +1,364 @@
+use chrono::Utc;
+use sea_orm::DatabaseConnection;
+use std::collections::{HashMap, VecDeque};
+use uuid::Uuid;
+
+use crate::api::ApiError;
+use crate::api::chat::message::{build_message_views, fetch_from, fetch_latest, find_anchor, Direction, MessageCursor};
+use crate::api::chat::ws::WsResponse;
+use crate::models::entities::chat_message::Model as ChatMessageModel;
+
+// Messages replayed to a websocket that joins a room without a `since` id
+pub(crate) const REPLAY_LIMIT: u64 = 50;
+
+// Most messages replayed after a `since` id; clients page through the rest over REST
+const RESUME_LIMIT: u64 = 200;
+
+// Messages kept per cached room
+const ROOM_CAPACITY: usize = 100;
+
+// Rooms kept before the least recently used one is dropped
+const MAX_CACHED_ROOMS: usize = 256;
+
+struct CachedRoom {
+    messages: VecDeque<WsResponse>,
+    last_used: u64,
+}
+
+/// Latest messages of recently joined rooms, in front of `chat_messages`
+///
+/// A room is only cached after its latest messages were loaded from the database,
+/// so a cached room always ends with the newest message of the room.
+#[derive(Default)]
+pub struct HistoryCache {
+    rooms: HashMap<String, CachedRoom>,
+    clock: u64,
+    // Bumped whenever a load in flight may have become stale, so it is not cached
+    generation: u64,
+}
+
+impl HistoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn touch(&mut self, room_id: &str) -> Option<&mut CachedRoom> {
+        self.clock += 1;
+        let clock = self.clock;
+        let room = self.rooms.get_mut(room_id)?;
+        room.last_used = clock;
+        Some(room)
+    }
+
+    /// The newest `limit` messages of a cached room, oldest first
+    pub fn latest(&mut self, room_id: &str, limit: usize) -> Option<Vec<WsResponse>> {
+        let room = self.touch(room_id)?;
+        let skip = room.messages.len().saturating_sub(limit);
+        Some(room.messages.iter().skip(skip).cloned().collect())
+    }
+
+    /// Messages of a cached room after `message_id`, or None when the cache does not reach back to it
+    pub fn after(&mut self, room_id: &str, message_id: &str) -> Option<Vec<WsResponse>> {
+        let room = self.touch(room_id)?;
+        let position = room
+            .messages
+            .iter()
+            .position(|m| m.message_id.as_deref() == Some(message_id))?;
+        Some(room.messages.iter().skip(position + 1).cloned().collect())
+    }
+
+    /// Cache the latest messages of a room, unless it was invalidated since `generation`
+    pub fn fill(&mut self, room_id: String, messages: Vec<WsResponse>, generation: u64) {
+        if generation != self.generation {
+            return;
+        }
+
+        let mut messages: VecDeque<WsResponse> = messages.into();
+        while messages.len() > ROOM_CAPACITY {
+            messages.pop_front();
+        }
+
+        self.clock += 1;
+        self.rooms.insert(room_id, CachedRoom { messages, last_used: self.clock });
+
+        if self.rooms.len() > MAX_CACHED_ROOMS {
+            let oldest = self
+                .rooms
+                .iter()
+                .min_by_key(|(_, room)| room.last_used)
+                .map(|(room_id, _)| room_id.clone());
+            if let Some(oldest) = oldest {
+                self.rooms.remove(&oldest);
+            }
+        }
+    }
+
+    /// Append a new message to a cached room; rooms that are not cached are loaded on their next join
+    pub fn push(&mut self, room_id: &str, message: WsResponse) {
+        let Some(room) = self.rooms.get_mut(room_id) else {
+            return;
+        };
+        if message.message_id.is_some() && room.messages.iter().any(|m| m.message_id == message.message_id) {
+            return;
+        }
+
+        room.messages.push_back(message);
+        if room.messages.len() > ROOM_CAPACITY {
+            room.messages.pop_front();
+        }
+    }
+
+    /// Overwrite fields of a cached message
+    pub fn patch(&mut self, room_id: &str, message_id: &str, fields: serde_json::Value) {
+        let Some(room) = self.rooms.get_mut(room_id) else {
+            // A load of the room in flight may have read the message before the change
+            self.generation += 1;
+            return;
+        };
+        let Some(message) = room.messages.iter_mut().find(|m| m.message_id.as_deref() == Some(message_id)) else {
+            return;
+        };
+
+        if let serde_json::Value::Object(fields) = fields {
+            for (key, value) in fields {
+                message.data[key] = value;
+            }
+        }
+    }
+
+    /// Drop a room whose cached messages no longer match the database
+    pub fn invalidate(&mut self, room_id: &str) {
+        self.rooms.remove(room_id);
+        self.generation += 1;
+    }
+
+    /// Drop every room, after changes to them may have been missed
+    pub fn clear(&mut self) {
+        self.rooms.clear();
+        self.generation += 1;
+    }
+}
+
+// Messages to replay to a joining websocket, before they are rendered
+pub(crate) struct Replay {
+    pub messages: Vec<ChatMessageModel>,
+    // More messages follow the replayed ones after `since`, or precede the latest page
+    pub has_more: bool,
+    // The replay continues after the client's `since` id rather than replacing its history
+    pub resumed: bool,
+}
+
+impl Replay {
+    // Cursor for paging the rest of a resumed replay over REST
+    pub fn next_cursor(&self) -> Option<String> {
+        match self.messages.last() {
+            Some(last) if self.resumed && self.has_more => Some(MessageCursor::new(Direction::After, last).encode()),
+            _ => None,
+        }
+    }
+}
+
+// The messages after `since`, or the latest ones when `since` is missing or not in the room
+pub(crate) async fn fetch_replay(db: &DatabaseConnection, room_id: Uuid, since: Option<Uuid>) -> Result<Replay, ApiError> {
+    if let Some(since) = since {
+        match find_anchor(db, room_id, since).await {
+            Ok(anchor) => {
+                let (messages, has_more) =
+                    fetch_from(db, room_id, Direction::After, anchor.created_at, anchor.id, RESUME_LIMIT).await?;
+                return Ok(Replay { messages, has_more, resumed: true });
+            }
+            Err(ApiError::NotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    let page = fetch_latest(db, room_id, REPLAY_LIMIT).await?;
+    Ok(Replay {
+        messages: page.messages,
+        has_more: page.has_more_before,
+        resumed: false,
+    })
+}
+
+// Render replayed messages as the `message` frames live messages are sent as
+pub(crate) async fn replay_frames(db: &DatabaseConnection, messages: Vec<ChatMessageModel>) -> Vec<WsResponse> {
+    let timestamps: Vec<i64> = messages.iter().map(|m| m.created_at.timestamp()).collect();
+    let views = build_message_views(db, messages).await;
+
+    views
+        .into_iter()
+        .zip(timestamps)
+        .map(|(mut view, timestamp)| {
+            let message_id = view["id"].as_str().map(str::to_string);
+            view["timestamp"] = serde_json::json!(timestamp);
+            WsResponse {
+                message_type: "message".to_string(),
+                data: view,
+                timestamp: Utc::now().timestamp(),
+                message_id,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: usize) -> WsResponse {
+        WsResponse {
+            message_type: "message".to_string(),
+            data: serde_json::json!({ "id": id.to_string(), "content": format!("message {}", id) }),
+            timestamp: 0,
+            message_id: Some(id.to_string()),
+        }
+    }
+
+    fn messages(ids: std::ops::Range<usize>) -> Vec<WsResponse> {
+        ids.map(message).collect()
+    }
+
+    fn ids(messages: &[WsResponse]) -> Vec<String> {
+        messages.iter().filter_map(|m| m.message_id.clone()).collect()
+    }
+
+    fn room(n: usize) -> String {
+        format!("room-{}", n)
+    }
+
+    #[test]
+    fn latest_and_after_read_from_the_cached_room() {
+        let mut cache = HistoryCache::new();
+        cache.fill(room(0), messages(0..5), cache.generation());
+
+        assert_eq!(ids(&cache.latest(&room(0), 2).unwrap()), ["3", "4"]);
+        assert_eq!(ids(&cache.after(&room(0), "2").unwrap()), ["3", "4"]);
+        assert!(cache.after(&room(0), "missing").is_none());
+        assert!(cache.latest(&room(1), 2).is_none());
+    }
+
+    #[test]
+    fn rooms_keep_only_their_newest_messages() {
+        let mut cache = HistoryCache::new();
+        cache.fill(room(0), messages(0..ROOM_CAPACITY + 10), cache.generation());
+        cache.push(&room(0), message(ROOM_CAPACITY + 10));
+
+        let cached = cache.latest(&room(0), usize::MAX).unwrap();
+        assert_eq!(cached.len(), ROOM_CAPACITY);
+        assert_eq!(cached.first().unwrap().message_id.as_deref(), Some("11"));
+        assert_eq!(cached.last().unwrap().message_id, Some((ROOM_CAPACITY + 10).to_string()));
+    }
+
+    #[test]
+    fn push_skips_messages_already_cached_and_rooms_not_cached() {
+        let mut cache = HistoryCache::new();
+        cache.fill(room(0), messages(0..2), cache.generation());
+        cache.push(&room(0), message(1));
+        cache.push(&room(1), message(5));
+
+        assert_eq!(ids(&cache.latest(&room(0), 10).unwrap()), ["0", "1"]);
+        assert!(cache.latest(&room(1), 10).is_none());
+    }
+
+    #[test]
+    fn least_recently_used_room_is_evicted() {
+        let mut cache = HistoryCache::new();
+        for n in 0..MAX_CACHED_ROOMS {
+            cache.fill(room(n), messages(0..1), cache.generation());
+        }
+        // Reading room 0 makes room 1 the least recently used
+        cache.latest(&room(0), 1);
+        cache.fill(room(MAX_CACHED_ROOMS), messages(0..1), cache.generation());
+
+        assert!(cache.latest(&room(0), 1).is_some());
+        assert!(cache.latest(&room(1), 1).is_none());
+        assert!(cache.latest(&room(2), 1).is_some());
+        assert!(cache.latest(&room(MAX_CACHED_ROOMS), 1).is_some());
+    }
+
+    #[test]
+    fn edit_patches_the_cached_message() {
+        let mut cache = HistoryCache::new();
+        cache.fill(room(0), messages(0..3), cache.generation());
+        let generation = cache.generation();
+
+        cache.patch(&room(0), "1", serde_json::json!({ "content": "edited", "edited_at": "2025-09-02T00:00:00Z" }));
+
+        let cached = cache.latest(&room(0), 10).unwrap();
+        assert_eq!(cached[1].data["content"], "edited");
+        assert_eq!(cached[1].data["edited_at"], "2025-09-02T00:00:00Z");
+        assert_eq!(cached[0].data["content"], "message 0");
+        // Patching a cached room leaves loads in flight valid
+        assert_eq!(cache.generation(), generation);
+    }
+
+    #[test]
+    fn delete_turns_the_cached_message_into_a_tombstone() {
+        let mut cache = HistoryCache::new();
+        cache.fill(room(0), messages(0..3), cache.generation());
+
+        cache.patch(&room(0), "2", serde_json::json!({ "content": "", "deleted": true }));
+
+        let cached = cache.latest(&room(0), 10).unwrap();
+        assert_eq!(cached[2].data["content"], "");
+        assert_eq!(cached[2].data["deleted"], true);
+    }
+
+    #[test]
+    fn edit_during_a_load_discards_the_loaded_messages() {
+        let mut cache = HistoryCache::new();
+        let generation = cache.generation();
+
+        cache.patch(&room(0), "1", serde_json::json!({ "content": "edited" }));
+        cache.fill(room(0), messages(0..3), generation);
+
+        assert!(cache.latest(&room(0), 10).is_none());
+    }
+
+    #[test]
+    fn delete_during_a_load_discards_the_loaded_messages() {
+        let mut cache = HistoryCache::new();
+        let generation = cache.generation();
+
+        cache.patch(&room(0), "1", serde_json::json!({ "content": "", "deleted": true }));
+        cache.fill(room(0), messages(0..3), generation);
+
+        assert!(cache.latest(&room(0), 10).is_none());
+
+        // A load started after the delete is cached
+        cache.fill(room(0), messages(0..3), cache.generation());
+        assert!(cache.latest(&room(0), 10).is_some());
+    }
+
+    #[test]
+    fn clear_drops_every_room_and_loads_in_flight() {
+        let mut cache = HistoryCache::new();
+        cache.fill(room(0), messages(0..3), cache.generation());
+        cache.fill(room(1), messages(0..3), cache.generation());
+        let generation = cache.generation();
+
+        cache.clear();
+        assert!(cache.latest(&room(0), 10).is_none());
+        assert!(cache.latest(&room(1), 10).is_none());
+
+        cache.fill(room(0), messages(0..3), generation);
+        assert!(cache.latest(&room(0), 10).is_none());
+    }
+
+    #[test]
+    fn invalidate_drops_the_room_and_loads_in_flight() {
+        let mut cache = HistoryCache::new();
+        cache.fill(room(0), messages(0..3), cache.generation());
+        let generation = cache.generation();
+
+        cache.invalidate(&room(0));
+        assert!(cache.latest(&room(0), 10).is_none());
+
+        cache.fill(room(0), messages(0..3), generation);
+        assert!(cache.latest(&room(0), 10).is_none());
+    }
+}
@@ -1,10 +1,13 @@
-use actix_web::{web, HttpResponse, post, HttpRequest, Error};
+use actix_web::{web, HttpResponse, post, HttpRequest};
 use sea_orm::{DatabaseConnection, EntityTrait, ActiveValue, QueryFilter, ColumnTrait, PaginatorTrait};
 use uuid::Uuid;
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use crate::auth::{AuthUser, JwtAuth};
-use crate::models::entities::{ChatRoom, RoomResponseDto, RoomMembership, RoomMembershipActiveModel, User};
+use crate::auth::AuthUser;
+use crate::models::entities::{ChatRoom, RoomResponseDto, RoomMembership, RoomMembershipActiveModel, RoomRole, User};
+use crate::api::ApiError;
+use crate::api::chat::moderation::ensure_not_banned;
+use crate::api::chat::ws::cache_room_membership;
 
 use argon2::{
     password_hash::{
@@ -27,92 +30,25 @@ pub struct JoinRoomByCodeResponse {
     pub room: Option<RoomResponseDto>,
 }
 
-#[post("/api/chat/rooms/join-by-code")]
+#[post("/rooms/join-by-code")]
 pub async fn join_room_by_code(
+    auth: AuthUser,
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
-    jwt_secret: web::Data<String>,
+    _req: HttpRequest,
     join_data: web::Json<JoinRoomByCodeRequest>,
-) -> Result<HttpResponse, Error> {
-    // Extract token strictly from cookie (cookie-based auth)
-    let token = match req.cookie("auth_token") {
-        Some(cookie) => cookie.value().to_string(),
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(JoinRoomByCodeResponse {
-                success: false,
-                message: "Unauthorized: Missing auth_token cookie".to_string(),
-                room: None,
-            }));
-        }
-    };
-
-    // Validate token and get user
-    let claims = match JwtAuth::validate_token(&token, &jwt_secret) {
-        Ok(claims) => claims,
-        Err(_) => {
-            return Ok(HttpResponse::Unauthorized().json(JoinRoomByCodeResponse {
-                success: false,
-                message: "Unauthorized: Invalid authentication token".to_string(),
-                room: None,
-            }));
-        }
-    };
-
-    // Ensure backend_user_id is present and valid
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(JoinRoomByCodeResponse {
-                success: false,
-                message: "Unauthorized: Missing user ID in token".to_string(),
-                room: None,
-            }));
-        }
-    };
-
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
-        Err(e) => {
-            log::error!(
-                "Failed to parse backend_user_id into UUID. Value: '{}', Error: {}",
-                backend_user_id,
-                e
-            );
-            return Ok(HttpResponse::Unauthorized().json(JoinRoomByCodeResponse {
-                success: false,
-                message: "Unauthorized: Invalid user ID format in token".to_string(),
-                room: None,
-            }));
-        }
-    };
-
-    // Create AuthUser from claims
-    let auth = AuthUser {
-        id: user_id,
-        email: claims.email,
-        name: claims.name,
-        role: claims.user_role.unwrap_or_else(|| "user".to_string()),
-        profile_image: claims.profile_image,
-    };
+) -> Result<HttpResponse, ApiError> {
+    let _user_id = auth.id;
 
     // Ensure user exists to satisfy FK constraint
     match User::find_by_id(auth.id).one(db.get_ref()).await {
         Ok(Some(_)) => { /* exists */ },
         Ok(None) => {
             log::error!("join_room_by_code: Authenticated user not found in DB: {}", auth.id);
-            return Ok(HttpResponse::Unauthorized().json(JoinRoomByCodeResponse {
-                success: false,
-                message: "Unauthorized: user does not exist".to_string(),
-                room: None,
-            }));
+            return Err(ApiError::Unauthorized("User account no longer exists".to_string()));
         },
         Err(e) => {
             log::error!("join_room_by_code: Error querying user {}: {}", auth.id, e);
-            return Ok(HttpResponse::InternalServerError().json(JoinRoomByCodeResponse {
-                success: false,
-                message: "Server error while validating user".to_string(),
-                room: None,
-            }));
+            return Err(ApiError::Database(e));
         }
     }
 
@@ -123,16 +59,14 @@ pub async fn join_room_by_code(
         .one(db.get_ref())
         .await {
         Ok(Some(room)) => {
+            ensure_not_banned(db.get_ref(), room.id, auth.id).await?;
+
             // --- MODIFICATION START: Argon2 Password Verification ---
             if let Some(password_hash) = &room.password_hash {
                 let provided_password = match &join_data.password {
                     Some(p) => p,
                     None => {
-                        return Ok(HttpResponse::BadRequest().json(JoinRoomByCodeResponse {
-                            success: false,
-                            message: "This room requires a password.".to_string(),
-                            room: None,
-                        }));
+                        return Err(ApiError::RoomPasswordRequired);
                     }
                 };
 
@@ -142,11 +76,7 @@ pub async fn join_room_by_code(
                     Err(e) => {
                         log::error!("Failed to parse password hash from DB for room {}: {}", room.id, e);
                         // This indicates a problem with the stored hash, which is a server error.
-                        return Ok(HttpResponse::InternalServerError().json(JoinRoomByCodeResponse {
-                            success: false,
-                            message: "Server configuration error.".to_string(),
-                            room: None,
-                        }));
+                        return Err(ApiError::Internal("Server configuration error.".to_string()));
                     }
                 };
 
@@ -158,20 +88,12 @@ pub async fn join_room_by_code(
                     },
                     Err(argon2::password_hash::Error::Password) => {
                         // This specific error means the password did not match.
-                        return Ok(HttpResponse::Unauthorized().json(JoinRoomByCodeResponse {
-                            success: false,
-                            message: "Invalid password.".to_string(),
-                            room: None,
-                        }));
+                        return Err(ApiError::RoomPasswordInvalid);
                     },
                     Err(e) => {
                         // Any other error is an internal server issue.
                         log::error!("Argon2 verification failed for room {}: {}", room.id, e);
-                        return Ok(HttpResponse::InternalServerError().json(JoinRoomByCodeResponse {
-                            success: false,
-                            message: "Error during password verification.".to_string(),
-                            room: None,
-                        }));
+                        return Err(ApiError::Internal("Error during password verification.".to_string()));
                     }
                 }
             }
@@ -208,6 +130,8 @@ pub async fn join_room_by_code(
                         is_owner: room.created_by == auth.id,
                         room_code: room.room_code,
                         user_count,
+                        unread_count: 0,
+                        first_unread_message_id: None,
                     };
 
                     return Ok(HttpResponse::Ok().json(JoinRoomByCodeResponse {
@@ -223,9 +147,15 @@ pub async fn join_room_by_code(
                         user_id: ActiveValue::Set(auth.id),
                         room_id: ActiveValue::Set(room.id),
                         joined_at: ActiveValue::Set(Utc::now()),
+                        last_read_message_id: ActiveValue::Set(None),
+                        last_read_at: ActiveValue::Set(None),
+                        role: ActiveValue::Set(RoomRole::Member.as_str().to_string()),
+                        muted_until: ActiveValue::Set(None),
                     };
                     match RoomMembership::insert(membership).exec(db.get_ref()).await {
                         Ok(_) => {
+                            cache_room_membership(room.id, auth.id);
+
                             // Get user count for this room (after joining)
                             let user_count_result = RoomMembership::find()
                                 .filter(crate::models::entities::room_membership::Column::RoomId.eq(room.id))
@@ -250,6 +180,8 @@ pub async fn join_room_by_code(
                                 is_owner: room.created_by == auth.id,
                                 room_code: room.room_code,
                                 user_count,
+                                unread_count: 0,
+                                first_unread_message_id: None,
                             };
                             Ok(HttpResponse::Ok().json(JoinRoomByCodeResponse {
                                 success: true,
@@ -259,38 +191,22 @@ pub async fn join_room_by_code(
                         },
                         Err(e) => {
                             log::error!("Failed to create room membership: {}", e);
-                            Ok(HttpResponse::BadRequest().json(JoinRoomByCodeResponse {
-                                success: false,
-                                message: "Failed to join room due to invalid user or room reference".to_string(),
-                                room: None,
-                            }))
+                            Err(ApiError::BadRequest("Failed to join room due to invalid user or room reference".to_string()))
                         }
                     }
                 },
                 Err(e) => {
                     log::error!("Failed to check room membership: {}", e);
-                    Ok(HttpResponse::InternalServerError().json(JoinRoomByCodeResponse {
-                        success: false,
-                        message: "Failed to check room membership".to_string(),
-                        room: None,
-                    }))
+                    Err(ApiError::Database(e))
                 }
             }
         },
         Ok(None) => {
-            Ok(HttpResponse::NotFound().json(JoinRoomByCodeResponse {
-                success: false,
-                message: "Room not found with the provided code".to_string(),
-                room: None,
-            }))
+            Err(ApiError::NotFound("Room not found with the provided code".to_string()))
         },
         Err(e) => {
             log::error!("Failed to find room by code: {}", e);
-            Ok(HttpResponse::InternalServerError().json(JoinRoomByCodeResponse {
-                success: false,
-                message: "Failed to find room".to_string(),
-                room: None,
-            }))
+            Err(ApiError::Database(e))
         }
     }
 }
\ No newline at end of file
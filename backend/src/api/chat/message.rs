@@ -1,10 +1,19 @@
-use actix_web::{web, HttpResponse, post, get, Responder, HttpRequest, Error};
-use sea_orm::{DatabaseConnection, EntityTrait, Set, ActiveValue, QueryOrder, Order, QueryFilter, ColumnTrait, QuerySelect};
+use actix_web::{web, HttpResponse, post, get, HttpRequest};
+use sea_orm::{DatabaseConnection, DbErr, EntityTrait, ActiveValue, QueryOrder, Order, QueryFilter, ColumnTrait, QuerySelect, TransactionTrait};
+use sea_orm::sea_query::Expr;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use uuid::Uuid;
-use chrono::Utc;
-use serde::{Deserialize, Serialize};
-use crate::auth::{AuthUser, JwtAuth, extract_token_from_cookie_or_header};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use crate::auth::AuthUser;
+use crate::models::entities::chat_message::{Column as MessageColumn, Model as ChatMessageModel};
 use crate::models::entities::{ChatMessage, ChatMessageActiveModel, CreateMessageDto, MessageResponseDto, MessageWithUserDto, User, RoomMembership, MessageReaction};
+use crate::api::ApiError;
+use crate::api::chat::attachment::{attachment_view, attachments_for_messages, link_attachments};
+use crate::api::chat::thread::broadcast_thread_reply;
+use crate::api::chat::moderation::ensure_can_post;
+use crate::api::chat::ws::invalidate_room_history;
+use crate::metrics;
 use std::collections::{HashMap, HashSet};
 
 #[derive(Deserialize)]
@@ -12,67 +21,316 @@ pub struct MessagePath {
     room_id: Uuid,
 }
 
+// Page size when the client does not ask for one, and the largest page we serve
+const DEFAULT_PAGE_SIZE: u64 = 200;
+const MAX_PAGE_SIZE: u64 = 200;
+
+// At most one of the anchors (or a cursor) may be given; without any the latest page is returned
 #[derive(Deserialize)]
 pub struct MessageQuery {
     limit: Option<u64>,
-    offset: Option<u64>,
+    // Messages older than this message id
+    before: Option<Uuid>,
+    // Messages newer than this message id
+    after: Option<Uuid>,
+    // The page centred on this message id, for jump-to-message
+    around: Option<Uuid>,
+    // Opaque next/prev cursor from a previous response
+    cursor: Option<String>,
 }
 
-#[post("/api/chat/messages")]
-pub async fn send_message(
-    db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
-    jwt_secret: web::Data<String>,
-    message_data: web::Json<CreateMessageDto>,
-) -> Result<HttpResponse, Error> {
-    // Extract token from request
-    let token = match extract_token_from_cookie_or_header(&req) {
-        Some(token) => token,
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: No authentication token found"
-            })));
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Direction {
+    Before,
+    After,
+}
+
+// Position in a room's history, ordered by (created_at, id)
+pub(crate) struct MessageCursor {
+    pub direction: Direction,
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl MessageCursor {
+    pub fn new(direction: Direction, message: &ChatMessageModel) -> Self {
+        Self {
+            direction,
+            created_at: message.created_at,
+            id: message.id,
         }
+    }
+
+    pub fn encode(&self) -> String {
+        let direction = match self.direction {
+            Direction::Before => "b",
+            Direction::After => "a",
+        };
+        let raw = format!("{}:{}:{}", direction, self.created_at.timestamp_micros(), self.id);
+        URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let raw = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+        let mut parts = raw.splitn(3, ':');
+
+        let direction = match parts.next()? {
+            "b" => Direction::Before,
+            "a" => Direction::After,
+            _ => return None,
+        };
+        let created_at = DateTime::from_timestamp_micros(parts.next()?.parse().ok()?)?;
+        let id = Uuid::parse_str(parts.next()?).ok()?;
+
+        Some(Self { direction, created_at, id })
+    }
+}
+
+// One page of history in chronological order
+pub(crate) struct MessagePage {
+    pub messages: Vec<ChatMessageModel>,
+    pub has_more_before: bool,
+    pub has_more_after: bool,
+}
+
+// Up to `limit` messages strictly before or after `(created_at, id)`, in chronological order,
+// and whether more exist beyond them
+pub(crate) async fn fetch_from(
+    db: &DatabaseConnection,
+    room_id: Uuid,
+    direction: Direction,
+    created_at: DateTime<Utc>,
+    id: Uuid,
+    limit: u64,
+) -> Result<(Vec<ChatMessageModel>, bool), DbErr> {
+    let key = Expr::tuple([Expr::col(MessageColumn::CreatedAt).into(), Expr::col(MessageColumn::Id).into()]);
+    let anchor = Expr::tuple([Expr::value(created_at), Expr::value(id)]);
+
+    let (condition, order) = match direction {
+        Direction::Before => (key.lt(anchor), Order::Desc),
+        Direction::After => (key.gt(anchor), Order::Asc),
     };
 
-    // Validate token and get user
-    let claims = match JwtAuth::validate_token(&token, &jwt_secret) {
-        Ok(claims) => claims,
-        Err(_) => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Invalid authentication token"
-            })));
-        }
+    let mut messages = ChatMessage::find()
+        .filter(MessageColumn::RoomId.eq(room_id))
+        .filter(MessageColumn::ReplyToId.is_null())
+        .filter(condition)
+        .order_by(MessageColumn::CreatedAt, order.clone())
+        .order_by(MessageColumn::Id, order)
+        .limit(limit + 1)
+        .all(db)
+        .await?;
+
+    let has_more = messages.len() as u64 > limit;
+    messages.truncate(limit as usize);
+
+    if direction == Direction::Before {
+        messages.reverse();
+    }
+
+    Ok((messages, has_more))
+}
+
+// The newest `limit` messages of a room
+pub(crate) async fn fetch_latest(db: &DatabaseConnection, room_id: Uuid, limit: u64) -> Result<MessagePage, DbErr> {
+    let mut messages = ChatMessage::find()
+        .filter(MessageColumn::RoomId.eq(room_id))
+        .filter(MessageColumn::ReplyToId.is_null())
+        .order_by(MessageColumn::CreatedAt, Order::Desc)
+        .order_by(MessageColumn::Id, Order::Desc)
+        .limit(limit + 1)
+        .all(db)
+        .await?;
+
+    let has_more_before = messages.len() as u64 > limit;
+    messages.truncate(limit as usize);
+    messages.reverse();
+
+    Ok(MessagePage {
+        messages,
+        has_more_before,
+        has_more_after: false,
+    })
+}
+
+// Look up an anchor message, which must belong to the room being paged
+//
+// Replies are not part of the room timeline, so a reply anchors on the root of its thread.
+pub(crate) async fn find_anchor(db: &DatabaseConnection, room_id: Uuid, message_id: Uuid) -> Result<ChatMessageModel, ApiError> {
+    let message = match ChatMessage::find_by_id(message_id).one(db).await {
+        Ok(Some(message)) if message.room_id == room_id => message,
+        Ok(_) => return Err(ApiError::NotFound("Message not found in this room".to_string())),
+        Err(e) => return Err(ApiError::Database(e)),
     };
 
-    // Ensure backend_user_id is present and valid
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Missing user ID in token"
-            })));
+    match message.reply_to_id {
+        Some(root_id) => match ChatMessage::find_by_id(root_id).one(db).await {
+            Ok(Some(root)) => Ok(root),
+            Ok(None) => Err(ApiError::NotFound("Message not found in this room".to_string())),
+            Err(e) => Err(ApiError::Database(e)),
+        },
+        None => Ok(message),
+    }
+}
+
+// Fail unless the user is a member of the room
+pub(crate) async fn ensure_member(db: &DatabaseConnection, room_id: Uuid, user_id: Uuid) -> Result<(), ApiError> {
+    match RoomMembership::find()
+        .filter(crate::models::entities::room_membership::Column::RoomId.eq(room_id))
+        .filter(crate::models::entities::room_membership::Column::UserId.eq(user_id))
+        .one(db)
+        .await
+    {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(ApiError::NotRoomMember("Access denied: You are not a member of this room".to_string())),
+        Err(e) => Err(ApiError::Database(e)),
+    }
+}
+
+/// Find the thread a reply to `parent_id` belongs to
+///
+/// Threads are one level deep: replying to a reply joins the parent's thread.
+pub(crate) async fn resolve_thread_root(db: &DatabaseConnection, room_id: Uuid, parent_id: Uuid) -> Result<Uuid, ApiError> {
+    match ChatMessage::find_by_id(parent_id).one(db).await {
+        Ok(Some(parent)) if parent.room_id != room_id => {
+            Err(ApiError::Validation("Replies must be in the same room as the message they reply to".to_string()))
         }
-    };
+        Ok(Some(parent)) if parent.is_deleted() && parent.reply_to_id.is_none() => {
+            Err(ApiError::Conflict("Cannot reply to a deleted message".to_string()))
+        }
+        Ok(Some(parent)) => Ok(parent.reply_to_id.unwrap_or(parent.id)),
+        Ok(None) => Err(ApiError::NotFound("Message being replied to not found".to_string())),
+        Err(e) => Err(ApiError::Database(e)),
+    }
+}
 
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
-        Err(_) => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Invalid user ID format"
-            })));
+// Reply count and latest reply of a thread
+#[derive(Clone, Default)]
+pub(crate) struct ThreadSummary {
+    pub reply_count: i64,
+    pub last_reply_at: Option<DateTime<Utc>>,
+    pub last_reply_user_id: Option<Uuid>,
+}
+
+// Thread summaries of the given root messages; roots without live replies are left out
+pub(crate) async fn thread_summaries(
+    db: &DatabaseConnection,
+    root_ids: Vec<Uuid>,
+) -> Result<HashMap<Uuid, ThreadSummary>, DbErr> {
+    if root_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let counts: Vec<(Uuid, i64)> = ChatMessage::find()
+        .select_only()
+        .column(MessageColumn::ReplyToId)
+        .column_as(MessageColumn::Id.count(), "reply_count")
+        .filter(MessageColumn::ReplyToId.is_in(root_ids.clone()))
+        .filter(MessageColumn::DeletedAt.is_null())
+        .group_by(MessageColumn::ReplyToId)
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    // Latest live reply of every thread
+    let last_replies = ChatMessage::find()
+        .distinct_on([MessageColumn::ReplyToId])
+        .filter(MessageColumn::ReplyToId.is_in(root_ids))
+        .filter(MessageColumn::DeletedAt.is_null())
+        .order_by_asc(MessageColumn::ReplyToId)
+        .order_by_desc(MessageColumn::CreatedAt)
+        .all(db)
+        .await?;
+
+    let mut summaries: HashMap<Uuid, ThreadSummary> = counts
+        .into_iter()
+        .map(|(root_id, reply_count)| (root_id, ThreadSummary { reply_count, ..Default::default() }))
+        .collect();
+
+    for reply in last_replies {
+        if let Some(summary) = reply.reply_to_id.and_then(|root_id| summaries.get_mut(&root_id)) {
+            summary.last_reply_at = Some(reply.created_at);
+            summary.last_reply_user_id = Some(reply.user_id);
         }
-    };
+    }
+
+    Ok(summaries)
+}
+
+async fn load_message_page(
+    db: &DatabaseConnection,
+    room_id: Uuid,
+    query: &MessageQuery,
+) -> Result<MessagePage, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let anchors = [query.before.is_some(), query.after.is_some(), query.around.is_some(), query.cursor.is_some()];
+    if anchors.iter().filter(|given| **given).count() > 1 {
+        return Err(ApiError::Validation(
+            "Only one of before, after, around and cursor may be given".to_string(),
+        ));
+    }
 
-    // Create AuthUser from claims
-    let auth = AuthUser {
-        id: user_id,
-        email: claims.email,
-        name: claims.name,
-        role: claims.user_role.unwrap_or_else(|| "user".to_string()),
-        profile_image: claims.profile_image,
+    // Resolve the anchor to a direction and a (created_at, id) key
+    let (direction, created_at, id) = if let Some(cursor) = &query.cursor {
+        let cursor = MessageCursor::decode(cursor)
+            .ok_or_else(|| ApiError::Validation("Invalid cursor".to_string()))?;
+        (cursor.direction, cursor.created_at, cursor.id)
+    } else if let Some(message_id) = query.before {
+        let anchor = find_anchor(db, room_id, message_id).await?;
+        (Direction::Before, anchor.created_at, anchor.id)
+    } else if let Some(message_id) = query.after {
+        let anchor = find_anchor(db, room_id, message_id).await?;
+        (Direction::After, anchor.created_at, anchor.id)
+    } else if let Some(message_id) = query.around {
+        let anchor = find_anchor(db, room_id, message_id).await?;
+
+        // Split the page around the anchor, which is included
+        let before_limit = (limit - 1) / 2;
+        let after_limit = limit - 1 - before_limit;
+        let (mut messages, has_more_before) =
+            fetch_from(db, room_id, Direction::Before, anchor.created_at, anchor.id, before_limit).await?;
+        let (newer, has_more_after) =
+            fetch_from(db, room_id, Direction::After, anchor.created_at, anchor.id, after_limit).await?;
+
+        messages.push(anchor);
+        messages.extend(newer);
+
+        return Ok(MessagePage {
+            messages,
+            has_more_before,
+            has_more_after,
+        });
+    } else {
+        return Ok(fetch_latest(db, room_id, limit).await?);
     };
 
+    let (messages, has_more) = fetch_from(db, room_id, direction, created_at, id, limit).await?;
+
+    // Paging away from the anchor: the other side holds at least the anchor itself
+    Ok(match direction {
+        Direction::Before => MessagePage {
+            messages,
+            has_more_before: has_more,
+            has_more_after: true,
+        },
+        Direction::After => MessagePage {
+            messages,
+            has_more_before: true,
+            has_more_after: has_more,
+        },
+    })
+}
+
+#[post("/messages")]
+pub async fn send_message(
+    auth: AuthUser,
+    db: web::Data<DatabaseConnection>,
+    _req: HttpRequest,
+    message_data: web::Json<CreateMessageDto>,
+) -> Result<HttpResponse, ApiError> {
+    let _user_id = auth.id;
+
     let message_id = Uuid::new_v4();
 
     // SECURITY CHECK: Verify user is a member of this room
@@ -88,18 +346,28 @@ pub async fn send_message(
         }
         Ok(None) => {
             // User is not a member of this room
-            return Ok(HttpResponse::Forbidden().json(serde_json::json!({
-                "error": "Access denied: You are not a member of this room"
-            })));
+            return Err(ApiError::NotRoomMember("Access denied: You are not a member of this room".to_string()));
         }
         Err(e) => {
             log::error!("Failed to check room membership: {}", e);
-            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to verify room access"
-            })));
+            return Err(ApiError::Database(e));
         }
     }
 
+    ensure_can_post(db.get_ref(), message_data.room_id, auth.id).await?;
+
+    let reply_to_id = match message_data.reply_to {
+        Some(parent_id) => Some(resolve_thread_root(db.get_ref(), message_data.room_id, parent_id).await?),
+        None => None,
+    };
+
+    if reply_to_id.is_some() && !message_data.attachment_ids.is_empty() {
+        return Err(ApiError::Validation("Attachments can only be sent in top-level messages".to_string()));
+    }
+    if message_data.content.trim().is_empty() && message_data.attachment_ids.is_empty() {
+        return Err(ApiError::Validation("Message content cannot be empty".to_string()));
+    }
+
     let message = ChatMessageActiveModel {
         id: ActiveValue::Set(message_id),
         room_id: ActiveValue::Set(message_data.room_id),
@@ -107,15 +375,30 @@ pub async fn send_message(
         content: ActiveValue::Set(message_data.content.clone()),
         created_at: ActiveValue::Set(Utc::now()),
         updated_at: ActiveValue::Set(Utc::now()),
+        edited_at: ActiveValue::Set(None),
+        deleted_at: ActiveValue::Set(None),
+        deleted_by: ActiveValue::Set(None),
+        reply_to_id: ActiveValue::Set(reply_to_id),
     };
 
     // Debug: Log the message data before inserting
     log::info!("Attempting to insert message: room_id={}, user_id={}, content={}", 
         message_data.room_id, auth.id, message_data.content);
 
-    match ChatMessage::insert(message).exec(db.get_ref()).await {
-        Ok(result) => {
-            log::info!("Successfully inserted message with result: {:?}", result);
+    // The message and its attachments are stored together or not at all
+    let result = async {
+        let txn = db.begin().await?;
+        ChatMessage::insert(message).exec(&txn).await?;
+        let attachments = link_attachments(&txn, &message_data.attachment_ids, auth.id, message_data.room_id, message_id).await?;
+        txn.commit().await?;
+        Ok::<_, ApiError>(attachments)
+    }
+    .await;
+
+    match result {
+        Ok(attachments) => {
+            log::info!("Successfully inserted message {} with {} attachment(s)", message_id, attachments.len());
+            metrics::record_chat_message("persisted");
             
             let message_response = MessageResponseDto {
                 id: message_id,
@@ -124,70 +407,223 @@ pub async fn send_message(
                 content: message_data.content.clone(),
                 created_at: Utc::now(),
                 user_name: Some(auth.name.clone()),
+                reply_to_id,
+                attachments: attachments.iter().map(attachment_view).collect(),
             };
 
+            // Replies sent over REST still reach the thread's viewers
+            if reply_to_id.is_some() {
+                if let Ok(Some(reply)) = ChatMessage::find_by_id(message_id).one(db.get_ref()).await {
+                    broadcast_thread_reply(db.get_ref(), &reply, &auth.name, auth.profile_image.as_deref()).await;
+                }
+            } else {
+                // Nothing is broadcast for it, so the room's cached history reloads on the next join
+                invalidate_room_history(message_data.room_id);
+            }
+
             Ok(HttpResponse::Created().json(message_response))
         }
         Err(e) => {
-            log::error!("Failed to send message to database: {:?}", e);
-            log::error!("Error details: {}", e);
-            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Failed to send message: {}", e)
-            })))
+            log::error!("Failed to send message to database: {}", e);
+            metrics::record_chat_message("failed");
+            Err(e)
         }
     }
 }
 
-#[get("/api/chat/rooms/{room_id}/messages")]
-pub async fn get_messages(
-    db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
-    jwt_secret: web::Data<String>,
-    path: web::Path<MessagePath>,
-    query: web::Query<MessageQuery>,
-) -> Result<HttpResponse, Error> {
-    // Extract token from request
-    let token = match extract_token_from_cookie_or_header(&req) {
-        Some(token) => token,
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: No authentication token found"
-            })));
+// Render messages for clients, with author details, reactions and thread summaries
+pub(crate) async fn build_message_views(db: &DatabaseConnection, messages: Vec<ChatMessageModel>) -> Vec<serde_json::Value> {
+    let mut message_with_users = Vec::new();
+
+    for message in messages {
+        // Tombstones keep their place in the history but never expose their content
+        let deleted = message.is_deleted();
+        let content = if deleted { String::new() } else { message.content };
+
+        let user = User::find_by_id(message.user_id)
+            .one(db)
+            .await;
+
+        match user {
+            Ok(Some(user)) => {
+                message_with_users.push(MessageWithUserDto {
+                    id: message.id,
+                    room_id: message.room_id,
+                    user_id: message.user_id,
+                    user_name: user.name,
+                    user_profile_image: user.profile_image,
+                    content,
+                    created_at: message.created_at,
+                    edited_at: message.edited_at,
+                    deleted,
+                    reply_to_id: message.reply_to_id,
+                    reply_count: 0,
+                    last_reply_at: None,
+                    last_reply_user_id: None,
+                });
+            }
+            _ => {
+                message_with_users.push(MessageWithUserDto {
+                    id: message.id,
+                    room_id: message.room_id,
+                    user_id: message.user_id,
+                    user_name: "Unknown User".to_string(),
+                    user_profile_image: None,
+                    content,
+                    created_at: message.created_at,
+                    edited_at: message.edited_at,
+                    deleted,
+                    reply_to_id: message.reply_to_id,
+                    reply_count: 0,
+                    last_reply_at: None,
+                    last_reply_user_id: None,
+                });
+            }
         }
-    };
+    }
+
+    // Collect message IDs for reactions lookup
+    let message_ids: Vec<Uuid> = message_with_users.iter().map(|m| m.id).collect();
 
-    // Validate token and get user
-    let claims = match JwtAuth::validate_token(&token, &jwt_secret) {
-        Ok(claims) => claims,
-        Err(_) => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Invalid authentication token"
-            })));
+    // Thread summaries for the messages that start a thread
+    let root_ids: Vec<Uuid> = message_with_users.iter().filter(|m| m.reply_to_id.is_none()).map(|m| m.id).collect();
+    let threads = match thread_summaries(db, root_ids).await {
+        Ok(threads) => threads,
+        Err(e) => {
+            log::error!("Failed to load thread summaries: {}", e);
+            HashMap::new()
         }
     };
+    for m in message_with_users.iter_mut() {
+        if let Some(thread) = threads.get(&m.id) {
+            m.reply_count = thread.reply_count;
+            m.last_reply_at = thread.last_reply_at;
+            m.last_reply_user_id = thread.last_reply_user_id;
+        }
+    }
 
-    // Ensure backend_user_id is present and valid
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Missing user ID in token"
-            })));
+    // Attachments of the messages; those of deleted messages are left out below
+    let mut attachments = match attachments_for_messages(db, message_ids.clone()).await {
+        Ok(attachments) => attachments,
+        Err(e) => {
+            log::error!("Failed to load attachments: {}", e);
+            HashMap::new()
         }
     };
 
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
-        Err(_) => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Invalid user ID format"
-            })));
-        }
+    // Fetch reactions for all messages in batch
+    let reactions = if message_ids.is_empty() {
+        Vec::new()
+    } else {
+        match MessageReaction::find()
+            .filter(crate::models::entities::message_reaction::Column::MessageId.is_in(message_ids.clone()))
+            .all(db)
+            .await {
+                Ok(r) => r,
+                Err(e) => {
+                    log::error!("Failed to load reactions: {}", e);
+                    Vec::new()
+                }
+            }
     };
 
+    // Fetch all users involved in reactions to avoid N+1
+    let mut reaction_user_ids: HashSet<Uuid> = HashSet::new();
+    for r in &reactions { reaction_user_ids.insert(r.user_id); }
+
+    let reaction_users = if reaction_user_ids.is_empty() {
+        Vec::new()
+    } else {
+        match User::find()
+            .filter(crate::models::entities::user::Column::Id.is_in(reaction_user_ids.iter().cloned().collect::<Vec<_>>()))
+            .all(db)
+            .await {
+                Ok(us) => us,
+                Err(e) => {
+                    log::error!("Failed to load users for reactions: {}", e);
+                    Vec::new()
+                }
+            }
+    };
+
+    let user_map: HashMap<Uuid, crate::models::entities::user::Model> =
+        reaction_users.into_iter().map(|u| (u.id, u)).collect();
+
+    // Group reactions by message and emoji
+    let mut grouped: HashMap<Uuid, HashMap<String, Vec<serde_json::Value>>> = HashMap::new();
+    for r in reactions {
+        let emoji = r.emoji.clone();
+        let msg_id = r.message_id;
+        let user_info = if let Some(u) = user_map.get(&r.user_id) {
+            serde_json::json!({
+                "user_id": u.id,
+                "user_name": u.name,
+                "user_profile_image": u.profile_image
+            })
+        } else {
+            serde_json::json!({
+                "user_id": r.user_id,
+                "user_name": "Unknown User",
+                "user_profile_image": null
+            })
+        };
+        grouped
+            .entry(msg_id)
+            .or_default()
+            .entry(emoji)
+            .or_default()
+            .push(user_info);
+    }
+
+    // Build enriched messages including reactions as expected by frontend
+    message_with_users
+        .into_iter()
+        .map(|m| {
+            let reactions_json: Vec<serde_json::Value> = if let Some(by_emoji) = grouped.get(&m.id) {
+                by_emoji.iter().map(|(emoji, users)| {
+                    serde_json::json!({
+                        "id": format!("{}:{}", m.id, emoji),
+                        "emoji": emoji,
+                        "count": users.len(),
+                        "users": users
+                    })
+                }).collect()
+            } else { Vec::new() };
+
+            let attachments_json = if m.deleted { Vec::new() } else { attachments.remove(&m.id).unwrap_or_default() };
+
+            serde_json::json!({
+                "id": m.id,
+                "room_id": m.room_id,
+                "user_id": m.user_id,
+                "user_name": m.user_name,
+                "user_profile_image": m.user_profile_image,
+                "content": m.content,
+                "created_at": m.created_at,
+                "edited_at": m.edited_at,
+                "deleted": m.deleted,
+                "reply_to_id": m.reply_to_id,
+                "reply_count": m.reply_count,
+                "last_reply_at": m.last_reply_at,
+                "last_reply_user_id": m.last_reply_user_id,
+                "attachments": attachments_json,
+                "reactions": reactions_json
+            })
+        })
+        .collect()
+}
+
+#[get("/rooms/{room_id}/messages")]
+pub async fn get_messages(
+    auth_user: AuthUser,
+    db: web::Data<DatabaseConnection>,
+    _req: HttpRequest,
+    path: web::Path<MessagePath>,
+    query: web::Query<MessageQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = auth_user.id;
+
     let room_id = path.room_id;
-    let limit = query.limit.unwrap_or(200);
-    let offset = query.offset.unwrap_or(0);
 
     // SECURITY CHECK: Verify user is a member of this room
     let membership_check = RoomMembership::find()
@@ -202,165 +638,39 @@ pub async fn get_messages(
         }
         Ok(None) => {
             // User is not a member of this room
-            return Ok(HttpResponse::Forbidden().json(serde_json::json!({
-                "error": "Access denied: You are not a member of this room"
-            })));
+            return Err(ApiError::NotRoomMember("Access denied: You are not a member of this room".to_string()));
         }
         Err(e) => {
             log::error!("Failed to check room membership: {}", e);
-            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to verify room access"
-            })));
+            return Err(ApiError::Database(e));
         }
     }
 
-    let messages = ChatMessage::find()
-        .filter(crate::models::entities::chat_message::Column::RoomId.eq(room_id))
-        .order_by(crate::models::entities::chat_message::Column::CreatedAt, Order::Asc)
-        .limit(limit)
-        .offset(offset)
-        .all(db.get_ref())
-        .await;
-
-    match messages {
-        Ok(messages) => {
-            let mut message_with_users = Vec::new();
-
-            for message in messages {
-                let user = User::find_by_id(message.user_id)
-                    .one(db.get_ref())
-                    .await;
-
-                match user {
-                    Ok(Some(user)) => {
-                        message_with_users.push(MessageWithUserDto {
-                            id: message.id,
-                            room_id: message.room_id,
-                            user_id: message.user_id,
-                            user_name: user.name,
-                            user_profile_image: user.profile_image,
-                            content: message.content,
-                            created_at: message.created_at,
-                        });
-                    }
-                    _ => {
-                        message_with_users.push(MessageWithUserDto {
-                            id: message.id,
-                            room_id: message.room_id,
-                            user_id: message.user_id,
-                            user_name: "Unknown User".to_string(),
-                            user_profile_image: None,
-                            content: message.content,
-                            created_at: message.created_at,
-                        });
-                    }
-                }
-            }
-
-            // Collect message IDs for reactions lookup
-            let message_ids: Vec<Uuid> = message_with_users.iter().map(|m| m.id).collect();
-
-            // Fetch reactions for all messages in batch
-            let reactions = if message_ids.is_empty() {
-                Vec::new()
-            } else {
-                match MessageReaction::find()
-                    .filter(crate::models::entities::message_reaction::Column::MessageId.is_in(message_ids.clone()))
-                    .all(db.get_ref())
-                    .await {
-                        Ok(r) => r,
-                        Err(e) => {
-                            log::error!("Failed to load reactions: {}", e);
-                            Vec::new()
-                        }
-                    }
+    match load_message_page(db.get_ref(), room_id, &query).await {
+        Ok(page) => {
+            // Cursors for the neighbouring pages: prev is older, next is newer
+            let prev_cursor = match page.messages.first() {
+                Some(first) if page.has_more_before => Some(MessageCursor::new(Direction::Before, first).encode()),
+                _ => None,
             };
-
-            // Fetch all users involved in reactions to avoid N+1
-            let mut reaction_user_ids: HashSet<Uuid> = HashSet::new();
-            for r in &reactions { reaction_user_ids.insert(r.user_id); }
-
-            let reaction_users = if reaction_user_ids.is_empty() {
-                Vec::new()
-            } else {
-                match User::find()
-                    .filter(crate::models::entities::user::Column::Id.is_in(reaction_user_ids.iter().cloned().collect::<Vec<_>>()))
-                    .all(db.get_ref())
-                    .await {
-                        Ok(us) => us,
-                        Err(e) => {
-                            log::error!("Failed to load users for reactions: {}", e);
-                            Vec::new()
-                        }
-                    }
+            let next_cursor = match page.messages.last() {
+                Some(last) if page.has_more_after => Some(MessageCursor::new(Direction::After, last).encode()),
+                _ => None,
             };
 
-            let user_map: HashMap<Uuid, crate::models::entities::user::Model> =
-                reaction_users.into_iter().map(|u| (u.id, u)).collect();
-
-            // Group reactions by message and emoji
-            let mut grouped: HashMap<Uuid, HashMap<String, Vec<serde_json::Value>>> = HashMap::new();
-            for r in reactions {
-                let emoji = r.emoji.clone();
-                let msg_id = r.message_id;
-                let user_info = if let Some(u) = user_map.get(&r.user_id) {
-                    serde_json::json!({
-                        "user_id": u.id,
-                        "user_name": u.name,
-                        "user_profile_image": u.profile_image
-                    })
-                } else {
-                    serde_json::json!({
-                        "user_id": r.user_id,
-                        "user_name": "Unknown User",
-                        "user_profile_image": null
-                    })
-                };
-                grouped
-                    .entry(msg_id)
-                    .or_default()
-                    .entry(emoji)
-                    .or_default()
-                    .push(user_info);
-            }
-
-            // Build enriched messages including reactions as expected by frontend
-            let enriched_messages: Vec<serde_json::Value> = message_with_users
-                .into_iter()
-                .map(|m| {
-                    let reactions_json: Vec<serde_json::Value> = if let Some(by_emoji) = grouped.get(&m.id) {
-                        by_emoji.iter().map(|(emoji, users)| {
-                            serde_json::json!({
-                                "id": format!("{}:{}", m.id, emoji),
-                                "emoji": emoji,
-                                "count": users.len(),
-                                "users": users
-                            })
-                        }).collect()
-                    } else { Vec::new() };
-
-                    serde_json::json!({
-                        "id": m.id,
-                        "room_id": m.room_id,
-                        "user_id": m.user_id,
-                        "user_name": m.user_name,
-                        "user_profile_image": m.user_profile_image,
-                        "content": m.content,
-                        "created_at": m.created_at,
-                        "reactions": reactions_json
-                    })
-                })
-                .collect();
+            let enriched_messages = build_message_views(db.get_ref(), page.messages).await;
 
             Ok(HttpResponse::Ok().json(serde_json::json!({
-                "messages": enriched_messages
+                "messages": enriched_messages,
+                "prev_cursor": prev_cursor,
+                "next_cursor": next_cursor,
+                "has_more_before": page.has_more_before,
+                "has_more_after": page.has_more_after
             })))
         }
         Err(e) => {
             log::error!("Failed to get messages: {}", e);
-            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get messages"
-            })))
+            Err(e)
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,238 @@
+use actix_web::{web, HttpResponse, put, delete, get};
+use sea_orm::{DatabaseConnection, EntityTrait, ActiveModelTrait, ActiveValue, QueryFilter, QueryOrder, ColumnTrait, TransactionTrait};
+use uuid::Uuid;
+use chrono::Utc;
+use serde::Deserialize;
+use log::{error, info};
+
+use crate::auth::AuthUser;
+use crate::models::entities::{ChatMessage, ChatMessageModel, ChatMessageActiveModel, ChatMessageEdit, ChatMessageEditActiveModel, RoomRole};
+use crate::models::entities::chat_message_edit;
+use crate::api::chat::message::ensure_member;
+use crate::api::chat::moderation::acting_role;
+use crate::api::chat::ws::{broadcast_to_room, update_history_message, WsResponse, MAX_MESSAGE_SIZE};
+use crate::api::ApiError;
+
+#[derive(Deserialize)]
+pub struct MessageIdPath {
+    message_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct EditMessageRequest {
+    content: String,
+}
+
+pub(crate) async fn find_live_message(db: &DatabaseConnection, message_id: Uuid) -> Result<ChatMessageModel, ApiError> {
+    match ChatMessage::find_by_id(message_id).one(db).await {
+        Ok(Some(message)) if message.is_deleted() => {
+            Err(ApiError::Conflict("Message has been deleted".to_string()))
+        }
+        Ok(Some(message)) => Ok(message),
+        Ok(None) => Err(ApiError::NotFound("Message not found".to_string())),
+        Err(e) => Err(ApiError::Database(e)),
+    }
+}
+
+/// Replace the content of a message, keeping the previous content as a revision
+///
+/// Only the author may edit, and only while still a member of the room.
+pub async fn edit_message(
+    db: &DatabaseConnection,
+    message_id: Uuid,
+    editor_id: Uuid,
+    content: String,
+) -> Result<ChatMessageModel, ApiError> {
+    let content = content.trim().to_string();
+    if content.is_empty() {
+        return Err(ApiError::Validation("Message content cannot be empty".to_string()));
+    }
+    if content.len() > MAX_MESSAGE_SIZE {
+        return Err(ApiError::PayloadTooLarge("Message is too large".to_string()));
+    }
+
+    let message = find_live_message(db, message_id).await?;
+
+    if message.user_id != editor_id {
+        return Err(ApiError::Forbidden("Only the author can edit this message".to_string()));
+    }
+    ensure_member(db, message.room_id, editor_id).await?;
+
+    if message.content == content {
+        return Ok(message);
+    }
+
+    let now = Utc::now();
+    let txn = db.begin().await?;
+
+    let revision = ChatMessageEditActiveModel {
+        id: ActiveValue::Set(Uuid::new_v4()),
+        message_id: ActiveValue::Set(message.id),
+        editor_id: ActiveValue::Set(editor_id),
+        previous_content: ActiveValue::Set(message.content.clone()),
+        edited_at: ActiveValue::Set(now),
+    };
+    ChatMessageEdit::insert(revision).exec(&txn).await?;
+
+    let mut active: ChatMessageActiveModel = message.into();
+    active.content = ActiveValue::Set(content);
+    active.edited_at = ActiveValue::Set(Some(now));
+    active.updated_at = ActiveValue::Set(now);
+    let updated = active.update(&txn).await?;
+
+    txn.commit().await?;
+
+    info!("Message {} edited by user {}", updated.id, editor_id);
+    Ok(updated)
+}
+
+/// Soft-delete a message, leaving a tombstone in the room's history
+///
+/// Members may delete their own messages; room moderators, owners and admins may delete anyone's.
+pub async fn delete_message(
+    db: &DatabaseConnection,
+    message_id: Uuid,
+    user_id: Uuid,
+    user_role: &str,
+) -> Result<ChatMessageModel, ApiError> {
+    let message = find_live_message(db, message_id).await?;
+
+    let role = acting_role(db, message.room_id, user_id, user_role).await?;
+    if message.user_id != user_id && role < RoomRole::Moderator {
+        return Err(ApiError::Forbidden(
+            "Only the author, a room moderator or an admin can delete this message".to_string(),
+        ));
+    }
+
+    let now = Utc::now();
+    let mut active: ChatMessageActiveModel = message.into();
+    active.deleted_at = ActiveValue::Set(Some(now));
+    active.deleted_by = ActiveValue::Set(Some(user_id));
+    active.updated_at = ActiveValue::Set(now);
+    let deleted = active.update(db).await?;
+
+    info!("Message {} deleted by user {}", deleted.id, user_id);
+    Ok(deleted)
+}
+
+// Tell the room about an edit and update the chat server's recent history
+pub fn broadcast_message_edited(message: &ChatMessageModel) {
+    let event = WsResponse {
+        message_type: "message_edited".to_string(),
+        data: serde_json::json!({
+            "id": message.id,
+            "room_id": message.room_id,
+            "user_id": message.user_id,
+            "content": message.content,
+            "edited_at": message.edited_at,
+        }),
+        timestamp: Utc::now().timestamp(),
+        message_id: Some(message.id.to_string()),
+    };
+
+    let room_id = message.room_id.to_string();
+    update_history_message(
+        &room_id,
+        &message.id.to_string(),
+        serde_json::json!({
+            "content": message.content,
+            "edited_at": message.edited_at,
+        }),
+    );
+    broadcast_to_room(&room_id, event);
+}
+
+// Tell the room about a deletion and turn the message into a tombstone in recent history
+pub fn broadcast_message_deleted(message: &ChatMessageModel) {
+    let event = WsResponse {
+        message_type: "message_deleted".to_string(),
+        data: serde_json::json!({
+            "id": message.id,
+            "room_id": message.room_id,
+            "deleted_by": message.deleted_by,
+            "deleted_at": message.deleted_at,
+        }),
+        timestamp: Utc::now().timestamp(),
+        message_id: Some(message.id.to_string()),
+    };
+
+    let room_id = message.room_id.to_string();
+    update_history_message(
+        &room_id,
+        &message.id.to_string(),
+        serde_json::json!({
+            "content": "",
+            "deleted": true,
+        }),
+    );
+    broadcast_to_room(&room_id, event);
+}
+
+#[put("/messages/{message_id}")]
+pub async fn edit_message_handler(
+    auth_user: AuthUser,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<MessageIdPath>,
+    body: web::Json<EditMessageRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let message = edit_message(db.get_ref(), path.message_id, auth_user.id, body.into_inner().content).await?;
+
+    broadcast_message_edited(&message);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "id": message.id,
+        "room_id": message.room_id,
+        "user_id": message.user_id,
+        "content": message.content,
+        "created_at": message.created_at,
+        "edited_at": message.edited_at
+    })))
+}
+
+#[delete("/messages/{message_id}")]
+pub async fn delete_message_handler(
+    auth_user: AuthUser,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<MessageIdPath>,
+) -> Result<HttpResponse, ApiError> {
+    let message = delete_message(db.get_ref(), path.message_id, auth_user.id, &auth_user.role).await?;
+
+    broadcast_message_deleted(&message);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "id": message.id,
+        "deleted_at": message.deleted_at
+    })))
+}
+
+// Revision history of a message, oldest first; visible to members of its room
+#[get("/messages/{message_id}/edits")]
+pub async fn get_message_edits(
+    auth_user: AuthUser,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<MessageIdPath>,
+) -> Result<HttpResponse, ApiError> {
+    let message = find_live_message(db.get_ref(), path.message_id).await?;
+    ensure_member(db.get_ref(), message.room_id, auth_user.id).await?;
+
+    let edits = match ChatMessageEdit::find()
+        .filter(chat_message_edit::Column::MessageId.eq(message.id))
+        .order_by_asc(chat_message_edit::Column::EditedAt)
+        .all(db.get_ref())
+        .await
+    {
+        Ok(edits) => edits,
+        Err(e) => {
+            error!("Failed to load message edits: {}", e);
+            return Err(ApiError::Database(e));
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message_id": message.id,
+        "content": message.content,
+        "edited_at": message.edited_at,
+        "edits": edits
+    })))
+}
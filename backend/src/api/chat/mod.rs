@@ -1,7 +1,17 @@
 pub mod ws;
+pub mod backplane;
+pub mod history;
 pub mod room;
 pub mod message;
+pub mod message_edit;
+pub mod thread;
+pub mod read_receipt;
+pub mod search;
+pub mod moderation;
+pub mod presence;
 pub mod upload;
+pub mod attachment;
+pub mod preview;
 pub mod join_room_by_code;
 pub mod voice;
 
@@ -9,6 +19,12 @@ pub mod voice;
 pub use ws::ws_index;
 pub use room::{create_room, get_rooms, get_room, verify_room_password, delete_room, leave_room_membership};
 pub use message::{send_message, get_messages};
-pub use upload::{upload_chat_image, get_chat_image, upload_chat_video, get_chat_video};
+pub use message_edit::{edit_message_handler, delete_message_handler, get_message_edits};
+pub use thread::get_thread;
+pub use search::search_messages;
+pub use moderation::{change_member_role, kick_member, mute_member, unmute_member, get_room_bans, ban_member, unban_member};
+pub use presence::get_room_members;
+pub use upload::{upload_chat_image, get_chat_image, upload_chat_video, get_chat_video, get_upload};
+pub use attachment::get_attachment;
 pub use join_room_by_code::join_room_by_code as join_room_by_code_handler;
 pub use voice::{upload_voice_message, get_voice_message};
\ No newline at end of file
@@ -0,0 +1,395 @@
+use actix_web::{web, HttpResponse, post, put, get, delete};
+use sea_orm::{DatabaseConnection, DbErr, EntityTrait, ActiveModelTrait, ActiveValue, QueryFilter, QueryOrder, ColumnTrait, TransactionTrait};
+use uuid::Uuid;
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use log::{error, info};
+
+use crate::auth::AuthUser;
+use crate::models::entities::{ChatRoom, RoomBan, RoomBanActiveModel, RoomMembership, RoomMembershipActiveModel, RoomMembershipModel, RoomRole, User};
+use crate::models::entities::{room_ban, room_membership};
+use crate::api::chat::ws::{broadcast_to_room, evict_room_member, set_room_mute, WsResponse};
+use crate::api::ApiError;
+
+// Longest mute a moderator can hand out
+const MAX_MUTE_SECONDS: i64 = 30 * 24 * 60 * 60;
+
+#[derive(Deserialize)]
+pub struct RoomPath {
+    room_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct RoomMemberPath {
+    room_id: Uuid,
+    user_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct ChangeRoleRequest {
+    role: String,
+}
+
+#[derive(Deserialize)]
+pub struct BanRequest {
+    user_id: Uuid,
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct MuteRequest {
+    duration_seconds: i64,
+}
+
+async fn find_membership(db: &DatabaseConnection, room_id: Uuid, user_id: Uuid) -> Result<Option<RoomMembershipModel>, DbErr> {
+    RoomMembership::find()
+        .filter(room_membership::Column::RoomId.eq(room_id))
+        .filter(room_membership::Column::UserId.eq(user_id))
+        .one(db)
+        .await
+}
+
+/// The role a user acts with in a room; admins act as owners of every room
+pub(crate) async fn acting_role(db: &DatabaseConnection, room_id: Uuid, user_id: Uuid, user_role: &str) -> Result<RoomRole, ApiError> {
+    match ChatRoom::find_by_id(room_id).one(db).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(ApiError::NotFound("Room not found".to_string())),
+        Err(e) => return Err(ApiError::Database(e)),
+    }
+
+    if user_role.to_lowercase() == "admin" {
+        return Ok(RoomRole::Owner);
+    }
+
+    match find_membership(db, room_id, user_id).await {
+        Ok(Some(membership)) => Ok(membership.room_role()),
+        Ok(None) => Err(ApiError::NotRoomMember("Access denied: You are not a member of this room".to_string())),
+        Err(e) => Err(ApiError::Database(e)),
+    }
+}
+
+// Moderators act on members, owners on everyone else; nobody acts on their equals
+fn ensure_outranks(actor: RoomRole, target: RoomRole) -> Result<(), ApiError> {
+    if actor < RoomRole::Moderator {
+        return Err(ApiError::Forbidden("Only room moderators can do this".to_string()));
+    }
+    if target >= actor {
+        return Err(ApiError::Forbidden("You cannot moderate a member with the same or a higher role".to_string()));
+    }
+    Ok(())
+}
+
+async fn find_target(db: &DatabaseConnection, room_id: Uuid, user_id: Uuid) -> Result<RoomMembershipModel, ApiError> {
+    match find_membership(db, room_id, user_id).await {
+        Ok(Some(membership)) => Ok(membership),
+        Ok(None) => Err(ApiError::NotFound("Member not found in this room".to_string())),
+        Err(e) => Err(ApiError::Database(e)),
+    }
+}
+
+/// Fail if the user is banned from the room
+pub(crate) async fn ensure_not_banned(db: &DatabaseConnection, room_id: Uuid, user_id: Uuid) -> Result<(), ApiError> {
+    match RoomBan::find()
+        .filter(room_ban::Column::RoomId.eq(room_id))
+        .filter(room_ban::Column::UserId.eq(user_id))
+        .one(db)
+        .await
+    {
+        Ok(Some(_)) => Err(ApiError::Forbidden("You are banned from this room".to_string())),
+        Ok(None) => Ok(()),
+        Err(e) => Err(ApiError::Database(e)),
+    }
+}
+
+/// Fail unless the user is a member of the room and not muted in it
+pub(crate) async fn ensure_can_post(db: &DatabaseConnection, room_id: Uuid, user_id: Uuid) -> Result<(), ApiError> {
+    match find_membership(db, room_id, user_id).await {
+        Ok(Some(membership)) if membership.is_muted() => Err(ApiError::Forbidden(format!(
+            "You are muted in this room until {}",
+            membership.muted_until.map(|until| until.to_rfc3339()).unwrap_or_default()
+        ))),
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(ApiError::NotRoomMember("Access denied: You are not a member of this room".to_string())),
+        Err(e) => Err(ApiError::Database(e)),
+    }
+}
+
+fn room_event(message_type: &str, data: serde_json::Value) -> WsResponse {
+    WsResponse {
+        message_type: message_type.to_string(),
+        data,
+        timestamp: Utc::now().timestamp(),
+        message_id: None,
+    }
+}
+
+// Tell the room a member was removed and drop their connections from it
+fn evict_member(room_id: Uuid, user_id: Uuid, removed_by: Uuid, banned: bool) {
+    let event = room_event(
+        "member_kicked",
+        serde_json::json!({
+            "room_id": room_id,
+            "user_id": user_id,
+            "kicked_by": removed_by,
+            "banned": banned,
+        }),
+    );
+
+    evict_room_member(room_id, user_id, event);
+}
+
+// Promote a member to moderator or demote a moderator; owners only
+#[put("/rooms/{room_id}/members/{user_id}/role")]
+pub async fn change_member_role(
+    auth_user: AuthUser,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<RoomMemberPath>,
+    body: web::Json<ChangeRoleRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let role = match RoomRole::parse(&body.role) {
+        Some(RoomRole::Owner) | None => {
+            return Err(ApiError::Validation("Role must be 'moderator' or 'member'".to_string()));
+        }
+        Some(role) => role,
+    };
+
+    if acting_role(db.get_ref(), path.room_id, auth_user.id, &auth_user.role).await? != RoomRole::Owner {
+        return Err(ApiError::Forbidden("Only the room owner can change member roles".to_string()));
+    }
+
+    let target = find_target(db.get_ref(), path.room_id, path.user_id).await?;
+    if target.room_role() == RoomRole::Owner {
+        return Err(ApiError::Forbidden("The room owner's role cannot be changed".to_string()));
+    }
+
+    let mut active: RoomMembershipActiveModel = target.into();
+    active.role = ActiveValue::Set(role.as_str().to_string());
+    let updated = active.update(db.get_ref()).await?;
+
+    info!("User {} set role of {} in room {} to {}", auth_user.id, updated.user_id, updated.room_id, updated.role);
+
+    broadcast_to_room(
+        &updated.room_id.to_string(),
+        room_event(
+            "member_role_changed",
+            serde_json::json!({
+                "room_id": updated.room_id,
+                "user_id": updated.user_id,
+                "role": updated.role,
+                "changed_by": auth_user.id,
+            }),
+        ),
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "room_id": updated.room_id,
+        "user_id": updated.user_id,
+        "role": updated.role
+    })))
+}
+
+// Remove a member from the room; they may join again with the room code
+#[post("/rooms/{room_id}/members/{user_id}/kick")]
+pub async fn kick_member(
+    auth_user: AuthUser,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<RoomMemberPath>,
+) -> Result<HttpResponse, ApiError> {
+    let actor = acting_role(db.get_ref(), path.room_id, auth_user.id, &auth_user.role).await?;
+    let target = find_target(db.get_ref(), path.room_id, path.user_id).await?;
+    ensure_outranks(actor, target.room_role())?;
+
+    RoomMembership::delete_by_id(target.id).exec(db.get_ref()).await?;
+
+    info!("User {} kicked {} from room {}", auth_user.id, target.user_id, target.room_id);
+    evict_member(target.room_id, target.user_id, auth_user.id, false);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "message": "Member removed from the room"
+    })))
+}
+
+// Silence a member for a while; they stay in the room and can still read
+#[post("/rooms/{room_id}/members/{user_id}/mute")]
+pub async fn mute_member(
+    auth_user: AuthUser,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<RoomMemberPath>,
+    body: web::Json<MuteRequest>,
+) -> Result<HttpResponse, ApiError> {
+    if body.duration_seconds <= 0 || body.duration_seconds > MAX_MUTE_SECONDS {
+        return Err(ApiError::Validation(format!(
+            "duration_seconds must be between 1 and {}",
+            MAX_MUTE_SECONDS
+        )));
+    }
+
+    let actor = acting_role(db.get_ref(), path.room_id, auth_user.id, &auth_user.role).await?;
+    let target = find_target(db.get_ref(), path.room_id, path.user_id).await?;
+    ensure_outranks(actor, target.room_role())?;
+
+    let mut active: RoomMembershipActiveModel = target.into();
+    active.muted_until = ActiveValue::Set(Some(Utc::now() + Duration::seconds(body.duration_seconds)));
+    let updated = active.update(db.get_ref()).await?;
+
+    info!("User {} muted {} in room {} until {:?}", auth_user.id, updated.user_id, updated.room_id, updated.muted_until);
+    set_mute_and_broadcast(&updated, auth_user.id);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "user_id": updated.user_id,
+        "muted_until": updated.muted_until
+    })))
+}
+
+#[delete("/rooms/{room_id}/members/{user_id}/mute")]
+pub async fn unmute_member(
+    auth_user: AuthUser,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<RoomMemberPath>,
+) -> Result<HttpResponse, ApiError> {
+    let actor = acting_role(db.get_ref(), path.room_id, auth_user.id, &auth_user.role).await?;
+    let target = find_target(db.get_ref(), path.room_id, path.user_id).await?;
+    ensure_outranks(actor, target.room_role())?;
+
+    let mut active: RoomMembershipActiveModel = target.into();
+    active.muted_until = ActiveValue::Set(None);
+    let updated = active.update(db.get_ref()).await?;
+
+    info!("User {} unmuted {} in room {}", auth_user.id, updated.user_id, updated.room_id);
+    set_mute_and_broadcast(&updated, auth_user.id);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "user_id": updated.user_id,
+        "muted_until": null
+    })))
+}
+
+// Apply a mute change to the chat server and tell the room
+fn set_mute_and_broadcast(membership: &RoomMembershipModel, muted_by: Uuid) {
+    let event = room_event(
+        "member_muted",
+        serde_json::json!({
+            "room_id": membership.room_id,
+            "user_id": membership.user_id,
+            "muted_until": membership.muted_until,
+            "muted_by": muted_by,
+        }),
+    );
+
+    set_room_mute(membership.room_id, membership.user_id, membership.muted_until);
+    broadcast_to_room(&membership.room_id.to_string(), event);
+}
+
+// Users banned from a room, newest first; moderators only
+#[get("/rooms/{room_id}/bans")]
+pub async fn get_room_bans(
+    auth_user: AuthUser,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<RoomPath>,
+) -> Result<HttpResponse, ApiError> {
+    if acting_role(db.get_ref(), path.room_id, auth_user.id, &auth_user.role).await? < RoomRole::Moderator {
+        return Err(ApiError::Forbidden("Only room moderators can do this".to_string()));
+    }
+
+    let bans = match RoomBan::find()
+        .filter(room_ban::Column::RoomId.eq(path.room_id))
+        .order_by_desc(room_ban::Column::CreatedAt)
+        .all(db.get_ref())
+        .await
+    {
+        Ok(bans) => bans,
+        Err(e) => {
+            error!("Failed to load room bans: {}", e);
+            return Err(ApiError::Database(e));
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "bans": bans
+    })))
+}
+
+// Ban a user from the room, removing their membership if they have one
+#[post("/rooms/{room_id}/bans")]
+pub async fn ban_member(
+    auth_user: AuthUser,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<RoomPath>,
+    body: web::Json<BanRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let room_id = path.room_id;
+    let body = body.into_inner();
+
+    let actor = acting_role(db.get_ref(), room_id, auth_user.id, &auth_user.role).await?;
+
+    match User::find_by_id(body.user_id).one(db.get_ref()).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(ApiError::NotFound("User not found".to_string())),
+        Err(e) => return Err(ApiError::Database(e)),
+    }
+
+    // Users outside the room are banned as plain members
+    let membership = find_membership(db.get_ref(), room_id, body.user_id).await?;
+    let target_role = membership.as_ref().map_or(RoomRole::Member, |m| m.room_role());
+    ensure_outranks(actor, target_role)?;
+
+    if ensure_not_banned(db.get_ref(), room_id, body.user_id).await.is_err() {
+        return Err(ApiError::Conflict("User is already banned from this room".to_string()));
+    }
+
+    let reason = body.reason.map(|r| r.trim().to_string()).filter(|r| !r.is_empty());
+    let txn = db.begin().await?;
+
+    let ban = RoomBanActiveModel {
+        id: ActiveValue::Set(Uuid::new_v4()),
+        room_id: ActiveValue::Set(room_id),
+        user_id: ActiveValue::Set(body.user_id),
+        banned_by: ActiveValue::Set(auth_user.id),
+        reason: ActiveValue::Set(reason),
+        created_at: ActiveValue::Set(Utc::now()),
+    };
+    let ban = ban.insert(&txn).await?;
+
+    if let Some(membership) = membership {
+        RoomMembership::delete_by_id(membership.id).exec(&txn).await?;
+    }
+
+    txn.commit().await?;
+
+    info!("User {} banned {} from room {}", auth_user.id, ban.user_id, room_id);
+    evict_member(room_id, ban.user_id, auth_user.id, true);
+
+    Ok(HttpResponse::Created().json(ban))
+}
+
+#[delete("/rooms/{room_id}/bans/{user_id}")]
+pub async fn unban_member(
+    auth_user: AuthUser,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<RoomMemberPath>,
+) -> Result<HttpResponse, ApiError> {
+    if acting_role(db.get_ref(), path.room_id, auth_user.id, &auth_user.role).await? < RoomRole::Moderator {
+        return Err(ApiError::Forbidden("Only room moderators can do this".to_string()));
+    }
+
+    let result = RoomBan::delete_many()
+        .filter(room_ban::Column::RoomId.eq(path.room_id))
+        .filter(room_ban::Column::UserId.eq(path.user_id))
+        .exec(db.get_ref())
+        .await?;
+
+    if result.rows_affected == 0 {
+        return Err(ApiError::NotFound("Ban not found".to_string()));
+    }
+
+    info!("User {} unbanned {} from room {}", auth_user.id, path.user_id, path.room_id);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "message": "User unbanned"
+    })))
+}
@@ -0,0 +1,112 @@
+use actix_web::{web, HttpResponse, get};
+use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, ColumnTrait, UpdateMany};
+use sea_orm::sea_query::Expr;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use log::error;
+use std::collections::HashMap;
+
+use crate::auth::AuthUser;
+use crate::models::entities::{RoomMembership, User};
+use crate::models::entities::{room_membership, user};
+use crate::api::ApiError;
+use crate::api::chat::message::ensure_member;
+use crate::api::chat::ws::user_presence;
+
+/// Whether a user is connected to the chat, and active on any of their connections
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceStatus {
+    Offline,
+    // Connected, but no connection has pinged for a while
+    Away,
+    Online,
+}
+
+#[derive(Deserialize)]
+pub struct RoomPath {
+    room_id: Uuid,
+}
+
+#[derive(Serialize)]
+struct RoomMemberDto {
+    user_id: Uuid,
+    user_name: String,
+    user_profile_image: Option<String>,
+    role: String,
+    status: PresenceStatus,
+    // Now for connected users, otherwise when their last connection closed
+    last_seen_at: Option<DateTime<Utc>>,
+}
+
+// Remember when the user's last chat connection closed
+pub(crate) async fn record_last_seen(db: &DatabaseConnection, user_id: Uuid, at: DateTime<Utc>) {
+    let update: UpdateMany<User> = User::update_many()
+        .col_expr(user::Column::LastSeenAt, Expr::value(at))
+        .filter(user::Column::Id.eq(user_id));
+
+    if let Err(e) = update.exec(db).await {
+        error!("Failed to record last seen time of user {}: {}", user_id, e);
+    }
+}
+
+// Members of a room with their presence, connected members first
+#[get("/rooms/{room_id}/members")]
+pub async fn get_room_members(
+    auth_user: AuthUser,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<RoomPath>,
+) -> Result<HttpResponse, ApiError> {
+    ensure_member(db.get_ref(), path.room_id, auth_user.id).await?;
+
+    let memberships = match RoomMembership::find()
+        .filter(room_membership::Column::RoomId.eq(path.room_id))
+        .all(db.get_ref())
+        .await
+    {
+        Ok(memberships) => memberships,
+        Err(e) => {
+            error!("Failed to load room members: {}", e);
+            return Err(ApiError::Database(e));
+        }
+    };
+
+    let user_ids: Vec<Uuid> = memberships.iter().map(|m| m.user_id).collect();
+    let users: HashMap<Uuid, user::Model> = match User::find()
+        .filter(user::Column::Id.is_in(user_ids.clone()))
+        .all(db.get_ref())
+        .await
+    {
+        Ok(users) => users.into_iter().map(|u| (u.id, u)).collect(),
+        Err(e) => {
+            error!("Failed to load users of room members: {}", e);
+            return Err(ApiError::Database(e));
+        }
+    };
+
+    let presence = user_presence(user_ids).await;
+    let now = Utc::now();
+
+    let mut members: Vec<RoomMemberDto> = memberships
+        .into_iter()
+        .filter_map(|membership| {
+            let user = users.get(&membership.user_id)?;
+            let status = presence.get(&user.id).copied().unwrap_or(PresenceStatus::Offline);
+            Some(RoomMemberDto {
+                user_id: user.id,
+                user_name: user.name.clone(),
+                user_profile_image: user.profile_image.clone(),
+                role: membership.role,
+                status,
+                last_seen_at: if status == PresenceStatus::Offline { user.last_seen_at } else { Some(now) },
+            })
+        })
+        .collect();
+
+    members.sort_by(|a, b| b.status.cmp(&a.status).then_with(|| a.user_name.to_lowercase().cmp(&b.user_name.to_lowercase())));
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "members": members
+    })))
+}
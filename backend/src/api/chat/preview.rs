@@ -0,0 +1,620 @@
+use actix_web::web::{self, Bytes};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageError};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::api::ApiError;
+use crate::api::media::{Format, MediaKind};
+use crate::models::entities::chat_attachment::Model as AttachmentModel;
+
+/// Longest side, in pixels, of the previews generated for chat images
+pub(crate) const PREVIEW_SIZES: [u32; 3] = [1280, 320, 64];
+
+const ORIGINAL_JPEG_QUALITY: u8 = 90;
+const PREVIEW_JPEG_QUALITY: u8 = 80;
+
+// Blurhash components across and down; 4x3 suits the landscape photos most chats send
+const BLURHASH_COMPONENTS: (u32, u32) = (4, 3);
+
+// JPEG segments and PNG and WebP chunks that carry EXIF, XMP, IPTC or free text,
+// any of which may hold the camera's location
+const JPEG_METADATA_MARKERS: [u8; 3] = [0xE1, 0xED, 0xFE];
+const PNG_METADATA_CHUNKS: [&[u8]; 5] = [b"eXIf", b"tEXt", b"zTXt", b"iTXt", b"tIME"];
+const WEBP_METADATA_CHUNKS: [&[u8]; 2] = [b"EXIF", b"XMP "];
+
+/// A smaller copy of an uploaded image
+pub(crate) struct Preview {
+    pub size: u32,
+    pub format: Format,
+    pub width: u32,
+    pub height: u32,
+    pub data: Bytes,
+}
+
+/// An uploaded image made ready to store
+pub(crate) struct ProcessedImage {
+    /// The upload without its metadata and turned upright; re-encoded only when it had to be rotated
+    pub data: Bytes,
+    pub format: Format,
+    pub width: u32,
+    pub height: u32,
+    pub blurhash: String,
+    /// Largest first; sizes the image already fits in are skipped
+    pub previews: Vec<Preview>,
+}
+
+/// A preview as recorded in `chat_attachments.previews`
+#[derive(Serialize, Deserialize)]
+pub(crate) struct StoredPreview {
+    pub size: u32,
+    pub mime_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub storage_key: String,
+}
+
+// An image file with its metadata removed, and the EXIF block that was removed
+struct Stripped {
+    data: Vec<u8>,
+    exif: Option<Vec<u8>>,
+}
+
+/// Strip an uploaded image's metadata and generate its previews and blurhash
+///
+/// Decoding and encoding are CPU-bound, so they run off the async workers.
+pub(crate) async fn process_image(data: Bytes, format: Format) -> Result<ProcessedImage, ApiError> {
+    web::block(move || process_blocking(&data, format))
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to process image: {}", e)))?
+}
+
+fn process_blocking(data: &Bytes, format: Format) -> Result<ProcessedImage, ApiError> {
+    let image_format = format
+        .image_format()
+        .ok_or_else(|| ApiError::UnsupportedMediaType("Only images can have previews".to_string()))?;
+    let image = image::load_from_memory_with_format(data, image_format).map_err(|e| {
+        debug!("Rejected {:?} image that does not decode: {}", format, e);
+        ApiError::UnsupportedMediaType("Image could not be decoded".to_string())
+    })?;
+
+    let stripped = strip_metadata(data, format);
+    let orientation = stripped
+        .as_ref()
+        .and_then(|stripped| stripped.exif.as_deref())
+        .and_then(exif_orientation)
+        .unwrap_or(1);
+    let image = orient(image, orientation);
+
+    // Keep the uploaded bytes when they can be shown as they are, so the original loses no quality
+    let (data, format) = match stripped {
+        Some(stripped) if orientation == 1 => (Bytes::from(stripped.data), format),
+        _ => {
+            let format = opaque_or_alpha(&image);
+            (Bytes::from(encode(&image, format, ORIGINAL_JPEG_QUALITY).map_err(encode_failed)?), format)
+        }
+    };
+
+    // Each preview is scaled from the next larger one rather than from the full image
+    let mut previews = Vec::new();
+    let mut scaled: Option<DynamicImage> = None;
+    for size in PREVIEW_SIZES {
+        if image.width().max(image.height()) <= size {
+            continue;
+        }
+        let source = scaled.as_ref().unwrap_or(&image).resize(size, size, FilterType::CatmullRom);
+        // The image crate only writes lossless WebP, so a JPEG or PNG copy is kept alongside it
+        for preview_format in [opaque_or_alpha(&source), Format::WebP] {
+            let data = encode(&source, preview_format, PREVIEW_JPEG_QUALITY).map_err(encode_failed)?;
+            previews.push(Preview {
+                size,
+                format: preview_format,
+                width: source.width(),
+                height: source.height(),
+                data: Bytes::from(data),
+            });
+        }
+        scaled = Some(source);
+    }
+
+    let placeholder = scaled.as_ref().unwrap_or(&image).thumbnail(32, 32).to_rgba8();
+    let blurhash = blurhash::encode(
+        BLURHASH_COMPONENTS.0,
+        BLURHASH_COMPONENTS.1,
+        placeholder.width(),
+        placeholder.height(),
+        placeholder.as_raw(),
+    )
+    .map_err(|e| ApiError::Internal(format!("Failed to compute blurhash: {:?}", e)))?;
+
+    Ok(ProcessedImage {
+        data,
+        format,
+        width: image.width(),
+        height: image.height(),
+        blurhash,
+        previews,
+    })
+}
+
+fn encode_failed(e: ImageError) -> ApiError {
+    ApiError::Internal(format!("Failed to encode image: {}", e))
+}
+
+// JPEG for opaque images, PNG for ones with transparency
+fn opaque_or_alpha(image: &DynamicImage) -> Format {
+    if image.color().has_alpha() {
+        Format::Png
+    } else {
+        Format::Jpeg
+    }
+}
+
+fn encode(image: &DynamicImage, format: Format, jpeg_quality: u8) -> Result<Vec<u8>, ImageError> {
+    let mut data = Vec::new();
+    match format {
+        Format::Jpeg => image
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut data, jpeg_quality))?,
+        Format::WebP => image.to_rgba8().write_with_encoder(WebPEncoder::new_lossless(&mut data))?,
+        _ => image.to_rgba8().write_with_encoder(PngEncoder::new(&mut data))?,
+    }
+    Ok(data)
+}
+
+// Turn an image upright according to its EXIF orientation
+fn orient(image: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+// The orientation tag of an EXIF block's first IFD
+fn exif_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |pos: usize| -> Option<u16> {
+        let bytes = tiff.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    };
+    let u32_at = |pos: usize| -> Option<u32> {
+        let bytes = tiff.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    };
+
+    let ifd = u32_at(4)? as usize;
+    (0..u16_at(ifd)? as usize)
+        .map(|entry| ifd + 2 + entry * 12)
+        .find(|&entry| u16_at(entry) == Some(0x0112))
+        .and_then(|entry| u16_at(entry + 8))
+}
+
+// Remove metadata without re-encoding; None if the file's structure is not what it should be
+fn strip_metadata(data: &[u8], format: Format) -> Option<Stripped> {
+    match format {
+        Format::Jpeg => strip_jpeg(data),
+        Format::Png => strip_png(data),
+        Format::WebP => strip_webp(data),
+        // GIF has no EXIF
+        _ => Some(Stripped { data: data.to_vec(), exif: None }),
+    }
+}
+
+fn strip_jpeg(data: &[u8]) -> Option<Stripped> {
+    let mut out = data.get(..2)?.to_vec();
+    let mut exif = None;
+    let mut pos = 2;
+    loop {
+        if *data.get(pos)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        match marker {
+            // Fill byte before a marker
+            0xFF => pos += 1,
+            // Start of scan or end of image; what follows is image data
+            0xDA | 0xD9 => {
+                out.extend_from_slice(&data[pos..]);
+                return Some(Stripped { data: out, exif });
+            }
+            // Markers without a payload
+            0x01 | 0xD0..=0xD7 => {
+                out.extend_from_slice(&data[pos..pos + 2]);
+                pos += 2;
+            }
+            _ => {
+                let length = u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]) as usize;
+                let segment = data.get(pos..pos + 2 + length.max(2))?;
+                if JPEG_METADATA_MARKERS.contains(&marker) {
+                    if let Some(tiff) = segment[4..].strip_prefix(b"Exif\0\0") {
+                        exif.get_or_insert_with(|| tiff.to_vec());
+                    }
+                } else {
+                    out.extend_from_slice(segment);
+                }
+                pos += segment.len();
+            }
+        }
+    }
+}
+
+fn strip_png(data: &[u8]) -> Option<Stripped> {
+    let mut out = data.get(..8)?.to_vec();
+    let mut exif = None;
+    let mut pos = 8;
+    while pos < data.len() {
+        let length = u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        // Length, type, data and CRC
+        let chunk = data.get(pos..pos.checked_add(length)?.checked_add(12)?)?;
+        let chunk_type = &chunk[4..8];
+        if chunk_type == b"eXIf" {
+            exif.get_or_insert_with(|| chunk[8..8 + length].to_vec());
+        }
+        if !PNG_METADATA_CHUNKS.contains(&chunk_type) {
+            out.extend_from_slice(chunk);
+        }
+        pos += chunk.len();
+        if chunk_type == b"IEND" {
+            break;
+        }
+    }
+    Some(Stripped { data: out, exif })
+}
+
+fn strip_webp(data: &[u8]) -> Option<Stripped> {
+    let mut out = data.get(..12)?.to_vec();
+    let mut exif = None;
+    let mut pos = 12;
+    while pos < data.len() {
+        let length = u32::from_le_bytes(data.get(pos + 4..pos + 8)?.try_into().ok()?) as usize;
+        // FourCC, size and data, padded to an even length
+        let chunk = data.get(pos..pos.checked_add(length)?.checked_add(8 + length % 2)?)?;
+        let fourcc = &chunk[..4];
+        if fourcc == b"EXIF" {
+            let payload = &chunk[8..8 + length];
+            exif.get_or_insert_with(|| payload.strip_prefix(b"Exif\0\0").unwrap_or(payload).to_vec());
+        }
+        if !WEBP_METADATA_CHUNKS.contains(&fourcc) {
+            let start = out.len();
+            out.extend_from_slice(chunk);
+            if fourcc == b"VP8X" {
+                // Clear the flags announcing EXIF and XMP chunks
+                *out.get_mut(start + 8)? &= !0x0C;
+            }
+        }
+        pos += chunk.len();
+    }
+    let riff_size = u32::try_from(out.len() - 8).ok()?;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Some(Stripped { data: out, exif })
+}
+
+/// Where a preview of the image stored under `storage_key` is stored
+pub(crate) fn preview_key(storage_key: &str, preview: &Preview) -> String {
+    let stem = storage_key.rsplit_once('.').map_or(storage_key, |(stem, _)| stem);
+    format!("{}_{}.{}", stem, preview.size, preview.format.extension(MediaKind::Image))
+}
+
+/// The previews recorded for an attachment; none for files that are not images
+pub(crate) fn stored_previews(attachment: &AttachmentModel) -> Vec<StoredPreview> {
+    attachment
+        .previews
+        .clone()
+        .and_then(|previews| serde_json::from_value(previews).ok())
+        .unwrap_or_default()
+}
+
+/// The smallest preview at least `size` pixels on its longest side, in WebP or not;
+/// without a size, the largest one
+///
+/// None means the original is the best fit.
+pub(crate) fn pick_preview(previews: &[StoredPreview], size: Option<u32>, webp: bool) -> Option<&StoredPreview> {
+    let candidates = previews
+        .iter()
+        .filter(|preview| (preview.mime_type == Format::WebP.mime_type(MediaKind::Image)) == webp);
+    match size {
+        Some(size) => candidates.filter(|preview| preview.size >= size).min_by_key(|preview| preview.size),
+        None => candidates.max_by_key(|preview| preview.size),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgb, Rgba};
+
+    const XMP: &[u8] = b"http://ns.adobe.com/xap/1.0/\0<x:xmpmeta><exif:GPSLatitude>52,31.0N</exif:GPSLatitude></x:xmpmeta>";
+
+    // A TIFF header and first IFD with a Make tag and, when given, an orientation tag
+    fn exif(big_endian: bool, orientation: Option<u16>) -> Vec<u8> {
+        let u16_bytes = |value: u16| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        let u32_bytes = |value: u32| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+
+        let mut tiff = if big_endian { b"MM".to_vec() } else { b"II".to_vec() };
+        tiff.extend_from_slice(&u16_bytes(42));
+        tiff.extend_from_slice(&u32_bytes(8));
+        tiff.extend_from_slice(&u16_bytes(1 + u16::from(orientation.is_some())));
+        // Make, ASCII, 4 bytes stored in the entry itself
+        tiff.extend_from_slice(&u16_bytes(0x010F));
+        tiff.extend_from_slice(&u16_bytes(2));
+        tiff.extend_from_slice(&u32_bytes(4));
+        tiff.extend_from_slice(b"Cam\0");
+        if let Some(orientation) = orientation {
+            tiff.extend_from_slice(&u16_bytes(0x0112));
+            tiff.extend_from_slice(&u16_bytes(3));
+            tiff.extend_from_slice(&u32_bytes(1));
+            tiff.extend_from_slice(&u16_bytes(orientation));
+            tiff.extend_from_slice(&[0, 0]);
+        }
+        tiff.extend_from_slice(&u32_bytes(0));
+        tiff
+    }
+
+    fn photo() -> DynamicImage {
+        DynamicImage::ImageRgb8(ImageBuffer::from_fn(8, 4, |x, y| Rgb([x as u8 * 30, y as u8 * 60, 128])))
+    }
+
+    fn jpeg_segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xFF, marker];
+        segment.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+        segment.extend_from_slice(payload);
+        segment
+    }
+
+    // A JPEG with EXIF, XMP, IPTC and comment segments between SOI and the image data
+    fn jpeg_with_metadata(orientation: u16) -> Vec<u8> {
+        let encoded = encode(&photo(), Format::Jpeg, 90).unwrap();
+        let mut data = encoded[..2].to_vec();
+        data.extend(jpeg_segment(0xE1, &[b"Exif\0\0".as_slice(), &exif(true, Some(orientation))].concat()));
+        data.extend(jpeg_segment(0xE1, XMP));
+        data.extend(jpeg_segment(0xED, b"Photoshop 3.0\0location"));
+        data.extend(jpeg_segment(0xFE, b"shot at home"));
+        data.extend_from_slice(&encoded[2..]);
+        data
+    }
+
+    // Markers of a JPEG's segments up to the start of scan
+    fn jpeg_markers(data: &[u8]) -> Vec<u8> {
+        let mut markers = Vec::new();
+        let mut pos = 2;
+        while pos + 4 <= data.len() && data[pos] == 0xFF {
+            let marker = data[pos + 1];
+            markers.push(marker);
+            if marker == 0xDA {
+                break;
+            }
+            pos += 2 + u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        }
+        markers
+    }
+
+    // A chunk with a zero CRC; the stripper does not check CRCs
+    fn png_chunk(chunk_type: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut chunk = (payload.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(chunk_type);
+        chunk.extend_from_slice(payload);
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    // A PNG with eXIf, text and time chunks after IHDR
+    fn png_with_metadata() -> Vec<u8> {
+        let encoded = encode(&photo(), Format::Png, 90).unwrap();
+        // Signature and IHDR
+        let (head, rest) = encoded.split_at(8 + 25);
+        let mut data = head.to_vec();
+        data.extend(png_chunk(b"eXIf", &exif(false, Some(6))));
+        data.extend(png_chunk(b"tEXt", b"Comment\0shot at home"));
+        data.extend(png_chunk(b"iTXt", &[b"XML:com.adobe.xmp\0\0\0\0\0".as_slice(), XMP].concat()));
+        data.extend(png_chunk(b"tIME", &[7, 233, 9, 7, 12, 0, 0]));
+        data.extend_from_slice(rest);
+        data
+    }
+
+    fn png_chunks(data: &[u8]) -> Vec<Vec<u8>> {
+        let mut chunks = Vec::new();
+        let mut pos = 8;
+        while pos + 8 <= data.len() {
+            let length = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+            chunks.push(data[pos + 4..pos + 8].to_vec());
+            pos += length + 12;
+        }
+        chunks
+    }
+
+    fn webp_chunk(fourcc: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut chunk = fourcc.to_vec();
+        chunk.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(payload);
+        if payload.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    // An extended WebP whose VP8X header announces the EXIF and XMP chunks that follow the image
+    fn webp_with_metadata() -> Vec<u8> {
+        let image = photo();
+        let encoded = encode(&image, Format::WebP, 90).unwrap();
+
+        let mut vp8x = vec![0x0C, 0, 0, 0];
+        vp8x.extend_from_slice(&(image.width() - 1).to_le_bytes()[..3]);
+        vp8x.extend_from_slice(&(image.height() - 1).to_le_bytes()[..3]);
+
+        let mut body = b"WEBP".to_vec();
+        body.extend(webp_chunk(b"VP8X", &vp8x));
+        body.extend_from_slice(&encoded[12..]);
+        body.extend(webp_chunk(b"EXIF", &[b"Exif\0\0".as_slice(), &exif(false, Some(8))].concat()));
+        body.extend(webp_chunk(b"XMP ", XMP));
+
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        data.extend(body);
+        data
+    }
+
+    fn webp_chunks(data: &[u8]) -> Vec<Vec<u8>> {
+        let mut chunks = Vec::new();
+        let mut pos = 12;
+        while pos + 8 <= data.len() {
+            let length = u32::from_le_bytes(data[pos + 4..pos + 8].try_into().unwrap()) as usize;
+            chunks.push(data[pos..pos + 4].to_vec());
+            pos += 8 + length + length % 2;
+        }
+        chunks
+    }
+
+    fn contains(data: &[u8], needle: &[u8]) -> bool {
+        data.windows(needle.len()).any(|window| window == needle)
+    }
+
+    fn stored(size: u32, mime_type: &str) -> StoredPreview {
+        StoredPreview {
+            size,
+            mime_type: mime_type.to_string(),
+            width: size,
+            height: size,
+            storage_key: format!("{}_{}", size, mime_type),
+        }
+    }
+
+    #[test]
+    fn exif_orientation_reads_both_byte_orders() {
+        assert_eq!(exif_orientation(&exif(true, Some(6))), Some(6));
+        assert_eq!(exif_orientation(&exif(false, Some(8))), Some(8));
+    }
+
+    #[test]
+    fn exif_orientation_is_none_without_the_tag_or_a_valid_header() {
+        assert_eq!(exif_orientation(&exif(true, None)), None);
+        assert_eq!(exif_orientation(b"XX\0*\0\0\0\x08"), None);
+        assert_eq!(exif_orientation(&[]), None);
+
+        let truncated = exif(false, Some(6));
+        assert_eq!(exif_orientation(&truncated[..truncated.len() - 10]), None);
+    }
+
+    #[test]
+    fn strip_jpeg_removes_every_metadata_segment() {
+        let original = jpeg_with_metadata(6);
+        let stripped = strip_jpeg(&original).unwrap();
+
+        let markers = jpeg_markers(&stripped.data);
+        assert!(!markers.contains(&0xE1), "APP1 survived: {:02X?}", markers);
+        assert!(!markers.contains(&0xED), "APP13 survived: {:02X?}", markers);
+        assert!(!markers.contains(&0xFE), "COM survived: {:02X?}", markers);
+        assert_eq!(markers.last(), Some(&0xDA));
+        assert!(!contains(&stripped.data, b"Exif\0\0"));
+        assert!(!contains(&stripped.data, b"GPSLatitude"));
+
+        assert_eq!(stripped.exif, Some(exif(true, Some(6))));
+        assert!(image::load_from_memory_with_format(&stripped.data, image::ImageFormat::Jpeg).is_ok());
+    }
+
+    #[test]
+    fn strip_jpeg_rejects_a_broken_file() {
+        let original = jpeg_with_metadata(1);
+        assert!(strip_jpeg(&original[..6]).is_none());
+        assert!(strip_jpeg(b"\xFF\xD8garbage").is_none());
+    }
+
+    #[test]
+    fn strip_png_removes_every_metadata_chunk() {
+        let original = png_with_metadata();
+        let stripped = strip_png(&original).unwrap();
+
+        let chunks = png_chunks(&stripped.data);
+        for metadata in PNG_METADATA_CHUNKS {
+            assert!(!chunks.iter().any(|chunk| chunk == metadata), "{:?} survived", String::from_utf8_lossy(metadata));
+        }
+        assert_eq!(chunks.first().map(Vec::as_slice), Some(b"IHDR".as_slice()));
+        assert_eq!(chunks.last().map(Vec::as_slice), Some(b"IEND".as_slice()));
+        assert!(!contains(&stripped.data, b"GPSLatitude"));
+
+        assert_eq!(stripped.exif, Some(exif(false, Some(6))));
+        assert!(image::load_from_memory_with_format(&stripped.data, image::ImageFormat::Png).is_ok());
+    }
+
+    #[test]
+    fn strip_webp_removes_metadata_chunks_and_their_flags() {
+        let original = webp_with_metadata();
+        let stripped = strip_webp(&original).unwrap();
+
+        let chunks = webp_chunks(&stripped.data);
+        for metadata in WEBP_METADATA_CHUNKS {
+            assert!(!chunks.iter().any(|chunk| chunk == metadata), "{:?} survived", String::from_utf8_lossy(metadata));
+        }
+        assert_eq!(chunks.first().map(Vec::as_slice), Some(b"VP8X".as_slice()));
+        // VP8X flags byte: EXIF and XMP no longer announced
+        assert_eq!(stripped.data[20] & 0x0C, 0);
+        assert_eq!(
+            u32::from_le_bytes(stripped.data[4..8].try_into().unwrap()) as usize,
+            stripped.data.len() - 8
+        );
+        assert!(!contains(&stripped.data, b"GPSLatitude"));
+
+        assert_eq!(stripped.exif, Some(exif(false, Some(8))));
+        assert!(image::load_from_memory_with_format(&stripped.data, image::ImageFormat::WebP).is_ok());
+    }
+
+    #[test]
+    fn process_turns_the_image_upright_and_drops_its_exif() {
+        let processed = process_blocking(&Bytes::from(jpeg_with_metadata(6)), Format::Jpeg).unwrap();
+
+        // Orientation 6 is a quarter turn, so the 8x4 upload becomes 4x8
+        assert_eq!((processed.width, processed.height), (4, 8));
+        assert!(!jpeg_markers(&processed.data).contains(&0xE1));
+        assert!(!contains(&processed.data, b"Exif\0\0"));
+        assert!(!processed.blurhash.is_empty());
+    }
+
+    #[test]
+    fn process_keeps_upright_uploads_without_reencoding() {
+        let original = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(2000, 10, Rgba([0, 0, 0, 128])));
+        let data = Bytes::from(encode(&original, Format::Png, 90).unwrap());
+        let processed = process_blocking(&data, Format::Png).unwrap();
+
+        assert_eq!(processed.data, data);
+        let sizes: Vec<(u32, Format)> = processed.previews.iter().map(|p| (p.size, p.format)).collect();
+        assert_eq!(
+            sizes,
+            [
+                (1280, Format::Png),
+                (1280, Format::WebP),
+                (320, Format::Png),
+                (320, Format::WebP),
+                (64, Format::Png),
+                (64, Format::WebP),
+            ]
+        );
+    }
+
+    #[test]
+    fn pick_preview_takes_the_smallest_large_enough_or_the_largest_without_a_size() {
+        let previews = [
+            stored(1280, "image/jpeg"),
+            stored(1280, "image/webp"),
+            stored(320, "image/jpeg"),
+            stored(320, "image/webp"),
+        ];
+
+        assert_eq!(pick_preview(&previews, Some(300), false).unwrap().storage_key, "320_image/jpeg");
+        assert_eq!(pick_preview(&previews, Some(321), true).unwrap().storage_key, "1280_image/webp");
+        assert!(pick_preview(&previews, Some(2000), true).is_none());
+        assert_eq!(pick_preview(&previews, None, true).unwrap().storage_key, "1280_image/webp");
+        assert!(pick_preview(&[], None, true).is_none());
+    }
+}
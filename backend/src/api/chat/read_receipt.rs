@@ -0,0 +1,143 @@
+use sea_orm::{DatabaseConnection, DbErr, EntityTrait, ActiveModelTrait, ActiveValue, QueryFilter, QueryOrder, QuerySelect, ColumnTrait, PaginatorTrait};
+use sea_orm::sea_query::{Expr, SimpleExpr};
+use uuid::Uuid;
+use chrono::Utc;
+use log::info;
+
+use crate::models::entities::{ChatMessage, RoomMembership, RoomMembershipActiveModel, RoomMembershipModel};
+use crate::models::entities::{chat_message, room_membership};
+use crate::api::chat::ws::{broadcast_to_room, WsResponse};
+use crate::api::ApiError;
+
+// How far behind a member is in a room's timeline
+#[derive(Default)]
+pub(crate) struct UnreadState {
+    pub count: u64,
+    pub first_unread_message_id: Option<Uuid>,
+}
+
+// Condition matching timeline messages newer than the member's read marker
+//
+// Members who have not read anything yet have read everything up to the moment they joined.
+async fn unread_condition(db: &DatabaseConnection, membership: &RoomMembershipModel) -> Result<SimpleExpr, DbErr> {
+    let marker = match membership.last_read_message_id {
+        Some(message_id) => ChatMessage::find_by_id(message_id).one(db).await?,
+        None => None,
+    };
+
+    Ok(match marker {
+        Some(message) => {
+            let key = Expr::tuple([
+                Expr::col(chat_message::Column::CreatedAt).into(),
+                Expr::col(chat_message::Column::Id).into(),
+            ]);
+            key.gt(Expr::tuple([Expr::value(message.created_at), Expr::value(message.id)]))
+        }
+        None => chat_message::Column::CreatedAt.gt(membership.joined_at),
+    })
+}
+
+/// Count the unread timeline messages of a member and find the first of them
+///
+/// Only live top-level messages from other members count; thread replies and
+/// the member's own messages never make a room unread.
+pub(crate) async fn unread_state(db: &DatabaseConnection, membership: &RoomMembershipModel) -> Result<UnreadState, DbErr> {
+    let condition = unread_condition(db, membership).await?;
+
+    let unread = ChatMessage::find()
+        .filter(chat_message::Column::RoomId.eq(membership.room_id))
+        .filter(chat_message::Column::ReplyToId.is_null())
+        .filter(chat_message::Column::DeletedAt.is_null())
+        .filter(chat_message::Column::UserId.ne(membership.user_id))
+        .filter(condition);
+
+    let count = unread.clone().count(db).await?;
+    if count == 0 {
+        return Ok(UnreadState::default());
+    }
+
+    let first = unread
+        .order_by_asc(chat_message::Column::CreatedAt)
+        .order_by_asc(chat_message::Column::Id)
+        .limit(1)
+        .one(db)
+        .await?;
+
+    Ok(UnreadState {
+        count,
+        first_unread_message_id: first.map(|message| message.id),
+    })
+}
+
+/// Move a member's read marker to `message_id`
+///
+/// Markers only move forward: reading an older message (or a reply in a
+/// thread older than the marker) leaves the marker where it is. Replies mark
+/// their thread's root as read. Returns the membership and whether the marker moved.
+pub async fn mark_read(
+    db: &DatabaseConnection,
+    room_id: Uuid,
+    user_id: Uuid,
+    message_id: Uuid,
+) -> Result<(RoomMembershipModel, bool), ApiError> {
+    let membership = match RoomMembership::find()
+        .filter(room_membership::Column::RoomId.eq(room_id))
+        .filter(room_membership::Column::UserId.eq(user_id))
+        .one(db)
+        .await
+    {
+        Ok(Some(membership)) => membership,
+        Ok(None) => return Err(ApiError::NotRoomMember("Access denied: You are not a member of this room".to_string())),
+        Err(e) => return Err(ApiError::Database(e)),
+    };
+
+    let message = match ChatMessage::find_by_id(message_id).one(db).await? {
+        Some(message) if message.room_id == room_id => message,
+        _ => return Err(ApiError::NotFound("Message not found in this room".to_string())),
+    };
+
+    let message = match message.reply_to_id {
+        Some(root_id) => match ChatMessage::find_by_id(root_id).one(db).await? {
+            Some(root) => root,
+            None => return Err(ApiError::NotFound("Message not found in this room".to_string())),
+        },
+        None => message,
+    };
+
+    if let Some(current_id) = membership.last_read_message_id {
+        if current_id == message.id {
+            return Ok((membership, false));
+        }
+        if let Some(current) = ChatMessage::find_by_id(current_id).one(db).await? {
+            if (current.created_at, current.id) > (message.created_at, message.id) {
+                return Ok((membership, false));
+            }
+        }
+    }
+
+    let mut active: RoomMembershipActiveModel = membership.into();
+    active.last_read_message_id = ActiveValue::Set(Some(message.id));
+    active.last_read_at = ActiveValue::Set(Some(Utc::now()));
+    let updated = active.update(db).await?;
+
+    info!("User {} read room {} up to message {}", user_id, room_id, message.id);
+    Ok((updated, true))
+}
+
+// Tell the room how far a member has read
+pub fn broadcast_read_receipt(membership: &RoomMembershipModel, user_name: &str) {
+    let event = WsResponse {
+        message_type: "read_receipt".to_string(),
+        data: serde_json::json!({
+            "room_id": membership.room_id,
+            "user_id": membership.user_id,
+            "user_name": user_name,
+            "last_read_message_id": membership.last_read_message_id,
+            "last_read_at": membership.last_read_at,
+        }),
+        timestamp: Utc::now().timestamp(),
+        message_id: None,
+    };
+
+    broadcast_to_room(&membership.room_id.to_string(), event);
+}
@@ -1,10 +1,15 @@
-use actix_web::{web, HttpResponse, post, get, delete, Responder, HttpRequest, Error};
-use sea_orm::{DatabaseConnection, EntityTrait, Set, ActiveValue, QueryOrder, Order, QueryFilter, ColumnTrait, DeleteResult, PaginatorTrait};
+use actix_web::{web, HttpResponse, post, get, delete, HttpRequest};
+use sea_orm::{DatabaseConnection, EntityTrait, ActiveValue, QueryFilter, ColumnTrait, PaginatorTrait};
 use uuid::Uuid;
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use crate::auth::{AuthUser, JwtAuth, extract_token_from_cookie_or_header};
-use crate::models::entities::{ChatRoom, ChatRoomActiveModel, CreateRoomDto, RoomResponseDto, RoomMembership, RoomMembershipActiveModel};
+use crate::auth::AuthUser;
+use crate::models::entities::{ChatRoom, ChatRoomActiveModel, CreateRoomDto, RoomResponseDto, RoomMembership, RoomMembershipActiveModel, RoomRole};
+use crate::api::ApiError;
+use crate::api::chat::read_receipt::{unread_state, UnreadState};
+use crate::api::chat::moderation::ensure_not_banned;
+use crate::api::chat::ws::{cache_room_membership, forget_room, forget_room_membership};
+use std::collections::HashMap;
 use argon2::{
     password_hash::{
         rand_core::OsRng,
@@ -34,12 +39,6 @@ pub struct VerifyRoomPasswordResponse {
     message: String,
 }
 
-#[derive(Serialize)]
-pub struct PasswordProtectedResponse {
-    is_protected: bool,
-    message: String,
-}
-
 #[derive(Serialize)]
 pub struct DeleteRoomResponse {
     success: bool,
@@ -52,60 +51,14 @@ pub struct LeaveRoomResponse {
     message: String,
 }
 
-#[post("/api/chat/rooms")]
+#[post("/rooms")]
 pub async fn create_room(
+    auth: AuthUser,
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
-    jwt_secret: web::Data<String>,
+    _req: HttpRequest,
     room_data: web::Json<CreateRoomDto>,
-) -> Result<HttpResponse, Error> {
-    // Extract token from request
-    let token = match extract_token_from_cookie_or_header(&req) {
-        Some(token) => token,
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: No authentication token found"
-            })));
-        }
-    };
-
-    // Validate token and get user
-    let claims = match JwtAuth::validate_token(&token, &jwt_secret) {
-        Ok(claims) => claims,
-        Err(_) => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Invalid authentication token"
-            })));
-        }
-    };
-
-    // Ensure backend_user_id is present and valid
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Missing user ID in token"
-            })));
-        }
-    };
-
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
-        Err(_) => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Invalid user ID format"
-            })));
-        }
-    };
-
-    // Create AuthUser from claims
-    let auth = AuthUser {
-        id: user_id,
-        email: claims.email,
-        name: claims.name,
-        role: claims.user_role.unwrap_or_else(|| "user".to_string()),
-        profile_image: claims.profile_image,
-    };
+) -> Result<HttpResponse, ApiError> {
+    let _user_id = auth.id;
 
     let room_id = Uuid::new_v4();
     
@@ -130,9 +83,7 @@ pub async fn create_room(
                 Ok(hash) => Some(hash.to_string()),
                 Err(e) => {
                     log::error!("Failed to hash room password: {}", e);
-                    return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                        "error": "Failed to create room: password hashing error"
-                    })));
+                    return Err(ApiError::Internal("Failed to create room: password hashing error".to_string()));
                 }
             }
         } else {
@@ -162,10 +113,16 @@ pub async fn create_room(
                 user_id: ActiveValue::Set(auth.id),
                 room_id: ActiveValue::Set(room_id),
                 joined_at: ActiveValue::Set(Utc::now()),
+                last_read_message_id: ActiveValue::Set(None),
+                last_read_at: ActiveValue::Set(None),
+                role: ActiveValue::Set(RoomRole::Owner.as_str().to_string()),
+                muted_until: ActiveValue::Set(None),
             };
             
             match RoomMembership::insert(creator_membership).exec(db.get_ref()).await {
                 Ok(_) => {
+                    cache_room_membership(room_id, auth.id);
+
                     let room_response = RoomResponseDto {
                         id: room_id,
                         name: room_data.name.clone(),
@@ -176,74 +133,35 @@ pub async fn create_room(
                         is_owner: true, // Creator is always the owner
                         room_code: room_code,
                         user_count: 1, // Creator is the first member
+                        unread_count: 0,
+                        first_unread_message_id: None,
                     };
 
                     Ok(HttpResponse::Created().json(room_response))
                 }
                 Err(e) => {
                     log::error!("Failed to add creator as member: {}", e);
-                    Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                        "error": "Failed to create room: could not add creator as member"
-                    })))
+                    Err(ApiError::Database(e))
                 }
             }
         }
         Err(e) => {
             log::error!("Failed to create room: {}", e);
-            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to create room"
-            })))
+            Err(ApiError::Database(e))
         }
     }
 }
 
-#[get("/api/chat/rooms")]
+#[get("/rooms")]
 pub async fn get_rooms(
+    auth_user: AuthUser,
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
-    jwt_secret: web::Data<String>,
-) -> Result<HttpResponse, Error> {
-    // Extract token from request
-    let token = match extract_token_from_cookie_or_header(&req) {
-        Some(token) => token,
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: No authentication token found"
-            })));
-        }
-    };
-
-    // Validate token and get user
-    let claims = match JwtAuth::validate_token(&token, &jwt_secret) {
-        Ok(claims) => claims,
-        Err(_) => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Invalid authentication token"
-            })));
-        }
-    };
-
-    // Ensure backend_user_id is present and valid
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Missing user ID in token"
-            })));
-        }
-    };
-
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
-        Err(_) => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Invalid user ID format"
-            })));
-        }
-    };
+    _req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = auth_user.id;
 
     // Get rooms that the user created or joined
-    use sea_orm::QuerySelect;
+    
     
     // First, get all rooms created by the user
     let created_rooms_result = ChatRoom::find()
@@ -263,9 +181,7 @@ pub async fn get_rooms(
         Ok(rooms) => rooms,
         Err(e) => {
             log::error!("Failed to get created rooms: {}", e);
-            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get created rooms"
-            })));
+            return Err(ApiError::Database(e));
         }
     };
     
@@ -273,17 +189,17 @@ pub async fn get_rooms(
         Ok(rooms) => rooms,
         Err(e) => {
             log::error!("Failed to get joined rooms: {}", e);
-            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get joined rooms"
-            })));
+            return Err(ApiError::Database(e));
         }
     };
     
     // Combine the two lists, removing duplicates
     let mut all_rooms = created_rooms;
     
-    // Add joined rooms that weren't created by the user
-    for (_, rooms) in joined_rooms {
+    // Add joined rooms that weren't created by the user, remembering the read marker of each
+    let mut memberships = HashMap::new();
+    for (membership, rooms) in joined_rooms {
+        memberships.insert(membership.room_id, membership);
         for room in rooms {
             if !all_rooms.iter().any(|r| r.id == room.id) {
                 all_rooms.push(room);
@@ -312,6 +228,17 @@ pub async fn get_rooms(
             }
         };
         
+        let unread = match memberships.get(&room.id) {
+            Some(membership) => match unread_state(db.get_ref(), membership).await {
+                Ok(unread) => unread,
+                Err(e) => {
+                    log::error!("Failed to get unread count for room {}: {}", room.id, e);
+                    UnreadState::default()
+                }
+            },
+            None => UnreadState::default(),
+        };
+        
         room_responses.push(RoomResponseDto {
             id: room.id,
             name: room.name,
@@ -322,6 +249,8 @@ pub async fn get_rooms(
             is_owner: room.created_by == user_id,
             room_code: room.room_code,
             user_count,
+            unread_count: unread.count,
+            first_unread_message_id: unread.first_unread_message_id,
         });
     }
 
@@ -330,67 +259,23 @@ pub async fn get_rooms(
     })))
 }
 
-#[post("/api/chat/rooms/{room_id}/verify-password")]
+#[post("/rooms/{room_id}/verify-password")]
 pub async fn verify_room_password(
+    auth: AuthUser,
     db: web::Data<DatabaseConnection>,
-    http_req: HttpRequest,
-    jwt_secret: web::Data<String>,
+    _http_req: HttpRequest,
     path: web::Path<RoomPath>,
     password_req: web::Json<VerifyRoomPasswordRequest>,
-) -> Result<HttpResponse, Error> {
-    // Extract token from request
-    let token = match extract_token_from_cookie_or_header(&http_req) {
-        Some(token) => token,
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: No authentication token found"
-            })));
-        }
-    };
-
-    // Validate token and get user
-    let claims = match JwtAuth::validate_token(&token, &jwt_secret) {
-        Ok(claims) => claims,
-        Err(_) => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Invalid authentication token"
-            })));
-        }
-    };
-
-    // Ensure backend_user_id is present and valid
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Missing user ID in token"
-            })));
-        }
-    };
-
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
-        Err(_) => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Invalid user ID format"
-            })));
-        }
-    };
-
-    // Create AuthUser from claims
-    let auth = AuthUser {
-        id: user_id,
-        email: claims.email,
-        name: claims.name,
-        role: claims.user_role.unwrap_or_else(|| "user".to_string()),
-        profile_image: claims.profile_image,
-    };
+) -> Result<HttpResponse, ApiError> {
+    let _user_id = auth.id;
 
     let room_id = path.room_id;
 
     // Find the room
     match ChatRoom::find_by_id(room_id).one(db.get_ref()).await {
         Ok(Some(room)) => {
+            ensure_not_banned(db.get_ref(), room.id, auth.id).await?;
+
             // Check if room has a password
             if let Some(password_hash) = room.password_hash {
                 // Verify the password
@@ -398,9 +283,7 @@ pub async fn verify_room_password(
                     Ok(hash) => hash,
                     Err(e) => {
                         log::error!("Failed to parse stored password hash: {:?}", e);
-                        return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                            "error": "Internal server error"
-                        })));
+                        return Err(ApiError::Internal("Internal server error".to_string()));
                     }
                 };
 
@@ -412,87 +295,31 @@ pub async fn verify_room_password(
                     }))
                 } else {
                     // Password is incorrect
-                    Ok(HttpResponse::Unauthorized().json(VerifyRoomPasswordResponse {
-                        success: false,
-                        message: "Incorrect password".to_string(),
-                    }))
+                    Err(ApiError::RoomPasswordInvalid)
                 }
             } else {
                 // Room doesn't have a password
-                Ok(HttpResponse::BadRequest().json(VerifyRoomPasswordResponse {
-                    success: false,
-                    message: "Room is not password protected".to_string(),
-                }))
+                Err(ApiError::BadRequest("Room is not password protected".to_string()))
             }
         }
         Ok(None) => {
-            Ok(HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Room not found"
-            })))
+            Err(ApiError::NotFound("Room not found".to_string()))
         }
         Err(e) => {
             log::error!("Failed to get room: {}", e);
-            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get room"
-            })))
+            Err(ApiError::Database(e))
         }
     }
 }
 
-#[get("/api/chat/rooms/{room_id}")]
+#[get("/rooms/{room_id}")]
 pub async fn get_room(
+    auth: AuthUser,
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
-    jwt_secret: web::Data<String>,
+    _req: HttpRequest,
     path: web::Path<RoomPath>,
-) -> Result<HttpResponse, Error> {
-    // Extract token from request
-    let token = match extract_token_from_cookie_or_header(&req) {
-        Some(token) => token,
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: No authentication token found"
-            })));
-        }
-    };
-
-    // Validate token and get user
-    let claims = match JwtAuth::validate_token(&token, &jwt_secret) {
-        Ok(claims) => claims,
-        Err(_) => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Invalid authentication token"
-            })));
-        }
-    };
-
-    // Ensure backend_user_id is present and valid
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Missing user ID in token"
-            })));
-        }
-    };
-
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
-        Err(_) => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Invalid user ID format"
-            })));
-        }
-    };
-
-    // Create AuthUser from claims
-    let auth = AuthUser {
-        id: user_id,
-        email: claims.email,
-        name: claims.name,
-        role: claims.user_role.unwrap_or_else(|| "user".to_string()),
-        profile_image: claims.profile_image,
-    };
+) -> Result<HttpResponse, ApiError> {
+    let user_id = auth.id;
 
     let room_id = path.room_id;
 
@@ -503,10 +330,7 @@ pub async fn get_room(
             // Check if room is password protected
             if room.password_hash.is_some() {
                 // Return a special response for password-protected rooms
-                return Ok(HttpResponse::Forbidden().json(PasswordProtectedResponse {
-                    is_protected: true,
-                    message: "This room is password protected. Please verify your password.".to_string(),
-                }));
+                return Err(ApiError::RoomPasswordRequired);
             }
             
             // Get user count for this room
@@ -523,6 +347,24 @@ pub async fn get_room(
                 }
             };
             
+            // Unread state of the requesting user, if they are a member
+            let unread = match RoomMembership::find()
+                .filter(crate::models::entities::room_membership::Column::RoomId.eq(room.id))
+                .filter(crate::models::entities::room_membership::Column::UserId.eq(user_id))
+                .one(db.get_ref())
+                .await
+            {
+                Ok(Some(membership)) => unread_state(db.get_ref(), &membership).await.unwrap_or_else(|e| {
+                    log::error!("Failed to get unread count for room {}: {}", room.id, e);
+                    UnreadState::default()
+                }),
+                Ok(None) => UnreadState::default(),
+                Err(e) => {
+                    log::error!("Failed to get membership for room {}: {}", room.id, e);
+                    UnreadState::default()
+                }
+            };
+            
             let room_response = RoomResponseDto {
                 id: room.id,
                 name: room.name,
@@ -533,69 +375,30 @@ pub async fn get_room(
                 is_owner: room.created_by == user_id,
                 room_code: room.room_code,
                 user_count,
+                unread_count: unread.count,
+                first_unread_message_id: unread.first_unread_message_id,
             };
 
             Ok(HttpResponse::Ok().json(room_response))
         }
         Ok(None) => {
-            Ok(HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Room not found"
-            })))
+            Err(ApiError::NotFound("Room not found".to_string()))
         }
         Err(e) => {
             log::error!("Failed to get room: {}", e);
-            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get room"
-            })))
+            Err(ApiError::Database(e))
         }
     }
 }
 
-#[delete("/api/chat/rooms/{room_id}")]
+#[delete("/rooms/{room_id}")]
 pub async fn delete_room(
+    auth_user: AuthUser,
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
-    jwt_secret: web::Data<String>,
+    _req: HttpRequest,
     path: web::Path<RoomPath>,
-) -> Result<HttpResponse, Error> {
-    // Extract token from request
-    let token = match extract_token_from_cookie_or_header(&req) {
-        Some(token) => token,
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: No authentication token found"
-            })));
-        }
-    };
-
-    // Validate token and get user
-    let claims = match JwtAuth::validate_token(&token, &jwt_secret) {
-        Ok(claims) => claims,
-        Err(_) => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Invalid authentication token"
-            })));
-        }
-    };
-
-    // Ensure backend_user_id is present and valid
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Missing user ID in token"
-            })));
-        }
-    };
-
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
-        Err(_) => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Invalid user ID format"
-            })));
-        }
-    };
+) -> Result<HttpResponse, ApiError> {
+    let user_id = auth_user.id;
 
     let room_id = path.room_id;
 
@@ -604,15 +407,14 @@ pub async fn delete_room(
         Ok(Some(room)) => {
             // Check if the user is the creator of the room
             if room.created_by != user_id {
-                return Ok(HttpResponse::Forbidden().json(DeleteRoomResponse {
-                    success: false,
-                    message: "You can only delete rooms that you have created".to_string(),
-                }));
+                return Err(ApiError::Forbidden("You can only delete rooms that you have created".to_string()));
             }
 
             // Delete the room
             match ChatRoom::delete_by_id(room_id).exec(db.get_ref()).await {
                 Ok(_) => {
+                    forget_room(room_id);
+
                     Ok(HttpResponse::Ok().json(DeleteRoomResponse {
                         success: true,
                         message: "Room deleted successfully".to_string(),
@@ -620,74 +422,28 @@ pub async fn delete_room(
                 }
                 Err(e) => {
                     log::error!("Failed to delete room: {}", e);
-                    Ok(HttpResponse::InternalServerError().json(DeleteRoomResponse {
-                        success: false,
-                        message: "Failed to delete room".to_string(),
-                    }))
+                    Err(ApiError::Database(e))
                 }
             }
         }
         Ok(None) => {
-            Ok(HttpResponse::NotFound().json(DeleteRoomResponse {
-                success: false,
-                message: "Room not found".to_string(),
-            }))
+            Err(ApiError::NotFound("Room not found".to_string()))
         }
         Err(e) => {
             log::error!("Failed to get room: {}", e);
-            Ok(HttpResponse::InternalServerError().json(DeleteRoomResponse {
-                success: false,
-                message: "Failed to get room".to_string(),
-            }))
+            Err(ApiError::Database(e))
         }
     }
 }
 
-#[delete("/api/chat/rooms/{room_id}/membership")]
+#[delete("/rooms/{room_id}/membership")]
 pub async fn leave_room_membership(
+    auth_user: AuthUser,
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
-    jwt_secret: web::Data<String>,
+    _req: HttpRequest,
     path: web::Path<RoomPath>,
-) -> Result<HttpResponse, Error> {
-    // Extract token
-    let token = match extract_token_from_cookie_or_header(&req) {
-        Some(token) => token,
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: No authentication token found"
-            })));
-        }
-    };
-
-    // Validate token
-    let claims = match JwtAuth::validate_token(&token, &jwt_secret) {
-        Ok(claims) => claims,
-        Err(_) => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Invalid authentication token"
-            })));
-        }
-    };
-
-    // Extract user id
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Missing user ID in token"
-            })));
-        }
-    };
-
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
-        Err(_) => {
-            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized: Invalid user ID format"
-            })));
-        }
-    };
+) -> Result<HttpResponse, ApiError> {
+    let user_id = auth_user.id;
 
     let room_id = path.room_id;
 
@@ -695,24 +451,15 @@ pub async fn leave_room_membership(
     match ChatRoom::find_by_id(room_id).one(db.get_ref()).await {
         Ok(Some(room)) => {
             if room.created_by == user_id {
-                return Ok(HttpResponse::Forbidden().json(LeaveRoomResponse {
-                    success: false,
-                    message: "Room owners cannot leave their own room".to_string(),
-                }));
+                return Err(ApiError::Forbidden("Room owners cannot leave their own room".to_string()));
             }
         }
         Ok(None) => {
-            return Ok(HttpResponse::NotFound().json(LeaveRoomResponse {
-                success: false,
-                message: "Room not found".to_string(),
-            }));
+            return Err(ApiError::NotFound("Room not found".to_string()));
         }
         Err(e) => {
             log::error!("Failed to get room: {}", e);
-            return Ok(HttpResponse::InternalServerError().json(LeaveRoomResponse {
-                success: false,
-                message: "Failed to process request".to_string(),
-            }));
+            return Err(ApiError::Database(e));
         }
     }
 
@@ -728,23 +475,18 @@ pub async fn leave_room_membership(
     match delete_result {
         Ok(result) => {
             if result.rows_affected > 0 {
+                forget_room_membership(room_id, user_id);
                 Ok(HttpResponse::Ok().json(LeaveRoomResponse {
                     success: true,
                     message: "Left room successfully".to_string(),
                 }))
             } else {
-                Ok(HttpResponse::NotFound().json(LeaveRoomResponse {
-                    success: false,
-                    message: "Membership not found".to_string(),
-                }))
+                Err(ApiError::NotFound("Membership not found".to_string()))
             }
         }
         Err(e) => {
             log::error!("Failed to delete room membership: {}", e);
-            Ok(HttpResponse::InternalServerError().json(LeaveRoomResponse {
-                success: false,
-                message: "Failed to leave room".to_string(),
-            }))
+            Err(ApiError::Database(e))
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,206 @@
+use actix_web::{web, HttpResponse, get};
+use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect, ColumnTrait};
+use sea_orm::sea_query::{Expr, Query};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use log::error;
+use std::collections::HashMap;
+
+use crate::auth::AuthUser;
+use crate::models::entities::{ChatAttachment, ChatMessage, RoomMembership};
+use crate::models::entities::chat_attachment::Column as AttachmentColumn;
+use crate::models::entities::chat_message::Column as MessageColumn;
+use crate::models::entities::room_membership;
+use crate::api::chat::message::{build_message_views, ensure_member, Direction, MessageCursor};
+use crate::api::ApiError;
+
+const DEFAULT_SEARCH_PAGE_SIZE: u64 = 20;
+const MAX_SEARCH_PAGE_SIZE: u64 = 100;
+const MAX_QUERY_LENGTH: usize = 200;
+
+// ts_headline marks matches with these control characters; they are turned into
+// <mark> tags after the rest of the snippet has been HTML-escaped
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+const HEADLINE_OPTIONS: &str = "StartSel=\u{2}, StopSel=\u{3}, MaxWords=24, MinWords=8, MaxFragments=2, FragmentDelimiter=\" … \"";
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    q: String,
+    // Only search this room
+    room_id: Option<Uuid>,
+    // Only messages by this author
+    user_id: Option<Uuid>,
+    // Only messages sent at or after / at or before these times (RFC 3339)
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    // Only messages with (true) or without (false) an attachment
+    has_attachment: Option<bool>,
+    limit: Option<u64>,
+    // Opaque next_cursor from a previous response
+    cursor: Option<String>,
+}
+
+// Escape a snippet for HTML and turn the match markers into <mark> tags
+fn render_snippet(snippet: &str) -> String {
+    let mut rendered = String::with_capacity(snippet.len());
+    for c in snippet.chars() {
+        match c {
+            MATCH_START => rendered.push_str("<mark>"),
+            MATCH_END => rendered.push_str("</mark>"),
+            '&' => rendered.push_str("&amp;"),
+            '<' => rendered.push_str("&lt;"),
+            '>' => rendered.push_str("&gt;"),
+            '"' => rendered.push_str("&quot;"),
+            '\'' => rendered.push_str("&#39;"),
+            c => rendered.push(c),
+        }
+    }
+    rendered
+}
+
+// Full-text search over the messages of the caller's rooms, newest first
+//
+// `q` uses web search syntax: quoted phrases, `or` and `-excluded` words.
+#[get("/search")]
+pub async fn search_messages(
+    auth_user: AuthUser,
+    db: web::Data<DatabaseConnection>,
+    query: web::Query<SearchQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let q = query.q.trim().to_string();
+    if q.is_empty() {
+        return Err(ApiError::Validation("Search query cannot be empty".to_string()));
+    }
+    if q.chars().count() > MAX_QUERY_LENGTH {
+        return Err(ApiError::Validation(format!("Search query cannot be longer than {} characters", MAX_QUERY_LENGTH)));
+    }
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from > to {
+            return Err(ApiError::Validation("'from' must not be after 'to'".to_string()));
+        }
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_SEARCH_PAGE_SIZE).clamp(1, MAX_SEARCH_PAGE_SIZE);
+
+    let mut search = ChatMessage::find()
+        .filter(Expr::cust_with_values("search_vector @@ websearch_to_tsquery('simple', $1)", [q.clone()]))
+        .filter(MessageColumn::DeletedAt.is_null());
+
+    // SECURITY CHECK: only rooms the caller is a member of
+    match query.room_id {
+        Some(room_id) => {
+            ensure_member(db.get_ref(), room_id, auth_user.id).await?;
+            search = search.filter(MessageColumn::RoomId.eq(room_id));
+        }
+        None => {
+            search = search.filter(
+                MessageColumn::RoomId.in_subquery(
+                    Query::select()
+                        .column(room_membership::Column::RoomId)
+                        .from(RoomMembership)
+                        .and_where(Expr::col(room_membership::Column::UserId).eq(auth_user.id))
+                        .to_owned(),
+                ),
+            );
+        }
+    }
+
+    if let Some(user_id) = query.user_id {
+        search = search.filter(MessageColumn::UserId.eq(user_id));
+    }
+    if let Some(from) = query.from {
+        search = search.filter(MessageColumn::CreatedAt.gte(from));
+    }
+    if let Some(to) = query.to {
+        search = search.filter(MessageColumn::CreatedAt.lte(to));
+    }
+    if let Some(has_attachment) = query.has_attachment {
+        // Messages carrying an uploaded image, video or voice note
+        let attached = Expr::exists(
+            Query::select()
+                .expr(Expr::val(1))
+                .from(ChatAttachment)
+                .and_where(
+                    Expr::col((ChatAttachment, AttachmentColumn::MessageId)).equals((ChatMessage, MessageColumn::Id)),
+                )
+                .to_owned(),
+        );
+        search = search.filter(if has_attachment { attached } else { attached.not() });
+    }
+
+    if let Some(cursor) = &query.cursor {
+        let cursor = match MessageCursor::decode(cursor) {
+            Some(cursor) if cursor.direction == Direction::Before => cursor,
+            _ => return Err(ApiError::Validation("Invalid cursor".to_string())),
+        };
+        let key = Expr::tuple([Expr::col(MessageColumn::CreatedAt).into(), Expr::col(MessageColumn::Id).into()]);
+        search = search.filter(key.lt(Expr::tuple([Expr::value(cursor.created_at), Expr::value(cursor.id)])));
+    }
+
+    let mut messages = match search
+        .order_by_desc(MessageColumn::CreatedAt)
+        .order_by_desc(MessageColumn::Id)
+        .limit(limit + 1)
+        .all(db.get_ref())
+        .await
+    {
+        Ok(messages) => messages,
+        Err(e) => {
+            error!("Failed to search messages: {}", e);
+            return Err(ApiError::Database(e));
+        }
+    };
+
+    let has_more = messages.len() as u64 > limit;
+    messages.truncate(limit as usize);
+
+    let next_cursor = match messages.last() {
+        Some(last) if has_more => Some(MessageCursor::new(Direction::Before, last).encode()),
+        _ => None,
+    };
+
+    // Highlighted snippets for the page
+    let ids: Vec<Uuid> = messages.iter().map(|m| m.id).collect();
+    let snippets: HashMap<Uuid, String> = if ids.is_empty() {
+        HashMap::new()
+    } else {
+        match ChatMessage::find()
+            .select_only()
+            .column(MessageColumn::Id)
+            .column_as(
+                Expr::cust_with_values(
+                    "ts_headline('simple', content, websearch_to_tsquery('simple', $1), $2)",
+                    [q.clone(), HEADLINE_OPTIONS.to_string()],
+                ),
+                "snippet",
+            )
+            .filter(MessageColumn::Id.is_in(ids))
+            .into_tuple::<(Uuid, String)>()
+            .all(db.get_ref())
+            .await
+        {
+            Ok(rows) => rows.into_iter().map(|(id, snippet)| (id, render_snippet(&snippet))).collect(),
+            Err(e) => {
+                error!("Failed to build search snippets: {}", e);
+                return Err(ApiError::Database(e));
+            }
+        }
+    };
+
+    let mut results = build_message_views(db.get_ref(), messages).await;
+    for result in results.iter_mut() {
+        let snippet = result["id"]
+            .as_str()
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .and_then(|id| snippets.get(&id).cloned());
+        result["snippet"] = serde_json::json!(snippet);
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "results": results,
+        "next_cursor": next_cursor,
+        "has_more": has_more
+    })))
+}
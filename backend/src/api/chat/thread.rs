@@ -0,0 +1,201 @@
+use actix_web::{web, HttpResponse, get};
+use sea_orm::{DatabaseConnection, EntityTrait, ActiveValue, QueryFilter, QueryOrder, QuerySelect, ColumnTrait};
+use sea_orm::sea_query::Expr;
+use uuid::Uuid;
+use chrono::Utc;
+use serde::Deserialize;
+use log::{error, info};
+
+use crate::auth::AuthUser;
+use crate::models::entities::{ChatMessage, ChatMessageActiveModel, ChatMessageModel};
+use crate::models::entities::chat_message::Column as MessageColumn;
+use crate::api::chat::message::{build_message_views, ensure_member, resolve_thread_root, thread_summaries};
+use crate::api::chat::ws::{broadcast_to_room, update_history_message, WsResponse, MAX_MESSAGE_SIZE};
+use crate::api::chat::moderation::ensure_can_post;
+use crate::api::ApiError;
+use crate::metrics;
+
+const DEFAULT_THREAD_PAGE_SIZE: u64 = 200;
+const MAX_THREAD_PAGE_SIZE: u64 = 200;
+
+#[derive(Deserialize)]
+pub struct ThreadPath {
+    message_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct ThreadQuery {
+    limit: Option<u64>,
+    // Id of the last reply already seen; the page starts right after it
+    after: Option<Uuid>,
+}
+
+/// Post a reply into the thread of `parent_id`
+///
+/// The reply is stored before anyone is told about it, so a `thread_reply`
+/// event always refers to a message that can be loaded from the thread.
+pub async fn create_thread_reply(
+    db: &DatabaseConnection,
+    room_id: Uuid,
+    user_id: Uuid,
+    parent_id: Uuid,
+    content: String,
+) -> Result<ChatMessageModel, ApiError> {
+    if content.trim().is_empty() {
+        return Err(ApiError::Validation("Message content cannot be empty".to_string()));
+    }
+    if content.len() > MAX_MESSAGE_SIZE {
+        return Err(ApiError::PayloadTooLarge("Message is too large".to_string()));
+    }
+
+    ensure_can_post(db, room_id, user_id).await?;
+    let root_id = resolve_thread_root(db, room_id, parent_id).await?;
+
+    let now = Utc::now();
+    let reply = ChatMessageActiveModel {
+        id: ActiveValue::Set(Uuid::new_v4()),
+        room_id: ActiveValue::Set(room_id),
+        user_id: ActiveValue::Set(user_id),
+        content: ActiveValue::Set(content),
+        created_at: ActiveValue::Set(now),
+        updated_at: ActiveValue::Set(now),
+        edited_at: ActiveValue::Set(None),
+        deleted_at: ActiveValue::Set(None),
+        deleted_by: ActiveValue::Set(None),
+        reply_to_id: ActiveValue::Set(Some(root_id)),
+    };
+
+    match ChatMessage::insert(reply).exec_with_returning(db).await {
+        Ok(reply) => {
+            metrics::record_chat_message("persisted");
+            info!("Reply {} posted to thread {} by user {}", reply.id, root_id, user_id);
+            Ok(reply)
+        }
+        Err(e) => {
+            metrics::record_chat_message("failed");
+            error!("Failed to persist thread reply: {}", e);
+            Err(ApiError::Database(e))
+        }
+    }
+}
+
+// Tell the room about a new reply, with the thread's updated summary so clients can refresh its root
+pub async fn broadcast_thread_reply(
+    db: &DatabaseConnection,
+    reply: &ChatMessageModel,
+    user_name: &str,
+    user_profile_image: Option<&str>,
+) {
+    let Some(root_id) = reply.reply_to_id else {
+        return;
+    };
+
+    let summary = match thread_summaries(db, vec![root_id]).await {
+        Ok(mut summaries) => summaries.remove(&root_id).unwrap_or_default(),
+        Err(e) => {
+            error!("Failed to load thread summary for {}: {}", root_id, e);
+            Default::default()
+        }
+    };
+
+    let event = WsResponse {
+        message_type: "thread_reply".to_string(),
+        data: serde_json::json!({
+            "id": reply.id,
+            "room_id": reply.room_id,
+            "user_id": reply.user_id,
+            "user_name": user_name,
+            "user_profile_image": user_profile_image,
+            "content": reply.content,
+            "created_at": reply.created_at,
+            "reply_to_id": root_id,
+            "reply_count": summary.reply_count,
+            "last_reply_at": summary.last_reply_at,
+            "last_reply_user_id": summary.last_reply_user_id,
+        }),
+        timestamp: Utc::now().timestamp(),
+        message_id: Some(reply.id.to_string()),
+    };
+
+    // The root's thread summary in the cached room history follows the new reply
+    update_history_message(
+        &reply.room_id.to_string(),
+        &root_id.to_string(),
+        serde_json::json!({
+            "reply_count": summary.reply_count,
+            "last_reply_at": summary.last_reply_at,
+            "last_reply_user_id": summary.last_reply_user_id,
+        }),
+    );
+    broadcast_to_room(&reply.room_id.to_string(), event);
+    metrics::record_chat_message("broadcast");
+}
+
+// A thread's root message and its replies, oldest first
+//
+// The id may be the root or any reply in the thread.
+#[get("/messages/{message_id}/thread")]
+pub async fn get_thread(
+    auth_user: AuthUser,
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<ThreadPath>,
+    query: web::Query<ThreadQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let message = match ChatMessage::find_by_id(path.message_id).one(db.get_ref()).await {
+        Ok(Some(message)) => message,
+        Ok(None) => return Err(ApiError::NotFound("Message not found".to_string())),
+        Err(e) => return Err(ApiError::Database(e)),
+    };
+
+    ensure_member(db.get_ref(), message.room_id, auth_user.id).await?;
+
+    let root = match message.reply_to_id {
+        Some(root_id) => match ChatMessage::find_by_id(root_id).one(db.get_ref()).await {
+            Ok(Some(root)) => root,
+            Ok(None) => return Err(ApiError::NotFound("Thread not found".to_string())),
+            Err(e) => return Err(ApiError::Database(e)),
+        },
+        None => message,
+    };
+
+    let limit = query.limit.unwrap_or(DEFAULT_THREAD_PAGE_SIZE).clamp(1, MAX_THREAD_PAGE_SIZE);
+
+    let mut replies_query = ChatMessage::find().filter(MessageColumn::ReplyToId.eq(root.id));
+
+    if let Some(after_id) = query.after {
+        let after = match ChatMessage::find_by_id(after_id).one(db.get_ref()).await {
+            Ok(Some(reply)) if reply.reply_to_id == Some(root.id) => reply,
+            Ok(_) => return Err(ApiError::NotFound("Reply not found in this thread".to_string())),
+            Err(e) => return Err(ApiError::Database(e)),
+        };
+
+        let key = Expr::tuple([Expr::col(MessageColumn::CreatedAt).into(), Expr::col(MessageColumn::Id).into()]);
+        replies_query = replies_query.filter(key.gt(Expr::tuple([Expr::value(after.created_at), Expr::value(after.id)])));
+    }
+
+    let mut replies = match replies_query
+        .order_by_asc(MessageColumn::CreatedAt)
+        .order_by_asc(MessageColumn::Id)
+        .limit(limit + 1)
+        .all(db.get_ref())
+        .await
+    {
+        Ok(replies) => replies,
+        Err(e) => {
+            error!("Failed to load thread replies: {}", e);
+            return Err(ApiError::Database(e));
+        }
+    };
+
+    let has_more = replies.len() as u64 > limit;
+    replies.truncate(limit as usize);
+
+    let root_view = build_message_views(db.get_ref(), vec![root]).await.pop();
+    let reply_views = build_message_views(db.get_ref(), replies).await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "root": root_view,
+        "replies": reply_views,
+        "has_more": has_more
+    })))
+}
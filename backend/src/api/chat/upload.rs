@@ -1,592 +1,399 @@
-use actix_web::{web, post, HttpResponse, Responder, HttpRequest};
-use actix_multipart::Multipart;
+use actix_web::{web, post, HttpResponse, HttpRequest};
+use actix_web::web::Bytes;
+use actix_multipart::{Field, Multipart};
 use futures::{StreamExt, TryStreamExt};
-use std::io::Write;
-use std::path::Path;
-use std::fs::{self, File};
-use std::env;
+use sea_orm::{DatabaseConnection, EntityTrait};
 use uuid::Uuid;
-use log::{debug, error, info, warn};
-use actix_web::http::header::{HeaderName, HeaderValue};
-
-use crate::auth::{JwtAuth, extract_token_from_cookie_or_header};
+use serde::Deserialize;
+use log::{debug, error, info};
+
+use crate::auth::AuthUser;
+use crate::api::ApiError;
+use crate::api::chat::attachment::{
+    attachment_url, attachment_view, authorize_download, find_by_storage_key, record_attachment, NewAttachment,
+    KIND_IMAGE, KIND_VIDEO,
+};
+use crate::api::chat::moderation::ensure_can_post;
+use crate::api::chat::preview::{pick_preview, preview_key, process_image, stored_previews};
+use crate::api::media::{check_format, validate_upload, Format, MediaKind};
+use crate::config::AppConfig;
+use crate::models::entities::ChatAttachment;
+use crate::models::entities::chat_attachment::Model as AttachmentModel;
+use crate::metrics;
+use crate::storage::{self, object_key, Caching, Storage, CHAT_IMAGES, CHAT_VIDEOS};
 
 // Constants for file upload
-const UPLOAD_DIR: &str = "uploads/chat_images";
 const MAX_FILE_SIZE: usize = 5 * 1024 * 1024; // 5MB
-const ALLOWED_TYPES: [&str; 3] = ["image/jpeg", "image/png", "image/gif"];
 
 // Video upload constants
-const VIDEO_UPLOAD_DIR: &str = "uploads/chat_videos";
 const MAX_VIDEO_SIZE: usize = 50 * 1024 * 1024; // 50MB
-const ALLOWED_VIDEO_TYPES: [&str; 3] = ["video/mp4", "video/webm", "video/ogg"];
-#[post("/api/chat/upload")]
-pub async fn upload_chat_image(
-    req: HttpRequest,
-    jwt_secret: web::Data<String>,
-    mut payload: Multipart,
-) -> impl Responder {
-    // Extract token from request
-    let token = match extract_token_from_cookie_or_header(&req) {
-        Some(token) => token,
-        None => {
-            warn!("Missing authentication token for chat image upload");
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({
-                    "error": "Unauthorized",
-                    "message": "Missing authentication token"
-                })
-            );
-        }
-    };
 
-    // Validate token and get user
-    let claims = match JwtAuth::validate_token(&token, &jwt_secret) {
-        Ok(claims) => claims,
-        Err(e) => {
-            warn!("Invalid token for chat image upload: {:?}", e);
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid authentication token"
-                })
-            );
-        }
-    };
+// A file field of a multipart form
+pub(crate) struct UploadedFile {
+    // As declared by the client
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+// A chat upload form: the room the file is for, the file, and for media its duration
+#[derive(Default)]
+pub(crate) struct UploadForm {
+    pub room_id: Option<Uuid>,
+    pub file: Option<UploadedFile>,
+    pub duration_ms: Option<i32>,
+}
 
-    // Ensure backend_user_id is present
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            warn!("Missing backend_user_id in token");
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({
-                    "error": "Unauthorized",
-                    "message": "Missing user ID in token"
-                })
-            );
+// Read a text field of a multipart form
+async fn read_text_field(field: &mut Field) -> Result<String, ApiError> {
+    let mut data = Vec::new();
+    while let Some(chunk) = field.next().await {
+        data.extend_from_slice(&chunk?);
+        if data.len() > 1024 {
+            return Err(ApiError::BadRequest("Form field is too long".to_string()));
         }
-    };
+    }
+    String::from_utf8(data).map_err(|_| ApiError::BadRequest("Form fields must be UTF-8".to_string()))
+}
 
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
-        Err(e) => {
-            warn!("Invalid backend_user_id format in token: {:?}", e);
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid user ID format in token"
-                })
-            );
+// Read a file field, failing once it grows past `max_size`
+async fn read_file_field(field: &mut Field, max_size: usize, too_large: &str) -> Result<Vec<u8>, ApiError> {
+    let mut data = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                error!("Error reading chunk: {:?}", e);
+                return Err(e.into());
+            }
+        };
+
+        // Check file size limit
+        if data.len() + chunk.len() > max_size {
+            return Err(ApiError::PayloadTooLarge(too_large.to_string()));
         }
-    };
-    
-    debug!("Uploading chat image for user ID: {}", user_id);
-    
-    // Ensure the upload directory exists
-    if let Err(e) = fs::create_dir_all(UPLOAD_DIR) {
-        error!("Failed to create upload directory: {:?}", e);
-        return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Server error",
-            "message": "Failed to create upload directory"
-        }));
+        data.extend_from_slice(&chunk);
     }
-    
-    // Process the multipart form
-    while let Ok(Some(mut field)) = payload.try_next().await {
-        // Check if this is the file field
-        if field.name() == Some("file") {
-            // Get content type and check if it's allowed
-            let _content_disposition = field.content_disposition();
-            let content_type = field.content_type();
-            
-            // Get the content type string
-            let content_type_str = content_type.map(|ct| ct.to_string()).unwrap_or_else(|| "application/octet-stream".to_string());
-            
-            if !ALLOWED_TYPES.contains(&content_type_str.as_str()) {
-                return HttpResponse::BadRequest().json(serde_json::json!({
-                    "error": "Invalid file type",
-                    "message": "Only JPEG, PNG, and GIF images are allowed"
-                }));
+    Ok(data)
+}
+
+// Read a chat upload form; nothing is stored until the whole file has arrived
+pub(crate) async fn read_upload_form(
+    payload: &mut Multipart,
+    file_field: &str,
+    max_size: usize,
+    too_large: &str,
+) -> Result<UploadForm, ApiError> {
+    let mut form = UploadForm::default();
+
+    while let Some(mut field) = payload.try_next().await? {
+        let name = field.name().unwrap_or_default().to_string();
+        match name.as_str() {
+            "room_id" => {
+                let room_id = read_text_field(&mut field).await?;
+                form.room_id = Some(
+                    Uuid::parse_str(room_id.trim())
+                        .map_err(|_| ApiError::BadRequest("Invalid room_id format".to_string()))?,
+                );
             }
-            
-            // Generate a unique filename
-            let file_ext = match content_type_str.as_str() {
-                "image/jpeg" => "jpg",
-                "image/png" => "png",
-                "image/gif" => "gif",
-                _ => "bin", // Should not happen due to check above
-            };
-            
-            // Use UUID for filename to prevent path traversal attacks
-            let uuid = Uuid::new_v4();
-            let filename = format!("{}.{}", uuid, file_ext);
-            
-            // Ensure the filepath is properly constructed and sanitized
-            let filepath = format!("{}/{}", UPLOAD_DIR, filename);
-            
-            // Create the file
-            let mut file = match File::create(&filepath) {
-                Ok(file) => file,
-                Err(e) => {
-                    error!("Failed to create file: {:?}", e);
-                    return HttpResponse::InternalServerError().json(serde_json::json!({
-                        "error": "Server error",
-                        "message": "Failed to create file"
-                    }));
-                }
-            };
-            
-            // Write the file data
-            let mut size: usize = 0;
-            while let Some(chunk) = field.next().await {
-                let data = match chunk {
-                    Ok(data) => data,
-                    Err(e) => {
-                        error!("Error reading chunk: {:?}", e);
-                        // Clean up the partial file
-                        if let Err(cleanup_err) = fs::remove_file(&filepath) {
-                            error!("Failed to clean up partial file after error: {:?}", cleanup_err);
-                        }
-                        return HttpResponse::InternalServerError().json(serde_json::json!({
-                            "error": "Server error",
-                            "message": "Failed to read uploaded file"
-                        }));
-                    }
-                };
-                
-                // Check file size limit
-                size += data.len();
-                if size > MAX_FILE_SIZE {
-                    // Delete the partial file
-                    if let Err(cleanup_err) = fs::remove_file(&filepath) {
-                        error!("Failed to clean up oversized file: {:?}", cleanup_err);
-                    } else {
-                        debug!("Successfully cleaned up oversized file: {}", filepath);
-                    }
-                    
-                    return HttpResponse::BadRequest().json(serde_json::json!({
-                        "error": "File too large",
-                        "message": "Maximum file size is 5MB"
-                    }));
-                }
-                
-                // Write the chunk to the file
-                if let Err(e) = file.write_all(&data) {
-                    error!("Error writing to file: {:?}", e);
-                    
-                    // Close the file handle before attempting to remove it
-                    drop(file);
-                    
-                    // Clean up the partial file
-                    if let Err(cleanup_err) = fs::remove_file(&filepath) {
-                        error!("Failed to clean up partial file after write error: {:?}", cleanup_err);
-                    } else {
-                        debug!("Successfully cleaned up partial file after write error: {}", filepath);
-                    }
-                    
-                    return HttpResponse::InternalServerError().json(serde_json::json!({
-                        "error": "Server error",
-                        "message": "Failed to write to file"
-                    }));
-                }
+            "duration_ms" => {
+                let duration = read_text_field(&mut field).await?;
+                form.duration_ms = Some(
+                    duration
+                        .trim()
+                        .parse::<i32>()
+                        .ok()
+                        .filter(|duration| *duration >= 0)
+                        .ok_or_else(|| ApiError::BadRequest("Invalid duration_ms".to_string()))?,
+                );
             }
-            
-            // Verify the file was created and has content
-            if size == 0 {
-                // Clean up empty file
-                if let Err(cleanup_err) = fs::remove_file(&filepath) {
-                    error!("Failed to clean up empty file: {:?}", cleanup_err);
-                }
-                return HttpResponse::BadRequest().json(serde_json::json!({
-                    "error": "Empty file",
-                    "message": "Uploaded file is empty"
-                }));
+            name if name == file_field => {
+                let content_type = field
+                    .content_type()
+                    .map(|ct| ct.to_string())
+                    .unwrap_or_else(|| "application/octet-stream".to_string());
+                let data = read_file_field(&mut field, max_size, too_large).await?;
+                form.file = Some(UploadedFile { content_type, data });
+            }
+            _ => {
+                // Skip unknown fields
+                while field.next().await.is_some() {}
             }
-            
-            // Create the URL path for the image
-            // Get the backend URL from the environment variable or use a default
-            let backend_url = env::var("API_URL")
-                .or_else(|_| env::var("BACKEND_URL"))
-                .unwrap_or_else(|_| {
-                    // Fallback to frontend env var if backend-specific ones are not set
-                    env::var("NEXT_PUBLIC_API_URL").unwrap_or_else(|_| {
-                        warn!("No API URL environment variables set (API_URL, BACKEND_URL, NEXT_PUBLIC_API_URL), using default value");
-                        "http://localhost:8080".to_string()
-                    })
-                });
-            
-            // Construct the full URL including the backend server
-            let image_url = format!("{}/api/chat/image/{}", backend_url, filename);
-            
-            info!("Successfully uploaded chat image: {} (size: {} bytes)", image_url, size);
-            return HttpResponse::Ok().json(serde_json::json!({
-                "success": true,
-                "image_url": image_url,
-                "filename": filename,
-                "size": size
-            }));
         }
     }
-    
-    // If we get here, no file was uploaded
-    HttpResponse::BadRequest().json(serde_json::json!({
-        "error": "No file uploaded",
-        "message": "Please provide a file"
-    }))
+
+    Ok(form)
 }
 
-// Video upload endpoint
-#[post("/api/chat/upload-video")]
-pub async fn upload_chat_video(
-    req: HttpRequest,
-    jwt_secret: web::Data<String>,
-    mut payload: Multipart,
-) -> impl Responder {
-    // Authentication
-    let token = match extract_token_from_cookie_or_header(&req) {
-        Some(token) => token,
-        None => {
-            warn!("Missing authentication token for chat video upload");
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({
-                    "error": "Unauthorized",
-                    "message": "Missing authentication token"
-                })
-            );
-        }
-    };
+#[derive(Deserialize)]
+pub struct UploadQuery {
+    size: Option<u32>,
+    format: Option<String>,
+}
 
-    let claims = match JwtAuth::validate_token(&token, &jwt_secret) {
-        Ok(claims) => claims,
-        Err(e) => {
-            warn!("Invalid token for chat video upload: {:?}", e);
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid authentication token"
-                })
-            );
+// Delete the objects of an upload that could not be recorded; without a record nobody could download them
+async fn discard_upload(storage: &dyn Storage, keys: &[String]) {
+    for key in keys {
+        if let Err(cleanup_err) = storage.delete(key).await {
+            error!("Failed to clean up unrecorded upload {}: {}", key, cleanup_err);
         }
-    };
+    }
+}
 
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            warn!("Missing backend_user_id in token");
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({
-                    "error": "Unauthorized",
-                    "message": "Missing user ID in token"
-                })
-            );
+// Store an uploaded chat file and its previews, and record it as an attachment of the room
+pub(crate) async fn store_chat_upload(
+    db: &DatabaseConnection,
+    storage: &dyn Storage,
+    new: NewAttachment<'_>,
+) -> Result<AttachmentModel, ApiError> {
+    let mut keys = vec![new.storage_key.clone()];
+    storage.put(&new.storage_key, new.data.clone(), &new.mime_type).await?;
+
+    for preview in new.previews {
+        let key = preview_key(&new.storage_key, preview);
+        if let Err(e) = storage.put(&key, preview.data.clone(), preview.format.mime_type(MediaKind::Image)).await {
+            discard_upload(storage, &keys).await;
+            return Err(e.into());
         }
-    };
+        keys.push(key);
+    }
 
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
+    match record_attachment(db, new).await {
+        Ok(attachment) => Ok(attachment),
         Err(e) => {
-            warn!("Invalid backend_user_id format in token: {:?}", e);
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid user ID format in token"
-                })
-            );
+            discard_upload(storage, &keys).await;
+            Err(e)
         }
-    };
-
-    debug!("Uploading chat video for user ID: {}", user_id);
+    }
+}
 
-    // Create upload directory
-    if let Err(e) = fs::create_dir_all(VIDEO_UPLOAD_DIR) {
-        error!("Failed to create video upload directory: {:?}", e);
-        return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Server error",
-            "message": "Failed to create upload directory"
-        }));
+#[post("/upload")]
+pub async fn upload_chat_image(
+    auth_user: AuthUser,
+    config: web::Data<AppConfig>,
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<dyn Storage>,
+    _req: HttpRequest,
+    mut payload: Multipart,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = auth_user.id;
+    
+    debug!("Uploading chat image for user ID: {}", user_id);
+    
+    // Process the multipart form
+    let form = read_upload_form(&mut payload, "file", MAX_FILE_SIZE, "Maximum file size is 5MB").await?;
+    let room_id = form.room_id.ok_or_else(|| ApiError::BadRequest("Missing room_id".to_string()))?;
+    let UploadedFile { content_type: content_type_str, data } =
+        form.file.ok_or_else(|| ApiError::BadRequest("Please provide a file".to_string()))?;
+    
+    // Only members who may post in the room can attach files to it
+    ensure_can_post(db.get_ref(), room_id, user_id).await?;
+    
+    // Verify the file has content
+    let size = data.len();
+    if size == 0 {
+        return Err(ApiError::BadRequest("Uploaded file is empty".to_string()));
     }
+    
+    // The file's own bytes decide its type, not what the client declared
+    let data = Bytes::from(data);
+    let format = check_format(&data, &content_type_str, MediaKind::Image)?;
+    
+    // Drop metadata such as where the photo was taken, and scale down copies for display
+    let image = process_image(data, format).await?;
+    
+    // Use UUID for filename to prevent path traversal attacks
+    let filename = format!("{}.{}", Uuid::new_v4(), image.format.extension(MediaKind::Image));
+    let key = object_key(CHAT_IMAGES, &filename)?;
+    
+    let attachment = store_chat_upload(
+        db.get_ref(),
+        storage.get_ref(),
+        NewAttachment {
+            owner_id: user_id,
+            room_id,
+            message_id: None,
+            kind: KIND_IMAGE,
+            storage_key: key,
+            mime_type: image.format.mime_type(MediaKind::Image).to_string(),
+            data: &image.data,
+            duration_ms: None,
+            blurhash: Some(image.blurhash.clone()),
+            previews: &image.previews,
+        },
+    )
+    .await?;
+    
+    // Construct the full URL including the backend server
+    let image_url = format!("{}{}", config.public_api_url, attachment_url(attachment.id));
+    
+    info!("Successfully uploaded chat image: {} (size: {} bytes)", image_url, size);
+    metrics::record_upload("image", size);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "image_url": image_url,
+        "filename": filename,
+        "size": size,
+        "width": image.width,
+        "height": image.height,
+        "blurhash": image.blurhash,
+        "attachment": attachment_view(&attachment)
+    })))
+}
 
-    while let Ok(Some(mut field)) = payload.try_next().await {
-        if field.name() == Some("file") {
-            let content_type = field.content_type();
-            let content_type_str = content_type
-                .map(|ct| ct.to_string())
-                .unwrap_or_else(|| "application/octet-stream".to_string());
-
-            if !ALLOWED_VIDEO_TYPES.contains(&content_type_str.as_str()) {
-                return HttpResponse::BadRequest().json(serde_json::json!({
-                    "error": "Invalid file type",
-                    "message": "Only MP4, WebM, and Ogg videos are allowed"
-                }));
-            }
+// Video upload endpoint
+#[post("/upload-video")]
+pub async fn upload_chat_video(
+    auth_user: AuthUser,
+    config: web::Data<AppConfig>,
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<dyn Storage>,
+    _req: HttpRequest,
+    mut payload: Multipart,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = auth_user.id;
 
-            let file_ext = match content_type_str.as_str() {
-                "video/mp4" => "mp4",
-                "video/webm" => "webm",
-                "video/ogg" => "ogv",
-                _ => "bin",
-            };
-
-            let uuid = Uuid::new_v4();
-            let filename = format!("{}.{}", uuid, file_ext);
-            let filepath = format!("{}/{}", VIDEO_UPLOAD_DIR, filename);
-
-            let mut file = match File::create(&filepath) {
-                Ok(file) => file,
-                Err(e) => {
-                    error!("Failed to create video file: {:?}", e);
-                    return HttpResponse::InternalServerError().json(serde_json::json!({
-                        "error": "Server error",
-                        "message": "Failed to create file"
-                    }));
-                }
-            };
-
-            let mut size: usize = 0;
-            while let Some(chunk) = field.next().await {
-                let data = match chunk {
-                    Ok(data) => data,
-                    Err(e) => {
-                        error!("Error reading video chunk: {:?}", e);
-                        if let Err(cleanup_err) = fs::remove_file(&filepath) {
-                            error!("Failed to clean up partial video file after error: {:?}", cleanup_err);
-                        }
-                        return HttpResponse::InternalServerError().json(serde_json::json!({
-                            "error": "Server error",
-                            "message": "Failed to read uploaded file"
-                        }));
-                    }
-                };
-
-                size += data.len();
-                if size > MAX_VIDEO_SIZE {
-                    if let Err(cleanup_err) = fs::remove_file(&filepath) {
-                        error!("Failed to clean up oversized video file: {:?}", cleanup_err);
-                    } else {
-                        debug!("Successfully cleaned up oversized video file: {}", filepath);
-                    }
-
-                    return HttpResponse::BadRequest().json(serde_json::json!({
-                        "error": "File too large",
-                        "message": "Maximum video file size is 50MB"
-                    }));
-                }
-
-                if let Err(e) = file.write_all(&data) {
-                    error!("Error writing to video file: {:?}", e);
-                    drop(file);
-                    if let Err(cleanup_err) = fs::remove_file(&filepath) {
-                        error!("Failed to clean up partial video file after write error: {:?}", cleanup_err);
-                    } else {
-                        debug!("Successfully cleaned up partial video file after write error: {}", filepath);
-                    }
-
-                    return HttpResponse::InternalServerError().json(serde_json::json!({
-                        "error": "Server error",
-                        "message": "Failed to write to file"
-                    }));
-                }
-            }
+    debug!("Uploading chat video for user ID: {}", user_id);
 
-            if size == 0 {
-                if let Err(cleanup_err) = fs::remove_file(&filepath) {
-                    error!("Failed to clean up empty video file: {:?}", cleanup_err);
-                }
-                return HttpResponse::BadRequest().json(serde_json::json!({
-                    "error": "Empty file",
-                    "message": "Uploaded file is empty"
-                }));
-            }
+    let form = read_upload_form(&mut payload, "file", MAX_VIDEO_SIZE, "Maximum video file size is 50MB").await?;
+    let room_id = form.room_id.ok_or_else(|| ApiError::BadRequest("Missing room_id".to_string()))?;
+    let UploadedFile { content_type: content_type_str, data } =
+        form.file.ok_or_else(|| ApiError::BadRequest("Please provide a file".to_string()))?;
 
-            let backend_url = env::var("API_URL")
-                .or_else(|_| env::var("BACKEND_URL"))
-                .unwrap_or_else(|_| {
-                    env::var("NEXT_PUBLIC_API_URL").unwrap_or_else(|_| {
-                        warn!("No API URL environment variables set (API_URL, BACKEND_URL, NEXT_PUBLIC_API_URL), using default value");
-                        "http://localhost:8080".to_string()
-                    })
-                });
-
-            let video_url = format!("{}/api/chat/video/{}", backend_url, filename);
-
-            info!("Successfully uploaded chat video: {} (size: {} bytes)", video_url, size);
-            return HttpResponse::Ok().json(serde_json::json!({
-                "success": true,
-                "video_url": video_url,
-                "filename": filename,
-                "size": size
-            }));
-        }
+    ensure_can_post(db.get_ref(), room_id, user_id).await?;
+
+    let size = data.len();
+    if size == 0 {
+        return Err(ApiError::BadRequest("Uploaded file is empty".to_string()));
     }
 
-    HttpResponse::BadRequest().json(serde_json::json!({
-        "error": "No file uploaded",
-        "message": "Please provide a file"
-    }))
+    let data = Bytes::from(data);
+    let format = validate_upload(&data, &content_type_str, MediaKind::Video).await?;
+
+    let filename = format!("{}.{}", Uuid::new_v4(), format.extension(MediaKind::Video));
+    let key = object_key(CHAT_VIDEOS, &filename)?;
+
+    let attachment = store_chat_upload(
+        db.get_ref(),
+        storage.get_ref(),
+        NewAttachment {
+            owner_id: user_id,
+            room_id,
+            message_id: None,
+            kind: KIND_VIDEO,
+            storage_key: key,
+            mime_type: format.mime_type(MediaKind::Video).to_string(),
+            data: &data,
+            duration_ms: form.duration_ms,
+            blurhash: None,
+            previews: &[],
+        },
+    )
+    .await?;
+
+    let video_url = format!("{}{}", config.public_api_url, attachment_url(attachment.id));
+
+    info!("Successfully uploaded chat video: {} (size: {} bytes)", video_url, size);
+    metrics::record_upload("video", size);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "video_url": video_url,
+        "filename": filename,
+        "size": size,
+        "attachment": attachment_view(&attachment)
+    })))
 }
 
-// Endpoint to serve video files
-#[actix_web::get("/api/chat/video/{filename}")]
+// Endpoint to serve video files uploaded before attachments had ids
+#[actix_web::get("/video/{filename}")]
 pub async fn get_chat_video(
-    path: web::Path<String>,
     req: HttpRequest,
-) -> impl Responder {
+    auth_user: AuthUser,
+    path: web::Path<String>,
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<dyn Storage>,
+) -> Result<HttpResponse, ApiError> {
     let filename = path.into_inner();
 
-    let sanitized_filename = Path::new(&filename)
-        .file_name()
-        .and_then(|name| name.to_str())
-        .unwrap_or("");
+    // Only the last path component is used, so the name cannot leave the folder
+    let key = object_key(CHAT_VIDEOS, &filename)?;
 
-    if sanitized_filename.is_empty() {
-        return HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Invalid filename"
-        }));
-    }
-
-    let filepath = format!("{}/{}", VIDEO_UPLOAD_DIR, sanitized_filename);
-
-    if !Path::new(&filepath).exists() {
-        return HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Video not found"
-        }));
-    }
+    // Only members of the room the video was uploaded to may watch it
+    let attachment = find_by_storage_key(db.get_ref(), &key).await?;
+    let attachment = authorize_download(db.get_ref(), attachment, auth_user.id).await?;
 
-    let metadata = match fs::metadata(&filepath) {
-        Ok(meta) => meta,
+    match storage::serve(&req, storage.get_ref(), &key, &attachment.mime_type, Caching::Private).await {
+        Ok(response) => Ok(response),
+        Err(storage::StorageError::NotFound) => Err(ApiError::NotFound("Video not found".to_string())),
         Err(e) => {
-            error!("Failed to get video metadata: {:?}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to retrieve video metadata"
-            }));
-        }
-    };
-
-    if metadata.len() == 0 {
-        return HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Video file is empty"
-        }));
-    }
-
-    let content_type = match Path::new(&sanitized_filename).extension().and_then(|ext| ext.to_str()) {
-        Some("mp4") => "video/mp4",
-        Some("webm") => "video/webm",
-        Some("ogv") | Some("ogg") => "video/ogg",
-        _ => "application/octet-stream",
-    };
-
-    match actix_files::NamedFile::open(filepath) {
-        Ok(file) => {
-            let mut response = file.into_response(&req);
-
-            if let (Ok(cache_name), Ok(cache_value)) = (
-                "Cache-Control".parse::<HeaderName>(),
-                "public, max-age=31536000".parse::<HeaderValue>()
-            ) {
-                response.headers_mut().insert(cache_name, cache_value);
-            }
-
-            if let (Ok(content_name), Ok(content_value)) = (
-                "Content-Type".parse::<HeaderName>(),
-                content_type.parse::<HeaderValue>()
-            ) {
-                response.headers_mut().insert(content_name, content_value);
-            }
-
-            response
-        }
-        Err(e) => {
-            error!("Failed to open video file: {:?}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to retrieve video"
-            }))
+            error!("Failed to retrieve video {}: {}", key, e);
+            Err(ApiError::Internal("Failed to retrieve video".to_string()))
         }
     }
 }
 
-#[actix_web::get("/api/chat/image/{filename}")]
+#[actix_web::get("/image/{filename}")]
 pub async fn get_chat_image(
-    path: web::Path<String>,
     req: HttpRequest,
-) -> impl Responder {
+    auth_user: AuthUser,
+    path: web::Path<String>,
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<dyn Storage>,
+) -> Result<HttpResponse, ApiError> {
     let filename = path.into_inner();
     
     // Sanitize filename to prevent directory traversal
-    let sanitized_filename = Path::new(&filename).file_name()
-        .and_then(|name| name.to_str())
-        .unwrap_or("");
-    
-    if sanitized_filename.is_empty() {
-        return HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Invalid filename"
-        }));
-    }
-    
-    let filepath = format!("{}/{}", UPLOAD_DIR, sanitized_filename);
+    let key = object_key(CHAT_IMAGES, &filename)?;
     
-    // Check if file exists
-    if !Path::new(&filepath).exists() {
-        return HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Image not found"
-        }));
-    }
+    // Only members of the room the image was uploaded to may see it
+    let attachment = find_by_storage_key(db.get_ref(), &key).await?;
+    let attachment = authorize_download(db.get_ref(), attachment, auth_user.id).await?;
     
-    // Get file metadata
-    let metadata = match fs::metadata(&filepath) {
-        Ok(meta) => meta,
+    // Serve the file, or redirect to it, with cache headers
+    match storage::serve(&req, storage.get_ref(), &key, &attachment.mime_type, Caching::Private).await {
+        Ok(response) => Ok(response),
+        Err(storage::StorageError::NotFound) => Err(ApiError::NotFound("Image not found".to_string())),
         Err(e) => {
-            error!("Failed to get file metadata: {:?}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to retrieve image metadata"
-            }));
+            error!("Failed to retrieve image {}: {}", key, e);
+            Err(ApiError::Internal("Failed to retrieve image".to_string()))
         }
-    };
-    
-    // Check if file is empty
-    if metadata.len() == 0 {
-        return HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Image file is empty"
-        }));
     }
-    
-    // Determine content type based on file extension
-    let content_type = match Path::new(&sanitized_filename).extension().and_then(|ext| ext.to_str()) {
-        Some("jpg") | Some("jpeg") => "image/jpeg",
-        Some("png") => "image/png",
-        Some("gif") => "image/gif",
-        _ => "application/octet-stream",
+}
+
+// Download an uploaded file; with `size`, the smallest preview at least that large, and with
+// `format=webp` its WebP copy, or the largest WebP preview when no size is given. Files without
+// a fitting preview are served as uploaded.
+#[actix_web::get("/upload/{attachment_id}")]
+pub async fn get_upload(
+    req: HttpRequest,
+    auth_user: AuthUser,
+    path: web::Path<Uuid>,
+    query: web::Query<UploadQuery>,
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<dyn Storage>,
+) -> Result<HttpResponse, ApiError> {
+    let webp = match query.format.as_deref() {
+        None => false,
+        Some("webp") => true,
+        Some(_) => return Err(ApiError::BadRequest("format must be webp".to_string())),
     };
-    
-    // Serve the file with proper headers
-    match actix_files::NamedFile::open(filepath) {
-        Ok(file) => {
-            // Create response with proper headers
-            let mut response = file.into_response(&req);
-            
-            // Add cache headers for better performance
-            if let (Ok(cache_name), Ok(cache_value)) = (
-                "Cache-Control".parse::<HeaderName>(),
-                "public, max-age=31536000".parse::<HeaderValue>()
-            ) {
-                response.headers_mut().insert(cache_name, cache_value);
-            }
-            
-            // Set content type header
-            if let (Ok(content_name), Ok(content_value)) = (
-                "Content-Type".parse::<HeaderName>(),
-                content_type.parse::<HeaderValue>()
-            ) {
-                response.headers_mut().insert(content_name, content_value);
-            }
-            
-            response
-        }
-        Err(e) => {
-            error!("Failed to open image file: {:?}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to retrieve image"
-            }))
-        }
-    }
-}
\ No newline at end of file
+
+    let attachment = ChatAttachment::find_by_id(path.into_inner())
+        .one(db.get_ref())
+        .await
+        .map_err(ApiError::Database)?;
+    let attachment = authorize_download(db.get_ref(), attachment, auth_user.id).await?;
+
+    let previews = stored_previews(&attachment);
+    let preview = match (query.size, webp) {
+        // The original is the full-size version, unless WebP was asked for and it is not one
+        (None, false) => None,
+        (None, true) if attachment.mime_type == Format::WebP.mime_type(MediaKind::Image) => None,
+        (size, webp) => pick_preview(&previews, size, webp),
+    };
+    let (key, content_type) = match preview {
+        Some(preview) => (&preview.storage_key, &preview.mime_type),
+        None => (&attachment.storage_key, &attachment.mime_type),
+    };
+
+    Ok(storage::serve(&req, storage.get_ref(), key, content_type, Caching::Private).await?)
+}
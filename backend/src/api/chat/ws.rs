@@ -1,27 +1,50 @@
-use actix::{Actor, StreamHandler, Handler, Message, Context, Addr, AsyncContext, ActorContext};
+use actix::{Actor, StreamHandler, Handler, Message, MessageResult, Context, Addr, AsyncContext, ActorContext};
 use actix_web::{web, Error, HttpRequest, HttpResponse, get};
 use actix_web_actors::ws;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use uuid::Uuid;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use std::collections::{HashMap, HashSet};
-use std::sync::Mutex;
-use log::{error, info, debug};
-use sea_orm::{DatabaseConnection, EntityTrait, ActiveValue, QueryFilter, ColumnTrait, QuerySelect};
-
-use crate::auth::{JwtAuth, extract_token_from_cookie_or_header};
-use crate::models::entities::{UserResponseDto, ChatMessage, ChatMessageActiveModel, RoomMembership};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use log::{error, info, debug, warn};
+use sea_orm::{DatabaseConnection, DbErr, EntityTrait, ActiveValue, QueryFilter, ColumnTrait, TransactionTrait};
+use tokio::sync::mpsc;
+
+use crate::auth::AuthUser;
+use crate::config::{AppConfig, HeartbeatConfig};
+use crate::models::entities::{User, UserResponseDto, ChatMessage, ChatMessageActiveModel, RoomMembership, RoomMembershipModel};
+use crate::models::entities::room_membership;
 use crate::models::entities::message_reaction::{Entity as MessageReaction, ActiveModel as MessageReactionActiveModel, Column as MessageReactionColumn};
+use crate::api::ApiError;
+use crate::api::chat::message_edit::{edit_message, delete_message, broadcast_message_edited, broadcast_message_deleted};
+use crate::api::chat::thread::{create_thread_reply, broadcast_thread_reply};
+use crate::api::chat::read_receipt::{mark_read, broadcast_read_receipt};
+use crate::api::chat::backplane::{Backplane, BackplaneMessage};
+use crate::api::chat::history::{fetch_replay, replay_frames, HistoryCache, Replay, REPLAY_LIMIT};
+use crate::api::chat::presence::{record_last_seen, PresenceStatus};
+use crate::api::chat::attachment::{attachment_view, link_attachments};
+use crate::models::entities::chat_attachment::Model as AttachmentModel;
+use crate::metrics;
 
 // Constants
-const MAX_MESSAGE_SIZE: usize = 1024 * 1024; // 1MB
+pub(crate) const MAX_MESSAGE_SIZE: usize = 1024 * 1024; // 1MB
 const MAX_MESSAGES_PER_MINUTE: usize = 30;
+// A connection that has not pinged for this long counts as away
+const AWAY_AFTER_SECS: i64 = 60;
+// How often the chat server drops connections whose session actor died without disconnecting
+const REAP_INTERVAL: Duration = Duration::from_secs(60);
+// How often the chat server tells other instances it is alive
+const INSTANCE_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+// Presence from another instance is dropped once it has not been heard from for this long
+const INSTANCE_TTL: Duration = Duration::from_secs(35);
 
 #[derive(thiserror::Error, Debug)]
 pub enum WsError {
     #[error("Authentication failed")]
     Authentication,
-    #[error("Invalid message format")]
+    #[error("Invalid message: {0}")]
     InvalidMessage(String),
     #[error("Message too large")]
     MessageTooLarge,
@@ -29,30 +52,81 @@ pub enum WsError {
     RateLimitExceeded,
     #[error("Access denied: {0}")]
     AccessDenied(String),
-    #[error("Database error")]
-    DatabaseError,
+    #[error("You are muted in this room until {0}")]
+    Muted(DateTime<Utc>),
     #[error("Database error: {0}")]
     Database(String),
 }
 
+impl WsError {
+    /// Stable identifier of the error kind, sent to clients as `code` like REST errors
+    pub fn code(&self) -> &'static str {
+        match self {
+            WsError::Authentication => "AUTH_REQUIRED",
+            WsError::InvalidMessage(_) => "INVALID_MESSAGE",
+            WsError::MessageTooLarge => "PAYLOAD_TOO_LARGE",
+            WsError::RateLimitExceeded => "RATE_LIMITED",
+            WsError::AccessDenied(_) => "ROOM_ACCESS_DENIED",
+            WsError::Muted(_) => "ROOM_MUTED",
+            WsError::Database(_) => "DATABASE_ERROR",
+        }
+    }
+
+    // Text for the user; database details stay in the server log
+    fn client_message(&self) -> String {
+        match self {
+            WsError::Database(_) => "Failed to process the request".to_string(),
+            other => other.to_string(),
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(tag = "type")]
 pub enum WsMessage {
     #[serde(rename = "join")]
-    Join { room_id: String },
+    Join {
+        room_id: String,
+        // Last message the client has, to resume after a reconnect instead of replaying the latest page
+        #[serde(default)]
+        since: Option<String>,
+    },
     #[serde(rename = "leave")]
     Leave { room_id: String },
     #[serde(rename = "message")]
-    Message { room_id: String, content: String, temp_id: Option<String> },
+    Message {
+        room_id: String,
+        content: String,
+        temp_id: Option<String>,
+        // Id of the message being replied to; the reply goes into that message's thread
+        #[serde(default)]
+        reply_to: Option<String>,
+        // Uploaded attachments to send with the message
+        #[serde(default)]
+        attachment_ids: Vec<Uuid>,
+    },
     #[serde(rename = "typing")]
     Typing { room_id: String, is_typing: bool },
     #[serde(rename = "reaction")]
     Reaction { message_id: String, emoji: String, add: bool },
+    #[serde(rename = "edit")]
+    Edit { message_id: String, content: String },
+    #[serde(rename = "delete")]
+    Delete { message_id: String },
+    #[serde(rename = "read")]
+    Read { room_id: String, message_id: String },
     #[serde(rename = "ping")]
     Ping,
 }
 
-#[derive(Serialize, Debug, Clone)]
+impl WsMessage {
+    // Frames the chat server authorizes from its membership cache
+    fn needs_membership(&self) -> bool {
+        matches!(self, WsMessage::Join { .. } | WsMessage::Message { .. } | WsMessage::Typing { .. })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WsResponse {
     pub message_type: String,
     pub data: serde_json::Value,
@@ -67,39 +141,153 @@ pub struct Ack {
     pub success: bool,
 }
 
+// Snapshot of the chat server's live state
+#[derive(Debug, Clone, Copy)]
+pub struct ChatStats {
+    pub connections: usize,
+    pub active_rooms: usize,
+}
+
+// A chat message accepted from a session, waiting for its database insert
+struct PendingMessage {
+    id: Uuid,
+    room_id: Uuid,
+    user: UserResponseDto,
+    content: String,
+    attachment_ids: Vec<Uuid>,
+    temp_id: Option<String>,
+    created_at: DateTime<Utc>,
+    sender: Addr<ChatSession>,
+}
+
+// A joining websocket's history replay, loaded by the writer after the inserts queued before it
+struct HistoryRequest {
+    addr: Addr<ChatSession>,
+    room_id: Uuid,
+    since: Option<Uuid>,
+    // Cache generation when the join started; the loaded page is only cached if it is unchanged
+    generation: u64,
+}
+
+// A rendered history replay for a joining websocket
+struct ReplayPage {
+    frames: Vec<WsResponse>,
+    next_cursor: Option<String>,
+    // Continues after the client's `since` id rather than being the room's latest messages
+    resumed: bool,
+}
+
+// Work for the writer task, carried out one job at a time in order
+enum WriterJob {
+    // Boxed so every job on the channel is not as large as a message
+    Insert(Box<PendingMessage>),
+    FetchHistory(HistoryRequest),
+}
+
+// Live events for a websocket whose history replay is still loading, with whether each was stored in history
+//
+// They are held back until the replay is sent so the client sees every message once and in order.
+type HeldEvents = Vec<(WsResponse, bool)>;
+
+// A user's presence as last published by another instance, with the rooms to tell when it goes away
+struct RemotePresence {
+    status: PresenceStatus,
+    user_name: String,
+    rooms: Vec<String>,
+}
+
 // Chat server actor
+//
+// Started once at boot; sessions and HTTP handlers talk to it only through messages.
 pub struct ChatServer {
     sessions: HashMap<Addr<ChatSession>, UserResponseDto>,
     rooms: HashMap<String, HashSet<Addr<ChatSession>>>,
-    message_history: HashMap<String, Vec<WsResponse>>,
+    // Latest messages of recently joined rooms, replayed without a database round trip
+    history: HistoryCache,
+    // Websockets that joined a room and wait for their history replay
+    pending_joins: HashMap<(String, Addr<ChatSession>), HeldEvents>,
     user_message_counts: HashMap<Uuid, (usize, i64)>, // (count, timestamp)
-    db: Option<DatabaseConnection>,
+    // Live connections per user_sessions row, so terminating a session can close them
+    session_connections: HashMap<Uuid, HashSet<Addr<ChatSession>>>,
+    // Active room mutes by (room_id, user_id), so muted users are stopped without a database round trip
+    mutes: HashMap<(String, Uuid), DateTime<Utc>>,
+    // Rooms each connected user is a member of, checked before joining, posting and typing
+    memberships: HashMap<Uuid, HashSet<String>>,
+    // Bumped on every membership or mute change, so a load that read the database before it is not cached
+    membership_generation: u64,
+    // Users whose memberships are being loaded, with the frames that wait for them
+    membership_loads: HashMap<Uuid, Vec<ClientMessage>>,
+    // Connections whose client has not pinged for a while
+    idle_connections: HashSet<Addr<ChatSession>>,
+    // Last presence announced to the rooms of each user who is not offline
+    presence: HashMap<Uuid, PresenceStatus>,
+    // Presence of users on other instances, by user and instance
+    remote_presence: HashMap<Uuid, HashMap<Uuid, RemotePresence>>,
+    // When each other instance was last heard from, to drop the presence of instances that died
+    remote_instances: HashMap<Uuid, Instant>,
+    // Identifies this instance's presence updates to the others
+    instance_id: Uuid,
+    // Queue of the task that inserts chat messages one at a time, in the order they were accepted
+    writer: Option<mpsc::UnboundedSender<WriterJob>>,
+    // Shares changes with the chat servers of other instances
+    backplane: Arc<dyn Backplane>,
+    db: DatabaseConnection,
 }
 
 impl ChatServer {
-    pub fn new() -> Self {
+    pub fn new(db: DatabaseConnection, backplane: Arc<dyn Backplane>) -> Self {
         Self {
             sessions: HashMap::new(),
             rooms: HashMap::new(),
-            message_history: HashMap::new(),
+            history: HistoryCache::new(),
+            pending_joins: HashMap::new(),
             user_message_counts: HashMap::new(),
-            db: None,
+            session_connections: HashMap::new(),
+            mutes: HashMap::new(),
+            memberships: HashMap::new(),
+            membership_generation: 0,
+            membership_loads: HashMap::new(),
+            idle_connections: HashSet::new(),
+            presence: HashMap::new(),
+            remote_presence: HashMap::new(),
+            remote_instances: HashMap::new(),
+            instance_id: Uuid::new_v4(),
+            writer: None,
+            backplane,
+            db,
         }
     }
 
-    pub fn set_database(&mut self, db: DatabaseConnection) {
-        self.db = Some(db);
-    }
-
-    pub fn join_room(&mut self, room_id: String, addr: Addr<ChatSession>, user: UserResponseDto) {
+    fn join_room(&mut self, room_id: String, room_uuid: Uuid, since: Option<Uuid>, addr: Addr<ChatSession>, user: UserResponseDto) {
         // Add user to room
         self.rooms.entry(room_id.clone()).or_insert_with(HashSet::new).insert(addr.clone());
 
-        // Send recent message history to the new user
-        if let Some(history) = self.message_history.get(&room_id) {
-            let recent_messages: Vec<WsResponse> = history.iter().rev().take(50).rev().cloned().collect();
-            for msg in recent_messages {
-                let _ = addr.do_send(SessionMessage(msg));
+        // Replay from the cache when it covers the request, otherwise from the database
+        let cached = match since {
+            Some(since) => self.history.after(&room_id, &since.to_string()),
+            None => self.history.latest(&room_id, REPLAY_LIMIT as usize),
+        };
+        match cached {
+            Some(messages) => {
+                let summary = replay_summary(&room_id, messages.len(), None, since.is_some());
+                for msg in messages {
+                    addr.do_send(SessionMessage(msg));
+                }
+                addr.do_send(SessionMessage(summary));
+            }
+            None => {
+                let request = HistoryRequest {
+                    addr: addr.clone(),
+                    room_id: room_uuid,
+                    since,
+                    generation: self.history.generation(),
+                };
+                match &self.writer {
+                    Some(writer) if writer.send(WriterJob::FetchHistory(request)).is_ok() => {
+                        self.pending_joins.insert((room_id.clone(), addr.clone()), Vec::new());
+                    }
+                    _ => error!("Message writer is not running; joining room {} without history", room_id),
+                }
             }
         }
 
@@ -119,7 +307,9 @@ impl ChatServer {
         self.broadcast_to_room(&room_id, &user_joined_msg, Some(&addr));
     }
 
-    pub fn leave_room(&mut self, room_id: &str, addr: &Addr<ChatSession>) {
+    fn leave_room(&mut self, room_id: &str, addr: &Addr<ChatSession>) {
+        self.pending_joins.remove(&(room_id.to_string(), addr.clone()));
+
         if let Some(room_sessions) = self.rooms.get_mut(room_id) {
             room_sessions.remove(addr);
 
@@ -141,17 +331,165 @@ impl ChatServer {
         }
     }
 
-    pub fn broadcast_to_room(&self, room_id: &str, message: &WsResponse, exclude: Option<&Addr<ChatSession>>) {
+    // Deliver to this instance's websockets in the room and publish to the other instances
+    fn broadcast_to_room(&mut self, room_id: &str, message: &WsResponse, exclude: Option<&Addr<ChatSession>>) {
+        self.deliver_to_room(room_id, message, exclude, false);
+        self.backplane.publish(BackplaneMessage::Broadcast {
+            room_id: room_id.to_string(),
+            event: message.clone(),
+            store_in_history: false,
+        });
+    }
+
+    fn deliver_to_room(&mut self, room_id: &str, message: &WsResponse, exclude: Option<&Addr<ChatSession>>, stored: bool) {
         if let Some(room_sessions) = self.rooms.get(room_id) {
             for session in room_sessions {
-                if exclude.map_or(true, |excluded| session != excluded) {
-                    let _ = session.do_send(SessionMessage(message.clone()));
+                if exclude.is_some_and(|excluded| session == excluded) {
+                    continue;
+                }
+                match self.pending_joins.get_mut(&(room_id.to_string(), session.clone())) {
+                    Some(held) => held.push((message.clone(), stored)),
+                    None => session.do_send(SessionMessage(message.clone())),
+                }
+            }
+        }
+    }
+
+    // Send a joining websocket its replay, then the live events held back while it loaded
+    fn finish_join(&mut self, request: HistoryRequest, page: Option<ReplayPage>) {
+        let room_id = request.room_id.to_string();
+        let Some(held) = self.pending_joins.remove(&(room_id.clone(), request.addr.clone())) else {
+            // The websocket left the room or disconnected meanwhile
+            return;
+        };
+
+        let Some(ReplayPage { frames, next_cursor, resumed }) = page else {
+            for (event, _) in held {
+                request.addr.do_send(SessionMessage(event));
+            }
+            return;
+        };
+
+        let replayed: HashSet<String> = frames.iter().filter_map(|frame| frame.message_id.clone()).collect();
+        let summary = replay_summary(&room_id, frames.len(), next_cursor, resumed);
+
+        // The latest page plus the messages stored while it loaded is the room's latest history
+        let mut latest = if resumed { None } else { Some(frames.clone()) };
+
+        for frame in frames {
+            request.addr.do_send(SessionMessage(frame));
+        }
+        request.addr.do_send(SessionMessage(summary));
+
+        for (event, stored) in held {
+            let duplicate = event.message_type == "message"
+                && event.message_id.as_ref().is_some_and(|id| replayed.contains(id));
+            if duplicate {
+                continue;
+            }
+            if stored {
+                if let Some(latest) = latest.as_mut() {
+                    latest.push(event.clone());
                 }
             }
+            request.addr.do_send(SessionMessage(event));
+        }
+
+        if let Some(latest) = latest {
+            self.history.fill(room_id, latest, request.generation);
+        }
+    }
+
+    // Only users with a live connection have a cache entry to update
+    fn add_membership(&mut self, room_id: &str, user_id: Uuid) {
+        if let Some(room_ids) = self.memberships.get_mut(&user_id) {
+            room_ids.insert(room_id.to_string());
+        }
+    }
+
+    // Forget a membership and drop the user's connections from the room
+    fn remove_membership(&mut self, room_id: &str, user_id: Uuid) {
+        if let Some(room_ids) = self.memberships.get_mut(&user_id) {
+            room_ids.remove(room_id);
+        }
+
+        self.remove_user_sessions(room_id, user_id);
+    }
+
+    // Take the user's connections out of a room
+    fn remove_user_sessions(&mut self, room_id: &str, user_id: Uuid) {
+        if let Some(room_sessions) = self.rooms.get_mut(room_id) {
+            for (addr, _) in self.sessions.iter().filter(|(_, user)| user.id == user_id) {
+                room_sessions.remove(addr);
+            }
+        }
+    }
+
+    // Changes published by other instances may have been missed: drop the cached history
+    // and read the memberships and mutes of connected users again
+    fn resync(&mut self, ctx: &mut Context<Self>) {
+        self.history.clear();
+        self.membership_generation += 1;
+        self.memberships.clear();
+        self.mutes.clear();
+
+        let user_ids: HashSet<Uuid> = self.sessions.values().map(|user| user.id).collect();
+        info!("Reloading room memberships of {} connected user(s)", user_ids.len());
+        for user_id in user_ids {
+            self.load_memberships(user_id, ctx);
+        }
+    }
+
+    // Read a user's memberships and mutes from the database, unless a load is already running
+    fn load_memberships(&mut self, user_id: Uuid, ctx: &mut Context<Self>) {
+        if self.membership_loads.contains_key(&user_id) {
+            return;
+        }
+        self.membership_loads.insert(user_id, Vec::new());
+        self.spawn_membership_load(user_id, ctx);
+    }
+
+    fn spawn_membership_load(&self, user_id: Uuid, ctx: &mut Context<Self>) {
+        let db = self.db.clone();
+        let server = ctx.address();
+        let generation = self.membership_generation;
+        actix::spawn(async move {
+            let result = RoomMembership::find()
+                .filter(room_membership::Column::UserId.eq(user_id))
+                .all(&db)
+                .await;
+            server.do_send(MembershipsLoaded { user_id, generation, result });
+        });
+    }
+
+    fn is_member(&self, room_id: &str, user_id: Uuid) -> bool {
+        self.memberships
+            .get(&user_id)
+            .is_some_and(|room_ids| room_ids.contains(room_id))
+    }
+
+    // Record or lift (`None`) a member's mute in a room
+    fn set_mute(&mut self, room_id: &str, user_id: Uuid, until: Option<DateTime<Utc>>) {
+        match until {
+            Some(until) => self.mutes.insert((room_id.to_string(), user_id), until),
+            None => self.mutes.remove(&(room_id.to_string(), user_id)),
+        };
+    }
+
+    // When the member's mute in a room ends, if they are muted right now
+    fn muted_until(&mut self, room_id: &str, user_id: Uuid) -> Option<DateTime<Utc>> {
+        let key = (room_id.to_string(), user_id);
+        match self.mutes.get(&key) {
+            Some(until) if *until > Utc::now() => Some(*until),
+            Some(_) => {
+                self.mutes.remove(&key);
+                None
+            }
+            None => None,
         }
     }
 
-    pub fn cleanup_session(&mut self, addr: &Addr<ChatSession>) {
+    fn cleanup_session(&mut self, addr: &Addr<ChatSession>) {
         // Remove from all rooms
         let rooms_to_leave: Vec<String> = self.rooms
             .iter()
@@ -167,138 +505,272 @@ impl ChatServer {
         for room_id in rooms_to_leave {
             self.leave_room(&room_id, addr);
         }
+        self.pending_joins.retain(|(_, pending), _| pending != addr);
+
+        // Remove from sessions, and the membership cache with the user's last connection
+        if let Some(user) = self.sessions.remove(addr) {
+            if !self.sessions.values().any(|other| other.id == user.id) {
+                self.memberships.remove(&user.id);
+            }
+        }
+
+        self.idle_connections.remove(addr);
 
-        // Remove from sessions
-        self.sessions.remove(addr);
+        // Remove from the connections of its login session
+        self.session_connections.retain(|_, connections| {
+            connections.remove(addr);
+            !connections.is_empty()
+        });
     }
 
-    pub fn check_rate_limit(&mut self, user_id: Uuid) -> bool {
-        let now = Utc::now().timestamp();
-        let (count, timestamp) = self.user_message_counts.entry(user_id).or_insert((0, now));
+    // Presence from this instance's connections of the user alone
+    fn local_presence(&self, user_id: Uuid) -> PresenceStatus {
+        self.sessions
+            .iter()
+            .filter(|(_, user)| user.id == user_id)
+            .map(|(addr, _)| {
+                if self.idle_connections.contains(addr) {
+                    PresenceStatus::Away
+                } else {
+                    PresenceStatus::Online
+                }
+            })
+            .max()
+            .unwrap_or(PresenceStatus::Offline)
+    }
 
-        // Reset count if more than 1 minute has passed
-        if now - *timestamp > 60 {
-            *count = 0;
-            *timestamp = now;
-        }
+    // Presence across all instances: the most present connection wins
+    fn presence_of(&self, user_id: Uuid) -> PresenceStatus {
+        let remote = self
+            .remote_presence
+            .get(&user_id)
+            .and_then(|instances| instances.values().map(|presence| presence.status).max())
+            .unwrap_or(PresenceStatus::Offline);
+        self.local_presence(user_id).max(remote)
+    }
 
-        if *count >= MAX_MESSAGES_PER_MINUTE {
-            return false;
+    // Recompute a user's presence after their connections here changed, and share it
+    fn refresh_presence(&mut self, user: &UserResponseDto, rooms: Vec<String>) {
+        let status = self.local_presence(user.id);
+        if status == PresenceStatus::Offline {
+            let db = self.db.clone();
+            let user_id = user.id;
+            actix::spawn(async move {
+                record_last_seen(&db, user_id, Utc::now()).await;
+            });
         }
 
-        *count += 1;
-        true
+        self.backplane.publish(BackplaneMessage::Presence {
+            instance: self.instance_id,
+            user_id: user.id,
+            user_name: user.name.clone(),
+            status,
+            rooms: rooms.clone(),
+        });
+        self.announce_presence(user.id, &user.name, &rooms);
     }
 
-    pub fn store_message_in_history(&mut self, room_id: String, message: WsResponse) {
-        let history = self.message_history.entry(room_id).or_insert_with(Vec::new);
-        history.push(message);
+    // Tell this instance's websockets in the user's rooms when the user's overall presence changed
+    fn announce_presence(&mut self, user_id: Uuid, user_name: &str, rooms: &[String]) {
+        let status = self.presence_of(user_id);
+        let previous = self.presence.get(&user_id).copied().unwrap_or(PresenceStatus::Offline);
+        if status == previous {
+            return;
+        }
 
-        // Keep only last 100 messages per room
-        if history.len() > 100 {
-            history.remove(0);
+        if status == PresenceStatus::Offline {
+            self.presence.remove(&user_id);
+        } else {
+            self.presence.insert(user_id, status);
         }
-    }
 
-    async fn persist_message(&self, room_id: Uuid, user_id: Uuid, content: String) -> Result<Uuid, WsError> {
-        if let Some(db) = &self.db {
-            let message_id = Uuid::new_v4();
-            let message = ChatMessageActiveModel {
-                id: ActiveValue::Set(message_id),
-                room_id: ActiveValue::Set(room_id),
-                user_id: ActiveValue::Set(user_id),
-                content: ActiveValue::Set(content),
-                created_at: ActiveValue::Set(Utc::now()),
-                updated_at: ActiveValue::Set(Utc::now()),
+        let now = Utc::now();
+        for room_id in rooms {
+            let event = WsResponse {
+                message_type: "presence".to_string(),
+                data: serde_json::json!({
+                    "room_id": room_id,
+                    "user_id": user_id,
+                    "user_name": user_name,
+                    "status": status,
+                    "last_seen_at": now
+                }),
+                timestamp: now.timestamp(),
+                message_id: None,
             };
+            self.deliver_to_room(room_id, &event, None, false);
+        }
+    }
+
+    // Tell other instances this one is alive, and forget the presence of instances that went silent
+    fn heartbeat_instances(&mut self) {
+        self.backplane.publish(BackplaneMessage::InstanceHeartbeat {
+            instance: self.instance_id,
+        });
+
+        let now = Instant::now();
+        let stale: Vec<Uuid> = self
+            .remote_instances
+            .iter()
+            .filter(|(_, last_seen)| now.duration_since(**last_seen) > INSTANCE_TTL)
+            .map(|(instance, _)| *instance)
+            .collect();
 
-            match ChatMessage::insert(message).exec(db).await {
-                Ok(_) => Ok(message_id),
-                Err(e) => {
-                    error!("Failed to persist message: {}", e);
-                    Err(WsError::Database(e.to_string()))
+        for instance in stale {
+            warn!("Instance {} stopped sending heartbeats, dropping the presence it reported", instance);
+            self.remote_instances.remove(&instance);
+
+            let mut dropped = Vec::new();
+            self.remote_presence.retain(|user_id, instances| {
+                if let Some(presence) = instances.remove(&instance) {
+                    dropped.push((*user_id, presence));
                 }
+                !instances.is_empty()
+            });
+            for (user_id, presence) in dropped {
+                self.announce_presence(user_id, &presence.user_name, &presence.rooms);
             }
-        } else {
-            Err(WsError::Database("Database connection not available".to_string()))
         }
     }
-}
-
-// Message to send to a session
-#[derive(Message)]
-#[rtype(result = "()")]
-pub struct SessionMessage(pub WsResponse);
 
-// Message to persist a chat message
-#[derive(Message)]
-#[rtype(result = "Result<Uuid, WsError>")]
-pub struct PersistMessage {
-    pub room_id: Uuid,
-    pub user_id: Uuid,
-    pub content: String,
-}
+    // Rooms the user is a member of, as far as the membership cache knows
+    fn rooms_of(&self, user_id: Uuid) -> Vec<String> {
+        self.memberships
+            .get(&user_id)
+            .map(|room_ids| room_ids.iter().cloned().collect())
+            .unwrap_or_default()
+    }
 
-impl Actor for ChatServer {
-    type Context = Context<Self>;
-}
+    // Forget a connection and update its user's presence
+    fn disconnect(&mut self, addr: &Addr<ChatSession>) {
+        // The membership cache goes with the user's last connection, so read their rooms first
+        let user = self.sessions.get(addr).cloned();
+        let rooms = user.as_ref().map(|user| self.rooms_of(user.id)).unwrap_or_default();
 
-impl Handler<SessionMessage> for ChatServer {
-    type Result = ();
+        self.cleanup_session(addr);
 
-    fn handle(&mut self, _msg: SessionMessage, _: &mut Context<Self>) -> Self::Result {
-        // This is handled by the ChatSession
+        if let Some(user) = user {
+            self.refresh_presence(&user, rooms);
+        }
     }
-}
 
-impl Handler<PersistMessage> for ChatServer {
-    type Result = Result<Uuid, WsError>;
+    // Drop connections whose session actor is gone without having sent Disconnect
+    fn reap_dead_sessions(&mut self) {
+        let dead: Vec<Addr<ChatSession>> = self.sessions.keys().filter(|addr| !addr.connected()).cloned().collect();
+        if dead.is_empty() {
+            return;
+        }
 
-    fn handle(&mut self, _msg: PersistMessage, _: &mut Context<Self>) -> Self::Result {
-        // This would be handled asynchronously in a real implementation
-        // For now, we'll return an error to indicate it's not implemented
-        Err(WsError::Database("Async persistence not implemented".to_string()))
+        info!("Reaping {} dead websocket connection(s)", dead.len());
+        for addr in dead {
+            self.disconnect(&addr);
+        }
     }
-}
 
-// Chat session actor
-pub struct ChatSession {
-    pub user: UserResponseDto,
-    pub addr: Addr<ChatSession>,
-    pub last_ping: i64,
-    pub message_queue: Vec<String>,
-}
+    fn check_rate_limit(&mut self, user_id: Uuid) -> bool {
+        let now = Utc::now().timestamp();
+        let (count, timestamp) = self.user_message_counts.entry(user_id).or_insert((0, now));
 
-impl ChatSession {
-    pub fn new(user: UserResponseDto) -> Self {
-        let (tx, _) = actix::dev::channel::channel::<ChatSession>(16);
-        Self {
-            user,
-            addr: Addr::new(tx),
-            last_ping: Utc::now().timestamp(),
-            message_queue: Vec::new(),
+        // Reset count if more than 1 minute has passed
+        if now - *timestamp > 60 {
+            *count = 0;
+            *timestamp = now;
+        }
+
+        if *count >= MAX_MESSAGES_PER_MINUTE {
+            return false;
         }
+
+        *count += 1;
+        true
     }
 
-    fn send_json(&self, ctx: &mut ws::WebsocketContext<Self>, message: WsResponse) {
-        let msg_str = match serde_json::to_string(&message) {
-            Ok(s) => s,
-            Err(e) => {
-                error!("Failed to serialize message: {}", e);
-                return;
+    // Apply a change to this instance only
+    fn apply(&mut self, msg: BackplaneMessage) {
+        if matches!(
+            msg,
+            BackplaneMessage::AddMembership { .. }
+                | BackplaneMessage::RemoveMembership { .. }
+                | BackplaneMessage::EvictMember { .. }
+                | BackplaneMessage::RemoveRoom { .. }
+                | BackplaneMessage::SetMute { .. }
+        ) {
+            self.membership_generation += 1;
+        }
+
+        match msg {
+            BackplaneMessage::Broadcast { room_id, event, store_in_history } => {
+                self.deliver_to_room(&room_id, &event, None, store_in_history);
+                if store_in_history {
+                    self.history.push(&room_id, event);
+                }
             }
-        };
-        ctx.text(msg_str);
+            BackplaneMessage::PatchHistory { room_id, message_id, fields } => {
+                self.history.patch(&room_id, &message_id, fields);
+            }
+            BackplaneMessage::InvalidateHistory { room_id } => self.history.invalidate(&room_id),
+            BackplaneMessage::AddMembership { room_id, user_id } => self.add_membership(&room_id, user_id),
+            BackplaneMessage::RemoveMembership { room_id, user_id } => self.remove_membership(&room_id, user_id),
+            BackplaneMessage::EvictMember { room_id, user_id } => {
+                self.set_mute(&room_id, user_id, None);
+                self.remove_membership(&room_id, user_id);
+            }
+            BackplaneMessage::RemoveRoom { room_id } => {
+                self.rooms.remove(&room_id);
+                self.history.invalidate(&room_id);
+                self.pending_joins.retain(|(pending_room_id, _), _| *pending_room_id != room_id);
+                self.mutes.retain(|(muted_room_id, _), _| *muted_room_id != room_id);
+                for room_ids in self.memberships.values_mut() {
+                    room_ids.remove(&room_id);
+                }
+            }
+            BackplaneMessage::SetMute { room_id, user_id, until } => self.set_mute(&room_id, user_id, until),
+            BackplaneMessage::Presence { instance, user_id, user_name, status, rooms } => {
+                self.remote_instances.insert(instance, Instant::now());
+                let instances = self.remote_presence.entry(user_id).or_default();
+                if status == PresenceStatus::Offline {
+                    instances.remove(&instance);
+                    if instances.is_empty() {
+                        self.remote_presence.remove(&user_id);
+                    }
+                } else {
+                    instances.insert(
+                        instance,
+                        RemotePresence {
+                            status,
+                            user_name: user_name.clone(),
+                            rooms: rooms.clone(),
+                        },
+                    );
+                }
+                self.announce_presence(user_id, &user_name, &rooms);
+            }
+            BackplaneMessage::InstanceHeartbeat { instance } => {
+                self.remote_instances.insert(instance, Instant::now());
+            }
+            // Close every websocket opened with a token from the given session
+            BackplaneMessage::CloseSessionConnections { session_id } => {
+                if let Some(connections) = self.session_connections.remove(&session_id) {
+                    info!("Closing {} websocket connection(s) for terminated session {}", connections.len(), session_id);
+                    for addr in connections {
+                        addr.do_send(CloseSession {
+                            reason: "Session terminated".to_string(),
+                        });
+                    }
+                }
+            }
+        }
     }
 
-    fn handle_ws_message_sync(&self, msg: WsMessage) -> Result<(), WsError> {
-        let mut server = match CHAT_SERVER.lock() {
-            Ok(server) => server,
-            Err(_) => return Err(WsError::Database("Failed to acquire chat server lock".to_string())),
-        };
+    // Apply a change here and on every other instance
+    fn dispatch(&mut self, msg: BackplaneMessage) {
+        self.backplane.publish(msg.clone());
+        self.apply(msg);
+    }
 
+    fn handle_client_message(&mut self, addr: Addr<ChatSession>, user: UserResponseDto, msg: WsMessage, ctx: &mut Context<Self>) -> Result<(), WsError> {
         match msg {
-            WsMessage::Join { room_id } => {
+            WsMessage::Join { room_id, since } => {
                 let room_uuid = match Uuid::parse_str(&room_id) {
                     Ok(id) => id,
                     Err(e) => {
@@ -307,40 +779,48 @@ impl ChatSession {
                     }
                 };
 
-                // Note: Security check is handled at HTTP API level
-                // WebSocket access is only granted to authenticated users who have passed HTTP security checks
+                // The caches are keyed by the canonical form of the id
+                let room_id = room_uuid.to_string();
+                if !self.is_member(&room_id, user.id) {
+                    return Err(WsError::AccessDenied(format!("You are not a member of room {}", room_uuid)));
+                }
+
+                let since = match since.as_deref().map(Uuid::parse_str).transpose() {
+                    Ok(since) => since,
+                    Err(e) => {
+                        error!("Invalid message ID format: {:?} - Error: {}", since, e);
+                        return Err(WsError::InvalidMessage(format!("Invalid message ID format: {:?}", since)));
+                    }
+                };
 
                 // Check if user is already in the room
-                if let Some(room_sessions) = server.rooms.get(&room_id) {
-                    if room_sessions.contains(&self.addr) {
-                        debug!("User {} already in room {}", self.user.name, room_id);
+                if let Some(room_sessions) = self.rooms.get(&room_id) {
+                    if room_sessions.contains(&addr) {
+                        debug!("User {} already in room {}", user.name, room_id);
                         return Ok(());
                     }
                 }
 
-                server.join_room(room_id, self.addr.clone(), self.user.clone());
-                info!("User {} joined room {}", self.user.name, room_uuid);
+                info!("User {} joined room {}", user.name, room_uuid);
+                self.join_room(room_id, room_uuid, since, addr, user);
             }
 
             WsMessage::Leave { room_id } => {
-                server.leave_room(&room_id, &self.addr);
-                info!("User {} left room {}", self.user.name, room_id);
+                self.leave_room(&room_id, &addr);
+                info!("User {} left room {}", user.name, room_id);
             }
 
-            WsMessage::Message { room_id, content, temp_id } => {
+            WsMessage::Message { room_id, content, temp_id, reply_to, attachment_ids } => {
                 // Check message size
                 if content.len() > MAX_MESSAGE_SIZE {
                     return Err(WsError::MessageTooLarge);
                 }
 
                 // Check rate limit
-                if !server.check_rate_limit(self.user.id) {
+                if !self.check_rate_limit(user.id) {
                     return Err(WsError::RateLimitExceeded);
                 }
 
-                // Note: Security check is handled at HTTP API level
-                // WebSocket access is only granted to authenticated users who have passed HTTP security checks
-
                 let room_uuid = match Uuid::parse_str(&room_id) {
                     Ok(id) => id,
                     Err(e) => {
@@ -349,93 +829,104 @@ impl ChatSession {
                     }
                 };
 
-                // Create message response with database ID
-                let message_id = Uuid::new_v4();
-                let message_response = WsResponse {
-                    message_type: "message".to_string(),
-                    data: serde_json::json!({
-                        "id": message_id.to_string(),
-                        "room_id": room_id,
-                        "user_id": self.user.id,
-                        "user_name": self.user.name,
-                        "user_profile_image": self.user.profile_image,
-                        "content": content,
-                        "timestamp": Utc::now().timestamp()
-                    }),
-                    timestamp: Utc::now().timestamp(),
-                    message_id: Some(message_id.to_string()),
-                };
-
-                // Store in history
-                server.store_message_in_history(room_id.clone(), message_response.clone());
-
-                // Broadcast to room
-                server.broadcast_to_room(&room_id, &message_response, None);
-                info!("Message broadcasted to room {}: {}", room_id, content);
-
-                // Send acknowledgment if temp_id is provided
-                if let Some(temp_id) = temp_id {
-                    let ack_response = WsResponse {
-                        message_type: "message_ack".to_string(),
-                        data: serde_json::json!({
-                            "temp_id": temp_id,
-                            "message_id": message_id.to_string(),
-                            "success": true
-                        }),
-                        timestamp: Utc::now().timestamp(),
-                        message_id: None,
-                    };
+                let room_id = room_uuid.to_string();
+                if !self.is_member(&room_id, user.id) {
+                    return Err(WsError::AccessDenied(format!("You are not a member of room {}", room_uuid)));
+                }
 
-                    // Send ack only to the sender
-                    let _ = self.addr.do_send(SessionMessage(ack_response));
+                if let Some(until) = self.muted_until(&room_id, user.id) {
+                    return Err(WsError::Muted(until));
                 }
 
-                // Persist message to database (async)
-                if let Some(db) = &server.db {
-                    let db_clone = db.clone();
-                    let room_uuid_clone = room_uuid;
-                    let user_id_clone = self.user.id;
-                    let content_clone = content.clone();
-                    let message_id_clone = message_id;
+                // Thread replies are persisted before they are broadcast and stay out of the room history
+                if let Some(reply_to) = reply_to {
+                    if !attachment_ids.is_empty() {
+                        return Err(WsError::InvalidMessage("Attachments can only be sent in top-level messages".to_string()));
+                    }
+
+                    let parent_id = match Uuid::parse_str(&reply_to) {
+                        Ok(id) => id,
+                        Err(e) => {
+                            error!("Invalid message ID format: {} - Error: {}", reply_to, e);
+                            return Err(WsError::InvalidMessage(format!("Invalid message ID format: {}", reply_to)));
+                        }
+                    };
+
+                    let db = self.db.clone();
 
-                    // Spawn async task for database persistence
                     tokio::spawn(async move {
-                        let message = ChatMessageActiveModel {
-                            id: ActiveValue::Set(message_id_clone),
-                            room_id: ActiveValue::Set(room_uuid_clone),
-                            user_id: ActiveValue::Set(user_id_clone),
-                            content: ActiveValue::Set(content_clone),
-                            created_at: ActiveValue::Set(Utc::now()),
-                            updated_at: ActiveValue::Set(Utc::now()),
-                        };
-
-                        match ChatMessage::insert(message).exec(&db_clone).await {
-                            Ok(_) => {
-                                #[cfg(debug_assertions)]
-                                debug!("Message persisted to database: {}", message_id_clone);
+                        match create_thread_reply(&db, room_uuid, user.id, parent_id, content).await {
+                            Ok(reply) => {
+                                broadcast_thread_reply(&db, &reply, &user.name, user.profile_image.as_deref()).await;
+
+                                if let Some(temp_id) = temp_id {
+                                    addr.do_send(SessionMessage(WsResponse {
+                                        message_type: "message_ack".to_string(),
+                                        data: serde_json::json!({
+                                            "temp_id": temp_id,
+                                            "message_id": reply.id.to_string(),
+                                            "reply_to_id": reply.reply_to_id,
+                                            "success": true
+                                        }),
+                                        timestamp: Utc::now().timestamp(),
+                                        message_id: None,
+                                    }));
+                                }
                             }
                             Err(e) => {
-                                error!("Failed to persist message to database: {}", e);
+                                error!("Failed to post thread reply to {}: {}", parent_id, e);
+                                addr.do_send(SessionMessage(api_error_response(&e)));
                             }
                         }
                     });
+
+                    return Ok(());
+                }
+
+                // Broadcast and ack only once the writer has stored the message
+                let pending = PendingMessage {
+                    id: Uuid::new_v4(),
+                    room_id: room_uuid,
+                    user,
+                    content,
+                    attachment_ids,
+                    temp_id,
+                    created_at: Utc::now(),
+                    sender: addr,
+                };
+
+                match &self.writer {
+                    Some(writer) if writer.send(WriterJob::Insert(Box::new(pending))).is_ok() => {}
+                    _ => return Err(WsError::Database("Message writer is not running".to_string())),
                 }
             }
 
             WsMessage::Typing { room_id, is_typing } => {
+                let room_id = match Uuid::parse_str(&room_id) {
+                    Ok(id) => id.to_string(),
+                    Err(e) => {
+                        error!("Invalid room ID format: {} - Error: {}", room_id, e);
+                        return Err(WsError::InvalidMessage(format!("Invalid room ID format: {}", room_id)));
+                    }
+                };
+
+                if !self.is_member(&room_id, user.id) {
+                    return Err(WsError::AccessDenied(format!("You are not a member of room {}", room_id)));
+                }
+
                 let typing_response = WsResponse {
                     message_type: "typing".to_string(),
                     data: serde_json::json!({
                         "room_id": room_id,
-                        "user_id": self.user.id,
-                        "user_name": self.user.name,
+                        "user_id": user.id,
+                        "user_name": user.name,
                         "is_typing": is_typing
                     }),
                     timestamp: Utc::now().timestamp(),
                     message_id: None,
                 };
 
-                server.broadcast_to_room(&room_id, &typing_response, Some(&self.addr));
+                self.broadcast_to_room(&room_id, &typing_response, Some(&addr));
             }
 
             WsMessage::Reaction { message_id, emoji, add } => {
@@ -448,29 +939,14 @@ impl ChatSession {
                     }
                 };
 
-                // Release the server lock before spawning async work to avoid holding it across await
-                drop(server);
-
-                let user_id = self.user.id;
-                let user_name = self.user.name.clone();
+                let db = self.db.clone();
+                let server = ctx.address();
+                let user_id = user.id;
+                let user_name = user.name;
                 let emoji_clone = emoji.clone();
 
                 // Perform DB operations and broadcast asynchronously
                 tokio::spawn(async move {
-                    // Acquire DB connection from chat server
-                    let db_opt = match CHAT_SERVER.lock() {
-                        Ok(server) => server.db.clone(),
-                        Err(_) => {
-                            error!("Failed to acquire chat server lock for reactions");
-                            None
-                        }
-                    };
-
-                    let Some(db) = db_opt else {
-                        error!("Database connection not available for reactions");
-                        return;
-                    };
-
                     // Find the message to get room_id and validate membership
                     match ChatMessage::find_by_id(msg_uuid).one(&db).await {
                         Ok(Some(chat_msg)) => {
@@ -556,11 +1032,15 @@ impl ChatSession {
                                 message_id: None,
                             };
 
-                            if let Ok(server) = CHAT_SERVER.lock() {
-                                server.broadcast_to_room(&chat_msg.room_id.to_string(), &reaction_response, None);
-                            } else {
-                                error!("Failed to acquire chat server lock to broadcast reaction");
-                            }
+                            // Cached messages carry their reactions, so the room is reloaded on its next join
+                            server.do_send(Dispatch(BackplaneMessage::InvalidateHistory {
+                                room_id: chat_msg.room_id.to_string(),
+                            }));
+                            server.do_send(Dispatch(BackplaneMessage::Broadcast {
+                                room_id: chat_msg.room_id.to_string(),
+                                event: reaction_response,
+                                store_in_history: false,
+                            }));
                         }
                         Ok(None) => {
                             error!("Message not found for reaction: {}", message_id);
@@ -572,71 +1052,647 @@ impl ChatSession {
                 });
             }
 
-            WsMessage::Ping => {
-                // Send pong response with timestamp (milliseconds for JavaScript compatibility)
-                let now_ms = Utc::now().timestamp_millis();
-                let pong_response = WsResponse {
-                    message_type: "pong".to_string(),
-                    data: serde_json::json!({
-                        "timestamp": now_ms
-                    }),
-                    timestamp: now_ms,
-                    message_id: None,
+            WsMessage::Edit { message_id, content } => {
+                let msg_uuid = match Uuid::parse_str(&message_id) {
+                    Ok(id) => id,
+                    Err(e) => {
+                        error!("Invalid message ID format: {} - Error: {}", message_id, e);
+                        return Err(WsError::InvalidMessage(format!("Invalid message ID format: {}", message_id)));
+                    }
                 };
-                
-                // Send pong back to the sender
-                let _ = self.addr.do_send(SessionMessage(pong_response));
-            }
-        }
-
-        Ok(())
-    }
-}
-
-impl Actor for ChatSession {
-    type Context = ws::WebsocketContext<Self>;
 
-    fn started(&mut self, ctx: &mut Self::Context) {
-        self.addr = ctx.address();
+                let db = self.db.clone();
 
-        // Set up ping interval
-        ctx.run_interval(std::time::Duration::from_secs(30), |act, ctx| {
-            let now = Utc::now().timestamp();
-            if now - act.last_ping > 60 {
-                // No ping for 60 seconds, close connection
-                ctx.stop();
-                return;
+                tokio::spawn(async move {
+                    match edit_message(&db, msg_uuid, user.id, content).await {
+                        Ok(message) => broadcast_message_edited(&message),
+                        Err(e) => {
+                            error!("Failed to edit message {}: {}", msg_uuid, e);
+                            addr.do_send(SessionMessage(api_error_response(&e)));
+                        }
+                    }
+                });
             }
 
-            // Send pong
-            let now_ms = Utc::now().timestamp_millis();
-            let pong_response = WsResponse {
-                message_type: "pong".to_string(),
-                data: serde_json::json!({
-                    "timestamp": now_ms
-                }),
-                timestamp: now_ms,
-                message_id: None,
-            };
-            act.send_json(ctx, pong_response);
+            WsMessage::Read { room_id, message_id } => {
+                let room_uuid = match Uuid::parse_str(&room_id) {
+                    Ok(id) => id,
+                    Err(e) => {
+                        error!("Invalid room ID format: {} - Error: {}", room_id, e);
+                        return Err(WsError::InvalidMessage(format!("Invalid room ID format: {}", room_id)));
+                    }
+                };
+                let msg_uuid = match Uuid::parse_str(&message_id) {
+                    Ok(id) => id,
+                    Err(e) => {
+                        error!("Invalid message ID format: {} - Error: {}", message_id, e);
+                        return Err(WsError::InvalidMessage(format!("Invalid message ID format: {}", message_id)));
+                    }
+                };
+
+                let db = self.db.clone();
+
+                tokio::spawn(async move {
+                    match mark_read(&db, room_uuid, user.id, msg_uuid).await {
+                        // Only tell the room when the marker actually moved
+                        Ok((membership, true)) => broadcast_read_receipt(&membership, &user.name),
+                        Ok((_, false)) => {}
+                        Err(e) => {
+                            error!("Failed to mark room {} read: {}", room_uuid, e);
+                            addr.do_send(SessionMessage(api_error_response(&e)));
+                        }
+                    }
+                });
+            }
+
+            WsMessage::Delete { message_id } => {
+                let msg_uuid = match Uuid::parse_str(&message_id) {
+                    Ok(id) => id,
+                    Err(e) => {
+                        error!("Invalid message ID format: {} - Error: {}", message_id, e);
+                        return Err(WsError::InvalidMessage(format!("Invalid message ID format: {}", message_id)));
+                    }
+                };
+
+                let db = self.db.clone();
+
+                tokio::spawn(async move {
+                    match delete_message(&db, msg_uuid, user.id, &user.role).await {
+                        Ok(message) => broadcast_message_deleted(&message),
+                        Err(e) => {
+                            error!("Failed to delete message {}: {}", msg_uuid, e);
+                            addr.do_send(SessionMessage(api_error_response(&e)));
+                        }
+                    }
+                });
+            }
+
+            WsMessage::Ping => {
+                addr.do_send(SessionMessage(pong_response()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Insert a chat message accepted by the chat server, with its attachments
+async fn insert_message(db: &DatabaseConnection, message: &PendingMessage) -> Result<Vec<AttachmentModel>, WsError> {
+    let model = ChatMessageActiveModel {
+        id: ActiveValue::Set(message.id),
+        room_id: ActiveValue::Set(message.room_id),
+        user_id: ActiveValue::Set(message.user.id),
+        content: ActiveValue::Set(message.content.clone()),
+        created_at: ActiveValue::Set(message.created_at),
+        updated_at: ActiveValue::Set(message.created_at),
+        edited_at: ActiveValue::Set(None),
+        deleted_at: ActiveValue::Set(None),
+        deleted_by: ActiveValue::Set(None),
+        reply_to_id: ActiveValue::Set(None),
+    };
+
+    let result = async {
+        let txn = db.begin().await?;
+        ChatMessage::insert(model).exec(&txn).await?;
+        let attachments = link_attachments(&txn, &message.attachment_ids, message.user.id, message.room_id, message.id).await?;
+        txn.commit().await?;
+        Ok::<_, ApiError>(attachments)
+    }
+    .await;
+
+    match result {
+        Ok(attachments) => Ok(attachments),
+        Err(ApiError::Validation(reason)) => Err(WsError::InvalidMessage(reason)),
+        Err(e) => {
+            error!("Failed to persist message to database: {}", e);
+            Err(WsError::Database(e.to_string()))
+        }
+    }
+}
+
+// Message to send to a session
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SessionMessage(pub WsResponse);
+
+// Message telling a session to close its websocket
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CloseSession {
+    pub reason: String,
+}
+
+// A websocket connected
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Connect {
+    pub addr: Addr<ChatSession>,
+    pub user: UserResponseDto,
+    pub session_id: Uuid,
+}
+
+// The chat server read a user's memberships, with the membership generation when it started
+#[derive(Message)]
+#[rtype(result = "()")]
+struct MembershipsLoaded {
+    user_id: Uuid,
+    generation: u64,
+    result: Result<Vec<RoomMembershipModel>, DbErr>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Disconnect {
+    pub addr: Addr<ChatSession>,
+}
+
+// A session's client stopped or resumed pinging
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetIdle {
+    pub addr: Addr<ChatSession>,
+    pub idle: bool,
+}
+
+#[derive(Message)]
+#[rtype(result = "HashMap<Uuid, PresenceStatus>")]
+pub struct GetPresence {
+    pub user_ids: Vec<Uuid>,
+}
+
+// A frame received on a session's websocket
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ClientMessage {
+    pub addr: Addr<ChatSession>,
+    pub msg: WsMessage,
+}
+
+// A change to apply here and publish to the other instances
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Dispatch(pub BackplaneMessage);
+
+// A change published by another instance
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RemoteEvent(pub BackplaneMessage);
+
+// The backplane reconnected and may have missed changes published by other instances
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Resync;
+
+#[derive(Message)]
+#[rtype(result = "ChatStats")]
+pub struct GetStats;
+
+// The writer read the messages to replay to a joining websocket
+#[derive(Message)]
+#[rtype(result = "()")]
+struct HistoryFetched {
+    request: HistoryRequest,
+    result: Result<Replay, ApiError>,
+}
+
+// A joining websocket's replay, rendered and ready to send
+#[derive(Message)]
+#[rtype(result = "()")]
+struct HistoryLoaded {
+    request: HistoryRequest,
+    page: ReplayPage,
+}
+
+// Outcome of the writer's insert for a pending chat message
+#[derive(Message)]
+#[rtype(result = "()")]
+struct MessagePersisted {
+    message: Box<PendingMessage>,
+    // The message's attachments once it is stored
+    result: Result<Vec<AttachmentModel>, WsError>,
+}
+
+impl Actor for ChatServer {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        // A single writer keeps inserts in the order messages were accepted
+        // without holding up the actor while the database works. History replays
+        // queue behind the inserts accepted before them, so no message falls between
+        // a replay and the live events that follow it.
+        let (tx, mut rx) = mpsc::unbounded_channel::<WriterJob>();
+        self.writer = Some(tx);
+
+        let db = self.db.clone();
+        let server = ctx.address();
+        actix::spawn(async move {
+            while let Some(job) = rx.recv().await {
+                match job {
+                    WriterJob::Insert(message) => {
+                        let result = insert_message(&db, &message).await;
+                        server.do_send(MessagePersisted { message, result });
+                    }
+                    WriterJob::FetchHistory(request) => {
+                        let result = fetch_replay(&db, request.room_id, request.since).await;
+                        server.do_send(HistoryFetched { request, result });
+                    }
+                }
+            }
         });
+
+        self.backplane.subscribe(ctx.address());
+
+        ctx.run_interval(REAP_INTERVAL, |act, _| act.reap_dead_sessions());
+        ctx.run_interval(INSTANCE_HEARTBEAT_INTERVAL, |act, _| act.heartbeat_instances());
+
+        info!("Chat server started");
+    }
+}
+
+impl Handler<Connect> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Connect, ctx: &mut Context<Self>) -> Self::Result {
+        let user = msg.user.clone();
+        self.sessions.insert(msg.addr.clone(), msg.user);
+        self.session_connections.entry(msg.session_id).or_default().insert(msg.addr);
+
+        // Another connection of the user keeps the cache up to date; otherwise presence
+        // is shared once the user's rooms are known
+        if self.memberships.contains_key(&user.id) {
+            let rooms = self.rooms_of(user.id);
+            self.refresh_presence(&user, rooms);
+        } else {
+            self.load_memberships(user.id, ctx);
+        }
+    }
+}
+
+impl Handler<MembershipsLoaded> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: MembershipsLoaded, ctx: &mut Context<Self>) -> Self::Result {
+        let MembershipsLoaded { user_id, generation, result } = msg;
+        let Some(user) = self.sessions.values().find(|user| user.id == user_id).cloned() else {
+            // Every connection of the user closed while loading
+            self.membership_loads.remove(&user_id);
+            return;
+        };
+
+        let memberships = match result {
+            // A membership or mute changed after the read started; read again
+            Ok(_) if generation != self.membership_generation => {
+                self.spawn_membership_load(user_id, ctx);
+                return;
+            }
+            Ok(memberships) => memberships,
+            Err(e) => {
+                error!("Failed to load room memberships for user {}: {}", user_id, e);
+                let error = WsError::Database(e.to_string());
+                for waiting in self.membership_loads.remove(&user_id).unwrap_or_default() {
+                    waiting.addr.do_send(SessionMessage(ws_error_response(&error)));
+                }
+                return;
+            }
+        };
+
+        let room_ids: HashSet<String> = memberships.iter().map(|m| m.room_id.to_string()).collect();
+        // After a resync the user may have lost rooms their connections are still in
+        let left: Vec<String> = self.rooms.keys().filter(|room_id| !room_ids.contains(*room_id)).cloned().collect();
+        for room_id in left {
+            self.remove_user_sessions(&room_id, user_id);
+        }
+        self.memberships.insert(user_id, room_ids);
+        for membership in memberships.iter().filter(|m| m.is_muted()) {
+            self.set_mute(&membership.room_id.to_string(), user_id, membership.muted_until);
+        }
+
+        let rooms = self.rooms_of(user_id);
+        self.refresh_presence(&user, rooms);
+
+        for waiting in self.membership_loads.remove(&user_id).unwrap_or_default() {
+            self.handle_client_frame(waiting, ctx);
+        }
+    }
+}
+
+impl Handler<Disconnect> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) -> Self::Result {
+        self.disconnect(&msg.addr);
     }
+}
+
+impl Handler<SetIdle> for ChatServer {
+    type Result = ();
 
-    fn stopping(&mut self, _: &mut Self::Context) -> actix::Running {
-        // Clean up session
-        if let Ok(mut server) = CHAT_SERVER.lock() {
-            server.cleanup_session(&self.addr);
+    fn handle(&mut self, msg: SetIdle, _: &mut Context<Self>) -> Self::Result {
+        let Some(user) = self.sessions.get(&msg.addr).cloned() else {
+            return;
+        };
+
+        let changed = if msg.idle {
+            self.idle_connections.insert(msg.addr)
         } else {
-            error!("Failed to acquire chat server lock during session cleanup");
+            self.idle_connections.remove(&msg.addr)
+        };
+        if changed {
+            let rooms = self.rooms_of(user.id);
+            self.refresh_presence(&user, rooms);
+        }
+    }
+}
+
+impl Handler<GetPresence> for ChatServer {
+    type Result = MessageResult<GetPresence>;
+
+    fn handle(&mut self, msg: GetPresence, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(msg.user_ids.into_iter().map(|user_id| (user_id, self.presence_of(user_id))).collect())
+    }
+}
+
+impl Handler<ClientMessage> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ClientMessage, ctx: &mut Context<Self>) -> Self::Result {
+        self.handle_client_frame(msg, ctx);
+    }
+}
+
+impl ChatServer {
+    fn handle_client_frame(&mut self, msg: ClientMessage, ctx: &mut Context<Self>) {
+        let Some(user) = self.sessions.get(&msg.addr).cloned() else {
+            error!("Dropping websocket message from an unregistered session");
+            return;
+        };
+
+        // Without cached memberships the frame waits for them to be read from the database
+        if msg.msg.needs_membership() && !self.memberships.contains_key(&user.id) {
+            self.load_memberships(user.id, ctx);
+            if let Some(waiting) = self.membership_loads.get_mut(&user.id) {
+                waiting.push(msg);
+            }
+            return;
+        }
+
+        match self.handle_client_message(msg.addr.clone(), user, msg.msg, ctx) {
+            Ok(_) => {
+                // Keep this log for debugging message handling
+                debug!("WebSocket message handled successfully");
+            }
+            Err(e) => {
+                error!("Failed to handle WebSocket message: {:?}", e);
+                // Send error response to client
+                msg.addr.do_send(SessionMessage(ws_error_response(&e)));
+            }
+        }
+    }
+}
+
+impl Handler<MessagePersisted> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: MessagePersisted, _: &mut Context<Self>) -> Self::Result {
+        let MessagePersisted { message, result } = msg;
+
+        // The room never sees a message that was not stored; only the sender learns of the failure
+        let attachments = match result {
+            Ok(attachments) => attachments,
+            Err(e) => {
+                metrics::record_chat_message("failed");
+                message.sender.do_send(SessionMessage(WsResponse {
+                    message_type: "message_ack".to_string(),
+                    data: serde_json::json!({
+                        "temp_id": message.temp_id,
+                        "message_id": message.id.to_string(),
+                        "success": false,
+                        "error": e.client_message(),
+                        "code": e.code()
+                    }),
+                    timestamp: Utc::now().timestamp(),
+                    message_id: None,
+                }));
+                return;
+            }
+        };
+        metrics::record_chat_message("persisted");
+
+        let room_id = message.room_id.to_string();
+        let message_response = WsResponse {
+            message_type: "message".to_string(),
+            data: serde_json::json!({
+                "id": message.id.to_string(),
+                "room_id": room_id,
+                "user_id": message.user.id,
+                "user_name": message.user.name,
+                "user_profile_image": message.user.profile_image,
+                "content": message.content,
+                "attachments": attachments.iter().map(attachment_view).collect::<Vec<_>>(),
+                "timestamp": message.created_at.timestamp()
+            }),
+            timestamp: Utc::now().timestamp(),
+            message_id: Some(message.id.to_string()),
+        };
+
+        // Store in history and broadcast to room
+        self.dispatch(BackplaneMessage::Broadcast {
+            room_id: room_id.clone(),
+            event: message_response,
+            store_in_history: true,
+        });
+        metrics::record_chat_message("broadcast");
+        info!("Message broadcasted to room {}: {}", room_id, message.content);
+
+        // Send ack only to the sender if temp_id is provided
+        if let Some(temp_id) = message.temp_id {
+            let ack = Ack {
+                temp_id,
+                message_id: message.id.to_string(),
+                success: true,
+            };
+            message.sender.do_send(SessionMessage(WsResponse {
+                message_type: "message_ack".to_string(),
+                data: serde_json::json!(ack),
+                timestamp: Utc::now().timestamp(),
+                message_id: None,
+            }));
+        }
+    }
+}
+
+impl Handler<HistoryFetched> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: HistoryFetched, ctx: &mut Context<Self>) -> Self::Result {
+        let HistoryFetched { request, result } = msg;
+
+        let replay = match result {
+            Ok(replay) => replay,
+            Err(e) => {
+                error!("Failed to load history of room {}: {}", request.room_id, e);
+                request.addr.do_send(SessionMessage(api_error_response(&e)));
+                self.finish_join(request, None);
+                return;
+            }
+        };
+
+        // Rendering looks up authors and reactions, so it runs outside the writer
+        let next_cursor = replay.next_cursor();
+        let resumed = replay.resumed;
+        let db = self.db.clone();
+        let server = ctx.address();
+        actix::spawn(async move {
+            let frames = replay_frames(&db, replay.messages).await;
+            server.do_send(HistoryLoaded {
+                request,
+                page: ReplayPage { frames, next_cursor, resumed },
+            });
+        });
+    }
+}
+
+impl Handler<HistoryLoaded> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: HistoryLoaded, _: &mut Context<Self>) -> Self::Result {
+        self.finish_join(msg.request, Some(msg.page));
+    }
+}
+
+impl Handler<Dispatch> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Dispatch, _: &mut Context<Self>) -> Self::Result {
+        self.dispatch(msg.0);
+    }
+}
+
+impl Handler<RemoteEvent> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: RemoteEvent, _: &mut Context<Self>) -> Self::Result {
+        self.apply(msg.0);
+    }
+}
+
+impl Handler<Resync> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, _: Resync, ctx: &mut Context<Self>) -> Self::Result {
+        self.resync(ctx);
+    }
+}
+
+impl Handler<GetStats> for ChatServer {
+    type Result = MessageResult<GetStats>;
+
+    fn handle(&mut self, _: GetStats, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(ChatStats {
+            connections: self.session_connections.values().map(|connections| connections.len()).sum(),
+            active_rooms: self.rooms.values().filter(|sessions| !sessions.is_empty()).count(),
+        })
+    }
+}
+
+// Chat session actor
+pub struct ChatSession {
+    pub user: UserResponseDto,
+    pub session_id: Uuid,
+    pub server: Addr<ChatServer>,
+    // Last frame from the client application; it pings while the page is open
+    pub last_ping: i64,
+    // Last frame of any kind, protocol pongs included
+    last_heartbeat: Instant,
+    heartbeat: HeartbeatConfig,
+    // Reported to the chat server as away after AWAY_AFTER_SECS without a ping
+    idle: bool,
+}
+
+impl ChatSession {
+    pub fn new(
+        user: UserResponseDto,
+        session_id: Uuid,
+        server: Addr<ChatServer>,
+        heartbeat: HeartbeatConfig,
+    ) -> Self {
+        Self {
+            user,
+            session_id,
+            server,
+            last_ping: Utc::now().timestamp(),
+            last_heartbeat: Instant::now(),
+            heartbeat,
+            idle: false,
+        }
+    }
+
+    fn send_json(&self, ctx: &mut ws::WebsocketContext<Self>, message: WsResponse) {
+        let msg_str = match serde_json::to_string(&message) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Failed to serialize message: {}", e);
+                return;
+            }
+        };
+        ctx.text(msg_str);
+    }
+
+    // Tell the chat server when the client stops or resumes pinging
+    fn update_idle(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        let idle = Utc::now().timestamp() - self.last_ping > AWAY_AFTER_SECS;
+        if idle != self.idle {
+            self.idle = idle;
+            self.server.do_send(SetIdle { addr: ctx.address(), idle });
         }
-        actix::Running::Stop
+    }
+}
+
+impl Actor for ChatSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        // Register with the chat server under its login session
+        self.server.do_send(Connect {
+            addr: ctx.address(),
+            user: self.user.clone(),
+            session_id: self.session_id,
+        });
+
+        // Ping the client at the heartbeat interval; browsers answer protocol pings
+        // on their own, so a connection that stays silent is half-open
+        ctx.run_interval(self.heartbeat.interval, |act, ctx| {
+            if act.last_heartbeat.elapsed() > act.heartbeat.client_timeout {
+                info!("Closing stale websocket for user {}", act.user.id);
+                ctx.close(Some(ws::CloseReason {
+                    code: ws::CloseCode::Away,
+                    description: Some("Heartbeat timeout".to_string()),
+                }));
+                ctx.stop();
+                return;
+            }
+
+            act.update_idle(ctx);
+            ctx.ping(b"");
+        });
+    }
+
+    fn stopped(&mut self, ctx: &mut Self::Context) {
+        // Runs however the session ended, so the chat server always forgets the connection
+        self.server.do_send(Disconnect { addr: ctx.address() });
     }
 }
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ChatSession {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        self.last_heartbeat = Instant::now();
+
         match msg {
             Ok(ws::Message::Text(text)) => {
+                // Any frame from the application shows the client is active
+                self.last_ping = Utc::now().timestamp();
+                self.update_idle(ctx);
+
                 // Parse the message
                 let ws_message: WsMessage = match serde_json::from_str(&text) {
                     Ok(msg) => msg,
@@ -648,54 +1704,23 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ChatSession {
 
                 // Handle ping separately
                 if let WsMessage::Ping = ws_message {
-                    self.last_ping = Utc::now().timestamp();
-                    
-                    // Send pong response with timestamp (milliseconds for JavaScript compatibility)
-                    let now_ms = Utc::now().timestamp_millis();
-                    let pong_response = WsResponse {
-                        message_type: "pong".to_string(),
-                        data: serde_json::json!({
-                            "timestamp": now_ms
-                        }),
-                        timestamp: now_ms,
-                        message_id: None,
-                    };
-                    
-                    self.send_json(ctx, pong_response);
+                    self.send_json(ctx, pong_response());
                     return;
                 }
 
-                // Handle other messages
-                match self.handle_ws_message_sync(ws_message) {
-                    Ok(_) => {
-                        // Keep this log for debugging message handling
-                        debug!("WebSocket message handled successfully");
-                    }
-                    Err(e) => {
-                        error!("Failed to handle WebSocket message: {:?}", e);
-                        // Send error response to client
-                        let error_response = WsResponse {
-                            message_type: "error".to_string(),
-                            data: serde_json::json!({
-                                "error": format!("{:?}", e)
-                            }),
-                            timestamp: Utc::now().timestamp(),
-                            message_id: None,
-                        };
-                        self.send_json(ctx, error_response);
-                    }
-                }
+                // Everything else is handled by the chat server
+                self.server.do_send(ClientMessage {
+                    addr: ctx.address(),
+                    msg: ws_message,
+                });
             }
-            Ok(ws::Message::Binary(bin)) => {
+            Ok(ws::Message::Binary(_bin)) => {
                 error!("Binary messages not supported");
             }
             Ok(ws::Message::Ping(msg)) => {
                 ctx.pong(&msg);
-                self.last_ping = Utc::now().timestamp();
-            }
-            Ok(ws::Message::Pong(_)) => {
-                self.last_ping = Utc::now().timestamp();
             }
+            Ok(ws::Message::Pong(_)) => {}
             Ok(ws::Message::Close(reason)) => {
                 ctx.close(reason);
             }
@@ -705,6 +1730,10 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ChatSession {
             Ok(ws::Message::Nop) => {}
             Err(e) => {
                 error!("WebSocket error: {:?}", e);
+                ctx.close(Some(ws::CloseReason {
+                    code: ws::CloseCode::Protocol,
+                    description: Some("Protocol error".to_string()),
+                }));
                 ctx.stop();
             }
         }
@@ -719,128 +1748,235 @@ impl Handler<SessionMessage> for ChatSession {
     }
 }
 
-// Global chat server instance
-lazy_static::lazy_static! {
-    pub static ref CHAT_SERVER: Mutex<ChatServer> = Mutex::new(ChatServer::new());
+impl Handler<CloseSession> for ChatSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: CloseSession, ctx: &mut Self::Context) -> Self::Result {
+        info!("Closing websocket for user {}: {}", self.user.id, msg.reason);
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Policy,
+            description: Some(msg.reason),
+        }));
+        ctx.stop();
+    }
 }
 
-#[get("/api/chat/ws")]
-pub async fn ws_index(req: HttpRequest, stream: web::Payload, jwt_secret: web::Data<String>, db: web::Data<DatabaseConnection>) -> Result<HttpResponse, Error> {
-    // Only log in development
-    #[cfg(debug_assertions)]
-    debug!("WebSocket connection attempt - Path: {}", req.path());
+// Pong with a timestamp in milliseconds for JavaScript compatibility
+fn pong_response() -> WsResponse {
+    let now_ms = Utc::now().timestamp_millis();
+    WsResponse {
+        message_type: "pong".to_string(),
+        data: serde_json::json!({
+            "timestamp": now_ms
+        }),
+        timestamp: now_ms,
+        message_id: None,
+    }
+}
 
-    // Only log in development
-    #[cfg(debug_assertions)]
-    {
-        // Check for auth_token cookie
-        if let Some(cookie) = req.cookie("auth_token") {
-            debug!("Found auth_token cookie: {}", cookie.value());
-        } else {
-            debug!("No auth_token cookie found");
+// Sent after the replayed messages of a join; `has_more` tells the client to page the rest over REST
+fn replay_summary(room_id: &str, count: usize, next_cursor: Option<String>, resumed: bool) -> WsResponse {
+    WsResponse {
+        message_type: "history_replayed".to_string(),
+        data: serde_json::json!({
+            "room_id": room_id,
+            "count": count,
+            "resumed": resumed,
+            "has_more": next_cursor.is_some(),
+            "next_cursor": next_cursor
+        }),
+        timestamp: Utc::now().timestamp(),
+        message_id: None,
+    }
+}
 
-            // Check all cookies for debugging
-            if let Ok(cookies) = req.cookies() {
-                for cookie in cookies.iter() {
-                    debug!("Cookie found: {} = {}", cookie.name(), cookie.value());
-                }
-            }
-        }
+// Error frame for a client message the server rejected
+fn ws_error_response(e: &WsError) -> WsResponse {
+    let mut data = serde_json::json!({
+        "error": e.client_message(),
+        "code": e.code()
+    });
+    if let WsError::Muted(until) = e {
+        data["muted_until"] = serde_json::json!(until);
+    }
+    WsResponse {
+        message_type: "error".to_string(),
+        data,
+        timestamp: Utc::now().timestamp(),
+        message_id: None,
+    }
+}
 
-        // Check for Authorization header
-        if let Some(auth_header) = req.headers().get("Authorization") {
-            debug!("Found Authorization header: {}", auth_header.to_str().unwrap_or("invalid"));
-        } else {
-            debug!("No Authorization header found");
-        }
+// Error frame for a failed action that was carried out outside the session actor
+fn api_error_response(e: &ApiError) -> WsResponse {
+    WsResponse {
+        message_type: "error".to_string(),
+        data: serde_json::json!({
+            "error": e.to_string(),
+            "code": e.code()
+        }),
+        timestamp: Utc::now().timestamp(),
+        message_id: None,
     }
+}
 
-    // Try to extract token
-    let token = match extract_token_from_cookie_or_header(&req) {
-        Some(token) => {
-            #[cfg(debug_assertions)]
-            debug!("Token extracted successfully, length: {}", token.len());
-            token
-        },
-        None => {
-            error!("No authentication token found in cookie or header");
-            #[cfg(debug_assertions)]
-            {
-                error!("Available cookies: {:?}", req.cookies());
-                error!("Available headers: {:?}", req.headers());
-            }
-            return Err(actix_web::error::ErrorUnauthorized("Authentication token not found. Please log in again."));
-        }
+// The chat server actor, started once at boot
+static CHAT_SERVER: OnceLock<Addr<ChatServer>> = OnceLock::new();
+
+/// Start the chat server actor; later calls return the running one
+pub fn start_chat_server(db: DatabaseConnection, backplane: Arc<dyn Backplane>) -> Addr<ChatServer> {
+    CHAT_SERVER.get_or_init(|| ChatServer::new(db, backplane).start()).clone()
+}
+
+fn chat_server() -> Option<&'static Addr<ChatServer>> {
+    let server = CHAT_SERVER.get();
+    if server.is_none() {
+        error!("Chat server is not running");
+    }
+    server
+}
+
+// Apply a change on every instance without waiting for it to be handled
+fn dispatch(msg: BackplaneMessage) {
+    if let Some(server) = chat_server() {
+        server.do_send(Dispatch(msg));
+    }
+}
+
+// Send an event to every websocket in a room
+pub fn broadcast_to_room(room_id: &str, event: WsResponse) {
+    dispatch(BackplaneMessage::Broadcast {
+        room_id: room_id.to_string(),
+        event,
+        store_in_history: false,
+    });
+}
+
+// Send a new room message to every websocket in the room and keep it in the room's cached history
+pub fn broadcast_room_message(room_id: &str, event: WsResponse) {
+    dispatch(BackplaneMessage::Broadcast {
+        room_id: room_id.to_string(),
+        event,
+        store_in_history: true,
+    });
+}
+
+// Drop a room's cached history after a change the cache cannot follow
+pub fn invalidate_room_history(room_id: Uuid) {
+    dispatch(BackplaneMessage::InvalidateHistory {
+        room_id: room_id.to_string(),
+    });
+}
+
+// Overwrite fields of a message kept in a room's recent history
+pub fn update_history_message(room_id: &str, message_id: &str, fields: serde_json::Value) {
+    dispatch(BackplaneMessage::PatchHistory {
+        room_id: room_id.to_string(),
+        message_id: message_id.to_string(),
+        fields,
+    });
+}
+
+// Let connected websockets of the user into a room they just became a member of
+pub fn cache_room_membership(room_id: Uuid, user_id: Uuid) {
+    dispatch(BackplaneMessage::AddMembership {
+        room_id: room_id.to_string(),
+        user_id,
+    });
+}
+
+// Remove a user who left a room from it on every connected websocket
+pub fn forget_room_membership(room_id: Uuid, user_id: Uuid) {
+    dispatch(BackplaneMessage::RemoveMembership {
+        room_id: room_id.to_string(),
+        user_id,
+    });
+}
+
+// Tell a room a member was removed, then drop their connections from it
+pub fn evict_room_member(room_id: Uuid, user_id: Uuid, event: WsResponse) {
+    broadcast_to_room(&room_id.to_string(), event);
+    dispatch(BackplaneMessage::EvictMember {
+        room_id: room_id.to_string(),
+        user_id,
+    });
+}
+
+// Drop a deleted room from the chat server
+pub fn forget_room(room_id: Uuid) {
+    dispatch(BackplaneMessage::RemoveRoom {
+        room_id: room_id.to_string(),
+    });
+}
+
+pub fn set_room_mute(room_id: Uuid, user_id: Uuid, until: Option<DateTime<Utc>>) {
+    dispatch(BackplaneMessage::SetMute {
+        room_id: room_id.to_string(),
+        user_id,
+        until,
+    });
+}
+
+// Close all websockets belonging to a terminated login session
+pub fn close_session_connections(session_id: Uuid) {
+    dispatch(BackplaneMessage::CloseSessionConnections { session_id });
+}
+
+// Presence of the given users across all instances; everyone is offline when the chat server does not answer
+pub async fn user_presence(user_ids: Vec<Uuid>) -> HashMap<Uuid, PresenceStatus> {
+    let Some(server) = chat_server() else {
+        return HashMap::new();
     };
+    match server.send(GetPresence { user_ids }).await {
+        Ok(presence) => presence,
+        Err(e) => {
+            error!("Chat server did not answer a presence request: {}", e);
+            HashMap::new()
+        }
+    }
+}
 
-    #[cfg(debug_assertions)]
-    debug!("Validating token...");
-    let claims = match JwtAuth::validate_token(&token, &jwt_secret) {
-        Ok(claims) => {
-            #[cfg(debug_assertions)]
-            {
-                debug!("Token validated successfully for user: {}", claims.name);
-                debug!("Token structure - sub: {}, backend_user_id: {:?}, email: {}",
-                       claims.sub, claims.backend_user_id, claims.email);
-            }
-            claims
-        },
+// Connection and active room counts, or None when the chat server does not answer
+pub async fn chat_stats() -> Option<ChatStats> {
+    match chat_server()?.send(GetStats).await {
+        Ok(stats) => Some(stats),
         Err(e) => {
-            error!("Token validation failed: {:?}", e);
-            match e.kind() {
-                jsonwebtoken::errors::ErrorKind::ExpiredSignature => {
-                    return Err(actix_web::error::ErrorUnauthorized("Token expired. Please log in again."));
-                }
-                jsonwebtoken::errors::ErrorKind::InvalidSignature => {
-                    return Err(actix_web::error::ErrorUnauthorized("Invalid token signature."));
-                }
-                _ => {
-                    return Err(actix_web::error::ErrorUnauthorized("Invalid token. Please log in again."));
-                }
-            }
+            error!("Chat server did not answer a stats request: {}", e);
+            None
         }
-    };
+    }
+}
 
-    // Parse user ID safely - use backend_user_id instead of sub
-    let user_id = match &claims.backend_user_id {
-        Some(backend_user_id) => {
-            match Uuid::parse_str(backend_user_id) {
-                Ok(id) => {
-                    #[cfg(debug_assertions)]
-                    debug!("User ID parsed successfully from backend_user_id: {}", id);
-                    id
-                },
-                Err(e) => {
-                    error!("Invalid backend_user_id in token: {} - Error: {}", backend_user_id, e);
-                    return Err(actix_web::error::ErrorUnauthorized("Invalid backend_user_id in token"));
-                }
-            }
-        },
-        None => {
-            error!("Missing backend_user_id in token. Sub field contains: {}", claims.sub);
-            return Err(actix_web::error::ErrorUnauthorized("Missing backend_user_id in token"));
+#[get("/ws")]
+pub async fn ws_index(
+    req: HttpRequest,
+    stream: web::Payload,
+    auth_user: AuthUser,
+    db: web::Data<DatabaseConnection>,
+    config: web::Data<AppConfig>,
+) -> Result<HttpResponse, Error> {
+    // Only log in development
+    #[cfg(debug_assertions)]
+    debug!("WebSocket connection attempt - Path: {}", req.path());
+
+    // JwtAuth has already validated the token and its session, so load the current profile
+    let user: UserResponseDto = match User::find_by_id(auth_user.id).one(db.get_ref()).await {
+        Ok(Some(user)) => user.into(),
+        Ok(None) => {
+            error!("User {} from a valid token no longer exists", auth_user.id);
+            return Err(ApiError::Unauthorized("User account no longer exists. Please log in again.".to_string()).into());
+        }
+        Err(e) => {
+            error!("Database error when loading user for WebSocket: {:?}", e);
+            return Err(ApiError::Database(e).into());
         }
     };
 
-    let user = UserResponseDto {
-        id: user_id,
-        email: claims.email,
-        name: claims.name,
-        profile_image: claims.profile_image,
-        provider: claims.provider.unwrap_or_default(),
-        role: claims.user_role.unwrap_or_default(),
-        is_active: claims.is_active.unwrap_or(true),
+    let Some(server) = chat_server() else {
+        return Err(ApiError::Internal("Chat server is not running".to_string()).into());
     };
 
-    // Set database connection in chat server
-    {
-        if let Ok(mut server) = CHAT_SERVER.lock() {
-            server.set_database(db.get_ref().clone());
-        } else {
-            error!("Failed to acquire chat server lock for database setup");
-        }
-    }
-
     info!("Starting WebSocket session for user: {}", user.name);
-    ws::start(ChatSession::new(user), &req, stream)
-}
\ No newline at end of file
+    let session = ChatSession::new(user, auth_user.session_id, server.clone(), config.chat_heartbeat);
+    ws::start(session, &req, stream)
+}
@@ -0,0 +1,243 @@
+use actix_multipart::MultipartError;
+use actix_web::{
+    error::{JsonPayloadError, PathError, QueryPayloadError},
+    http::StatusCode,
+    HttpRequest, HttpResponse, ResponseError,
+};
+use jsonwebtoken::errors::ErrorKind;
+use log::error;
+use sea_orm::DbErr;
+use serde::Serialize;
+
+use crate::api::request_id::current_request_id;
+use crate::auth::RefreshError;
+use crate::storage::StorageError;
+
+/// Error returned by every API handler and middleware
+///
+/// Each variant maps to an HTTP status and a stable machine-readable `code`
+/// that clients can branch on; the human-readable message may change freely.
+#[derive(thiserror::Error, Debug)]
+pub enum ApiError {
+    // Authentication
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("Invalid email or password")]
+    InvalidCredentials,
+    #[error("Token expired")]
+    TokenExpired,
+    #[error("{0}")]
+    InvalidToken(String),
+    #[error("Token has been invalidated")]
+    TokenRevoked,
+    #[error("Session has been terminated")]
+    SessionTerminated,
+    #[error("{0}")]
+    RefreshTokenRejected(String),
+    #[error("Invalid two-factor authentication code")]
+    InvalidTwoFactorCode,
+
+    // Authorization
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("Admin role required for this resource")]
+    AdminRequired,
+    #[error("Account is disabled")]
+    AccountDisabled,
+    #[error("This room requires a password")]
+    RoomPasswordRequired,
+    #[error("Invalid room password")]
+    RoomPasswordInvalid,
+    #[error("{0}")]
+    NotRoomMember(String),
+
+    // Client errors
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Validation(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    PayloadTooLarge(String),
+    #[error("{0}")]
+    UnsupportedMediaType(String),
+    // No endpoint throttles yet, but clients can already handle the code
+    #[allow(dead_code)]
+    #[error("Too many requests, please try again later")]
+    RateLimited,
+    #[error("Invalid multipart payload: {0}")]
+    Multipart(String),
+
+    // Server errors
+    #[error("Database error")]
+    Database(#[from] DbErr),
+    #[error("{0}")]
+    Internal(String),
+}
+
+// JSON body of every error response
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: &'a str,
+    error: String,
+    // Same text as `error`; older clients read the message from this field
+    message: String,
+    request_id: Option<String>,
+}
+
+impl ApiError {
+    /// Stable identifier of the error kind, sent to clients as `code`
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Unauthorized(_) => "AUTH_REQUIRED",
+            ApiError::InvalidCredentials => "AUTH_INVALID_CREDENTIALS",
+            ApiError::TokenExpired => "AUTH_TOKEN_EXPIRED",
+            ApiError::InvalidToken(_) => "AUTH_TOKEN_INVALID",
+            ApiError::TokenRevoked => "AUTH_TOKEN_REVOKED",
+            ApiError::SessionTerminated => "AUTH_SESSION_TERMINATED",
+            ApiError::RefreshTokenRejected(_) => "AUTH_REFRESH_TOKEN_INVALID",
+            ApiError::InvalidTwoFactorCode => "AUTH_2FA_INVALID_CODE",
+            ApiError::Forbidden(_) => "FORBIDDEN",
+            ApiError::AdminRequired => "ADMIN_REQUIRED",
+            ApiError::AccountDisabled => "ACCOUNT_DISABLED",
+            ApiError::RoomPasswordRequired => "ROOM_PASSWORD_REQUIRED",
+            ApiError::RoomPasswordInvalid => "ROOM_PASSWORD_INVALID",
+            ApiError::NotRoomMember(_) => "ROOM_ACCESS_DENIED",
+            ApiError::BadRequest(_) => "BAD_REQUEST",
+            ApiError::Validation(_) => "VALIDATION_FAILED",
+            ApiError::NotFound(_) => "NOT_FOUND",
+            ApiError::Conflict(_) => "CONFLICT",
+            ApiError::PayloadTooLarge(_) => "PAYLOAD_TOO_LARGE",
+            ApiError::UnsupportedMediaType(_) => "UNSUPPORTED_MEDIA_TYPE",
+            ApiError::RateLimited => "RATE_LIMITED",
+            ApiError::Multipart(_) => "INVALID_MULTIPART",
+            ApiError::Database(_) => "DATABASE_ERROR",
+            ApiError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::Unauthorized(_)
+            | ApiError::InvalidCredentials
+            | ApiError::TokenExpired
+            | ApiError::InvalidToken(_)
+            | ApiError::TokenRevoked
+            | ApiError::SessionTerminated
+            | ApiError::RefreshTokenRejected(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_)
+            | ApiError::AdminRequired
+            | ApiError::AccountDisabled
+            | ApiError::RoomPasswordRequired
+            | ApiError::RoomPasswordInvalid
+            | ApiError::NotRoomMember(_) => StatusCode::FORBIDDEN,
+            ApiError::InvalidTwoFactorCode
+            | ApiError::BadRequest(_)
+            | ApiError::Validation(_)
+            | ApiError::Multipart(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Database(_) | ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let request_id = current_request_id();
+
+        // Details of server errors stay in the logs; clients only get the request id
+        if let ApiError::Database(e) = self {
+            error!("Database error (request {}): {:?}", request_id.as_deref().unwrap_or("-"), e);
+        }
+
+        let message = self.to_string();
+
+        HttpResponse::build(self.status_code()).json(ErrorBody {
+            code: self.code(),
+            error: message.clone(),
+            message,
+            request_id,
+        })
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for ApiError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        match e.kind() {
+            ErrorKind::ExpiredSignature => ApiError::TokenExpired,
+            ErrorKind::InvalidSignature => ApiError::InvalidToken("Invalid token signature".to_string()),
+            _ => ApiError::InvalidToken("Invalid token".to_string()),
+        }
+    }
+}
+
+impl From<RefreshError> for ApiError {
+    fn from(e: RefreshError) -> Self {
+        match e {
+            RefreshError::Database(e) => ApiError::Database(e),
+            RefreshError::Token(e) => ApiError::Internal(format!("Failed to generate access token: {}", e)),
+            RefreshError::UserInactive => ApiError::AccountDisabled,
+            e => ApiError::RefreshTokenRejected(e.to_string()),
+        }
+    }
+}
+
+impl From<MultipartError> for ApiError {
+    fn from(e: MultipartError) -> Self {
+        ApiError::Multipart(e.to_string())
+    }
+}
+
+impl From<StorageError> for ApiError {
+    fn from(e: StorageError) -> Self {
+        match e {
+            StorageError::NotFound => ApiError::NotFound("File not found".to_string()),
+            StorageError::InvalidKey(_) => ApiError::BadRequest("Invalid filename".to_string()),
+            e => {
+                error!("Storage error: {}", e);
+                ApiError::Internal("Failed to access file storage".to_string())
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for ApiError {
+    fn from(e: std::io::Error) -> Self {
+        error!("I/O error: {:?}", e);
+        ApiError::Internal("Failed to access file storage".to_string())
+    }
+}
+
+// Extractor errors are rendered through ApiError so malformed requests get the same envelope
+
+pub fn json_error_handler(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    match err {
+        JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => {
+            ApiError::PayloadTooLarge("Request body is too large".to_string()).into()
+        }
+        JsonPayloadError::ContentType => {
+            ApiError::UnsupportedMediaType("Expected a JSON request body".to_string()).into()
+        }
+        e => ApiError::Validation(e.to_string()).into(),
+    }
+}
+
+pub fn query_error_handler(err: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    ApiError::Validation(err.to_string()).into()
+}
+
+pub fn path_error_handler(err: PathError, _req: &HttpRequest) -> actix_web::Error {
+    ApiError::NotFound(err.to_string()).into()
+}
+
+/// Fallback for requests that match no route
+pub async fn not_found() -> Result<HttpResponse, ApiError> {
+    Err(ApiError::NotFound("Resource not found".to_string()))
+}
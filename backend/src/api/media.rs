@@ -0,0 +1,364 @@
+use actix_web::web::{self, Bytes};
+use log::debug;
+
+use crate::api::ApiError;
+
+/// What an upload is meant to be
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MediaKind {
+    Image,
+    Video,
+    Audio,
+}
+
+impl MediaKind {
+    fn unsupported(self) -> ApiError {
+        ApiError::UnsupportedMediaType(
+            match self {
+                MediaKind::Image => "Only JPEG, PNG, GIF and WebP images are allowed",
+                MediaKind::Video => "Only MP4, WebM, and Ogg videos are allowed",
+                MediaKind::Audio => "Only WebM, Ogg, MP4, MP3, WAV, FLAC and AAC audio is allowed",
+            }
+            .to_string(),
+        )
+    }
+}
+
+/// A file format recognised from its leading bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Jpeg,
+    Png,
+    Gif,
+    WebP,
+    // ISO base media file; M4a is its audio-only brand
+    Mp4,
+    M4a,
+    // Matroska with the "webm" doc type, holding audio or video
+    Webm,
+    OggVideo,
+    OggAudio,
+    Mp3,
+    Wav,
+    Flac,
+    Aac,
+}
+
+impl Format {
+    fn kinds(self) -> &'static [MediaKind] {
+        match self {
+            Format::Jpeg | Format::Png | Format::Gif | Format::WebP => &[MediaKind::Image],
+            // Browsers record audio into MP4 and WebM containers too
+            Format::Mp4 | Format::Webm => &[MediaKind::Video, MediaKind::Audio],
+            Format::OggVideo => &[MediaKind::Video],
+            Format::M4a | Format::OggAudio | Format::Mp3 | Format::Wav | Format::Flac | Format::Aac => &[MediaKind::Audio],
+        }
+    }
+
+    // Content types a client may declare for a file of this format
+    fn declared_types(self) -> &'static [&'static str] {
+        match self {
+            Format::Jpeg => &["image/jpeg", "image/jpg", "image/pjpeg"],
+            Format::Png => &["image/png"],
+            Format::Gif => &["image/gif"],
+            Format::WebP => &["image/webp"],
+            Format::Mp4 => &["video/mp4", "audio/mp4", "audio/x-m4a", "audio/m4a"],
+            Format::M4a => &["audio/mp4", "audio/x-m4a", "audio/m4a", "video/mp4"],
+            Format::Webm => &["video/webm", "audio/webm"],
+            Format::OggVideo => &["video/ogg", "application/ogg"],
+            Format::OggAudio => &["audio/ogg", "audio/opus", "application/ogg"],
+            Format::Mp3 => &["audio/mpeg", "audio/mp3"],
+            Format::Wav => &["audio/wav", "audio/x-wav", "audio/wave", "audio/vnd.wave"],
+            Format::Flac => &["audio/flac", "audio/x-flac"],
+            Format::Aac => &["audio/aac", "audio/aacp"],
+        }
+    }
+
+    /// The content type to store and serve a file of this format as
+    pub fn mime_type(self, kind: MediaKind) -> &'static str {
+        match (self, kind) {
+            (Format::Jpeg, _) => "image/jpeg",
+            (Format::Png, _) => "image/png",
+            (Format::Gif, _) => "image/gif",
+            (Format::WebP, _) => "image/webp",
+            (Format::Mp4, MediaKind::Audio) | (Format::M4a, _) => "audio/mp4",
+            (Format::Mp4, _) => "video/mp4",
+            (Format::Webm, MediaKind::Audio) => "audio/webm",
+            (Format::Webm, _) => "video/webm",
+            (Format::OggVideo, _) => "video/ogg",
+            (Format::OggAudio, _) => "audio/ogg",
+            (Format::Mp3, _) => "audio/mpeg",
+            (Format::Wav, _) => "audio/wav",
+            (Format::Flac, _) => "audio/flac",
+            (Format::Aac, _) => "audio/aac",
+        }
+    }
+
+    /// File extension for stored files of this format
+    pub fn extension(self, kind: MediaKind) -> &'static str {
+        match (self, kind) {
+            (Format::Jpeg, _) => "jpg",
+            (Format::Png, _) => "png",
+            (Format::Gif, _) => "gif",
+            (Format::WebP, _) => "webp",
+            (Format::Mp4, MediaKind::Audio) | (Format::M4a, _) => "m4a",
+            (Format::Mp4, _) => "mp4",
+            (Format::Webm, _) => "webm",
+            (Format::OggVideo, _) => "ogv",
+            (Format::OggAudio, _) => "ogg",
+            (Format::Mp3, _) => "mp3",
+            (Format::Wav, _) => "wav",
+            (Format::Flac, _) => "flac",
+            (Format::Aac, _) => "aac",
+        }
+    }
+
+    /// The decoder for an image format
+    pub fn image_format(self) -> Option<image::ImageFormat> {
+        match self {
+            Format::Jpeg => Some(image::ImageFormat::Jpeg),
+            Format::Png => Some(image::ImageFormat::Png),
+            Format::Gif => Some(image::ImageFormat::Gif),
+            Format::WebP => Some(image::ImageFormat::WebP),
+            _ => None,
+        }
+    }
+}
+
+// Major brands of the MP4 files accepted; other ISO base media files such as HEIC,
+// AVIF and QuickTime movies share the ftyp box but are not MP4
+const MP4_BRANDS: &[&[u8]] = &[
+    b"isom", b"iso2", b"iso3", b"iso4", b"iso5", b"iso6", b"mp41", b"mp42", b"avc1", b"dash", b"M4V ",
+];
+
+// Major brands of audio-only MP4 files
+const M4A_BRANDS: &[&[u8]] = &[b"M4A ", b"M4B "];
+
+// Whether `needle` occurs in the first `limit` bytes of `data`
+fn contains_within(data: &[u8], needle: &[u8], limit: usize) -> bool {
+    data[..data.len().min(limit)].windows(needle.len()).any(|window| window == needle)
+}
+
+/// Recognise a file from its magic bytes
+pub fn sniff(data: &[u8]) -> Option<Format> {
+    let starts = |magic: &[u8]| data.starts_with(magic);
+    let at = |offset: usize, magic: &[u8]| data.get(offset..offset + magic.len()) == Some(magic);
+
+    if starts(&[0xFF, 0xD8, 0xFF]) {
+        Some(Format::Jpeg)
+    } else if starts(b"\x89PNG\r\n\x1a\n") {
+        Some(Format::Png)
+    } else if starts(b"GIF87a") || starts(b"GIF89a") {
+        Some(Format::Gif)
+    } else if starts(b"RIFF") && at(8, b"WEBP") {
+        Some(Format::WebP)
+    } else if starts(b"RIFF") && at(8, b"WAVE") {
+        Some(Format::Wav)
+    } else if at(4, b"ftyp") {
+        match data.get(8..12) {
+            Some(brand) if M4A_BRANDS.contains(&brand) => Some(Format::M4a),
+            Some(brand) if MP4_BRANDS.contains(&brand) => Some(Format::Mp4),
+            _ => None,
+        }
+    } else if starts(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        // The doc type is near the start of the EBML header; plain Matroska is not accepted
+        contains_within(data, b"webm", 64).then_some(Format::Webm)
+    } else if starts(b"OggS") {
+        // The first page carries the identification header of the first stream
+        if contains_within(data, b"\x80theora", 64) {
+            Some(Format::OggVideo)
+        } else if contains_within(data, b"OpusHead", 64) || contains_within(data, b"\x01vorbis", 64) {
+            Some(Format::OggAudio)
+        } else {
+            None
+        }
+    } else if starts(b"fLaC") {
+        Some(Format::Flac)
+    } else if starts(b"ID3") || (data.len() > 1 && data[0] == 0xFF && data[1] & 0xE6 == 0xE2) {
+        // An ID3 tag, or an MPEG audio layer III frame sync
+        Some(Format::Mp3)
+    } else if data.len() > 1 && data[0] == 0xFF && data[1] & 0xF6 == 0xF0 {
+        // ADTS frame sync
+        Some(Format::Aac)
+    } else {
+        None
+    }
+}
+
+/// Check an upload's format against what the client declared, without decoding it
+///
+/// The file must be a supported format of `kind`, and the content type the client declared
+/// must fit that format; `application/octet-stream` counts as undeclared.
+pub fn check_format(data: &[u8], declared: &str, kind: MediaKind) -> Result<Format, ApiError> {
+    let format = match sniff(data) {
+        Some(format) if format.kinds().contains(&kind) => format,
+        _ => return Err(kind.unsupported()),
+    };
+
+    // Parameters such as "; codecs=opus" do not change the type
+    let declared = declared.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    if declared != "application/octet-stream" && !format.declared_types().contains(&declared.as_str()) {
+        debug!("Rejected upload declared as {} but sniffed as {:?}", declared, format);
+        return Err(ApiError::UnsupportedMediaType(format!(
+            "File content does not match its declared type {}",
+            declared
+        )));
+    }
+
+    Ok(format)
+}
+
+/// Check an upload against what its content actually is
+///
+/// Besides passing [`check_format`], images must decode.
+pub async fn validate_upload(data: &Bytes, declared: &str, kind: MediaKind) -> Result<Format, ApiError> {
+    let format = check_format(data, declared, kind)?;
+
+    if let Some(image_format) = format.image_format() {
+        // Decoding is CPU-bound, so keep it off the async workers
+        let data = data.clone();
+        let decoded = web::block(move || image::load_from_memory_with_format(&data, image_format).map(|_| ()))
+            .await
+            .map_err(|e| ApiError::Internal(format!("Failed to check image: {}", e)))?;
+        if let Err(e) = decoded {
+            debug!("Rejected {:?} image that does not decode: {}", format, e);
+            return Err(ApiError::UnsupportedMediaType("Image could not be decoded".to_string()));
+        }
+    }
+
+    Ok(format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The start of an ISO base media file with the given major brand
+    fn ftyp(brand: &[u8; 4]) -> Vec<u8> {
+        let mut data = vec![0, 0, 0, 0x18];
+        data.extend_from_slice(b"ftyp");
+        data.extend_from_slice(brand);
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data
+    }
+
+    // An EBML header with the given doc type
+    fn ebml(doc_type: &[u8]) -> Vec<u8> {
+        let mut data = vec![0x1A, 0x45, 0xDF, 0xA3, 0x9F, 0x42, 0x86, 0x81, 0x01, 0x42, 0x82, 0x84];
+        data.extend_from_slice(doc_type);
+        data
+    }
+
+    // The first page of an Ogg stream whose identification header starts with `header`
+    fn ogg(header: &[u8]) -> Vec<u8> {
+        let mut data = b"OggS\0\x02".to_vec();
+        data.extend_from_slice(&[0; 22]);
+        data.extend_from_slice(header);
+        data
+    }
+
+    #[test]
+    fn sniffs_every_supported_signature() {
+        let cases: Vec<(Vec<u8>, Format)> = vec![
+            (vec![0xFF, 0xD8, 0xFF, 0xE0], Format::Jpeg),
+            (b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec(), Format::Png),
+            (b"GIF87a\x01\0\x01\0".to_vec(), Format::Gif),
+            (b"GIF89a\x01\0\x01\0".to_vec(), Format::Gif),
+            (b"RIFF\x24\0\0\0WEBPVP8 ".to_vec(), Format::WebP),
+            (b"RIFF\x24\0\0\0WAVEfmt ".to_vec(), Format::Wav),
+            (ftyp(b"M4A "), Format::M4a),
+            (ftyp(b"M4B "), Format::M4a),
+            (ebml(b"webm"), Format::Webm),
+            (ogg(b"\x80theora"), Format::OggVideo),
+            (ogg(b"OpusHead"), Format::OggAudio),
+            (ogg(b"\x01vorbis"), Format::OggAudio),
+            (b"fLaC\0\0\0\x22".to_vec(), Format::Flac),
+            (b"ID3\x04\0\0".to_vec(), Format::Mp3),
+            (vec![0xFF, 0xFB, 0x90, 0x64], Format::Mp3),
+            (vec![0xFF, 0xF1, 0x50, 0x80], Format::Aac),
+        ];
+        for (data, format) in cases {
+            assert_eq!(sniff(&data), Some(format), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn accepts_only_mp4_brands_of_iso_base_media() {
+        for brand in MP4_BRANDS {
+            let brand: &[u8; 4] = (*brand).try_into().unwrap();
+            assert_eq!(sniff(&ftyp(brand)), Some(Format::Mp4), "{:?}", brand);
+        }
+        // HEIC and AVIF images and QuickTime movies
+        for brand in [b"heic", b"mif1", b"avif", b"qt  "] {
+            assert_eq!(sniff(&ftyp(brand)), None, "{:?}", brand);
+        }
+    }
+
+    #[test]
+    fn rejects_containers_without_a_supported_stream() {
+        assert_eq!(sniff(&ebml(b"matroska")), None);
+        assert_eq!(sniff(&ogg(b"\x80skeleton")), None);
+    }
+
+    #[test]
+    fn rejects_truncated_and_garbage_input() {
+        let cases: [&[u8]; 8] = [
+            b"",
+            &[0xFF],
+            &[0xFF, 0xD8],
+            b"\x89PNG",
+            b"RIFF\x24\0\0\0",
+            b"\0\0\0\x18ftyp",
+            b"\0\0\0\x18ftypis",
+            b"<!DOCTYPE html><html></html>",
+        ];
+        for data in cases {
+            assert_eq!(sniff(data), None, "{:?}", data);
+            assert!(matches!(
+                check_format(data, "application/octet-stream", MediaKind::Image),
+                Err(ApiError::UnsupportedMediaType(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn accepts_declared_types_that_fit_the_format() {
+        let png = b"\x89PNG\r\n\x1a\n";
+        assert_eq!(check_format(png, "image/png", MediaKind::Image).unwrap(), Format::Png);
+        assert_eq!(check_format(png, "IMAGE/PNG", MediaKind::Image).unwrap(), Format::Png);
+
+        let webm = ebml(b"webm");
+        assert_eq!(check_format(&webm, "audio/webm; codecs=opus", MediaKind::Audio).unwrap(), Format::Webm);
+        assert_eq!(check_format(&webm, "video/webm", MediaKind::Video).unwrap(), Format::Webm);
+    }
+
+    #[test]
+    fn octet_stream_counts_as_undeclared() {
+        let gif = b"GIF89a\x01\0\x01\0";
+        assert_eq!(check_format(gif, "application/octet-stream", MediaKind::Image).unwrap(), Format::Gif);
+        assert_eq!(check_format(&ftyp(b"isom"), "application/octet-stream", MediaKind::Video).unwrap(), Format::Mp4);
+    }
+
+    #[test]
+    fn rejects_a_declared_type_that_does_not_match_the_content() {
+        let png = b"\x89PNG\r\n\x1a\n";
+        for declared in ["image/jpeg", "image/svg+xml", "text/html", ""] {
+            assert!(matches!(
+                check_format(png, declared, MediaKind::Image),
+                Err(ApiError::UnsupportedMediaType(_))
+            ));
+        }
+        assert!(matches!(
+            check_format(&ftyp(b"mp42"), "image/png", MediaKind::Video),
+            Err(ApiError::UnsupportedMediaType(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_formats_of_another_kind() {
+        let png = b"\x89PNG\r\n\x1a\n";
+        assert!(check_format(png, "image/png", MediaKind::Video).is_err());
+        assert!(check_format(&ogg(b"OpusHead"), "audio/ogg", MediaKind::Video).is_err());
+        assert!(check_format(&ftyp(b"M4A "), "audio/mp4", MediaKind::Video).is_err());
+    }
+}
@@ -3,5 +3,10 @@ pub mod user;
 pub mod basic;
 pub mod admin;
 pub mod chat;
+pub mod error;
+pub mod media;
+pub mod request_id;
 
 // Re-export all API handlers for easier access
+pub use error::ApiError;
+pub use request_id::RequestIdMiddleware;
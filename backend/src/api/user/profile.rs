@@ -1,263 +1,75 @@
-use actix_web::{web, post, HttpResponse, Responder, HttpRequest, http::header};
+use actix_web::{web, post, HttpRequest, HttpResponse};
+use actix_web::web::Bytes;
 use actix_multipart::Multipart;
-use actix_files::NamedFile;
 use sea_orm::{DatabaseConnection, EntityTrait, Set, ActiveModelTrait};
 use futures::{StreamExt, TryStreamExt};
-use std::io::Write;
 use std::path::Path;
-use std::fs::{self, File};
-use std::env;
 use uuid::Uuid;
-use log::{debug, error, info, warn};
-use jsonwebtoken::{decode, DecodingKey, Validation, errors::ErrorKind};
+use log::{debug, error, info};
 
-use crate::auth::Claims;
-use crate::api::auth::is_token_blacklisted;
+use crate::auth::AuthUser;
 use crate::models::entities::{User, UserActiveModel};
+use crate::api::ApiError;
+use crate::api::media::{validate_upload, MediaKind};
+use crate::config::AppConfig;
+use crate::metrics;
+use crate::storage::{self, object_key, Caching, Storage, StorageError, PROFILE_PICTURES};
 
 // Constants for file upload
-const UPLOAD_DIR: &str = "uploads/profile_pictures";
 const MAX_FILE_SIZE: usize = 5 * 1024 * 1024; // 5MB
-const ALLOWED_TYPES: [&str; 3] = ["image/jpeg", "image/png", "image/gif"];
 
-#[post("/api/user/profile/upload")]
+#[post("/profile/upload")]
 pub async fn upload_profile_picture(
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
-    jwt_secret: web::Data<String>,
+    config: web::Data<AppConfig>,
+    storage: web::Data<dyn Storage>,
+    auth_user: AuthUser,
     mut payload: Multipart,
-) -> impl Responder {
-    // Try to extract the token from the secure `HttpOnly` cookie first.
-    let mut token_str: Option<String> = None;
-
-    // 1. Try to extract the token from the secure `HttpOnly` cookie first.
-    if let Some(cookie) = req.cookie("auth_token") {
-        debug!("Found 'auth_token' cookie for profile upload endpoint");
-        token_str = Some(cookie.value().to_string());
-    }
-
-    // 2. If no cookie is found, fall back to checking the Authorization header.
-    if token_str.is_none() {
-        debug!("No 'auth_token' cookie, checking Authorization header for profile upload endpoint");
-        if let Some(auth_header) = req.headers().get(header::AUTHORIZATION) {
-            if let Ok(auth_str) = auth_header.to_str() {
-                if auth_str.starts_with("Bearer ") {
-                    token_str = Some(auth_str[7..].to_string());
-                }
-            }
-        }
-    }
-
-    // 3. If no token was found in either place, return an Unauthorized error.
-    let token = match token_str {
-        Some(t) => t,
-        None => {
-            warn!("Authentication token not found for profile upload endpoint");
-            return HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Unauthorized",
-                "message": "Authentication token is missing"
-            }));
-        }
-    };
-
-    // Check if token is blacklisted
-    if is_token_blacklisted(&token) {
-        warn!("Token is blacklisted");
-        return HttpResponse::Unauthorized().json(
-            serde_json::json!({
-                "error": "Unauthorized",
-                "message": "Token has been invalidated"
-            })
-        );
-    }
-
-    // Configure validation to explicitly check expiration
-    let mut validation = Validation::default();
-    validation.validate_exp = true;
-    validation.leeway = 0; // No leeway for expiration time
-
-    // Validate the token
-    let token_data = match decode::<Claims>(
-        &token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
-        &validation,
-    ) {
-        Ok(data) => data,
-        Err(e) => {
-            match e.kind() {
-                ErrorKind::ExpiredSignature => {
-                    warn!("Token expired");
-                    return HttpResponse::Unauthorized().json(
-                        serde_json::json!({
-                            "error": "Unauthorized",
-                            "message": "Token expired"
-                        })
-                    );
-                }
-                ErrorKind::InvalidSignature => {
-                    warn!("Invalid token signature");
-                    return HttpResponse::Unauthorized().json(
-                        serde_json::json!({
-                            "error": "Unauthorized",
-                            "message": "Invalid token signature"
-                        })
-                    );
-                }
-                _ => {
-                    warn!("Invalid token: {:?}", e);
-                    return HttpResponse::Unauthorized().json(
-                        serde_json::json!({
-                            "error": "Unauthorized",
-                            "message": "Invalid token"
-                        })
-                    );
-                }
-            }
-        }
-    };
-
-    let claims = token_data.claims;
-
-    // Ensure backend_user_id is present
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            warn!("Missing backend_user_id in token");
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({
-                    "error": "Unauthorized",
-                    "message": "Missing user ID in token"
-                })
-            );
-        }
-    };
-
-    let user_id = match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => id,
-        Err(e) => {
-            warn!("Invalid backend_user_id format in token: {:?}", e);
-            return HttpResponse::Unauthorized().json(
-                serde_json::json!({
-                    "error": "Unauthorized",
-                    "message": "Invalid user ID format in token"
-                })
-            );
-        }
-    };
+) -> Result<HttpResponse, ApiError> {
+    let user_id = auth_user.id;
     
     debug!("Uploading profile picture for user ID: {}", user_id);
     
-    // Ensure upload directory exists
-    if let Err(e) = fs::create_dir_all(UPLOAD_DIR) {
-        error!("Failed to create upload directory: {:?}", e);
-        return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Server error",
-            "message": "Failed to create upload directory"
-        }));
-    }
-    
     // Process the multipart form
     while let Ok(Some(mut field)) = payload.try_next().await {
         // Check if this is the file field
         if field.name() == Some("file") {
-            // Get content type and check if it's allowed
-            let content_disposition = field.content_disposition();
-            let content_type = field.content_type();
+            // Get the declared content type; it is checked against the file's content below
+            let content_type_str = field.content_type().map(|ct| ct.to_string()).unwrap_or_else(|| "application/octet-stream".to_string());
             
-            // Get the content type string
-            let content_type_str = content_type.map(|ct| ct.to_string()).unwrap_or_else(|| "application/octet-stream".to_string());
-            
-            if !ALLOWED_TYPES.contains(&content_type_str.as_str()) {
-                return HttpResponse::BadRequest().json(serde_json::json!({
-                    "error": "Invalid file type",
-                    "message": "Only JPEG, PNG, and GIF images are allowed"
-                }));
-            }
-            
-            // Generate a unique filename
-            let file_ext = match content_type_str.as_str() {
-                "image/jpeg" => "jpg",
-                "image/png" => "png",
-                "image/gif" => "gif",
-                "image/webp" => "webp",
-                "image/svg+xml" => "svg",
-                _ => "bin", // Should not happen due to check above
-            };
-            
-            // Use UUID for filename to prevent path traversal attacks
-            let uuid = Uuid::new_v4();
-            let filename = format!("{}.{}", uuid, file_ext);
-            
-            // Ensure the filepath is properly constructed and sanitized
-            let filepath = format!("{}/{}", UPLOAD_DIR, filename);
-            
-            // Create the file
-            let mut file = match File::create(&filepath) {
-                Ok(file) => file,
-                Err(e) => {
-                    error!("Failed to create file: {:?}", e);
-                    return HttpResponse::InternalServerError().json(serde_json::json!({
-                        "error": "Server error",
-                        "message": "Failed to create file"
-                    }));
-                }
-            };
-            
-            // Write the file data
-            let mut size: usize = 0;
+            // Read the file data, nothing is stored until it is complete
+            let mut data = Vec::new();
             while let Some(chunk) = field.next().await {
-                let data = match chunk {
-                    Ok(data) => data,
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
                     Err(e) => {
                         error!("Error reading chunk: {:?}", e);
-                        // Clean up the partial file
-                        if let Err(cleanup_err) = fs::remove_file(&filepath) {
-                            error!("Failed to clean up partial file after error: {:?}", cleanup_err);
-                        }
-                        return HttpResponse::InternalServerError().json(serde_json::json!({
-                            "error": "Server error",
-                            "message": "Failed to read uploaded file"
-                        }));
+                        return Err(e.into());
                     }
                 };
                 
                 // Check file size limit
-                size += data.len();
-                if size > MAX_FILE_SIZE {
-                    // Delete the partial file
-                    if let Err(cleanup_err) = fs::remove_file(&filepath) {
-                        error!("Failed to clean up oversized file: {:?}", cleanup_err);
-                    } else {
-                        debug!("Successfully cleaned up oversized file: {}", filepath);
-                    }
-                    
-                    return HttpResponse::BadRequest().json(serde_json::json!({
-                        "error": "File too large",
-                        "message": "Maximum file size is 5MB"
-                    }));
-                }
-                
-                // Write the chunk to the file
-                if let Err(e) = file.write_all(&data) {
-                    error!("Error writing to file: {:?}", e);
-                    
-                    // Close the file handle before attempting to remove it
-                    drop(file);
-                    
-                    // Clean up the partial file
-                    if let Err(cleanup_err) = fs::remove_file(&filepath) {
-                        error!("Failed to clean up partial file after write error: {:?}", cleanup_err);
-                    } else {
-                        debug!("Successfully cleaned up partial file after write error: {}", filepath);
-                    }
-                    
-                    return HttpResponse::InternalServerError().json(serde_json::json!({
-                        "error": "Server error",
-                        "message": "Failed to write to file"
-                    }));
+                if data.len() + chunk.len() > MAX_FILE_SIZE {
+                    return Err(ApiError::PayloadTooLarge("Maximum file size is 5MB".to_string()));
                 }
+                data.extend_from_slice(&chunk);
+            }
+            
+            if data.is_empty() {
+                return Err(ApiError::BadRequest("Uploaded file is empty".to_string()));
             }
             
+            // The file's own bytes decide its format and extension
+            let data = Bytes::from(data);
+            let format = validate_upload(&data, &content_type_str, MediaKind::Image).await?;
+            
+            // Use UUID for filename to prevent path traversal attacks
+            let uuid = Uuid::new_v4();
+            let filename = format!("{}.{}", uuid, format.extension(MediaKind::Image));
+            
+            let size = data.len();
+            storage.put(&object_key(PROFILE_PICTURES, &filename)?, data, format.mime_type(MediaKind::Image)).await?;
+            
             // Update the user's profile_image field
             let user_result = User::find_by_id(user_id)
                 .one(db.get_ref())
@@ -265,19 +77,8 @@ pub async fn upload_profile_picture(
             
             match user_result {
                 Ok(Some(user)) => {
-                    // Create the URL path for the profile image
-                    // Get the backend URL from the environment variable or use a default
-                    // Try backend-specific env var first, then fallback to frontend env var
-                    let backend_url = env::var("API_URL").or_else(|_| env::var("BACKEND_URL")).unwrap_or_else(|_| {
-                        // Fallback to frontend env var if backend-specific ones are not set
-                        env::var("NEXT_PUBLIC_API_URL").unwrap_or_else(|_| {
-                            warn!("No API URL environment variables set (API_URL, BACKEND_URL, NEXT_PUBLIC_API_URL), using default value");
-                            "http://localhost:8080".to_string()
-                        })
-                    });
-                    
-                    // Construct the full URL including the backend server
-                    let profile_image_url = format!("{}/api/user/profile/image/{}", backend_url, filename);
+                    // Create the URL for the profile image, served by this backend
+                    let profile_image_url = format!("{}/api/user/profile/image/{}", config.public_api_url, filename);
                     
                     // Update the user model
                     let mut user_active: UserActiveModel = user.into();
@@ -286,64 +87,55 @@ pub async fn upload_profile_picture(
                     match user_active.update(db.get_ref()).await {
                         Ok(_) => {
                             info!("Updated profile picture for user {}", user_id);
-                            return HttpResponse::Ok().json(serde_json::json!({
+                            metrics::record_upload("profile_image", size);
+                            return Ok(HttpResponse::Ok().json(serde_json::json!({
                                 "success": true,
                                 "profile_image": profile_image_url
-                            }));
+                            })));
                         }
                         Err(e) => {
                             error!("Failed to update user profile: {:?}", e);
-                            return HttpResponse::InternalServerError().json(serde_json::json!({
-                                "error": "Database error",
-                                "message": "Failed to update user profile"
-                            }));
+                            return Err(ApiError::Database(e));
                         }
                     }
                 }
                 Ok(None) => {
                     error!("User not found: {}", user_id);
-                    return HttpResponse::NotFound().json(serde_json::json!({
-                        "error": "User not found"
-                    }));
+                    return Err(ApiError::NotFound("User not found".to_string()));
                 }
                 Err(e) => {
                     error!("Database error when fetching user {}: {:?}", user_id, e);
-                    return HttpResponse::InternalServerError().json(serde_json::json!({
-                        "error": "Database error",
-                        "message": "Failed to retrieve user information"
-                    }));
+                    return Err(ApiError::Database(e));
                 }
             }
         }
     }
     
     // If we get here, no file was uploaded
-    HttpResponse::BadRequest().json(serde_json::json!({
-        "error": "No file uploaded",
-        "message": "Please provide a file"
-    }))
+    Err(ApiError::BadRequest("Please provide a file".to_string()))
 }
 
-#[actix_web::get("/api/user/profile/image/{filename}")]
+#[actix_web::get("/profile/image/{filename}")]
 pub async fn get_profile_image(
-    path: web::Path<String>,
     req: HttpRequest,
-) -> impl Responder {
+    path: web::Path<String>,
+    storage: web::Data<dyn Storage>,
+) -> Result<HttpResponse, ApiError> {
     let filename = path.into_inner();
-    let path = format!("{}/{}", UPLOAD_DIR, filename);
+    let key = object_key(PROFILE_PICTURES, &filename)?;
     
-    // Check if file exists
-    if !Path::new(&path).exists() {
-        return HttpResponse::NotFound().json(serde_json::json!({
-            "error": "File not found"
-        }));
-    }
+    let content_type = match Path::new(&key).extension().and_then(|ext| ext.to_str()) {
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    };
     
-    // Serve the file
-    match NamedFile::open(path) {
-        Ok(file) => file.into_response(&req),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to open file"
-        })),
+    // Serve the file, or redirect to it; profile pictures are shown to everyone
+    match storage::serve(&req, storage.get_ref(), &key, content_type, Caching::Public).await {
+        Ok(response) => Ok(response),
+        Err(StorageError::NotFound) => Err(ApiError::NotFound("File not found".to_string())),
+        Err(_) => Err(ApiError::Internal("Failed to open file".to_string())),
     }
-}
\ No newline at end of file
+}
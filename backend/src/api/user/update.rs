@@ -1,17 +1,15 @@
-use actix_web::{put, web, HttpResponse, Responder, HttpRequest, http::header};
-use sea_orm::{DatabaseConnection, EntityTrait, Set, ActiveModelTrait, QueryFilter, ColumnTrait};
+use actix_web::{put, web, HttpResponse};
+use sea_orm::{DatabaseConnection, EntityTrait, Set, ActiveModelTrait};
 use serde::{Deserialize, Serialize};
-use log::{debug, error, warn};
-use jsonwebtoken::{decode, DecodingKey, Validation, errors::ErrorKind};
-use uuid::Uuid;
+use log::error;
 use argon2::{password_hash::{
     rand_core::OsRng,
     PasswordHasher, SaltString
 }, Argon2, PasswordVerifier};
 
-use crate::auth::Claims;
-use crate::api::auth::is_token_blacklisted;
-use crate::models::entities::{User, UserActiveModel, user::Column};
+use crate::auth::AuthUser;
+use crate::models::entities::{User, UserActiveModel};
+use crate::api::ApiError;
 
 #[derive(Deserialize)]
 pub struct UpdateUsernameRequest {
@@ -30,109 +28,18 @@ pub struct UpdateResponse {
     message: String,
 }
 
-// Extract user ID from token
-async fn extract_user_id(req: &HttpRequest) -> Result<Uuid, HttpResponse> {
-    // Try to extract the token from the secure `HttpOnly` cookie first.
-    let mut token_str: Option<String> = None;
-    
-    if let Some(cookie) = req.cookie("auth_token") {
-        debug!("Found 'auth_token' cookie");
-        token_str = Some(cookie.value().to_string());
-    }
-
-    // If no cookie is found, fall back to checking the Authorization header.
-    if token_str.is_none() {
-        debug!("No 'auth_token' cookie, checking Authorization header");
-        if let Some(auth_header) = req.headers().get(header::AUTHORIZATION) {
-            if let Ok(auth_str) = auth_header.to_str() {
-                if auth_str.starts_with("Bearer ") {
-                    token_str = Some(auth_str[7..].to_string());
-                }
-            }
-        }
-    }
-
-    // If no token was found in either place, return an Unauthorized error.
-    let token = match token_str {
-        Some(t) => t,
-        None => {
-            warn!("Authentication token not found");
-            return Err(HttpResponse::Unauthorized().json(serde_json::json!({
-                "success": false,
-                "message": "Authentication token is missing"
-            })));
-        }
-    };
-
-    if is_token_blacklisted(&token) {
-        warn!("Blacklisted token used");
-        return Err(HttpResponse::Unauthorized().json(serde_json::json!({
-            "success": false,
-            "message": "Token has been invalidated"
-        })));
-    }
-
-    let jwt_secret = std::env::var("NEXTAUTH_SECRET").expect("NEXTAUTH_SECRET must be set");
-    let token_data = match decode::<Claims>(
-        &token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
-        &Validation::default(),
-    ) {
-        Ok(data) => data,
-        Err(e) => match *e.kind() {
-            ErrorKind::ExpiredSignature => {
-                return Err(HttpResponse::Unauthorized().json(serde_json::json!({
-                    "success": false,
-                    "message": "Token has expired"
-                })));
-            }
-            _ => {
-                warn!("Invalid token: {:?}", e);
-                return Err(HttpResponse::Unauthorized().json(serde_json::json!({
-                    "success": false,
-                    "message": "Invalid token"
-                })));
-            }
-        },
-    };
-
-    let claims = token_data.claims;
-    let user_id_str = match claims.backend_user_id {
-        Some(id) => id,
-        None => return Err(HttpResponse::Unauthorized().json(serde_json::json!({
-            "success": false,
-            "message": "Token missing user ID"
-        }))),
-    };
-
-    match Uuid::parse_str(&user_id_str) {
-        Ok(id) => Ok(id),
-        Err(_) => Err(HttpResponse::Unauthorized().json(serde_json::json!({
-            "success": false,
-            "message": "Invalid user ID format"
-        }))),
-    }
-}
-
-#[put("/api/user/update-username")]
+#[put("/update-username")]
 pub async fn update_username(
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
+    auth_user: AuthUser,
     update_req: web::Json<UpdateUsernameRequest>,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
     // Validate username
     if update_req.name.trim().is_empty() {
-        return HttpResponse::BadRequest().json(UpdateResponse {
-            success: false,
-            message: "Username cannot be empty".to_string(),
-        });
+        return Err(ApiError::Validation("Username cannot be empty".to_string()));
     }
 
-    // Extract user ID from token
-    let user_id = match extract_user_id(&req).await {
-        Ok(id) => id,
-        Err(response) => return response,
-    };
+    let user_id = auth_user.id;
 
     // Find the user
     match User::find_by_id(user_id).one(db.as_ref()).await {
@@ -143,56 +50,40 @@ pub async fn update_username(
 
             match user_active.update(db.as_ref()).await {
                 Ok(_) => {
-                    HttpResponse::Ok().json(UpdateResponse {
+                    Ok(HttpResponse::Ok().json(UpdateResponse {
                         success: true,
                         message: "Username updated successfully".to_string(),
-                    })
+                    }))
                 }
                 Err(e) => {
                     error!("Database error updating username: {:?}", e);
-                    HttpResponse::InternalServerError().json(UpdateResponse {
-                        success: false,
-                        message: "Failed to update username".to_string(),
-                    })
+                    Err(ApiError::Database(e))
                 }
             }
         }
         Ok(None) => {
             error!("User with ID {} not found in DB", user_id);
-            HttpResponse::NotFound().json(UpdateResponse {
-                success: false,
-                message: "User not found".to_string(),
-            })
+            Err(ApiError::NotFound("User not found".to_string()))
         }
         Err(e) => {
             error!("Database error fetching user {}: {:?}", user_id, e);
-            HttpResponse::InternalServerError().json(UpdateResponse {
-                success: false,
-                message: "Database error".to_string(),
-            })
+            Err(ApiError::Database(e))
         }
     }
 }
 
-#[put("/api/user/update-password")]
+#[put("/update-password")]
 pub async fn update_password(
     db: web::Data<DatabaseConnection>,
-    req: HttpRequest,
+    auth_user: AuthUser,
     update_req: web::Json<UpdatePasswordRequest>,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
     // Validate password
     if update_req.new_password.len() < 8 {
-        return HttpResponse::BadRequest().json(UpdateResponse {
-            success: false,
-            message: "Password must be at least 8 characters long".to_string(),
-        });
+        return Err(ApiError::Validation("Password must be at least 8 characters long".to_string()));
     }
 
-    // Extract user ID from token
-    let user_id = match extract_user_id(&req).await {
-        Ok(id) => id,
-        Err(response) => return response,
-    };
+    let user_id = auth_user.id;
 
     // Find the user
     match User::find_by_id(user_id).one(db.as_ref()).await {
@@ -202,10 +93,7 @@ pub async fn update_password(
             let password_hash = match user.password_hash {
                 Some(ref hash) => hash,
                 None => {
-                    return HttpResponse::BadRequest().json(UpdateResponse {
-                        success: false,
-                        message: "Cannot update password for OAuth users".to_string(),
-                    });
+                    return Err(ApiError::BadRequest("Cannot update password for OAuth users".to_string()));
                 }
             };
 
@@ -216,10 +104,7 @@ pub async fn update_password(
                 .is_ok();
 
             if !is_valid {
-                return HttpResponse::BadRequest().json(UpdateResponse {
-                    success: false,
-                    message: "Current password is incorrect".to_string(),
-                });
+                return Err(ApiError::BadRequest("Current password is incorrect".to_string()));
             }
 
             // Hash the new password
@@ -228,10 +113,7 @@ pub async fn update_password(
                 Ok(hash) => hash.to_string(),
                 Err(e) => {
                     error!("Error hashing password: {:?}", e);
-                    return HttpResponse::InternalServerError().json(UpdateResponse {
-                        success: false,
-                        message: "Error processing password".to_string(),
-                    });
+                    return Err(ApiError::Internal("Error processing password".to_string()));
                 }
             };
 
@@ -241,33 +123,24 @@ pub async fn update_password(
 
             match user_active.update(db.as_ref()).await {
                 Ok(_) => {
-                    HttpResponse::Ok().json(UpdateResponse {
+                    Ok(HttpResponse::Ok().json(UpdateResponse {
                         success: true,
                         message: "Password updated successfully".to_string(),
-                    })
+                    }))
                 }
                 Err(e) => {
                     error!("Database error updating password: {:?}", e);
-                    HttpResponse::InternalServerError().json(UpdateResponse {
-                        success: false,
-                        message: "Failed to update password".to_string(),
-                    })
+                    Err(ApiError::Database(e))
                 }
             }
         }
         Ok(None) => {
             error!("User with ID {} not found in DB", user_id);
-            HttpResponse::NotFound().json(UpdateResponse {
-                success: false,
-                message: "User not found".to_string(),
-            })
+            Err(ApiError::NotFound("User not found".to_string()))
         }
         Err(e) => {
             error!("Database error fetching user {}: {:?}", user_id, e);
-            HttpResponse::InternalServerError().json(UpdateResponse {
-                success: false,
-                message: "Database error".to_string(),
-            })
+            Err(ApiError::Database(e))
         }
     }
 }
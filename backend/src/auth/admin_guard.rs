@@ -1,12 +1,13 @@
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    error::ErrorForbidden,
     Error, HttpMessage,
 };
 use futures::future::{ready, LocalBoxFuture, Ready};
 use log::{debug, warn};
 
+use crate::api::ApiError;
 use crate::auth::AuthUser;
+use std::rc::Rc;
 
 // Admin guard middleware factory
 pub struct AdminGuard;
@@ -20,7 +21,7 @@ impl AdminGuard {
 // Middleware factory implementation
 impl<S, B> Transform<S, ServiceRequest> for AdminGuard
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static + Clone,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     B: 'static,
 {
     type Response = ServiceResponse<B>;
@@ -30,18 +31,18 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(AdminGuardMiddleware { service }))
+        ready(Ok(AdminGuardMiddleware { service: Rc::new(service) }))
     }
 }
 
 // Middleware service
 pub struct AdminGuardMiddleware<S> {
-    service: S,
+    service: Rc<S>,
 }
 
 impl<S, B> Service<ServiceRequest> for AdminGuardMiddleware<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static + Clone,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     B: 'static,
 {
     type Response = ServiceResponse<B>;
@@ -70,12 +71,12 @@ where
                     } else {
                         warn!("Admin access denied for user {} with role {} to path: {}", 
                               user.id, user.role, path);
-                        Err(ErrorForbidden("Admin role required for this resource"))
+                        Err(ApiError::AdminRequired.into())
                     }
                 }
                 None => {
                     warn!("Admin access denied: No authenticated user found for path: {}", path);
-                    Err(ErrorForbidden("Authentication required"))
+                    Err(ApiError::Unauthorized("Authentication required".to_string()).into())
                 }
             }
         })
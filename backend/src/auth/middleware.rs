@@ -1,13 +1,15 @@
 use actix_web::{
-    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    error::ErrorUnauthorized,
-    Error, HttpMessage,
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::Method,
+    web, Error, FromRequest, HttpMessage, HttpRequest,
 };
 use futures::future::{ready, LocalBoxFuture, Ready};
 use jsonwebtoken::{decode, DecodingKey, Validation, errors::ErrorKind};
+use crate::api::ApiError;
 use log::{debug, warn};
+use sea_orm::DatabaseConnection;
 use serde::{Deserialize, Serialize};
-use crate::api::auth::is_token_blacklisted;
+use crate::auth::{extract_token_from_cookie_or_header, is_token_blacklisted, is_session_active};
 use std::rc::Rc;
 use uuid::Uuid;
 
@@ -26,6 +28,8 @@ pub struct Claims {
     pub exp: usize,
     pub iat: usize,
     pub jti: String,
+    // Id of the user_sessions row the token was issued for
+    pub sid: Option<String>,
 }
 
 // User info extracted from JWT
@@ -36,7 +40,22 @@ pub struct AuthUser {
     pub name: String,
     pub role: String,
     pub profile_image: Option<String>,
+    pub session_id: Uuid,
+}
 
+// Lets handlers behind JwtAuth take the authenticated user as an argument
+impl FromRequest for AuthUser {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<AuthUser>()
+                .cloned()
+                .ok_or_else(|| ApiError::Unauthorized("Authentication required".to_string()).into()),
+        )
+    }
 }
 
 // Auth middleware factory
@@ -67,29 +86,10 @@ impl JwtAuth {
     }
 }
 
-// Extract token from cookie or header (helper function)
-pub fn extract_token_from_cookie_or_header(req: &actix_web::HttpRequest) -> Option<String> {
-    // Try cookie first
-    if let Some(cookie) = req.cookie("auth_token") {
-        return Some(cookie.value().to_string());
-    }
-
-    // Try Authorization header
-    if let Some(auth_header) = req.headers().get("Authorization") {
-        if let Ok(auth_str) = auth_header.to_str() {
-            if auth_str.starts_with("Bearer ") {
-                return Some(auth_str.trim_start_matches("Bearer ").trim().to_string());
-            }
-        }
-    }
-
-    None
-}
-
 // Middleware factory implementation
 impl<S, B> Transform<S, ServiceRequest> for JwtAuth
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static + Clone,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     B: 'static,
 {
     type Response = ServiceResponse<B>;
@@ -100,7 +100,7 @@ where
 
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(JwtAuthMiddleware {
-            service,
+            service: Rc::new(service),
             jwt_secret: self.jwt_secret.clone(),
         }))
     }
@@ -108,13 +108,13 @@ where
 
 // Middleware service
 pub struct JwtAuthMiddleware<S> {
-    service: S,
+    service: Rc<S>,
     jwt_secret: Rc<String>,
 }
 
 impl<S, B> Service<ServiceRequest> for JwtAuthMiddleware<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static + Clone,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     B: 'static,
 {
     type Response = ServiceResponse<B>;
@@ -129,11 +129,16 @@ where
         let path = req.path().to_string();
 
         // List of public paths that don't require authentication
-        let public_paths = vec![
+        let public_paths = [
             "/",
             "/api/health",
             "/api/auth/login",
+            "/api/auth/verify-2fa",
             "/api/auth/register",
+            "/api/auth/sync",
+            "/api/auth/refresh",
+            "/api/auth/forgot-password",
+            "/api/auth/reset-password",
             "/api/auth/oauth/google",
             "/api/auth/oauth/github",
             "/api/auth/oauth/callback",
@@ -141,8 +146,11 @@ where
             "/api/auth/validate", // Add validate endpoint to public paths
         ];
 
+        // Logging out via GET only clears cookies, so it must work with an expired token
+        let is_cookie_logout = req.method() == Method::GET && path == "/api/auth/logout";
+
         // Check if the path is public
-        if public_paths.iter().any(|&p| path == p) {
+        if is_cookie_logout || public_paths.iter().any(|&p| path == p) {
             debug!("Public path: {}, skipping authentication", path);
             return Box::pin(async move {
                 service.call(req).await
@@ -153,47 +161,53 @@ where
             debug!("Processing JWT authentication for path: {}", path);
 
             // Extract token
-            let token = match extract_token_from_cookie_or_header(&req.request()) {
+            let token = match extract_token_from_cookie_or_header(req.request()) {
                 Some(t) => t,
                 None => {
                     warn!("No authentication found for path: {}", path);
-                    return Err(ErrorUnauthorized("Authentication required"));
+                    return Err(ApiError::Unauthorized("Authentication required".to_string()).into());
                 }
             };
 
-            // Check if the token is blacklisted
-            if is_token_blacklisted(&token) {
-                warn!("Token is blacklisted for path: {}", path);
-                return Err(ErrorUnauthorized("Token has been invalidated"));
-            }
-
             // Validate the token using static method
             let claims = match JwtAuth::validate_token(&token, &jwt_secret) {
                 Ok(claims) => claims,
                 Err(e) => {
                     match e.kind() {
-                        ErrorKind::ExpiredSignature => {
-                            warn!("Token expired for path: {}", path);
-                            return Err(ErrorUnauthorized("Token expired"));
-                        }
-                        ErrorKind::InvalidSignature => {
-                            warn!("Invalid token signature for path: {}", path);
-                            return Err(ErrorUnauthorized("Invalid token signature"));
-                        }
-                        _ => {
-                            warn!("Invalid token for path: {}: {:?}", path, e);
-                            return Err(ErrorUnauthorized("Invalid token"));
-                        }
+                        ErrorKind::ExpiredSignature => warn!("Token expired for path: {}", path),
+                        ErrorKind::InvalidSignature => warn!("Invalid token signature for path: {}", path),
+                        _ => warn!("Invalid token for path: {}: {:?}", path, e),
                     }
+                    return Err(ApiError::from(e).into());
                 }
             };
 
+            // Check if the token has been revoked
+            let db = match req.app_data::<web::Data<DatabaseConnection>>() {
+                Some(db) => db.clone(),
+                None => {
+                    warn!("Database connection not found in app data for path: {}", path);
+                    return Err(ApiError::Internal("Unable to verify token".to_string()).into());
+                }
+            };
+
+            if is_token_blacklisted(db.get_ref(), &claims.jti).await {
+                warn!("Token is blacklisted for path: {}", path);
+                return Err(ApiError::TokenRevoked.into());
+            }
+
+            // Check that the session the token belongs to has not been terminated
+            if !is_session_active(db.get_ref(), &claims).await {
+                warn!("Session is no longer active for path: {}", path);
+                return Err(ApiError::SessionTerminated.into());
+            }
+
             // Ensure backend_user_id is present
             let backend_user_id = match claims.backend_user_id {
                 Some(id) => id,
                 None => {
                     warn!("Missing backend_user_id in token for path: {}", path);
-                    return Err(ErrorUnauthorized("Missing backend_user_id in token"));
+                    return Err(ApiError::InvalidToken("Missing backend_user_id in token".to_string()).into());
                 }
             };
 
@@ -201,7 +215,16 @@ where
                 Ok(id) => id,
                 Err(e) => {
                     warn!("Invalid backend_user_id format in token for path: {}: {:?}", path, e);
-                    return Err(ErrorUnauthorized("Invalid backend_user_id format"));
+                    return Err(ApiError::InvalidToken("Invalid backend_user_id format".to_string()).into());
+                }
+            };
+
+            // Tokens that passed the session check always carry a valid sid
+            let session_id = match claims.sid.as_deref().and_then(|sid| Uuid::parse_str(sid).ok()) {
+                Some(id) => id,
+                None => {
+                    warn!("Missing session id in token for path: {}", path);
+                    return Err(ApiError::InvalidToken("Token is not bound to a session".to_string()).into());
                 }
             };
 
@@ -212,6 +235,7 @@ where
                 name: claims.name,
                 role: claims.user_role.unwrap_or_else(|| "user".to_string()),
                 profile_image: claims.profile_image,
+                session_id,
             };
 
             debug!("Authenticated user {} with role {} for path: {}", user_id, auth_user.role, path);
@@ -1,6 +1,11 @@
 pub mod middleware;
 pub mod utils;
 pub mod admin_guard;
+pub mod revocation;
+pub mod tokens;
 
 pub use middleware::{AuthUser, Claims, JwtAuth};
-pub use utils::{extract_token_from_cookie_or_header, extract_user_id_from_token};
+pub use admin_guard::AdminGuard;
+pub use utils::extract_token_from_cookie_or_header;
+pub use revocation::{is_token_blacklisted, is_session_active, revoke_token, spawn_revocation_sweeper};
+pub use tokens::{TokenPair, RefreshError, issue_token_pair, rotate_refresh_token, revoke_session_family};
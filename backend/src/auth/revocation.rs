@@ -0,0 +1,133 @@
+use chrono::{DateTime, TimeZone, Utc};
+use lazy_static::lazy_static;
+use log::{debug, error, info};
+use sea_orm::{sea_query::OnConflict, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::auth::Claims;
+use crate::models::entities::{RevokedToken, RevokedTokenActiveModel, UserSession, revoked_token::Column as RevokedTokenColumn};
+
+// How often expired revocations are pruned from the database and the local cache
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+lazy_static! {
+    // Revocations this instance already knows about (jti -> token expiry).
+    // Revocations made by other replicas are picked up from the database on a cache miss.
+    static ref REVOKED_CACHE: Mutex<HashMap<String, DateTime<Utc>>> = Mutex::new(HashMap::new());
+}
+
+/// Record a token as revoked until its own expiry
+pub async fn revoke_token(db: &DatabaseConnection, claims: &Claims) -> Result<(), DbErr> {
+    let expires_at = Utc
+        .timestamp_opt(claims.exp as i64, 0)
+        .single()
+        .unwrap_or_else(Utc::now);
+
+    let user_id = claims
+        .backend_user_id
+        .as_deref()
+        .and_then(|id| Uuid::parse_str(id).ok());
+
+    let revoked = RevokedTokenActiveModel {
+        jti: Set(claims.jti.clone()),
+        user_id: Set(user_id),
+        expires_at: Set(expires_at),
+        revoked_at: Set(Utc::now()),
+    };
+
+    // Revoking the same token twice (e.g. double logout) is not an error
+    RevokedToken::insert(revoked)
+        .on_conflict(OnConflict::column(RevokedTokenColumn::Jti).do_nothing().to_owned())
+        .exec_without_returning(db)
+        .await?;
+
+    if let Ok(mut cache) = REVOKED_CACHE.lock() {
+        cache.insert(claims.jti.clone(), expires_at);
+    }
+
+    debug!("Revoked token {} until {}", claims.jti, expires_at);
+    Ok(())
+}
+
+/// Check whether the token with the given `jti` has been revoked
+///
+/// Fails closed: if the revocation store cannot be queried the token is treated as revoked.
+pub async fn is_token_blacklisted(db: &DatabaseConnection, jti: &str) -> bool {
+    let now = Utc::now();
+
+    if let Ok(cache) = REVOKED_CACHE.lock() {
+        if let Some(expires_at) = cache.get(jti) {
+            return *expires_at > now;
+        }
+    }
+
+    match RevokedToken::find_by_id(jti.to_string()).one(db).await {
+        Ok(Some(revoked)) => {
+            if let Ok(mut cache) = REVOKED_CACHE.lock() {
+                cache.insert(revoked.jti.clone(), revoked.expires_at);
+            }
+            revoked.expires_at > now
+        }
+        Ok(None) => false,
+        Err(e) => {
+            error!("Database error when checking token revocation: {:?}", e);
+            true
+        }
+    }
+}
+
+/// Check whether the session a token was issued for is still active
+///
+/// Tokens that are not bound to a session are rejected, and like revocation
+/// checks this fails closed when the database cannot be queried.
+pub async fn is_session_active(db: &DatabaseConnection, claims: &Claims) -> bool {
+    let session_id = match claims.sid.as_deref().and_then(|sid| Uuid::parse_str(sid).ok()) {
+        Some(id) => id,
+        None => return false,
+    };
+
+    match UserSession::find_by_id(session_id).one(db).await {
+        Ok(Some(session)) => session.is_active,
+        Ok(None) => false,
+        Err(e) => {
+            error!("Database error when checking session status: {:?}", e);
+            false
+        }
+    }
+}
+
+/// Delete revocations whose tokens have expired anyway
+pub async fn purge_expired_tokens(db: &DatabaseConnection) -> Result<u64, DbErr> {
+    let now = Utc::now();
+
+    if let Ok(mut cache) = REVOKED_CACHE.lock() {
+        cache.retain(|_, expires_at| *expires_at > now);
+    }
+
+    let result = RevokedToken::delete_many()
+        .filter(RevokedTokenColumn::ExpiresAt.lte(now))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected)
+}
+
+/// Spawn the background task that periodically prunes expired revocations
+pub fn spawn_revocation_sweeper(db: DatabaseConnection) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(SWEEP_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            match purge_expired_tokens(&db).await {
+                Ok(0) => {}
+                Ok(count) => info!("Purged {} expired token revocations", count),
+                Err(e) => error!("Failed to purge expired token revocations: {:?}", e),
+            }
+        }
+    });
+}
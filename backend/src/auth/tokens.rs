@@ -0,0 +1,300 @@
+use actix_web::cookie::{Cookie, SameSite};
+use chrono::{Duration, Utc};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use log::{debug, info, warn};
+use rand::distr::Alphanumeric;
+use rand::{rng, Rng};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, Set, TransactionTrait,
+};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::api::chat::ws::close_session_connections;
+use crate::auth::Claims;
+use crate::models::entities::{
+    refresh_token::Column as RefreshTokenColumn, user::Model as UserModel, RefreshToken,
+    RefreshTokenActiveModel, User, UserSession, UserSessionActiveModel,
+};
+
+// Lifetime of the JWT sent with every request
+pub const ACCESS_TOKEN_TTL_MINUTES: i64 = 15;
+// Lifetime of a single refresh token; every rotation issues a fresh one
+pub const REFRESH_TOKEN_TTL_DAYS: i64 = 30;
+// Name of the cookie carrying the refresh token
+pub const REFRESH_TOKEN_COOKIE: &str = "refresh_token";
+// The refresh cookie is only ever sent to the auth endpoints
+const REFRESH_TOKEN_COOKIE_PATH: &str = "/api/auth";
+
+/// Access and refresh token issued together for one session
+#[derive(Debug, Clone)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_in: i64,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RefreshError {
+    #[error("Invalid refresh token")]
+    Invalid,
+    #[error("Refresh token expired")]
+    Expired,
+    #[error("Refresh token reuse detected")]
+    Reused,
+    #[error("Session is no longer active")]
+    SessionInactive,
+    #[error("User account is disabled")]
+    UserInactive,
+    #[error("Database error: {0}")]
+    Database(#[from] DbErr),
+    #[error("Failed to generate access token: {0}")]
+    Token(#[from] jsonwebtoken::errors::Error),
+}
+
+// Generate an opaque refresh token; only its hash is ever stored
+fn generate_refresh_token() -> String {
+    rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect()
+}
+
+fn hash_refresh_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Sign a short-lived access token for the user, bound to one of their sessions
+pub fn generate_access_token(
+    user: &UserModel,
+    session_id: Uuid,
+    jwt_secret: &str,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let now = Utc::now();
+    let exp = (now + Duration::minutes(ACCESS_TOKEN_TTL_MINUTES)).timestamp() as usize;
+    let iat = now.timestamp() as usize;
+
+    let claims = Claims {
+        id: user.id.to_string(),
+        sub: user.email.clone(),
+        email: user.email.clone(),
+        name: user.name.clone(),
+        backend_user_id: Some(user.id.to_string()),
+        user_role: Some(user.role.clone()),
+        profile_image: user.profile_image.clone(),
+        provider: Some(user.provider.clone()),
+        is_active: Some(user.is_active),
+        exp,
+        iat,
+        jti: Uuid::new_v4().to_string(),
+        sid: Some(session_id.to_string()),
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(jwt_secret.as_bytes()),
+    )
+}
+
+/// Issue the first access/refresh token pair for a freshly created session
+pub async fn issue_token_pair(
+    db: &DatabaseConnection,
+    user: &UserModel,
+    session_id: Uuid,
+    jwt_secret: &str,
+) -> Result<TokenPair, RefreshError> {
+    let refresh_token = generate_refresh_token();
+
+    RefreshTokenActiveModel {
+        id: Set(Uuid::new_v4()),
+        session_id: Set(session_id),
+        user_id: Set(user.id),
+        token_hash: Set(hash_refresh_token(&refresh_token)),
+        expires_at: Set(Utc::now() + Duration::days(REFRESH_TOKEN_TTL_DAYS)),
+        used_at: Set(None),
+        replaced_by: Set(None),
+        revoked_at: Set(None),
+        created_at: Set(Utc::now()),
+    }
+    .insert(db)
+    .await?;
+
+    Ok(TokenPair {
+        access_token: generate_access_token(user, session_id, jwt_secret)?,
+        refresh_token,
+        expires_in: Duration::minutes(ACCESS_TOKEN_TTL_MINUTES).num_seconds(),
+    })
+}
+
+/// Exchange a refresh token for a new pair, invalidating the presented one
+///
+/// Presenting a token that was already exchanged means it leaked, so the
+/// whole session it belongs to is revoked.
+pub async fn rotate_refresh_token(
+    db: &DatabaseConnection,
+    presented: &str,
+    jwt_secret: &str,
+) -> Result<(UserModel, TokenPair), RefreshError> {
+    let now = Utc::now();
+
+    let stored = RefreshToken::find()
+        .filter(RefreshTokenColumn::TokenHash.eq(hash_refresh_token(presented)))
+        .one(db)
+        .await?
+        .ok_or(RefreshError::Invalid)?;
+
+    if stored.revoked_at.is_some() {
+        return Err(RefreshError::Invalid);
+    }
+
+    if stored.used_at.is_some() {
+        warn!("Refresh token reuse detected for session {}", stored.session_id);
+        revoke_session_family(db, stored.session_id).await?;
+        return Err(RefreshError::Reused);
+    }
+
+    if stored.expires_at <= now {
+        return Err(RefreshError::Expired);
+    }
+
+    let session = match UserSession::find_by_id(stored.session_id).one(db).await? {
+        Some(session) if session.is_active => session,
+        _ => return Err(RefreshError::SessionInactive),
+    };
+
+    let user = match User::find_by_id(stored.user_id).one(db).await? {
+        Some(user) if user.is_active => user,
+        _ => return Err(RefreshError::UserInactive),
+    };
+
+    let refresh_token = generate_refresh_token();
+    let replacement_id = Uuid::new_v4();
+
+    let txn = db.begin().await?;
+
+    // Claim the presented token; a concurrent refresh with the same token loses this race
+    let claimed = RefreshToken::update_many()
+        .col_expr(RefreshTokenColumn::UsedAt, Expr::value(now))
+        .col_expr(RefreshTokenColumn::ReplacedBy, Expr::value(replacement_id))
+        .filter(RefreshTokenColumn::Id.eq(stored.id))
+        .filter(RefreshTokenColumn::UsedAt.is_null())
+        .filter(RefreshTokenColumn::RevokedAt.is_null())
+        .exec(&txn)
+        .await?;
+
+    if claimed.rows_affected == 0 {
+        txn.rollback().await?;
+        warn!("Refresh token reuse detected for session {}", stored.session_id);
+        revoke_session_family(db, stored.session_id).await?;
+        return Err(RefreshError::Reused);
+    }
+
+    RefreshTokenActiveModel {
+        id: Set(replacement_id),
+        session_id: Set(stored.session_id),
+        user_id: Set(stored.user_id),
+        token_hash: Set(hash_refresh_token(&refresh_token)),
+        expires_at: Set(now + Duration::days(REFRESH_TOKEN_TTL_DAYS)),
+        used_at: Set(None),
+        replaced_by: Set(None),
+        revoked_at: Set(None),
+        created_at: Set(now),
+    }
+    .insert(&txn)
+    .await?;
+
+    let mut session_active: UserSessionActiveModel = session.into();
+    session_active.last_active_at = Set(now);
+    session_active.updated_at = Set(now);
+    session_active.update(&txn).await?;
+
+    txn.commit().await?;
+
+    debug!("Rotated refresh token for session {}", stored.session_id);
+
+    let access_token = generate_access_token(&user, stored.session_id, jwt_secret)?;
+
+    Ok((user, TokenPair {
+        access_token,
+        refresh_token,
+        expires_in: Duration::minutes(ACCESS_TOKEN_TTL_MINUTES).num_seconds(),
+    }))
+}
+
+/// Revoke every refresh token of a session and mark the session inactive
+pub async fn revoke_session_family(db: &DatabaseConnection, session_id: Uuid) -> Result<(), DbErr> {
+    let now = Utc::now();
+
+    RefreshToken::update_many()
+        .col_expr(RefreshTokenColumn::RevokedAt, Expr::value(now))
+        .filter(RefreshTokenColumn::SessionId.eq(session_id))
+        .filter(RefreshTokenColumn::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+
+    if let Some(session) = UserSession::find_by_id(session_id).one(db).await? {
+        if session.is_active {
+            let mut session_active: UserSessionActiveModel = session.into();
+            session_active.is_active = Set(false);
+            session_active.updated_at = Set(now);
+            session_active.update(db).await?;
+        }
+    }
+
+    // Drop any websocket still authenticated by this session
+    close_session_connections(session_id);
+
+    info!("Revoked refresh token family for session {}", session_id);
+    Ok(())
+}
+
+/// Look up the session a user's refresh token belongs to, if the token is known
+pub async fn find_refresh_token_session(
+    db: &DatabaseConnection,
+    presented: &str,
+    user_id: Uuid,
+) -> Result<Option<Uuid>, DbErr> {
+    let stored = RefreshToken::find()
+        .filter(RefreshTokenColumn::TokenHash.eq(hash_refresh_token(presented)))
+        .filter(RefreshTokenColumn::UserId.eq(user_id))
+        .one(db)
+        .await?;
+
+    Ok(stored.map(|token| token.session_id))
+}
+
+/// Build the `auth_token` and `refresh_token` cookies for a token pair
+///
+/// Cookies are only marked `Secure` in production so they still work over
+/// plain HTTP on localhost.
+pub fn build_auth_cookies(pair: &TokenPair, secure: bool) -> (Cookie<'static>, Cookie<'static>) {
+    let access_cookie = Cookie::build("auth_token", pair.access_token.clone())
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .secure(secure)
+        .max_age(actix_web::cookie::time::Duration::seconds(pair.expires_in))
+        .finish();
+
+    let refresh_cookie = Cookie::build(REFRESH_TOKEN_COOKIE, pair.refresh_token.clone())
+        .path(REFRESH_TOKEN_COOKIE_PATH)
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .secure(secure)
+        .max_age(actix_web::cookie::time::Duration::days(REFRESH_TOKEN_TTL_DAYS))
+        .finish();
+
+    (access_cookie, refresh_cookie)
+}
+
+/// Build a cookie that removes the refresh token from the browser
+pub fn clear_refresh_cookie() -> Cookie<'static> {
+    Cookie::build(REFRESH_TOKEN_COOKIE, "")
+        .path(REFRESH_TOKEN_COOKIE_PATH)
+        .http_only(true)
+        .max_age(actix_web::cookie::time::Duration::seconds(0))
+        .finish()
+}
@@ -1,9 +1,5 @@
 use actix_web::{HttpRequest, http::header};
 use log::{warn, debug};
-use uuid::Uuid;
-use jsonwebtoken::{decode, DecodingKey, Validation};
-
-use crate::auth::Claims;
 
 /// Extract the token from the cookie, Authorization header, or query parameter
 /// First tries to extract from the "auth_token" cookie, then Authorization header, then query parameter
@@ -64,66 +60,4 @@ pub fn extract_token_from_header(req: &HttpRequest) -> Option<String> {
     // Extract the token
     let token = auth_str.trim_start_matches("Bearer ").trim().to_string();
     Some(token)
-}
-
-/// Extract the user ID from the token
-pub fn extract_user_id_from_token(req: &HttpRequest) -> Option<Uuid> {
-    // Get the JWT secret from app data
-    let jwt_secret = match req.app_data::<actix_web::web::Data<String>>() {
-        Some(secret) => secret,
-        None => {
-            warn!("JWT secret not found in app data");
-            return None;
-        }
-    };
-
-    // Extract the token from cookie or header
-    let token = match extract_token_from_cookie_or_header(req) {
-        Some(token) => token,
-        None => {
-            warn!("No authentication token found (neither cookie nor Authorization header)");
-            return None;
-        }
-    };
-
-    // Configure validation
-    let mut validation = Validation::default();
-    validation.validate_exp = true;
-    validation.leeway = 0; // No leeway for expiration time
-
-    // Decode and validate the token
-    let token_data = match decode::<Claims>(
-        &token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
-        &validation,
-    ) {
-        Ok(data) => data,
-        Err(e) => {
-            warn!("Invalid token: {:?}", e);
-            return None;
-        }
-    };
-
-    let claims = token_data.claims;
-
-    // Ensure backend_user_id is present
-    let backend_user_id = match claims.backend_user_id {
-        Some(id) => id,
-        None => {
-            warn!("Missing backend_user_id in token");
-            return None;
-        }
-    };
-
-    // Parse the user ID
-    match Uuid::parse_str(&backend_user_id) {
-        Ok(id) => {
-            debug!("Extracted user ID from token: {}", id);
-            Some(id)
-        }
-        Err(e) => {
-            warn!("Invalid backend_user_id format in token: {:?}", e);
-            None
-        }
-    }
 }
\ No newline at end of file
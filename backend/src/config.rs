@@ -0,0 +1,621 @@
+use log::{info, warn};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml_edit::{DocumentMut, Item};
+
+// File read when CONFIG_FILE is not set; it is optional
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+// Secret used when NEXTAUTH_SECRET is missing outside production
+const DEVELOPMENT_JWT_SECRET: &str = "insecure_default_secret_only_for_development";
+
+// Shortest JWT secret accepted in production
+const MIN_PRODUCTION_SECRET_LEN: usize = 32;
+
+// Directory uploads are kept in with local storage
+const DEFAULT_STORAGE_ROOT: &str = "uploads";
+
+// Lifetime of presigned download URLs when S3_PRESIGN_EXPIRY_SECS is not set
+const DEFAULT_PRESIGN_EXPIRY_SECS: u64 = 15 * 60;
+
+// Websocket heartbeat defaults, in seconds
+const DEFAULT_HEARTBEAT_INTERVAL_SECS: u64 = 15;
+const DEFAULT_CLIENT_TIMEOUT_SECS: u64 = 45;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to read config file {0}: {1}")]
+    Read(String, std::io::Error),
+    #[error("Failed to parse config file {0}: {1}")]
+    Parse(String, String),
+    #[error("Invalid configuration:\n  - {}", .0.join("\n  - "))]
+    Invalid(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Environment {
+    Development,
+    Production,
+}
+
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Environment::Development => write!(f, "development"),
+            Environment::Production => write!(f, "production"),
+        }
+    }
+}
+
+// How chat events reach the websockets of other backend instances
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatBackplane {
+    // Single instance; nothing is shared
+    InProcess,
+    // PostgreSQL LISTEN/NOTIFY on the application database
+    Postgres,
+}
+
+impl fmt::Display for ChatBackplane {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChatBackplane::InProcess => write!(f, "in-process"),
+            ChatBackplane::Postgres => write!(f, "postgres"),
+        }
+    }
+}
+
+// How chat websockets are kept alive
+#[derive(Debug, Clone, Copy)]
+pub struct HeartbeatConfig {
+    // Time between protocol pings to the client
+    pub interval: Duration,
+    // A connection that sends nothing, pongs included, for this long is closed
+    pub client_timeout: Duration,
+}
+
+// An S3-compatible bucket uploads are kept in
+#[derive(Clone)]
+pub struct S3Config {
+    // Where the backend reaches the service, e.g. https://s3.eu-west-1.amazonaws.com or http://minio:9000
+    pub endpoint: String,
+    // Where browsers reach it, for presigned URLs; defaults to the endpoint
+    pub public_endpoint: Option<String>,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    // Address the bucket in the path rather than the host name, as MinIO expects
+    pub path_style: bool,
+    pub presign_expiry: Duration,
+}
+
+// Where uploaded files are kept
+#[derive(Clone)]
+pub enum StorageConfig {
+    Local { root: PathBuf },
+    S3(S3Config),
+}
+
+impl fmt::Display for StorageConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageConfig::Local { root } => write!(f, "local ({})", root.display()),
+            StorageConfig::S3(s3) => write!(f, "s3 (bucket {} at {})", s3.bucket, s3.endpoint),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct OAuthClientConfig {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+#[derive(Clone)]
+pub struct OAuthConfig {
+    pub redirect_url: String,
+    pub google: Option<OAuthClientConfig>,
+    pub github: Option<OAuthClientConfig>,
+}
+
+/// Application configuration, loaded once at startup
+///
+/// Values come from environment variables, falling back to an optional TOML
+/// file (`CONFIG_FILE`, default `config.toml`) and then to development defaults.
+#[derive(Clone)]
+pub struct AppConfig {
+    pub environment: Environment,
+    pub server: ServerConfig,
+    pub database_url: String,
+    pub jwt_secret: String,
+    // None allows any origin
+    pub cors_origin: Option<String>,
+    pub frontend_url: String,
+    // Public base URL of this API, used to build links to uploaded files
+    pub public_api_url: String,
+    pub smtp: SmtpConfig,
+    pub oauth: OAuthConfig,
+    pub chat_backplane: ChatBackplane,
+    pub chat_heartbeat: HeartbeatConfig,
+    pub storage: StorageConfig,
+}
+
+// Looks a setting up in the environment first and in the config file second
+struct Source {
+    env: HashMap<String, String>,
+    file: Option<DocumentMut>,
+}
+
+impl Source {
+    fn get(&self, env_keys: &[&str], file_path: &[&str]) -> Option<String> {
+        for key in env_keys {
+            if let Some(value) = self.env.get(*key) {
+                if !value.trim().is_empty() {
+                    return Some(value.trim().to_string());
+                }
+            }
+        }
+
+        let mut item: &Item = self.file.as_ref()?.as_item();
+        for key in file_path {
+            item = item.get(key)?;
+        }
+
+        match item.as_value()? {
+            value if value.is_str() => value.as_str().map(|s| s.trim().to_string()),
+            value if value.is_integer() => value.as_integer().map(|i| i.to_string()),
+            value if value.is_bool() => value.as_bool().map(|b| b.to_string()),
+            _ => None,
+        }
+        .filter(|value| !value.is_empty())
+    }
+}
+
+// Placeholder values shipped in the example env files
+fn is_placeholder(value: &str) -> bool {
+    let lower = value.to_lowercase();
+    lower.starts_with("your-")
+        || lower.starts_with("your_")
+        || lower.contains("change_me")
+        || lower.contains("changeme")
+}
+
+fn parse_port(value: Option<String>, key: &str, default: u16, problems: &mut Vec<String>) -> u16 {
+    match value {
+        Some(value) => match value.parse::<u16>() {
+            Ok(port) if port > 0 => port,
+            _ => {
+                problems.push(format!("{} must be a valid port number, got '{}'", key, value));
+                default
+            }
+        },
+        None => default,
+    }
+}
+
+fn parse_seconds(value: Option<String>, key: &str, default: u64, problems: &mut Vec<String>) -> Duration {
+    match value {
+        Some(value) => match value.parse::<u64>() {
+            Ok(seconds) if seconds > 0 => Duration::from_secs(seconds),
+            _ => {
+                problems.push(format!("{} must be a positive number of seconds, got '{}'", key, value));
+                Duration::from_secs(default)
+            }
+        },
+        None => Duration::from_secs(default),
+    }
+}
+
+fn s3_config(source: &Source, problems: &mut Vec<String>) -> S3Config {
+    let mut required = |env_key: &str, file_key: &str| match source.get(&[env_key], &["storage", "s3", file_key]) {
+        Some(value) => value,
+        None => {
+            problems.push(format!("{} must be set when STORAGE_BACKEND is 's3'", env_key));
+            String::new()
+        }
+    };
+
+    let bucket = required("S3_BUCKET", "bucket");
+    let access_key_id = required("S3_ACCESS_KEY_ID", "access_key_id");
+    let secret_access_key = required("S3_SECRET_ACCESS_KEY", "secret_access_key");
+
+    let region = source.get(&["S3_REGION"], &["storage", "s3", "region"]).unwrap_or_else(|| "us-east-1".to_string());
+    let endpoint = source
+        .get(&["S3_ENDPOINT"], &["storage", "s3", "endpoint"])
+        .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region))
+        .trim_end_matches('/')
+        .to_string();
+
+    let path_style = match source.get(&["S3_FORCE_PATH_STYLE"], &["storage", "s3", "force_path_style"]).as_deref() {
+        None | Some("true") | Some("1") => true,
+        Some("false") | Some("0") => false,
+        Some(other) => {
+            problems.push(format!("S3_FORCE_PATH_STYLE must be 'true' or 'false', got '{}'", other));
+            true
+        }
+    };
+
+    S3Config {
+        endpoint,
+        public_endpoint: source
+            .get(&["S3_PUBLIC_ENDPOINT"], &["storage", "s3", "public_endpoint"])
+            .map(|url| url.trim_end_matches('/').to_string()),
+        bucket,
+        region,
+        access_key_id,
+        secret_access_key,
+        path_style,
+        presign_expiry: parse_seconds(
+            source.get(&["S3_PRESIGN_EXPIRY_SECS"], &["storage", "s3", "presign_expiry_secs"]),
+            "S3_PRESIGN_EXPIRY_SECS",
+            DEFAULT_PRESIGN_EXPIRY_SECS,
+            problems,
+        ),
+    }
+}
+
+fn oauth_client(source: &Source, provider: &str, id_key: &str, secret_key: &str) -> Option<OAuthClientConfig> {
+    let client_id = source.get(&[id_key], &["oauth", provider, "client_id"]);
+    let client_secret = source.get(&[secret_key], &["oauth", provider, "client_secret"]);
+
+    match (client_id, client_secret) {
+        (Some(client_id), Some(client_secret)) if !is_placeholder(&client_id) && !is_placeholder(&client_secret) => {
+            Some(OAuthClientConfig { client_id, client_secret })
+        }
+        (None, None) => None,
+        _ => {
+            warn!("{} OAuth is not fully configured ({} / {}); {} login is disabled", provider, id_key, secret_key, provider);
+            None
+        }
+    }
+}
+
+impl AppConfig {
+    /// Load and validate the configuration
+    pub fn load() -> Result<Self, ConfigError> {
+        let explicit_file = env::var("CONFIG_FILE").ok().filter(|path| !path.is_empty());
+        let file_path = explicit_file.clone().unwrap_or_else(|| DEFAULT_CONFIG_FILE.to_string());
+
+        let file = if Path::new(&file_path).exists() {
+            let content = fs::read_to_string(&file_path).map_err(|e| ConfigError::Read(file_path.clone(), e))?;
+            let document = content
+                .parse::<DocumentMut>()
+                .map_err(|e| ConfigError::Parse(file_path.clone(), e.to_string()))?;
+            info!("Loaded configuration file {}", file_path);
+            Some(document)
+        } else if explicit_file.is_some() {
+            return Err(ConfigError::Read(
+                file_path,
+                std::io::Error::new(std::io::ErrorKind::NotFound, "file does not exist"),
+            ));
+        } else {
+            None
+        };
+
+        Self::from_source(&Source { env: env::vars().collect(), file })
+    }
+
+    fn from_source(source: &Source) -> Result<Self, ConfigError> {
+        let mut problems = Vec::new();
+
+        let environment = match source.get(&["ENVIRONMENT"], &["environment"]).as_deref() {
+            None | Some("development") | Some("dev") => Environment::Development,
+            Some("production") | Some("prod") => Environment::Production,
+            Some(other) => {
+                problems.push(format!("ENVIRONMENT must be 'development' or 'production', got '{}'", other));
+                Environment::Development
+            }
+        };
+        let production = environment == Environment::Production;
+
+        let server = ServerConfig {
+            host: source.get(&["HOST"], &["server", "host"]).unwrap_or_else(|| "0.0.0.0".to_string()),
+            port: parse_port(source.get(&["PORT"], &["server", "port"]), "PORT", 8080, &mut problems),
+        };
+
+        let database_url = match source.get(&["DATABASE_URL"], &["database_url"]) {
+            Some(url) => url,
+            None if production => {
+                problems.push("DATABASE_URL must be set in production".to_string());
+                String::new()
+            }
+            None => {
+                warn!("DATABASE_URL not set, using default value");
+                "postgres://postgres:postgres@db:5432/tforce".to_string()
+            }
+        };
+
+        let jwt_secret = match source.get(&["NEXTAUTH_SECRET"], &["jwt_secret"]) {
+            Some(secret) => {
+                if production {
+                    if secret == DEVELOPMENT_JWT_SECRET || is_placeholder(&secret) {
+                        problems.push("NEXTAUTH_SECRET is set to a placeholder value".to_string());
+                    } else if secret.len() < MIN_PRODUCTION_SECRET_LEN {
+                        problems.push(format!(
+                            "NEXTAUTH_SECRET must be at least {} characters long in production",
+                            MIN_PRODUCTION_SECRET_LEN
+                        ));
+                    }
+                }
+                secret
+            }
+            None if production => {
+                problems.push("NEXTAUTH_SECRET must be set in production".to_string());
+                String::new()
+            }
+            None => {
+                warn!("NEXTAUTH_SECRET not set, using a default value (not secure for production)");
+                DEVELOPMENT_JWT_SECRET.to_string()
+            }
+        };
+
+        let cors_origin = match source.get(&["CORS_ORIGIN"], &["cors_origin"]) {
+            Some(origin) if origin != "*" => Some(origin),
+            _ if production => {
+                problems.push("CORS_ORIGIN must name the frontend origin in production, not '*'".to_string());
+                None
+            }
+            _ => None,
+        };
+
+        let frontend_url = match source.get(&["FRONTEND_URL"], &["frontend_url"]) {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => {
+                warn!("FRONTEND_URL not set, using default: http://localhost:3000");
+                "http://localhost:3000".to_string()
+            }
+        };
+
+        let public_api_url = match source.get(&["API_URL", "BACKEND_URL", "NEXT_PUBLIC_API_URL"], &["public_api_url"]) {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => {
+                warn!("No API URL set (API_URL, BACKEND_URL, NEXT_PUBLIC_API_URL), using default value");
+                "http://localhost:8080".to_string()
+            }
+        };
+
+        let smtp = SmtpConfig {
+            host: source.get(&["SMTP_HOST"], &["smtp", "host"]).unwrap_or_else(|| "smtp.gmail.com".to_string()),
+            port: parse_port(source.get(&["SMTP_PORT"], &["smtp", "port"]), "SMTP_PORT", 587, &mut problems),
+            username: source.get(&["SMTP_USERNAME"], &["smtp", "username"]),
+            password: source.get(&["SMTP_PASSWORD"], &["smtp", "password"]),
+        };
+
+        if production && smtp.password.as_deref().is_some_and(is_placeholder) {
+            problems.push("SMTP_PASSWORD is set to a placeholder value".to_string());
+        }
+
+        let oauth = OAuthConfig {
+            redirect_url: source
+                .get(&["OAUTH_REDIRECT_URL"], &["oauth", "redirect_url"])
+                .unwrap_or_else(|| "http://localhost:8080/api/auth/oauth/callback".to_string()),
+            google: oauth_client(source, "google", "GOOGLE_CLIENT_ID", "GOOGLE_CLIENT_SECRET"),
+            github: oauth_client(source, "github", "GITHUB_CLIENT_ID", "GITHUB_CLIENT_SECRET"),
+        };
+
+        let chat_backplane = match source.get(&["CHAT_BACKPLANE"], &["chat", "backplane"]).as_deref() {
+            None | Some("in-process") | Some("memory") => ChatBackplane::InProcess,
+            Some("postgres") => ChatBackplane::Postgres,
+            Some(other) => {
+                problems.push(format!("CHAT_BACKPLANE must be 'in-process' or 'postgres', got '{}'", other));
+                ChatBackplane::InProcess
+            }
+        };
+
+        let chat_heartbeat = HeartbeatConfig {
+            interval: parse_seconds(
+                source.get(&["CHAT_HEARTBEAT_INTERVAL_SECS"], &["chat", "heartbeat_interval_secs"]),
+                "CHAT_HEARTBEAT_INTERVAL_SECS",
+                DEFAULT_HEARTBEAT_INTERVAL_SECS,
+                &mut problems,
+            ),
+            client_timeout: parse_seconds(
+                source.get(&["CHAT_CLIENT_TIMEOUT_SECS"], &["chat", "client_timeout_secs"]),
+                "CHAT_CLIENT_TIMEOUT_SECS",
+                DEFAULT_CLIENT_TIMEOUT_SECS,
+                &mut problems,
+            ),
+        };
+
+        // Healthy clients must get at least one ping, and time to answer it, before they time out
+        if chat_heartbeat.client_timeout <= chat_heartbeat.interval {
+            problems.push("CHAT_CLIENT_TIMEOUT_SECS must be longer than CHAT_HEARTBEAT_INTERVAL_SECS".to_string());
+        }
+
+        let storage = match source.get(&["STORAGE_BACKEND"], &["storage", "backend"]).as_deref() {
+            None | Some("local") => StorageConfig::Local {
+                root: PathBuf::from(
+                    source
+                        .get(&["STORAGE_LOCAL_ROOT"], &["storage", "local_root"])
+                        .unwrap_or_else(|| DEFAULT_STORAGE_ROOT.to_string()),
+                ),
+            },
+            Some("s3") => StorageConfig::S3(s3_config(source, &mut problems)),
+            Some(other) => {
+                problems.push(format!("STORAGE_BACKEND must be 'local' or 's3', got '{}'", other));
+                StorageConfig::Local { root: PathBuf::from(DEFAULT_STORAGE_ROOT) }
+            }
+        };
+
+        if !problems.is_empty() {
+            return Err(ConfigError::Invalid(problems));
+        }
+
+        Ok(AppConfig {
+            environment,
+            server,
+            database_url,
+            jwt_secret,
+            cors_origin,
+            frontend_url,
+            public_api_url,
+            smtp,
+            oauth,
+            chat_backplane,
+            chat_heartbeat,
+            storage,
+        })
+    }
+
+    pub fn is_production(&self) -> bool {
+        self.environment == Environment::Production
+    }
+
+    pub fn bind_address(&self) -> String {
+        format!("{}:{}", self.server.host, self.server.port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRODUCTION_SECRET: &str = "a-production-secret-that-is-long-enough";
+
+    fn source(env: &[(&str, &str)], file: Option<&str>) -> Source {
+        Source {
+            env: env.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+            file: file.map(|content| content.parse::<DocumentMut>().unwrap()),
+        }
+    }
+
+    // Every variable production requires, with acceptable values
+    fn production_env() -> Vec<(&'static str, &'static str)> {
+        vec![
+            ("ENVIRONMENT", "production"),
+            ("DATABASE_URL", "postgres://app:secret@db:5432/tforce"),
+            ("NEXTAUTH_SECRET", PRODUCTION_SECRET),
+            ("CORS_ORIGIN", "https://chat.example.com"),
+        ]
+    }
+
+    fn production_env_with(key: &'static str, value: Option<&'static str>) -> Vec<(&'static str, &'static str)> {
+        let mut env: Vec<_> = production_env().into_iter().filter(|(k, _)| *k != key).collect();
+        if let Some(value) = value {
+            env.push((key, value));
+        }
+        env
+    }
+
+    fn problems(source: &Source) -> Vec<String> {
+        match AppConfig::from_source(source) {
+            Err(ConfigError::Invalid(problems)) => problems,
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("configuration was accepted"),
+        }
+    }
+
+    #[test]
+    fn accepts_complete_production_config() {
+        let config = AppConfig::from_source(&source(&production_env(), None)).unwrap();
+        assert!(config.is_production());
+        assert_eq!(config.jwt_secret, PRODUCTION_SECRET);
+        assert_eq!(config.cors_origin.as_deref(), Some("https://chat.example.com"));
+    }
+
+    #[test]
+    fn development_falls_back_to_defaults() {
+        let config = AppConfig::from_source(&source(&[], None)).unwrap();
+        assert!(!config.is_production());
+        assert_eq!(config.jwt_secret, DEVELOPMENT_JWT_SECRET);
+        assert_eq!(config.cors_origin, None);
+    }
+
+    #[test]
+    fn production_rejects_missing_secret() {
+        let problems = problems(&source(&production_env_with("NEXTAUTH_SECRET", None), None));
+        assert_eq!(problems, vec!["NEXTAUTH_SECRET must be set in production"]);
+    }
+
+    #[test]
+    fn production_rejects_placeholder_secrets() {
+        for secret in [DEVELOPMENT_JWT_SECRET, "your-secret-key-here-for-jwt-signing", "please_change_me_before_deploying"] {
+            let problems = problems(&source(&production_env_with("NEXTAUTH_SECRET", Some(secret)), None));
+            assert_eq!(problems, vec!["NEXTAUTH_SECRET is set to a placeholder value"], "secret {}", secret);
+        }
+    }
+
+    #[test]
+    fn production_rejects_short_secret() {
+        let problems = problems(&source(&production_env_with("NEXTAUTH_SECRET", Some("too-short")), None));
+        assert_eq!(problems, vec!["NEXTAUTH_SECRET must be at least 32 characters long in production"]);
+    }
+
+    #[test]
+    fn production_rejects_wildcard_or_missing_cors_origin() {
+        for origin in [Some("*"), None] {
+            let problems = problems(&source(&production_env_with("CORS_ORIGIN", origin), None));
+            assert_eq!(problems, vec!["CORS_ORIGIN must name the frontend origin in production, not '*'"]);
+        }
+    }
+
+    #[test]
+    fn production_rejects_missing_database_url() {
+        let problems = problems(&source(&production_env_with("DATABASE_URL", None), None));
+        assert_eq!(problems, vec!["DATABASE_URL must be set in production"]);
+    }
+
+    #[test]
+    fn reports_every_problem_at_once() {
+        let problems = problems(&source(&[("ENVIRONMENT", "production")], None));
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+    }
+
+    #[test]
+    fn environment_overrides_file() {
+        let file = r#"
+            jwt_secret = "secret-from-the-config-file"
+            cors_origin = "https://file.example.com"
+
+            [server]
+            port = 9000
+        "#;
+        let config = AppConfig::from_source(&source(
+            &[("NEXTAUTH_SECRET", "secret-from-the-environment"), ("PORT", "7000")],
+            Some(file),
+        ))
+        .unwrap();
+        assert_eq!(config.jwt_secret, "secret-from-the-environment");
+        assert_eq!(config.server.port, 7000);
+        // Settings the environment does not have still come from the file
+        assert_eq!(config.cors_origin.as_deref(), Some("https://file.example.com"));
+    }
+
+    #[test]
+    fn blank_environment_values_fall_back_to_file() {
+        let file = r#"jwt_secret = "secret-from-the-config-file""#;
+        let config = AppConfig::from_source(&source(&[("NEXTAUTH_SECRET", "  ")], Some(file))).unwrap();
+        assert_eq!(config.jwt_secret, "secret-from-the-config-file");
+    }
+
+    #[test]
+    fn production_validates_values_from_file() {
+        let file = r#"
+            environment = "production"
+            database_url = "postgres://app:secret@db:5432/tforce"
+            jwt_secret = "short"
+            cors_origin = "https://chat.example.com"
+        "#;
+        let problems = problems(&source(&[], Some(file)));
+        assert_eq!(problems, vec!["NEXTAUTH_SECRET must be at least 32 characters long in production"]);
+    }
+}
@@ -1,20 +1,25 @@
 mod api;
 mod auth;
 mod models;
+mod config;
+mod metrics;
+mod storage;
 
 use actix_cors::Cors;
 use actix_web::{web, App, HttpServer, middleware::Logger};
 
 use dotenv::dotenv;
-use std::env;
+use std::sync::Arc;
 
 use migration::{Migrator, MigratorTrait, sea_orm::{Database, DatabaseConnection}};
 
 // Import API handlers explicitly
 use crate::api::auth::sync::sync_user;
+use crate::auth::{spawn_revocation_sweeper, AdminGuard, JwtAuth};
+use crate::config::{AppConfig, ChatBackplane};
 use crate::api::auth::logout::logout_get;
 use crate::api::auth::{
-    login_handler, register_handler, logout_handler, validate_session,
+    login_handler, register_handler, logout_handler, refresh_token_handler, validate_session,
     oauth_google_login, oauth_github_login, oauth_callback,
     two_factor_setup, two_factor_verify, two_factor_status, 
     two_factor_disable, two_factor_backup_codes, two_factor_regenerate_backup_codes,
@@ -24,9 +29,14 @@ use crate::api::auth::{
 };
 use crate::api::user::me::get_current_user;
 use crate::api::user::{upload_profile_picture, get_profile_image, update_username, update_password};
-use crate::api::basic::{root, health_check};
+use crate::api::basic::{root, health_check, health_live, health_ready, metrics as metrics_handler};
+use crate::api::error::{json_error_handler, not_found, path_error_handler, query_error_handler};
+use crate::api::RequestIdMiddleware;
+use crate::metrics::RequestMetrics;
 use crate::api::admin::users::{get_all_users, delete_user, change_user_role, toggle_user_active};
-use crate::api::chat::{ws_index, create_room, get_rooms, get_room, delete_room, leave_room_membership, send_message, get_messages, verify_room_password, upload_chat_image, get_chat_image, join_room_by_code_handler, upload_voice_message, get_voice_message, upload_chat_video, get_chat_video};
+use crate::api::chat::backplane::{Backplane, InProcessBackplane, PostgresBackplane};
+use crate::api::chat::ws::start_chat_server;
+use crate::api::chat::{ws_index, create_room, get_rooms, get_room, delete_room, leave_room_membership, send_message, get_messages, edit_message_handler, delete_message_handler, get_message_edits, get_thread, search_messages, change_member_role, kick_member, mute_member, unmute_member, get_room_bans, ban_member, unban_member, get_room_members, verify_room_password, upload_chat_image, get_chat_image, join_room_by_code_handler, upload_voice_message, get_voice_message, upload_chat_video, get_chat_video, get_upload, get_attachment};
 
 
 #[actix_web::main]
@@ -47,25 +57,22 @@ async fn main() -> std::io::Result<()> {
     // Initialize logger
     env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
     
-    // Get environment variables with better error messages
-    let database_url = env::var("DATABASE_URL")
-        .unwrap_or_else(|_| {
-            log::warn!("DATABASE_URL not set, using default value");
-            "postgres://postgres:postgres@db:5432/tforce".to_string()
-        });
+    // Load and validate configuration; refuse to start with an unsafe setup
+    let config = match AppConfig::load() {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()));
+        }
+    };
     
-    let jwt_secret = env::var("NEXTAUTH_SECRET")
-        .unwrap_or_else(|_| {
-            log::warn!("NEXTAUTH_SECRET not set, using a default value (not secure for production)");
-            "insecure_default_secret_only_for_development".to_string()
-        });
+    log::info!("Running in {} mode", config.environment);
     
-    let host = env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
-    let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
-    let server_url = format!("{}:{}", host, port);
+    // Register Prometheus metrics so they are exported before first use
+    metrics::init();
     
     // Connect to the database
-    let db: DatabaseConnection = Database::connect(&database_url)
+    let db: DatabaseConnection = Database::connect(&config.database_url)
         .await
         .expect("Failed to connect to database");
     
@@ -74,98 +81,165 @@ async fn main() -> std::io::Result<()> {
         .await
         .expect("Failed to run migrations");
     
+    // Prune expired token revocations in the background
+    spawn_revocation_sweeper(db.clone());
+    
+    // Start the chat server actor that owns every websocket room
+    log::info!("Using {} chat backplane", config.chat_backplane);
+    let backplane: Arc<dyn Backplane> = match config.chat_backplane {
+        ChatBackplane::InProcess => Arc::new(InProcessBackplane),
+        ChatBackplane::Postgres => Arc::new(PostgresBackplane::new(db.clone())),
+    };
+    start_chat_server(db.clone(), backplane);
+    
+    // Uploaded files go to the configured storage backend
+    log::info!("Using {} upload storage", config.storage);
+    let storage = web::Data::from(storage::from_config(&config.storage));
+    
+    match &config.cors_origin {
+        Some(origin) => log::info!("Using production CORS with origin: {}", origin),
+        None => log::warn!("Using permissive CORS (allow_any_origin) - not recommended for production"),
+    }
+    
+    let server_url = config.bind_address();
+    let jwt_secret = config.jwt_secret.clone();
+    let config = web::Data::new(config);
+    
     log::info!("Starting server at http://{}", server_url);
     
     // Start HTTP server
     HttpServer::new(move || {
         // Configure CORS based on environment
-        let cors_origin = env::var("CORS_ORIGIN").unwrap_or_else(|_| "*".to_string());
-        
-        let cors = if cors_origin == "*" {
-            // Development mode - permissive CORS
-            log::warn!("Using permissive CORS (allow_any_origin) - not recommended for production");
-            Cors::default()
-                .allow_any_origin()
+        let cors = match &config.cors_origin {
+            // Production mode - specific origin
+            Some(origin) => Cors::default()
+                .allowed_origin(origin)
                 .allow_any_method()
                 .allow_any_header()
                 .supports_credentials()
-                .max_age(3600)
-        } else {
-            // Production mode - specific origin
-            log::info!("Using production CORS with origin: {}", cors_origin);
-            Cors::default()
-                .allowed_origin(&cors_origin)
+                .max_age(3600),
+            // Development mode - permissive CORS
+            None => Cors::default()
+                .allow_any_origin()
                 .allow_any_method()
                 .allow_any_header()
                 .supports_credentials()
-                .max_age(3600)
+                .max_age(3600),
         };
         
-        // JWT secret is passed to the app as app_data
-        // Each route handler that needs authentication will use it
-        
         App::new()
+            .wrap(RequestMetrics)
             .wrap(Logger::default())
             .wrap(cors)
+            // Outermost so every response, including middleware errors, carries a request id
+            .wrap(RequestIdMiddleware)
             .app_data(web::Data::new(db.clone()))
-            .app_data(web::Data::new(jwt_secret.clone()))
-            // Register all routes directly
-            .service(sync_user)
-            .service(login_handler)
-            .service(verify_two_factor_handler)
-            .service(register_handler)
-            .service(logout_handler)
-            .service(logout_get)
-            .service(validate_session)
-            .service(get_current_user)
-            // Sessions endpoints
-            .service(get_sessions)
-            .service(terminate_session)
-            .service(terminate_all_sessions)
-            // Profile endpoints
-            .service(upload_profile_picture)
-            .service(get_profile_image)
-            .service(update_username)
-            .service(update_password)
-            // OAuth endpoints
-            .service(oauth_google_login)
-            .service(oauth_github_login)
-            .service(oauth_callback)
-            // 2FA endpoints
-            .service(two_factor_setup)
-            .service(two_factor_verify)
-            .service(two_factor_status)
-            .service(two_factor_disable)
-            .service(two_factor_backup_codes)
-            .service(two_factor_regenerate_backup_codes)
+            .app_data(config.clone())
+            .app_data(storage.clone())
+            // Malformed request bodies, queries and paths use the same error envelope as handlers
+            .app_data(web::JsonConfig::default().error_handler(json_error_handler))
+            .app_data(web::QueryConfig::default().error_handler(query_error_handler))
+            .app_data(web::PathConfig::default().error_handler(path_error_handler))
             // Basic endpoints
             .service(root)
             .service(health_check)
-            // Admin endpoints
-            .service(get_all_users)
-            .service(delete_user)
-            .service(change_user_role)
-            .service(toggle_user_active)
-            // Password reset endpoints
-            .service(forgot_password)
-            .service(reset_password)
+            .service(health_live)
+            .service(health_ready)
+            .service(metrics_handler)
+            // Auth endpoints; JwtAuth lets the login/registration flows through
+            .service(
+                web::scope("/api/auth")
+                    .wrap(JwtAuth::new(jwt_secret.clone()))
+                    .service(sync_user)
+                    .service(login_handler)
+                    .service(verify_two_factor_handler)
+                    .service(register_handler)
+                    .service(logout_handler)
+                    .service(logout_get)
+                    .service(refresh_token_handler)
+                    .service(validate_session)
+                    // Sessions endpoints
+                    .service(get_sessions)
+                    .service(terminate_session)
+                    .service(terminate_all_sessions)
+                    // OAuth endpoints
+                    .service(oauth_google_login)
+                    .service(oauth_github_login)
+                    .service(oauth_callback)
+                    // 2FA endpoints
+                    .service(two_factor_setup)
+                    .service(two_factor_verify)
+                    .service(two_factor_status)
+                    .service(two_factor_disable)
+                    .service(two_factor_backup_codes)
+                    .service(two_factor_regenerate_backup_codes)
+                    // Password reset endpoints
+                    .service(forgot_password)
+                    .service(reset_password)
+            )
+            // Current user endpoint
+            .service(
+                web::scope("/api/me")
+                    .wrap(JwtAuth::new(jwt_secret.clone()))
+                    .service(get_current_user)
+            )
+            // Profile endpoints
+            .service(
+                web::scope("/api/user")
+                    .wrap(JwtAuth::new(jwt_secret.clone()))
+                    .service(upload_profile_picture)
+                    .service(get_profile_image)
+                    .service(update_username)
+                    .service(update_password)
+            )
+            // Admin endpoints; AdminGuard runs after JwtAuth has identified the user
+            .service(
+                web::scope("/api/admin")
+                    .wrap(AdminGuard::new())
+                    .wrap(JwtAuth::new(jwt_secret.clone()))
+                    .service(get_all_users)
+                    .service(delete_user)
+                    .service(change_user_role)
+                    .service(toggle_user_active)
+            )
             // Chat endpoints
-            .service(ws_index)
-            .service(create_room)
-            .service(get_rooms)
-            .service(get_room)
-            .service(delete_room)
-            .service(leave_room_membership)
-            .service(send_message)
-            .service(get_messages)
-            .service(verify_room_password)
-            .service(upload_chat_image)
-            .service(get_chat_image)
-            .service(join_room_by_code_handler)
-            .service(upload_voice_message)
-            .service(get_voice_message)
-            .service(upload_chat_video)
-            .service(get_chat_video)
+            .service(
+                web::scope("/api/chat")
+                    .wrap(JwtAuth::new(jwt_secret.clone()))
+                    .service(ws_index)
+                    .service(create_room)
+                    .service(get_rooms)
+                    .service(get_room)
+                    .service(delete_room)
+                    .service(leave_room_membership)
+                    .service(send_message)
+                    .service(get_messages)
+                    .service(edit_message_handler)
+                    .service(delete_message_handler)
+                    .service(get_message_edits)
+                    .service(get_thread)
+                    .service(search_messages)
+                    // Moderation endpoints
+                    .service(change_member_role)
+                    .service(kick_member)
+                    .service(mute_member)
+                    .service(unmute_member)
+                    .service(get_room_bans)
+                    .service(ban_member)
+                    .service(unban_member)
+                    .service(get_room_members)
+                    .service(verify_room_password)
+                    .service(upload_chat_image)
+                    .service(get_chat_image)
+                    .service(join_room_by_code_handler)
+                    .service(upload_voice_message)
+                    .service(get_voice_message)
+                    .service(upload_chat_video)
+                    .service(get_chat_video)
+                    .service(get_upload)
+                    .service(get_attachment)
+            )
+            .default_service(web::to(not_found))
     })
     .bind(server_url)?
     .run()
@@ -0,0 +1,73 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::metrics::observe_http_request;
+
+// Route label for requests that matched no resource, so scanners can't blow up label cardinality
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+// Request metrics middleware factory
+pub struct RequestMetrics;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestMetricsService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestMetricsService { service: Rc::new(service) }))
+    }
+}
+
+// Middleware service
+pub struct RequestMetricsService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestMetricsService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let method = req.method().to_string();
+        let started = Instant::now();
+
+        Box::pin(async move {
+            let result = service.call(req).await;
+            let seconds = started.elapsed().as_secs_f64();
+
+            match &result {
+                Ok(res) => {
+                    let route = res.request().match_pattern().unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+                    observe_http_request(&method, &route, res.status().as_u16(), seconds);
+                }
+                // Errors from middlewares no longer carry the request, so the route is unknown
+                Err(e) => {
+                    let status = e.as_response_error().status_code().as_u16();
+                    observe_http_request(&method, UNMATCHED_ROUTE, status, seconds);
+                }
+            }
+
+            result
+        })
+    }
+}
@@ -0,0 +1,173 @@
+mod middleware;
+
+pub use middleware::RequestMetrics;
+
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge, register_int_gauge_vec,
+    Encoder, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, TextEncoder,
+};
+use sea_orm::DatabaseConnection;
+use std::time::Instant;
+
+lazy_static! {
+    static ref START_TIME: Instant = Instant::now();
+
+    // Process
+    static ref UPTIME_SECONDS: IntGauge = register_int_gauge!(
+        "tforce_uptime_seconds",
+        "Seconds since the backend process started"
+    ).unwrap();
+    static ref VERSION_INFO: IntGaugeVec = register_int_gauge_vec!(
+        "tforce_version_info",
+        "Backend version, always 1",
+        &["version"]
+    ).unwrap();
+
+    // HTTP
+    static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "tforce_http_requests_total",
+        "HTTP requests handled, by method, matched route and status code",
+        &["method", "route", "status"]
+    ).unwrap();
+    static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "tforce_http_request_duration_seconds",
+        "HTTP request latency, by method and matched route",
+        &["method", "route"],
+        vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]
+    ).unwrap();
+
+    // Chat
+    static ref WS_CONNECTIONS: IntGauge = register_int_gauge!(
+        "tforce_ws_active_connections",
+        "Open websocket connections"
+    ).unwrap();
+    static ref CHAT_ACTIVE_ROOMS: IntGauge = register_int_gauge!(
+        "tforce_chat_active_rooms",
+        "Rooms with at least one connected websocket"
+    ).unwrap();
+    static ref CHAT_MESSAGES: IntCounterVec = register_int_counter_vec!(
+        "tforce_chat_messages_total",
+        "Chat messages by outcome (broadcast, persisted, failed)",
+        &["outcome"]
+    ).unwrap();
+
+    // Auth
+    static ref LOGINS: IntCounterVec = register_int_counter_vec!(
+        "tforce_auth_logins_total",
+        "Successful logins, by method",
+        &["method"]
+    ).unwrap();
+    static ref LOGIN_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "tforce_auth_login_failures_total",
+        "Rejected login attempts, by reason",
+        &["reason"]
+    ).unwrap();
+    static ref TWO_FACTOR_CHALLENGES: IntCounterVec = register_int_counter_vec!(
+        "tforce_auth_two_factor_challenges_total",
+        "Two-factor challenges, by result (issued, passed, failed)",
+        &["result"]
+    ).unwrap();
+
+    // Uploads
+    static ref UPLOAD_BYTES: IntCounterVec = register_int_counter_vec!(
+        "tforce_upload_bytes_total",
+        "Bytes of uploaded media stored, by media type",
+        &["media_type"]
+    ).unwrap();
+    static ref UPLOADS: IntCounterVec = register_int_counter_vec!(
+        "tforce_uploads_total",
+        "Uploaded media files stored, by media type",
+        &["media_type"]
+    ).unwrap();
+
+    // Database
+    static ref DB_POOL_CONNECTIONS: IntGaugeVec = register_int_gauge_vec!(
+        "tforce_db_pool_connections",
+        "Database pool connections, by state (idle, in_use)",
+        &["state"]
+    ).unwrap();
+    static ref DB_POOL_MAX_CONNECTIONS: IntGauge = register_int_gauge!(
+        "tforce_db_pool_max_connections",
+        "Maximum size of the database pool"
+    ).unwrap();
+}
+
+/// Register every metric so they are exported from the first scrape on
+pub fn init() {
+    lazy_static::initialize(&START_TIME);
+    lazy_static::initialize(&UPTIME_SECONDS);
+    lazy_static::initialize(&HTTP_REQUESTS);
+    lazy_static::initialize(&HTTP_REQUEST_DURATION);
+    lazy_static::initialize(&WS_CONNECTIONS);
+    lazy_static::initialize(&CHAT_ACTIVE_ROOMS);
+    lazy_static::initialize(&CHAT_MESSAGES);
+    lazy_static::initialize(&LOGINS);
+    lazy_static::initialize(&LOGIN_FAILURES);
+    lazy_static::initialize(&TWO_FACTOR_CHALLENGES);
+    lazy_static::initialize(&UPLOAD_BYTES);
+    lazy_static::initialize(&UPLOADS);
+    lazy_static::initialize(&DB_POOL_CONNECTIONS);
+    lazy_static::initialize(&DB_POOL_MAX_CONNECTIONS);
+
+    VERSION_INFO.with_label_values(&[env!("CARGO_PKG_VERSION")]).set(1);
+}
+
+pub fn observe_http_request(method: &str, route: &str, status: u16, seconds: f64) {
+    HTTP_REQUESTS
+        .with_label_values(&[method, route, &status.to_string()])
+        .inc();
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[method, route])
+        .observe(seconds);
+}
+
+pub fn set_chat_stats(connections: usize, active_rooms: usize) {
+    WS_CONNECTIONS.set(connections as i64);
+    CHAT_ACTIVE_ROOMS.set(active_rooms as i64);
+}
+
+// `outcome` is one of "broadcast", "persisted" or "failed"
+pub fn record_chat_message(outcome: &str) {
+    CHAT_MESSAGES.with_label_values(&[outcome]).inc();
+}
+
+pub fn record_login(method: &str) {
+    LOGINS.with_label_values(&[method]).inc();
+}
+
+pub fn record_login_failure(reason: &str) {
+    LOGIN_FAILURES.with_label_values(&[reason]).inc();
+}
+
+// `result` is one of "issued", "passed" or "failed"
+pub fn record_two_factor_challenge(result: &str) {
+    TWO_FACTOR_CHALLENGES.with_label_values(&[result]).inc();
+}
+
+pub fn record_upload(media_type: &str, bytes: usize) {
+    UPLOADS.with_label_values(&[media_type]).inc();
+    UPLOAD_BYTES.with_label_values(&[media_type]).inc_by(bytes as u64);
+}
+
+/// Render all metrics in the Prometheus text format
+///
+/// Gauges that are sampled rather than updated as things happen (uptime and
+/// database pool usage) are refreshed first.
+pub fn render(db: &DatabaseConnection) -> Result<String, String> {
+    UPTIME_SECONDS.set(START_TIME.elapsed().as_secs() as i64);
+
+    let pool = db.get_postgres_connection_pool();
+    let size = pool.size() as i64;
+    let idle = pool.num_idle() as i64;
+    DB_POOL_CONNECTIONS.with_label_values(&["idle"]).set(idle);
+    DB_POOL_CONNECTIONS.with_label_values(&["in_use"]).set(size - idle);
+    DB_POOL_MAX_CONNECTIONS.set(pool.options().get_max_connections() as i64);
+
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .map_err(|e| e.to_string())?;
+
+    String::from_utf8(buffer).map_err(|e| e.to_string())
+}
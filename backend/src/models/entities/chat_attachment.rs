@@ -0,0 +1,64 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use serde_json::Value;
+
+// An uploaded file, downloadable by members of the room it was uploaded to
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "chat_attachments")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub owner_id: Uuid,
+    pub room_id: Uuid,
+    // Message the attachment was sent in; None until it is sent
+    pub message_id: Option<Uuid>,
+    // "image", "video" or "audio"
+    pub kind: String,
+    #[sea_orm(unique)]
+    pub storage_key: String,
+    pub mime_type: String,
+    pub size_bytes: Option<i64>,
+    // Hex-encoded SHA-256 of the file
+    pub checksum: Option<String>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub duration_ms: Option<i32>,
+    // Placeholder shown while an image loads
+    pub blurhash: Option<String>,
+    // Smaller copies of an image, as written by `api::chat::preview`
+    #[sea_orm(column_type = "JsonBinary", nullable)]
+    pub previews: Option<Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(belongs_to = "super::chat_room::Entity", from = "Column::RoomId", to = "super::chat_room::Column::Id")]
+    ChatRoom,
+    #[sea_orm(belongs_to = "super::chat_message::Entity", from = "Column::MessageId", to = "super::chat_message::Column::Id")]
+    ChatMessage,
+    #[sea_orm(belongs_to = "super::user::Entity", from = "Column::OwnerId", to = "super::user::Column::Id")]
+    User,
+}
+
+impl Related<super::chat_room::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ChatRoom.def()
+    }
+}
+
+impl Related<super::chat_message::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ChatMessage.def()
+    }
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
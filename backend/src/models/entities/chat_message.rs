@@ -13,6 +13,12 @@ pub struct Model {
     pub content: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
+    // Deleted messages are kept as tombstones; their content is never sent to clients
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub deleted_by: Option<Uuid>,
+    // Root message of the thread this message replies to
+    pub reply_to_id: Option<Uuid>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -21,6 +27,10 @@ pub enum Relation {
     ChatRoom,
     #[sea_orm(belongs_to = "super::user::Entity", from = "Column::UserId", to = "super::user::Column::Id")]
     User,
+    #[sea_orm(has_many = "super::chat_message_edit::Entity")]
+    ChatMessageEdit,
+    #[sea_orm(has_many = "super::chat_attachment::Entity")]
+    ChatAttachment,
 }
 
 impl Related<super::chat_room::Entity> for Entity {
@@ -35,13 +45,37 @@ impl Related<super::user::Entity> for Entity {
     }
 }
 
+impl Related<super::chat_message_edit::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ChatMessageEdit.def()
+    }
+}
+
+impl Related<super::chat_attachment::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ChatAttachment.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
 
+impl Model {
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+}
+
 // DTOs for message creation and responses
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateMessageDto {
     pub room_id: Uuid,
     pub content: String,
+    // Message being replied to; the reply joins that message's thread
+    #[serde(default)]
+    pub reply_to: Option<Uuid>,
+    // Uploaded attachments to send with the message
+    #[serde(default)]
+    pub attachment_ids: Vec<Uuid>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -52,6 +86,8 @@ pub struct MessageResponseDto {
     pub content: String,
     pub created_at: DateTime<Utc>,
     pub user_name: Option<String>,
+    pub reply_to_id: Option<Uuid>,
+    pub attachments: Vec<serde_json::Value>,
 }
 
 // This is a more complete DTO that includes user information
@@ -64,4 +100,11 @@ pub struct MessageWithUserDto {
     pub user_profile_image: Option<String>,
     pub content: String,
     pub created_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub deleted: bool,
+    pub reply_to_id: Option<Uuid>,
+    // Thread summary, for messages that have replies
+    pub reply_count: i64,
+    pub last_reply_at: Option<DateTime<Utc>>,
+    pub last_reply_user_id: Option<Uuid>,
 }
\ No newline at end of file
@@ -58,6 +58,9 @@ pub struct RoomResponseDto {
     pub is_owner: bool,
     pub room_code: String,
     pub user_count: u64,
+    // Timeline messages from other members after the user's read marker
+    pub unread_count: u64,
+    pub first_unread_message_id: Option<Uuid>,
 }
 
 impl From<Model> for RoomResponseDto {
@@ -72,6 +75,8 @@ impl From<Model> for RoomResponseDto {
             is_owner: false, // Will be set separately based on user context
             room_code: room.room_code,
             user_count: 0, // Will be set separately
+            unread_count: 0,
+            first_unread_message_id: None,
         }
     }
 }
\ No newline at end of file
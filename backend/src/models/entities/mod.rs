@@ -3,8 +3,13 @@ pub mod two_factor_auth;
 pub mod user_session;
 pub mod chat_room;
 pub mod chat_message;
+pub mod chat_message_edit;
+pub mod chat_attachment;
 pub mod message_reaction;
 pub mod room_membership;
+pub mod room_ban;
+pub mod revoked_token;
+pub mod refresh_token;
 
 pub use user::{Entity as User, Model as UserModel, ActiveModel as UserActiveModel};
 pub use user::{CreateUserDto, UserResponseDto};
@@ -21,8 +26,18 @@ pub use chat_room::{CreateRoomDto, RoomResponseDto};
 pub use chat_message::{Entity as ChatMessage, Model as ChatMessageModel, ActiveModel as ChatMessageActiveModel};
 pub use chat_message::{CreateMessageDto, MessageResponseDto, MessageWithUserDto};
 
+pub use chat_message_edit::{Entity as ChatMessageEdit, ActiveModel as ChatMessageEditActiveModel};
+
+pub use chat_attachment::{Entity as ChatAttachment, ActiveModel as ChatAttachmentActiveModel};
+
 pub use message_reaction::{Entity as MessageReaction, Model as MessageReactionModel, ActiveModel as MessageReactionActiveModel};
 pub use message_reaction::{CreateReactionDto, ReactionResponseDto, ReactionWithUserDto, ReactionCountDto, ReactionUserDto, MessageWithReactionsDto};
 
 pub use room_membership::{Entity as RoomMembership, Model as RoomMembershipModel, ActiveModel as RoomMembershipActiveModel};
-pub use room_membership::{RoomMembershipResponseDto};
\ No newline at end of file
+pub use room_membership::{RoomMembershipResponseDto, RoomRole};
+
+pub use room_ban::{Entity as RoomBan, ActiveModel as RoomBanActiveModel};
+
+pub use revoked_token::{Entity as RevokedToken, ActiveModel as RevokedTokenActiveModel};
+
+pub use refresh_token::{Entity as RefreshToken, ActiveModel as RefreshTokenActiveModel};
\ No newline at end of file
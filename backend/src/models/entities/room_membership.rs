@@ -11,6 +11,13 @@ pub struct Model {
     pub user_id: Uuid,
     pub room_id: Uuid,
     pub joined_at: DateTime<Utc>,
+    // Newest room message the member has read; None until they first read something
+    pub last_read_message_id: Option<Uuid>,
+    pub last_read_at: Option<DateTime<Utc>>,
+    // 'owner', 'moderator' or 'member'; see RoomRole
+    pub role: String,
+    // The member may not post until this time
+    pub muted_until: Option<DateTime<Utc>>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -35,6 +42,44 @@ impl Related<super::chat_room::Entity> for Entity {
 
 impl ActiveModelBehavior for ActiveModel {}
 
+/// Privilege of a member within a room, from least to most privileged
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RoomRole {
+    Member,
+    Moderator,
+    Owner,
+}
+
+impl RoomRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RoomRole::Member => "member",
+            RoomRole::Moderator => "moderator",
+            RoomRole::Owner => "owner",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "member" => Some(RoomRole::Member),
+            "moderator" => Some(RoomRole::Moderator),
+            "owner" => Some(RoomRole::Owner),
+            _ => None,
+        }
+    }
+}
+
+impl Model {
+    // Unknown roles are treated as plain members
+    pub fn room_role(&self) -> RoomRole {
+        RoomRole::parse(&self.role).unwrap_or(RoomRole::Member)
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted_until.is_some_and(|until| until > Utc::now())
+    }
+}
+
 // DTOs for room membership
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RoomMembershipResponseDto {
@@ -42,6 +87,8 @@ pub struct RoomMembershipResponseDto {
     pub user_id: Uuid,
     pub room_id: Uuid,
     pub joined_at: DateTime<Utc>,
+    pub role: String,
+    pub muted_until: Option<DateTime<Utc>>,
 }
 
 impl From<Model> for RoomMembershipResponseDto {
@@ -51,6 +98,8 @@ impl From<Model> for RoomMembershipResponseDto {
             user_id: membership.user_id,
             room_id: membership.room_id,
             joined_at: membership.joined_at,
+            role: membership.role,
+            muted_until: membership.muted_until,
         }
     }
 }
\ No newline at end of file
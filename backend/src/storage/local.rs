@@ -0,0 +1,86 @@
+use actix_web::web::Bytes;
+use async_trait::async_trait;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use tokio::fs;
+use uuid::Uuid;
+
+use super::{Storage, StorageError, StoredObject, CHAT_IMAGES, CHAT_VIDEOS, PROFILE_PICTURES, VOICE_MESSAGES};
+
+/// Files on the local disk, under a root directory
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    // Keys may only name files below the root
+    fn path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        let relative = Path::new(key);
+        if relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+            return Err(StorageError::InvalidKey(key.to_string()));
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, data: Bytes, _content_type: &str) -> Result<(), StorageError> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        // Write under a temporary name first so readers never see a partial file
+        let partial = path.with_extension(format!("partial-{}", Uuid::new_v4()));
+        if let Err(e) = fs::write(&partial, &data).await {
+            let _ = fs::remove_file(&partial).await;
+            return Err(e.into());
+        }
+        fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<StoredObject, StorageError> {
+        match fs::read(self.path(key)?).await {
+            Ok(data) => Ok(StoredObject {
+                data: Bytes::from(data),
+                content_type: None,
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(StorageError::NotFound),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match fs::remove_file(self.path(key)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn presigned_url(&self, _key: &str) -> Result<Option<String>, StorageError> {
+        Ok(None)
+    }
+
+    fn local_path(&self, key: &str) -> Result<Option<PathBuf>, StorageError> {
+        self.path(key).map(Some)
+    }
+
+    // Write and remove a probe file in every upload folder
+    async fn check(&self) -> Result<(), StorageError> {
+        for folder in [PROFILE_PICTURES, CHAT_IMAGES, CHAT_VIDEOS, VOICE_MESSAGES] {
+            let dir = self.root.join(folder);
+            let probe = dir.join(format!(".ready-{}", Uuid::new_v4()));
+            fs::create_dir_all(&dir).await?;
+            fs::write(&probe, b"").await?;
+            fs::remove_file(&probe).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+mod local;
+mod s3;
+
+use async_trait::async_trait;
+use actix_files::NamedFile;
+use actix_web::http::header::{HeaderValue, CACHE_CONTROL};
+use actix_web::web::Bytes;
+use actix_web::{mime, HttpRequest, HttpResponse};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::config::StorageConfig;
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+// Folders every upload lives under, one per kind of upload
+pub const PROFILE_PICTURES: &str = "profile_pictures";
+pub const CHAT_IMAGES: &str = "chat_images";
+pub const CHAT_VIDEOS: &str = "chat_videos";
+pub const VOICE_MESSAGES: &str = "voice_messages";
+
+#[derive(thiserror::Error, Debug)]
+pub enum StorageError {
+    #[error("Object not found")]
+    NotFound,
+    #[error("Invalid object key: {0}")]
+    InvalidKey(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Storage backend error: {0}")]
+    Backend(String),
+}
+
+/// Who may keep a copy of a served file
+#[derive(Clone, Copy, Debug)]
+pub enum Caching {
+    /// Anyone may download the file, so shared caches may keep it too
+    Public,
+    /// Only some users may download the file, so only their own browsers may keep it
+    Private,
+}
+
+impl Caching {
+    // Keys are never reused for other content, so a cached copy never goes stale
+    fn header_value(self) -> &'static str {
+        match self {
+            Caching::Public => "public, max-age=31536000, immutable",
+            Caching::Private => "private, max-age=31536000, immutable",
+        }
+    }
+}
+
+/// An object read back from storage
+pub struct StoredObject {
+    pub data: Bytes,
+    // As recorded when the object was stored, if the backend keeps it
+    pub content_type: Option<String>,
+}
+
+/// Where uploaded files are kept
+///
+/// Keys are relative paths such as `chat_images/<uuid>.png`; build them with [`object_key`].
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Store an object, replacing any object with the same key
+    async fn put(&self, key: &str, data: Bytes, content_type: &str) -> Result<(), StorageError>;
+
+    async fn get(&self, key: &str) -> Result<StoredObject, StorageError>;
+
+    /// Remove an object; removing a missing object is not an error
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+
+    /// A time-limited URL clients can download the object from directly,
+    /// or None when objects are only served through the API
+    async fn presigned_url(&self, key: &str) -> Result<Option<String>, StorageError>;
+
+    /// The file an object is kept in, for backends on the local disk, or None
+    fn local_path(&self, key: &str) -> Result<Option<PathBuf>, StorageError>;
+
+    /// Fail unless objects can be written and read, for readiness checks
+    async fn check(&self) -> Result<(), StorageError>;
+}
+
+/// The key of an uploaded file in a folder
+///
+/// Only the last path component of `filename` is used, so client-supplied names cannot escape the folder.
+pub fn object_key(folder: &str, filename: &str) -> Result<String, StorageError> {
+    let name = std::path::Path::new(filename)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .filter(|name| !name.starts_with('.'))
+        .ok_or_else(|| StorageError::InvalidKey(filename.to_string()))?;
+    Ok(format!("{}/{}", folder, name))
+}
+
+/// Answer a download: redirect to a presigned URL when the backend has one,
+/// otherwise send the object with long-lived cache headers
+///
+/// Files on the local disk are streamed, answering `Range` requests with `206 Partial Content`
+/// (unsatisfiable ranges get `416`), and carry an
+/// `ETag` and `Last-Modified` so `If-None-Match` and `If-Modified-Since` get `304 Not Modified`.
+/// Presigned URLs leave all of that to the backend.
+///
+/// There are no `multipart/byteranges` responses: of several ranges only the first is sent,
+/// as a plain `206` whose `Content-Range` tells the client which bytes it got. Media players
+/// only ever ask for one range, and anyone else asks again for the rest.
+///
+/// `content_type` is used when the backend did not record one for the object.
+pub async fn serve(
+    req: &HttpRequest,
+    storage: &dyn Storage,
+    key: &str,
+    content_type: &str,
+    caching: Caching,
+) -> Result<HttpResponse, StorageError> {
+    if let Some(url) = storage.presigned_url(key).await? {
+        // The URL expires, so the redirect itself must not be cached
+        return Ok(HttpResponse::Found()
+            .append_header(("Location", url))
+            .append_header(("Cache-Control", "no-store"))
+            .finish());
+    }
+
+    if let Some(path) = storage.local_path(key)? {
+        let file = match NamedFile::open_async(&path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Err(StorageError::NotFound),
+            Err(e) => return Err(e.into()),
+        };
+        let mut response = file
+            .set_content_type(content_type.parse().unwrap_or(mime::APPLICATION_OCTET_STREAM))
+            .disable_content_disposition()
+            .use_etag(true)
+            .use_last_modified(true)
+            .into_response(req);
+        response
+            .headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static(caching.header_value()));
+        return Ok(response);
+    }
+
+    let object = storage.get(key).await?;
+    if object.data.is_empty() {
+        return Err(StorageError::NotFound);
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(object.content_type.as_deref().unwrap_or(content_type))
+        .append_header(("Cache-Control", caching.header_value()))
+        .body(object.data))
+}
+
+/// Build the storage backend selected in the configuration
+pub fn from_config(config: &StorageConfig) -> Arc<dyn Storage> {
+    match config {
+        StorageConfig::Local { root } => Arc::new(LocalStorage::new(root.clone())),
+        StorageConfig::S3(s3) => Arc::new(S3Storage::new(s3.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RANGE};
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+    use uuid::Uuid;
+
+    const KEY: &str = "chat_videos/clip.mp4";
+
+    // A local storage root holding KEY, removed again when dropped
+    struct Fixture {
+        root: PathBuf,
+        data: Vec<u8>,
+    }
+
+    impl Fixture {
+        async fn new() -> Self {
+            let root = std::env::temp_dir().join(format!("tforce-serve-{}", Uuid::new_v4()));
+            let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+            LocalStorage::new(root.clone())
+                .put(KEY, Bytes::from(data.clone()), "video/mp4")
+                .await
+                .unwrap();
+            Self { root, data }
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    async fn download(req: HttpRequest, storage: web::Data<LocalStorage>) -> HttpResponse {
+        serve(&req, storage.get_ref(), KEY, "video/mp4", Caching::Private).await.unwrap()
+    }
+
+    macro_rules! app {
+        ($fixture:expr) => {
+            test::init_service(
+                App::new()
+                    .app_data(web::Data::new(LocalStorage::new($fixture.root.clone())))
+                    .route("/download", web::get().to(download)),
+            )
+            .await
+        };
+    }
+
+    fn header<B>(response: &actix_web::dev::ServiceResponse<B>, name: actix_web::http::header::HeaderName) -> Option<String> {
+        response.headers().get(name).map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[actix_web::test]
+    async fn whole_file_carries_validators_and_cache_control() {
+        let fixture = Fixture::new().await;
+        let app = app!(fixture);
+
+        let response = test::call_service(&app, test::TestRequest::get().uri("/download").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, CONTENT_TYPE).as_deref(), Some("video/mp4"));
+        assert_eq!(header(&response, CACHE_CONTROL).as_deref(), Some(Caching::Private.header_value()));
+        assert!(header(&response, ETAG).is_some());
+        assert!(header(&response, LAST_MODIFIED).is_some());
+        assert_eq!(test::read_body(response).await, fixture.data);
+    }
+
+    #[actix_web::test]
+    async fn single_range_is_partial_content() {
+        let fixture = Fixture::new().await;
+        let app = app!(fixture);
+
+        let request = test::TestRequest::get().uri("/download").insert_header((RANGE, "bytes=100-199")).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(header(&response, CONTENT_RANGE).as_deref(), Some("bytes 100-199/1000"));
+        assert_eq!(test::read_body(response).await, fixture.data[100..200]);
+
+        let request = test::TestRequest::get().uri("/download").insert_header((RANGE, "bytes=-10")).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(header(&response, CONTENT_RANGE).as_deref(), Some("bytes 990-999/1000"));
+        assert_eq!(test::read_body(response).await, fixture.data[990..]);
+    }
+
+    #[actix_web::test]
+    async fn several_ranges_get_only_the_first() {
+        let fixture = Fixture::new().await;
+        let app = app!(fixture);
+
+        let request = test::TestRequest::get()
+            .uri("/download")
+            .insert_header((RANGE, "bytes=0-9,500-509"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(header(&response, CONTENT_TYPE).as_deref(), Some("video/mp4"));
+        assert_eq!(header(&response, CONTENT_RANGE).as_deref(), Some("bytes 0-9/1000"));
+        assert_eq!(test::read_body(response).await, fixture.data[..10]);
+    }
+
+    #[actix_web::test]
+    async fn unsatisfiable_range_is_rejected() {
+        let fixture = Fixture::new().await;
+        let app = app!(fixture);
+
+        for range in ["bytes=1000-1999", "bytes=abc"] {
+            let request = test::TestRequest::get().uri("/download").insert_header((RANGE, range)).to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE, "{}", range);
+            assert_eq!(header(&response, CONTENT_RANGE).as_deref(), Some("bytes */1000"), "{}", range);
+        }
+    }
+
+    #[actix_web::test]
+    async fn matching_etag_is_not_modified() {
+        let fixture = Fixture::new().await;
+        let app = app!(fixture);
+
+        let response = test::call_service(&app, test::TestRequest::get().uri("/download").to_request()).await;
+        let etag = header(&response, ETAG).unwrap();
+
+        let request = test::TestRequest::get().uri("/download").insert_header((IF_NONE_MATCH, etag.as_str())).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(header(&response, CACHE_CONTROL).as_deref(), Some(Caching::Private.header_value()));
+        assert!(test::read_body(response).await.is_empty());
+
+        let request = test::TestRequest::get().uri("/download").insert_header((IF_NONE_MATCH, "\"other\"")).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn unmodified_since_is_not_modified() {
+        let fixture = Fixture::new().await;
+        let app = app!(fixture);
+
+        let response = test::call_service(&app, test::TestRequest::get().uri("/download").to_request()).await;
+        let last_modified = header(&response, LAST_MODIFIED).unwrap();
+
+        let request = test::TestRequest::get()
+            .uri("/download")
+            .insert_header((IF_MODIFIED_SINCE, last_modified.as_str()))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let request = test::TestRequest::get()
+            .uri("/download")
+            .insert_header((IF_MODIFIED_SINCE, "Thu, 01 Jan 1970 00:00:00 GMT"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -1,11 +1,15 @@
 import React, { useState } from 'react';
 import AudioPlayer from '@/components/chat/AudioPlayer';
 import { Dialog, DialogContent, DialogTrigger } from '@/components/ui/dialog';
+import { MessageAttachment } from '../types';
 
 interface MessageContentProps {
     content: string;
 }
 
+// Longest side of the preview shown in the message list; the original opens on click
+const INLINE_PREVIEW_SIZE = 640;
+
 // Regex patterns
 const imageMarkdownRegex = /\[image\]\((.*?)\)/;
 const audioMarkdownRegex = /\[audio\]\((.*?)\)/;
@@ -103,90 +107,132 @@ const YouTubePlayer: React.FC<{ videoId: string; url: string }> = ({ videoId, ur
 };
 
 /**
- * Parses the message content and renders it as an image, video, link, or plain text.
+ * Image with a click-to-zoom dialog, showing a smaller preview inline when there is one
  */
-export const MessageContent: React.FC<MessageContentProps> = ({ content }) => {
+const ChatImage: React.FC<{ src: string; previewSrc?: string; width?: number | null; height?: number | null }> = ({
+    src,
+    previewSrc,
+    width,
+    height,
+}) => {
     const [imageError, setImageError] = useState(false);
 
+    return (
+        <div className="mt-1">
+            {!imageError ? (
+                <Dialog>
+                    <DialogTrigger asChild>
+                        <button
+                            type="button"
+                            className="block group"
+                            onClick={(e) => e.stopPropagation()} // Prevent triggering parent click handlers
+                            aria-label="Zoom image"
+                        >
+                            <div className="relative overflow-hidden rounded-xl shadow-lg transition-all duration-300 hover:shadow-xl">
+                                <img
+                                    src={previewSrc ?? src}
+                                    width={width ?? undefined}
+                                    height={height ?? undefined}
+                                    alt="Image uploaded to chat"
+                                    className="max-w-[280px] md:max-w-xs w-full h-auto object-cover transition-transform duration-300 group-hover:scale-105"
+                                    onError={() => setImageError(true)}
+                                    onLoad={() => setImageError(false)}
+                                />
+                                <div className="absolute inset-0 bg-black/0 group-hover:bg-black/10 transition-colors duration-300" />
+                            </div>
+                        </button>
+                    </DialogTrigger>
+                    <DialogContent className="max-w-3xl w-[90vw] md:w-auto p-0 bg-transparent border-none shadow-none">
+                        <div className="relative">
+                            <img
+                                src={src}
+                                alt="Image uploaded in chat (expanded)"
+                                className="w-full h-auto rounded-xl"
+                            />
+                        </div>
+                    </DialogContent>
+                </Dialog>
+            ) : (
+                <div className="flex items-center gap-3 p-4 bg-gradient-to-r from-gray-100 to-gray-200 dark:from-gray-800 dark:to-gray-700 rounded-xl border border-dashed border-gray-300 dark:border-gray-600 shadow-sm">
+                    <div className="text-gray-500 dark:text-gray-400">
+                        <svg className="w-8 h-8" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                            <path strokeLinecap="round" strokeLinejoin="round" strokeWidth={2} d="M4 16l4.586-4.586a2 2 0 012.828 0L16 16m-2-2l1.586-1.586a2 2 0 012.828 0L20 14m-6-6h.01M6 20h12a2 2 0 002-2V6a2 2 0 00-2-2H6a2 2 0 00-2 2v12a2 2 0 002 2z" />
+                        </svg>
+                    </div>
+                    <div className="text-sm text-gray-600 dark:text-gray-300">
+                        <div className="font-semibold">Image not available</div>
+                        <div className="text-xs opacity-75">The image could not be loaded</div>
+                    </div>
+                </div>
+            )}
+        </div>
+    );
+};
+
+const ChatVideo: React.FC<{ src: string }> = ({ src }) => (
+    <div className="mt-1">
+        <video
+            src={src}
+            controls
+            className="max-w-[280px] md:max-w-xs w-full h-auto rounded-xl shadow-lg"
+        />
+    </div>
+);
+
+const ChatAudio: React.FC<{ src: string }> = ({ src }) => (
+    <div className="mt-1">
+        <AudioPlayer audioUrl={src} />
+    </div>
+);
+
+/**
+ * Renders a file sent with a message
+ */
+export const AttachmentContent: React.FC<{ attachment: MessageAttachment }> = ({ attachment }) => {
+    switch (attachment.kind) {
+        case 'image': {
+            // The largest preview that still fits inline
+            const preview = (attachment.previews ?? [])
+                .filter(p => p.size <= INLINE_PREVIEW_SIZE)
+                .sort((a, b) => b.size - a.size)[0];
+            return (
+                <ChatImage
+                    src={attachment.url}
+                    previewSrc={preview?.url}
+                    width={preview?.width ?? attachment.width}
+                    height={preview?.height ?? attachment.height}
+                />
+            );
+        }
+        case 'video':
+            return <ChatVideo src={attachment.url} />;
+        case 'audio':
+            return <ChatAudio src={attachment.url} />;
+        default:
+            return null;
+    }
+};
+
+/**
+ * Parses the message content and renders it as an image, video, link, or plain text.
+ */
+export const MessageContent: React.FC<MessageContentProps> = ({ content }) => {
     // 1. Check if the content is an audio file
     const audioMatch = content.match(audioMarkdownRegex);
     if (audioMatch && audioMatch[1]) {
-        const audioUrl = audioMatch[1];
-        console.log('Audio message found:', audioUrl);
-        return (
-            <div className="mt-1">
-                <AudioPlayer audioUrl={audioUrl} />
-            </div>
-        );
+        return <ChatAudio src={audioMatch[1]} />;
     }
 
     // 2. Check if the content is a video
     const videoMatch = content.match(videoMarkdownRegex);
     if (videoMatch && videoMatch[1]) {
-        const videoUrl = videoMatch[1];
-        return (
-            <div className="mt-1">
-                <video
-                    src={videoUrl}
-                    controls
-                    className="max-w-[280px] md:max-w-xs w-full h-auto rounded-xl shadow-lg"
-                />
-            </div>
-        );
+        return <ChatVideo src={videoMatch[1]} />;
     }
 
     // 3. Check if the content is an image
     const imageMatch = content.match(imageMarkdownRegex);
     if (imageMatch && imageMatch[1]) {
-        const imageUrl = imageMatch[1];
-        return (
-            <div className="mt-1">
-                {!imageError ? (
-                    <Dialog>
-                        <DialogTrigger asChild>
-                            <button
-                                type="button"
-                                className="block group"
-                                onClick={(e) => e.stopPropagation()} // Prevent triggering parent click handlers
-                                aria-label="Zoom image"
-                            >
-                                <div className="relative overflow-hidden rounded-xl shadow-lg transition-all duration-300 hover:shadow-xl">
-                                    <img
-                                        src={imageUrl}
-                                        alt="Image uploaded to chat"
-                                        className="max-w-[280px] md:max-w-xs w-full h-auto object-cover transition-transform duration-300 group-hover:scale-105"
-                                        onError={() => setImageError(true)}
-                                        onLoad={() => setImageError(false)}
-                                    />
-                                    <div className="absolute inset-0 bg-black/0 group-hover:bg-black/10 transition-colors duration-300" />
-                                </div>
-                            </button>
-                        </DialogTrigger>
-                        <DialogContent className="max-w-3xl w-[90vw] md:w-auto p-0 bg-transparent border-none shadow-none">
-                            <div className="relative">
-                                <img
-                                    src={imageUrl}
-                                    alt="Image uploaded in chat (expanded)"
-                                    className="w-full h-auto rounded-xl"
-                                />
-                            </div>
-                        </DialogContent>
-                    </Dialog>
-                ) : (
-                    <div className="flex items-center gap-3 p-4 bg-gradient-to-r from-gray-100 to-gray-200 dark:from-gray-800 dark:to-gray-700 rounded-xl border border-dashed border-gray-300 dark:border-gray-600 shadow-sm">
-                        <div className="text-gray-500 dark:text-gray-400">
-                            <svg className="w-8 h-8" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                                <path strokeLinecap="round" strokeLinejoin="round" strokeWidth={2} d="M4 16l4.586-4.586a2 2 0 012.828 0L16 16m-2-2l1.586-1.586a2 2 0 012.828 0L20 14m-6-6h.01M6 20h12a2 2 0 002-2V6a2 2 0 00-2-2H6a2 2 0 00-2 2v12a2 2 0 002 2z" />
-                            </svg>
-                        </div>
-                        <div className="text-sm text-gray-600 dark:text-gray-300">
-                            <div className="font-semibold">Image not available</div>
-                            <div className="text-xs opacity-75">The image could not be loaded</div>
-                        </div>
-                    </div>
-                )}
-            </div>
-        );
+        return <ChatImage src={imageMatch[1]} />;
     }
 
     // 4. Check if the content is a single YouTube URL
@@ -7,14 +7,15 @@ import { useToast } from '@/hooks/use-toast';
 import { Popover, PopoverContent, PopoverTrigger } from '@/components/ui/popover';
 import { useReactions } from '../hooks/useReactions';
 import { VoiceMessage } from '@/components/chat/VoiceMessage';
+import { MessageAttachment } from '../types';
 
 interface MessageInputProps {
   selectedRoom: any;
   isConnected: boolean;
-  onSendMessage: (content: string) => boolean;
+  onSendMessage: (content: string, attachments?: MessageAttachment[]) => boolean;
   onTypingIndicator: (isTyping: boolean) => void;
-  onUploadImage?: (file: File) => Promise<string | null>;
-  onUploadVideo?: (file: File) => Promise<string | null>;
+  onUploadImage?: (file: File) => Promise<MessageAttachment | null>;
+  onUploadVideo?: (file: File) => Promise<MessageAttachment | null>;
   onSendVoiceMessage?: (audioBlob: Blob) => Promise<boolean>;
 }
 
@@ -167,11 +168,10 @@ export function MessageInput({
     
     setUploadingType('image');
     try {
-      const imageUrl = await onUploadImage(file);
-      if (imageUrl) {
-        // Send the image message
-        const imageMessage = `[image](${imageUrl})`;
-        const success = onSendMessage(imageMessage);
+      const attachment = await onUploadImage(file);
+      if (attachment) {
+        // Send the image as a message of its own
+        const success = onSendMessage('', [attachment]);
         if (success) {
           toast({
             title: "Success",
@@ -225,10 +225,9 @@ export function MessageInput({
 
     setUploadingType('video');
     try {
-      const videoUrl = await onUploadVideo(file);
-      if (videoUrl) {
-        const videoMessage = `[video](${videoUrl})`;
-        const success = onSendMessage(videoMessage);
+      const attachment = await onUploadVideo(file);
+      if (attachment) {
+        const success = onSendMessage('', [attachment]);
         if (success) {
           toast({
             title: 'Success',
@@ -38,7 +38,7 @@ import {
 import { format } from "date-fns";
 import { Message, MessageReaction } from "../types";
 import { cn } from "@/lib/utils";
-import { MessageContent } from "@/app/dashboard/chat/components/MessageContent";
+import { AttachmentContent, MessageContent } from "@/app/dashboard/chat/components/MessageContent";
 
 interface MessageListProps {
   messages: Message[];
@@ -58,6 +58,9 @@ interface MessageListProps {
   lastSoundMessageIdRef: React.MutableRefObject<string>;
 }
 
+// Content of messages sent before attachments had ids: a single [image](url), [video](url) or [audio](url) link
+const legacyAttachmentRegex = /^\[(image|video|audio)\]\([^)]*\)$/;
+
 // Enhanced emoji data with icons and labels
 const EMOJI_DATA = [
   { emoji: "👍", icon: <ThumbsUp className="h-4 w-4" />, label: "Thumbs Up" },
@@ -455,7 +458,13 @@ export function MessageList({
           >
             {/* Message Content */}
             <div className="whitespace-pre-wrap break-words text-sm leading-relaxed">
-              <MessageContent content={message.content} />
+              {message.attachments?.map(attachment => (
+                <AttachmentContent key={attachment.id} attachment={attachment} />
+              ))}
+              {/* Messages from before attachments had ids carry their link in the content as well */}
+              {message.content && !(message.attachments?.length && legacyAttachmentRegex.test(message.content)) && (
+                <MessageContent content={message.content} />
+              )}
             </div>
 
             {/* Retry Button for Failed Messages */}
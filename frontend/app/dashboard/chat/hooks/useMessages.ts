@@ -1,18 +1,19 @@
 import { useState, useCallback, useEffect, useRef } from 'react';
 import { useToast } from '@/hooks/use-toast';
-import { Message } from '../types';
+import { fetchWithRefresh } from '@/lib/auth';
+import { Message, MessageAttachment } from '../types';
 
 interface UseMessagesOptions {
   user?: any;
   selectedRoom: any;
-  sendChatMessage: (roomId: string, content: string, tempId: string) => boolean;
+  sendChatMessage: (roomId: string, content: string, tempId: string, attachmentIds?: string[]) => boolean;
 }
 
 interface UseMessagesReturn {
   messages: Message[];
   isLoadingMessages: boolean;
   typingUsers: Record<string, { name: string; timestamp: number }>;
-  sendMessage: (content: string) => void;
+  sendMessage: (content: string, attachments?: MessageAttachment[]) => void;
   sendVoiceMessage: (audioBlob: Blob) => Promise<boolean>;
   retryMessage: (message: Message) => void;
   handleMessageEvent: (event: any) => void;
@@ -21,6 +22,14 @@ interface UseMessagesReturn {
   applyReactionLocal: (messageId: string, emoji: string, add: boolean) => void;
 }
 
+// Ids of a message's attachments, as the server expects them with the message
+const attachmentIdsOf = (message: Message) => message.attachments?.map(attachment => attachment.id);
+
+// Whether two messages carry the same attachments, in the same order
+const sameAttachments = (a: Message, b: { attachments?: MessageAttachment[] }) =>
+  (a.attachments ?? []).map(attachment => attachment.id).join(',') ===
+  (b.attachments ?? []).map(attachment => attachment.id).join(',');
+
 export function useMessages({ user, selectedRoom, sendChatMessage }: UseMessagesOptions): UseMessagesReturn {
   const [messages, setMessages] = useState<Message[]>([]);
   const [isLoadingMessages, setIsLoadingMessages] = useState(false);
@@ -35,7 +44,7 @@ export function useMessages({ user, selectedRoom, sendChatMessage }: UseMessages
     if (!user || !selectedRoom) return;
     setIsLoadingMessages(true);
     try {
-      const response = await fetch(`/api/chat/rooms/${selectedRoom.id}/messages`, {
+      const response = await fetchWithRefresh(`/api/chat/rooms/${selectedRoom.id}/messages`, {
         credentials: 'include',
       });
       if (!response.ok) throw new Error('Failed to fetch messages');
@@ -89,7 +98,7 @@ export function useMessages({ user, selectedRoom, sendChatMessage }: UseMessages
     }
 
     // Retry sending
-    const success = sendChatMessage(selectedRoom.id, message.content, tempId);
+    const success = sendChatMessage(selectedRoom.id, message.content, tempId, attachmentIdsOf(message));
     if (success) {
       pendingMessagesRef.current.set(tempId, { 
         message, 
@@ -109,7 +118,7 @@ export function useMessages({ user, selectedRoom, sendChatMessage }: UseMessages
       formData.append('room_id', selectedRoom.id);
       formData.append('audio', audioBlob, 'voice_message.webm');
 
-      const response = await fetch(`/api/chat/voice`, {
+      const response = await fetchWithRefresh(`/api/chat/voice`, {
         method: 'POST',
         credentials: 'include',
         body: formData,
@@ -124,7 +133,7 @@ export function useMessages({ user, selectedRoom, sendChatMessage }: UseMessages
       // Add temporary message to UI
       const tempMessage: Message = {
         id: tempId,
-        content: '[audio](uploading...)',
+        content: '',
         user_id: user.id,
         user_name: user.name,
         user_profile_image: user.profile_image,
@@ -138,7 +147,8 @@ export function useMessages({ user, selectedRoom, sendChatMessage }: UseMessages
       // Update with real message data
       const realMessage: Message = {
         id: data.message_id,
-        content: data.audio_url ? `[audio](${data.audio_url})` : '[audio](uploaded)',
+        content: '',
+        attachments: data.attachment ? [data.attachment] : [],
         user_id: user.id,
         user_name: user.name,
         user_profile_image: user.profile_image,
@@ -175,8 +185,8 @@ export function useMessages({ user, selectedRoom, sendChatMessage }: UseMessages
     }
   }, [user, selectedRoom,  toast]);
 
-  const sendMessage = useCallback((content: string) => {
-    if (!selectedRoom || !user || !content.trim()) return;
+  const sendMessage = useCallback((content: string, attachments: MessageAttachment[] = []) => {
+    if (!selectedRoom || !user || (!content.trim() && attachments.length === 0)) return;
 
     const trimmedContent = content.trim();
     const tempId = `temp-${Date.now()}-${Math.random().toString(36).substr(2, 9)}`;
@@ -187,7 +197,8 @@ export function useMessages({ user, selectedRoom, sendChatMessage }: UseMessages
     
     const isDuplicate = recentMessages.some(pending => 
       pending.message.content === trimmedContent && 
-      pending.message.user_id === user.id
+      pending.message.user_id === user.id &&
+      sameAttachments(pending.message, { attachments })
     );
     
     if (isDuplicate) {
@@ -203,6 +214,7 @@ export function useMessages({ user, selectedRoom, sendChatMessage }: UseMessages
       user_profile_image: user.profile_image || null,
       room_id: selectedRoom.id,
       created_at: new Date().toISOString(),
+      attachments,
       status: 'sending',
     };
 
@@ -218,7 +230,7 @@ export function useMessages({ user, selectedRoom, sendChatMessage }: UseMessages
     });
 
     // Send via WebSocket
-    const success = sendChatMessage(selectedRoom.id, trimmedContent, tempId);
+    const success = sendChatMessage(selectedRoom.id, trimmedContent, tempId, attachments.map(attachment => attachment.id));
     if (!success) {
       // If sending failed, mark as failed immediately
       setMessages(prev => prev.map(msg =>
@@ -238,7 +250,7 @@ export function useMessages({ user, selectedRoom, sendChatMessage }: UseMessages
       // Remove the failed message
       setMessages(prev => prev.filter(msg => msg.id !== message.id));
       // Send again
-      sendMessage(message.content);
+      sendMessage(message.content, message.attachments);
     }
   }, [sendMessage]);
 
@@ -363,7 +375,8 @@ export function useMessages({ user, selectedRoom, sendChatMessage }: UseMessages
           const existingTempMessage = Array.from(pendingMessagesRef.current.entries())
             .find(([_, pending]) => 
               pending.message.content === messageData.content &&
-              pending.message.user_id === messageData.user_id
+              pending.message.user_id === messageData.user_id &&
+              sameAttachments(pending.message, messageData)
             );
           
           if (existingTempMessage) {
@@ -376,6 +389,7 @@ export function useMessages({ user, selectedRoom, sendChatMessage }: UseMessages
                   const confirmedMessage = {
                     ...msg,
                     id: messageId,
+                    attachments: messageData.attachments ?? msg.attachments,
                     status: 'sent' as const,
                   };
                   return confirmedMessage;
@@ -405,6 +419,7 @@ export function useMessages({ user, selectedRoom, sendChatMessage }: UseMessages
           user_profile_image: messageData.user_profile_image,
           room_id: messageData.room_id,
           created_at: messageData.timestamp ? new Date(messageData.timestamp * 1000).toISOString() : new Date().toISOString(),
+          attachments: messageData.attachments,
           status: 'sent',
         };
 
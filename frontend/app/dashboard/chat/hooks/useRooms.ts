@@ -1,6 +1,7 @@
 
 import { useState, useCallback } from 'react';
 import { useToast } from '@/hooks/use-toast';
+import { fetchWithRefresh } from '@/lib/auth';
 
 export interface Room {
   id: string;
@@ -45,7 +46,7 @@ export function useRooms({ userId }: UseRoomsOptions): UseRoomsReturn {
   const fetchRooms = useCallback(async () => {
     setIsLoadingRooms(true);
     try {
-      const response = await fetch(`${API_BASE}/chat/rooms`, {
+      const response = await fetchWithRefresh(`${API_BASE}/chat/rooms`, {
         method: 'GET',
         credentials: 'include',
         headers: { 'Content-Type': 'application/json' },
@@ -69,7 +70,7 @@ export function useRooms({ userId }: UseRoomsOptions): UseRoomsReturn {
 
   const createRoom = useCallback(async (name: string, description?: string, password?: string): Promise<Room | null> => {
     try {
-      const response = await fetch(`${API_BASE}/chat/rooms`, {
+      const response = await fetchWithRefresh(`${API_BASE}/chat/rooms`, {
         method: 'POST',
         credentials: 'include',
         headers: { 'Content-Type': 'application/json' },
@@ -95,7 +96,7 @@ export function useRooms({ userId }: UseRoomsOptions): UseRoomsReturn {
 
   const joinProtectedRoom = useCallback(async (roomId: string, password: string): Promise<boolean> => {
     try {
-      const response = await fetch(`${API_BASE}/chat/rooms/${roomId}/verify-password`, {
+      const response = await fetchWithRefresh(`${API_BASE}/chat/rooms/${roomId}/verify-password`, {
         method: 'POST',
         credentials: 'include',
         headers: { 'Content-Type': 'application/json' },
@@ -118,7 +119,7 @@ export function useRooms({ userId }: UseRoomsOptions): UseRoomsReturn {
 
   const joinRoomByCode = useCallback(async (code: string, password?: string): Promise<Room | null> => {
     try {
-      const response = await fetch(`${API_BASE}/chat/rooms/join-by-code`, {
+      const response = await fetchWithRefresh(`${API_BASE}/chat/rooms/join-by-code`, {
         method: 'POST',
         credentials: 'include',
         headers: { 'Content-Type': 'application/json' },
@@ -166,7 +167,7 @@ export function useRooms({ userId }: UseRoomsOptions): UseRoomsReturn {
 
   const deleteRoom = useCallback(async (roomId: string): Promise<boolean> => {
     try {
-      const response = await fetch(`${API_BASE}/chat/rooms/${roomId}`, {
+      const response = await fetchWithRefresh(`${API_BASE}/chat/rooms/${roomId}`, {
         method: 'DELETE',
         credentials: 'include',
         headers: { 'Content-Type': 'application/json' }
@@ -194,7 +195,7 @@ export function useRooms({ userId }: UseRoomsOptions): UseRoomsReturn {
 
   const leaveRoomMembership = useCallback(async (roomId: string): Promise<boolean> => {
     try {
-      const response = await fetch(`${API_BASE}/chat/rooms/${roomId}/membership`, {
+      const response = await fetchWithRefresh(`${API_BASE}/chat/rooms/${roomId}/membership`, {
         method: 'DELETE',
         credentials: 'include',
         headers: { 'Content-Type': 'application/json' },
@@ -29,7 +29,7 @@ interface UseWebSocketOptions {
 interface UseWebSocketReturn {
   isConnected: boolean;
   connectionState: string;
-  sendMessage: (roomId: string, content: string, tempId: string) => boolean;
+  sendMessage: (roomId: string, content: string, tempId: string, attachmentIds?: string[]) => boolean;
   sendTyping: (roomId: string, isTyping: boolean) => boolean;
   sendReaction: (messageId: string, emoji: string, add: boolean) => boolean;
   joinRoom: (roomId: string) => void;
@@ -253,8 +253,8 @@ export function useWebSocket({ user, onMessage, onConnectionChange }: UseWebSock
     sendWsMessage({ type: 'leave', room_id: roomId }, true);
   }, [sendWsMessage]);
 
-  const sendMessage = useCallback((roomId: string, content: string, tempId: string): boolean => {
-    return sendWsMessage({ type: 'message', room_id: roomId, content, temp_id: tempId });
+  const sendMessage = useCallback((roomId: string, content: string, tempId: string, attachmentIds?: string[]): boolean => {
+    return sendWsMessage({ type: 'message', room_id: roomId, content, temp_id: tempId, attachment_ids: attachmentIds });
   }, [sendWsMessage]);
 
   const sendTyping = useCallback((roomId: string, isTyping: boolean): boolean => {
@@ -1,7 +1,7 @@
 'use client';
 
 import React, { useRef, useEffect, useCallback, useState, useMemo } from 'react';
-import { useAuth } from '@/lib/auth';
+import { fetchWithRefresh, useAuth } from '@/lib/auth';
 import { Card, CardContent } from '@/components/ui/card';
 import { Badge } from '@/components/ui/badge';
 import { useSearchParams } from 'next/navigation';
@@ -18,6 +18,7 @@ import { useReactions } from './hooks/useReactions';
 import { RoomList } from './components/RoomList';
 import { MessageList } from './components/MessageList';
 import { MessageInput } from './components/MessageInput';
+import { MessageAttachment } from './types';
 
 export default function ChatPage() {
   const { user, isLoading, isAuthenticated } = useAuth();
@@ -156,9 +157,10 @@ export default function ChatPage() {
 
     try {
       const formData = new FormData();
+      formData.append('room_id', selectedRoom.id);
       formData.append('file', file);
 
-      const response = await fetch('/api/chat/upload', {
+      const response = await fetchWithRefresh('/api/chat/upload', {
         method: 'POST',
         body: formData,
         credentials: 'include',
@@ -169,7 +171,7 @@ export default function ChatPage() {
       }
 
       const data = await response.json();
-      return data.image_url;
+      return data.attachment as MessageAttachment;
     } catch (error) {
       console.error('Image upload failed:', error);
       toast({ title: "Upload Failed", description: "Failed to upload image. Please try again.", variant: "destructive" });
@@ -186,9 +188,10 @@ export default function ChatPage() {
 
     try {
       const formData = new FormData();
+      formData.append('room_id', selectedRoom.id);
       formData.append('file', file);
 
-      const response = await fetch('/api/chat/upload-video', {
+      const response = await fetchWithRefresh('/api/chat/upload-video', {
         method: 'POST',
         body: formData,
         credentials: 'include',
@@ -199,7 +202,7 @@ export default function ChatPage() {
       }
 
       const data = await response.json();
-      return data.video_url;
+      return data.attachment as MessageAttachment;
     } catch (error) {
       console.error('Video upload failed:', error);
       toast({ title: 'Upload Failed', description: 'Failed to upload video. Please try again.', variant: 'destructive' });
@@ -438,8 +441,8 @@ export default function ChatPage() {
               <MessageInput
                 selectedRoom={selectedRoom}
                 isConnected={isConnected}
-                onSendMessage={(content: string) => {
-                  sendMessage(content);
+                onSendMessage={(content: string, attachments?: MessageAttachment[]) => {
+                  sendMessage(content, attachments);
                   return true; // Return true to indicate success
                 }}
                 onTypingIndicator={(isTyping: boolean) => {
@@ -10,6 +10,7 @@ export type WsMessage = {
   emoji?: string;
   add?: boolean;
   temp_id?: string; // For client-side message reconciliation
+  attachment_ids?: string[]; // Uploaded attachments sent with the message
 };
 
 export type WsResponse = {
@@ -39,6 +40,29 @@ export interface MessageReaction {
   count: number;
 }
 
+// A smaller copy of an image attachment
+export interface MessageAttachmentPreview {
+  size: number; // Longest side in pixels
+  url: string;
+  mime_type: string;
+  width: number;
+  height: number;
+}
+
+// A file sent with a message
+export interface MessageAttachment {
+  id: string;
+  kind: 'image' | 'video' | 'audio';
+  url: string;
+  mime_type: string;
+  size?: number | null;
+  width?: number | null;
+  height?: number | null;
+  duration_ms?: number | null;
+  blurhash?: string | null;
+  previews?: MessageAttachmentPreview[];
+}
+
 // Main data model for a Message
 export interface Message {
   id: string;
@@ -49,6 +73,7 @@ export interface Message {
   room_id: string;
   created_at: string;
   reactions?: MessageReaction[];
+  attachments?: MessageAttachment[];
   is_own?: boolean; // Client-side property
   status?: 'sending' | 'sent' | 'failed'; // Client-side property
 }
@@ -97,6 +97,40 @@ const clearAuthCookie = () => {
   }
 };
 
+// Access tokens live 15 minutes on the backend; refresh a minute before they run out
+const DEFAULT_ACCESS_TOKEN_TTL_SECONDS = 15 * 60;
+const REFRESH_MARGIN_SECONDS = 60;
+
+// The backend rotates the refresh token on every use, so concurrent callers share one refresh
+let refreshInFlight: Promise<number | null> | null = null;
+
+// Exchange the refresh cookie for a new access token; resolves to its lifetime in seconds, or null if refused
+export const refreshAccessToken = (): Promise<number | null> => {
+  if (!refreshInFlight) {
+    refreshInFlight = (async () => {
+      try {
+        const response = await fetch(`/api/auth/refresh`, { method: 'POST', credentials: 'include' });
+        if (!response.ok) return null;
+        const data = await response.json().catch(() => ({}));
+        return typeof data.expires_in === 'number' ? data.expires_in : DEFAULT_ACCESS_TOKEN_TTL_SECONDS;
+      } catch {
+        return null;
+      } finally {
+        refreshInFlight = null;
+      }
+    })();
+  }
+  return refreshInFlight;
+};
+
+// fetch that refreshes the access token and retries once when the backend answers 401
+export const fetchWithRefresh = async (input: RequestInfo | URL, init: RequestInit = {}): Promise<Response> => {
+  const response = await fetch(input, { credentials: 'include', ...init });
+  if (response.status !== 401) return response;
+  if ((await refreshAccessToken()) === null) return response;
+  return fetch(input, { credentials: 'include', ...init });
+};
+
 // Helper function to show notification
 const showNotification = (message: string, type: 'error' | 'warning' | 'info' = 'error') => {
   if (typeof window !== 'undefined') {
@@ -125,9 +159,36 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({ children }) =>
 
   const router = useRouter();
 
+  // Lifetime of the current access token, when the backend told us
+  const [accessTokenTtl, setAccessTokenTtl] = useState<number | null>(null);
+
+  // Refresh the access token shortly before it expires while signed in
+  useEffect(() => {
+    if (!authState.isAuthenticated) return;
+
+    const ttl = accessTokenTtl ?? DEFAULT_ACCESS_TOKEN_TTL_SECONDS;
+    const timer = setTimeout(async () => {
+      const nextTtl = await refreshAccessToken();
+      if (nextTtl === null) {
+        await logoutWithMessage('Your session has expired. Please log in again.');
+      } else {
+        setAccessTokenTtl(nextTtl);
+      }
+    }, Math.max(ttl - REFRESH_MARGIN_SECONDS, 10) * 1000);
+
+    return () => clearTimeout(timer);
+  }, [authState.isAuthenticated, accessTokenTtl]);
+
   // Central error handler for API responses
   const handleApiError = async (response: Response, silent: boolean = false): Promise<boolean> => {
     if (response.status === 401) {
+      // An expired access token is renewed rather than ending the session; the caller may retry
+      const nextTtl = await refreshAccessToken();
+      if (nextTtl !== null) {
+        setAccessTokenTtl(nextTtl);
+        return false;
+      }
+
       try {
         const errorData = await response.json();
 
@@ -167,10 +228,7 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({ children }) =>
         }
 
         // Use /api/me to both validate the session and get fresh user data
-        const response = await fetch(`/api/me`, {
-          method: 'GET',
-          credentials: 'include',
-        });
+        const response = await fetchWithRefresh(`/api/me`, { method: 'GET' });
 
         if (response.ok) {
           const userData: User = await response.json();
@@ -241,6 +299,7 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({ children }) =>
           pending2FAUser: null,
           isLoading: false
         }));
+        setAccessTokenTtl(data.expires_in ?? null);
         return { success: true };
       }
 
@@ -278,6 +337,7 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({ children }) =>
       await fetch(`/api/auth/logout`, { method: 'GET', credentials: 'include' });
     } catch {}
 
+    setAccessTokenTtl(null);
     setAuthState(prev => ({
       ...prev,
       user: null,
@@ -325,6 +385,7 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({ children }) =>
           temp2FAToken: null,
           pending2FAUser: null
         }));
+        setAccessTokenTtl(data.expires_in ?? null);
         return { success: true };
       }
 
@@ -339,7 +400,7 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({ children }) =>
   const fetchSessions = async () => {
     setAuthState(prev => ({ ...prev, isLoadingSessions: true }));
     try {
-      const response = await fetch(`/api/auth/sessions`, { method: 'GET', credentials: 'include' });
+      const response = await fetchWithRefresh(`/api/auth/sessions`, { method: 'GET' });
       if (response.ok) {
         const data = await response.json();
         setAuthState(prev => ({ ...prev, sessions: data || [], isLoadingSessions: false }));
@@ -355,7 +416,7 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({ children }) =>
 
   const terminateSession = async (sessionId: string) => {
     try {
-      const response = await fetch(`/api/auth/sessions/${sessionId}`, { method: 'DELETE', credentials: 'include' });
+      const response = await fetchWithRefresh(`/api/auth/sessions/${sessionId}`, { method: 'DELETE' });
       if (response.ok) {
         await fetchSessions();
         return { success: true };
@@ -370,7 +431,7 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({ children }) =>
 
   const terminateAllSessions = async () => {
     try {
-      const response = await fetch(`/api/auth/sessions`, { method: 'DELETE', credentials: 'include' });
+      const response = await fetchWithRefresh(`/api/auth/sessions`, { method: 'DELETE' });
       if (response.ok) {
         await fetchSessions();
         return { success: true };
@@ -385,7 +446,7 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({ children }) =>
 
   const refreshUser = async () => {
     try {
-      const userResponse = await fetch(`/api/me`, { method: 'GET', credentials: 'include' });
+      const userResponse = await fetchWithRefresh(`/api/me`, { method: 'GET' });
       if (userResponse.ok) {
         const userData: User = await userResponse.json();
         setAuthState(prev => ({ ...prev, user: userData }));
@@ -431,6 +492,7 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({ children }) =>
             temp2FAToken: null,
             pending2FAUser: null
           }));
+          setAccessTokenTtl(data.expires_in ?? null);
           return { success: true };
         }
       }
@@ -56,6 +56,10 @@ const nextConfig = {
         source: '/api/auth/logout',
         destination: `${apiUrl}/api/auth/logout`,
       },
+      {
+        source: '/api/auth/refresh',
+        destination: `${apiUrl}/api/auth/refresh`,
+      },
       {
         source: '/api/auth/sync',
         destination: `${apiUrl}/api/auth/sync`,
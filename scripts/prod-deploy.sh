@@ -169,7 +169,7 @@ run_health_checks() {
     
     
     # Check backend
-    wait_for_service "Backend" "curl -f http://localhost/health"
+    wait_for_service "Backend" "curl -f http://localhost/health/ready"
     
     log_success "All health checks passed"
 }
@@ -197,7 +197,7 @@ show_summary() {
     echo "Service URLs:"
     echo "  - Application: http://localhost"
     echo "  - Traefik Dashboard: http://localhost:8080/dashboard/"
-    echo "  - Health Check: http://localhost/health"
+    echo "  - Health Check: http://localhost/health/ready"
     echo ""
     echo "Useful Commands:"
     echo "  - View logs: docker compose -f $COMPOSE_FILE logs -f"